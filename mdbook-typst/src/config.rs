@@ -0,0 +1,340 @@
+//! Options from the `[output.typst]` table in `book.toml`.
+
+use std::path::{Component, Path, PathBuf};
+
+use pullup::markdown::to::typst::ImageCaptions;
+use pullup::mdbook::to::typst::{
+    ChapterNumbering, DraftPolicy, PartStyle, Preamble, SeparatorStyle,
+};
+use serde::Deserialize;
+
+/// Options for the Typst backend.
+///
+/// mdBook adds its own keys such as `command` and `optional` to the table, so unknown
+/// keys are ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// The name of the generated file in the output directory.
+    pub file: PathBuf,
+    /// Also write each top-level chapter to its own file, named after the chapter's
+    /// source file. Links to other chapters cannot be resolved within a chapter file,
+    /// so their labels are defined as empty metadata there and the links go nowhere.
+    pub chapter_files: bool,
+    /// Whether to start the book with a title page made from the title, description,
+    /// and authors in `[book]`.
+    pub title_page: bool,
+    /// Whether to add a table of contents after the title page.
+    pub outline: bool,
+    /// How to render the titles of parts in `SUMMARY.md`.
+    pub parts: Parts,
+    /// How to render separators in `SUMMARY.md`.
+    pub separators: Separators,
+    /// How to number the headings of numbered chapters.
+    pub chapter_numbering: Numbering,
+    /// The Typst numbering pattern when `chapter_numbering` is [`Numbering::Typst`].
+    pub chapter_numbering_pattern: String,
+    /// How to render draft chapters.
+    pub drafts: Drafts,
+    /// The text after the title of a draft chapter when `drafts` is [`Drafts::Title`].
+    pub draft_marker: String,
+    /// Whether to strip hidden lines, those starting with `#`, from Rust code blocks.
+    pub hidden_lines: bool,
+    /// What to use as the caption of image figures.
+    pub image_captions: Captions,
+    /// Whether to set up the page and text before the content. The options below
+    /// override the defaults of [`Preamble`].
+    pub preamble: bool,
+    /// The paper size, like `a4` or `us-letter`.
+    pub paper: Option<String>,
+    /// The page margins, as a Typst length or dictionary like `(x: 2cm, y: 3cm)`.
+    pub margin: Option<String>,
+    /// The numbering pattern of page numbers, like `1`.
+    pub page_numbering: Option<String>,
+    /// The font family.
+    pub font: Option<String>,
+    /// The font size, as a Typst length.
+    pub font_size: Option<String>,
+    /// The language of the text. Defaults to `book.language`.
+    pub lang: Option<String>,
+    /// Whether to justify paragraphs.
+    pub justify: Option<bool>,
+    /// The numbering pattern of headings, like `1.1`.
+    pub heading_numbering: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            file: PathBuf::from("book.typ"),
+            chapter_files: false,
+            title_page: true,
+            outline: true,
+            parts: Parts::Page,
+            separators: Separators::Line,
+            chapter_numbering: Numbering::Text,
+            chapter_numbering_pattern: "1.1.".to_string(),
+            drafts: Drafts::Title,
+            draft_marker: "(draft)".to_string(),
+            hidden_lines: true,
+            image_captions: Captions::TitleOrAlt,
+            preamble: true,
+            paper: None,
+            margin: None,
+            page_numbering: None,
+            font: None,
+            font_size: None,
+            lang: None,
+            justify: None,
+            heading_numbering: None,
+        }
+    }
+}
+
+/// How to render part titles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Parts {
+    /// A page of its own.
+    Page,
+    /// A heading before the part's first chapter.
+    Heading,
+    /// Leave part titles out.
+    None,
+}
+
+impl Parts {
+    pub fn style(self) -> Option<PartStyle> {
+        match self {
+            Parts::Page => Some(PartStyle::Page),
+            Parts::Heading => Some(PartStyle::Heading),
+            Parts::None => None,
+        }
+    }
+}
+
+/// How to render separators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Separators {
+    /// A horizontal line.
+    Line,
+    /// Vertical space.
+    Space,
+    /// Leave separators out.
+    None,
+}
+
+impl Separators {
+    pub fn style(self) -> Option<SeparatorStyle> {
+        match self {
+            Separators::Line => Some(SeparatorStyle::Line),
+            Separators::Space => Some(SeparatorStyle::Space),
+            Separators::None => None,
+        }
+    }
+}
+
+/// How to number chapter headings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Numbering {
+    /// Put the section number in the heading text, like the HTML renderer.
+    Text,
+    /// Let Typst number the headings.
+    Typst,
+    /// Leave chapters unnumbered.
+    None,
+}
+
+/// How to render draft chapters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Drafts {
+    /// Leave them out.
+    Skip,
+    /// Show their title followed by the draft marker.
+    Title,
+    /// Only list them in the table of contents.
+    Outline,
+}
+
+/// What to use as the caption of image figures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Captions {
+    /// The image title if it has one, otherwise the alt text.
+    TitleOrAlt,
+    /// Only the image title.
+    Title,
+    /// Only the alt text.
+    Alt,
+    /// Leave figures without a caption.
+    None,
+}
+
+impl Captions {
+    pub fn policy(self) -> ImageCaptions {
+        match self {
+            Captions::TitleOrAlt => ImageCaptions::TitleOrAlt,
+            Captions::Title => ImageCaptions::Title,
+            Captions::Alt => ImageCaptions::Alt,
+            Captions::None => ImageCaptions::None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the options from the book configuration, using the defaults if the table
+    /// is missing.
+    ///
+    /// The output file must be a relative path that stays inside the output directory.
+    pub fn from_book(config: &mdbook::Config) -> anyhow::Result<Self> {
+        let config: Self = config
+            .get_deserialized_opt("output.typst")?
+            .unwrap_or_default();
+        if !is_inside(&config.file) {
+            anyhow::bail!(
+                "file must be a relative path inside the output directory, not {}",
+                config.file.display()
+            );
+        }
+        Ok(config)
+    }
+
+    /// How to number chapter headings, if at all.
+    pub fn chapter_numbering(&self) -> Option<ChapterNumbering> {
+        match self.chapter_numbering {
+            Numbering::Text => Some(ChapterNumbering::Text),
+            Numbering::Typst => Some(ChapterNumbering::Typst(
+                self.chapter_numbering_pattern.clone(),
+            )),
+            Numbering::None => None,
+        }
+    }
+
+    /// How to render draft chapters.
+    pub fn drafts(&self) -> DraftPolicy {
+        match self.drafts {
+            Drafts::Skip => DraftPolicy::Skip,
+            Drafts::Title => DraftPolicy::Title(self.draft_marker.clone()),
+            Drafts::Outline => DraftPolicy::Outline,
+        }
+    }
+
+    /// The preamble to add before the content, if any.
+    pub fn preamble(&self) -> Option<Preamble> {
+        if !self.preamble {
+            return None;
+        }
+        let defaults = Preamble::default();
+        Some(Preamble {
+            paper: self.paper.clone().or(defaults.paper),
+            margin: self.margin.clone().or(defaults.margin),
+            page_numbering: self.page_numbering.clone().or(defaults.page_numbering),
+            font: self.font.clone().or(defaults.font),
+            font_size: self.font_size.clone().or(defaults.font_size),
+            lang: self.lang.clone().or(defaults.lang),
+            justify: self.justify.or(defaults.justify),
+            heading_numbering: self
+                .heading_numbering
+                .clone()
+                .or(defaults.heading_numbering),
+        })
+    }
+}
+
+/// Whether `path` names a file inside the directory it is relative to: it is not
+/// absolute, does not go up with `..`, and is not empty.
+pub fn is_inside(path: &Path) -> bool {
+    let mut components = path.components().peekable();
+    components.peek().is_some()
+        && components.all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        && path.file_name().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let config: mdbook::Config = "[output.typst]".parse().unwrap();
+        assert_eq!(Config::from_book(&config).unwrap(), Config::default());
+        let config = mdbook::Config::default();
+        assert_eq!(Config::from_book(&config).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn options() {
+        let config: mdbook::Config = "\
+[output.typst]
+command = \"mdbook-typst\"
+file = \"out.typ\"
+chapter-files = true
+title-page = false
+outline = false
+parts = \"heading\"
+separators = \"none\"
+chapter-numbering = \"typst\"
+chapter-numbering-pattern = \"I.a\"
+drafts = \"outline\"
+hidden-lines = false
+image-captions = \"alt\"
+"
+        .parse()
+        .unwrap();
+        assert_eq!(
+            Config::from_book(&config).unwrap(),
+            Config {
+                file: PathBuf::from("out.typ"),
+                chapter_files: true,
+                title_page: false,
+                outline: false,
+                parts: Parts::Heading,
+                separators: Separators::None,
+                chapter_numbering: Numbering::Typst,
+                chapter_numbering_pattern: "I.a".to_string(),
+                drafts: Drafts::Outline,
+                hidden_lines: false,
+                image_captions: Captions::Alt,
+                ..Config::default()
+            }
+        );
+    }
+
+    #[test]
+    fn file_outside_output() {
+        for file in [
+            "/tmp/book.typ",
+            "../book.typ",
+            "out/../../book.typ",
+            "",
+            ".",
+        ] {
+            let config: mdbook::Config =
+                format!("[output.typst]\nfile = {file:?}").parse().unwrap();
+            assert!(Config::from_book(&config).is_err(), "{file}");
+        }
+        let config: mdbook::Config = "[output.typst]\nfile = \"out/./book.typ\"".parse().unwrap();
+        assert!(Config::from_book(&config).is_ok());
+    }
+
+    #[test]
+    fn preamble() {
+        let config: mdbook::Config = "\
+[output.typst]
+paper = \"us-letter\"
+font = \"Inter\"
+"
+        .parse()
+        .unwrap();
+        let preamble = Config::from_book(&config).unwrap().preamble().unwrap();
+        assert_eq!(preamble.paper.as_deref(), Some("us-letter"));
+        assert_eq!(preamble.font.as_deref(), Some("Inter"));
+        assert_eq!(preamble.margin, Preamble::default().margin);
+
+        let config: mdbook::Config = "[output.typst]\npreamble = false".parse().unwrap();
+        assert_eq!(Config::from_book(&config).unwrap().preamble(), None);
+    }
+}
@@ -0,0 +1,205 @@
+//! An mdBook backend that renders books to Typst.
+//!
+//! mdBook runs the binary with the book's `RenderContext` as JSON on stdin and the
+//! output directory as the working directory. See
+//! <https://rust-lang.github.io/mdBook/for_developers/backends.html>.
+
+mod config;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use mdbook::renderer::RenderContext;
+use pulldown_mdbook::{ChapterSource, Event, Parser, Tag};
+use pulldown_typst::markup::TryTypstMarkup;
+use pullup::mdbook::to::typst::Conversion;
+use pullup::typst::TypstFilter;
+
+use config::Config;
+
+fn main() -> anyhow::Result<()> {
+    let ctx = RenderContext::from_json(io::stdin()).context("failed to read render context")?;
+    render(&ctx)
+}
+
+/// Render the book to the output directory.
+///
+/// The book is streamed into the output file. Chapter files are written from a second
+/// pass over the book, holding one top-level chapter at a time.
+fn render(ctx: &RenderContext) -> anyhow::Result<()> {
+    let config = Config::from_book(&ctx.config).context("invalid [output.typst] table")?;
+
+    write_typst(
+        &ctx.destination.join(&config.file),
+        &config,
+        Parser::from_rendercontext(ctx),
+    )?;
+    if config.chapter_files {
+        for (path, events) in Chapters::new(Parser::from_rendercontext(ctx)) {
+            let path = path.with_extension("typ");
+            if !config::is_inside(&path) {
+                bail!("chapter {} is outside the book", path.display());
+            }
+            write_typst(&ctx.destination.join(path), &config, events.into_iter())?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert mdBook events to Typst markup and write it to `path`.
+fn write_typst<'a>(
+    path: &Path,
+    config: &Config,
+    events: impl Iterator<Item = Event<'a>> + 'a,
+) -> anyhow::Result<()> {
+    let typst = TypstFilter(
+        Conversion::builder()
+            .events(events)
+            .title_page(config.title_page)
+            .outline(config.outline)
+            .parts(config.parts.style())
+            .separators(config.separators.style())
+            .chapter_numbering(config.chapter_numbering())
+            .drafts(config.drafts())
+            .hidden_lines(config.hidden_lines)
+            .image_captions(config.image_captions.policy())
+            .preamble(config.preamble())
+            .build(),
+    );
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut file = BufWriter::new(file);
+    for markup in TryTypstMarkup::new(typst) {
+        let markup = markup.with_context(|| format!("failed to generate {}", path.display()))?;
+        file.write_all(markup.as_bytes())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    file.flush()
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Split book events into the top-level chapters that have a source file, one chapter
+/// at a time. Each chapter includes its nested chapters.
+struct Chapters<'a, I> {
+    events: I,
+    /// The events of the current top-level chapter.
+    chapter: Vec<Event<'a>>,
+    depth: usize,
+}
+
+impl<'a, I> Chapters<'a, I>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    fn new(events: I) -> Self {
+        Self {
+            events,
+            chapter: vec![],
+            depth: 0,
+        }
+    }
+}
+
+impl<'a, I> Iterator for Chapters<'a, I>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    type Item = (PathBuf, Vec<Event<'a>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for event in self.events.by_ref() {
+            match &event {
+                Event::Start(Tag::Chapter(..)) => self.depth += 1,
+                Event::End(Tag::Chapter(..)) => self.depth -= 1,
+                // Only chapters are split out.
+                _ if self.depth == 0 => continue,
+                _ => {}
+            }
+            let end = match &event {
+                Event::End(Tag::Chapter(_, _, source, _)) if self.depth == 0 => {
+                    Some(source.clone())
+                }
+                _ => None,
+            };
+            self.chapter.push(event);
+            match end {
+                Some(Some(ChapterSource::Path(path))) => {
+                    return Some((path, core::mem::take(&mut self.chapter)))
+                }
+                Some(_) => self.chapter.clear(),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mdbook::book::{Book, BookItem, Chapter};
+
+    fn chapter(name: &str, content: &str, sub_items: Vec<BookItem>) -> BookItem {
+        let mut ch = Chapter::new(name, content.to_string(), format!("{name}.md"), vec![]);
+        ch.sub_items = sub_items;
+        BookItem::Chapter(ch)
+    }
+
+    fn context(book_toml: &str, destination: &Path) -> RenderContext {
+        let mut book = Book::new();
+        book.sections = vec![
+            chapter("one", "# One\n\nHello *world*, see [two](two.md).", vec![]),
+            chapter("two", "# Two", vec![chapter("three", "# Three", vec![])]),
+        ];
+        let config = book_toml.parse().unwrap();
+        RenderContext::new("/book", book, config, destination)
+    }
+
+    #[test]
+    fn renders_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let ctx = context("[book]\ntitle = \"Book\"\n[output.typst]", dir);
+        render(&ctx).unwrap();
+
+        let book = fs::read_to_string(dir.join("book.typ")).unwrap();
+        assert!(
+            book.contains("#heading(level: 1, outlined: false, bookmarked: false)[Book]"),
+            "{book}"
+        );
+        assert!(book.contains("#outline()"), "{book}");
+        assert!(book.contains("Hello #emph[world], see"), "{book}");
+        assert!(book.contains("= one <one>"), "{book}");
+        assert!(book.contains("#set page(paper: \"a4\""), "{book}");
+        assert!(book.contains("#link(<two>)[two]"), "{book}");
+        assert!(!book.contains("#metadata(none)"), "{book}");
+        assert!(!dir.join("one.typ").exists());
+    }
+
+    #[test]
+    fn renders_chapter_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let ctx = context(
+            "[output.typst]\nfile = \"all.typ\"\nchapter-files = true",
+            dir,
+        );
+        render(&ctx).unwrap();
+
+        assert!(dir.join("all.typ").exists());
+        let one = fs::read_to_string(dir.join("one.typ")).unwrap();
+        assert!(one.contains("Hello") && !one.contains("Three"), "{one}");
+        assert!(!one.contains("#outline()"), "{one}");
+        let two = fs::read_to_string(dir.join("two.typ")).unwrap();
+        assert!(two.contains("Three") && !two.contains("Hello"), "{two}");
+        assert!(!dir.join("three.typ").exists());
+        // The label of the other chapter is not defined in this file.
+        assert!(one.contains("#metadata(none) <two>"), "{one}");
+    }
+}
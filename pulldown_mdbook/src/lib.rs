@@ -1,12 +1,13 @@
 use pulldown_cmark::CowStr;
 use std::path::PathBuf;
 
+pub mod links;
 pub mod markdown;
 pub mod parser;
 
 pub use parser::Parser;
 
-use markdown::TextMergeStream;
+use markdown::{FootnotesFirst, TextMergeStream};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Event<'a> {
@@ -20,6 +21,10 @@ pub enum Event<'a> {
     Root(PathBuf),
     /// The title of the book.
     Title(CowStr<'a>),
+    /// A short description of the book.
+    Description(CowStr<'a>),
+    /// The language of the book, like `en`.
+    Language(CowStr<'a>),
     /// An author of the book.
     Author(CowStr<'a>),
     /// Separators can be added before, in-between, and after any other element.
@@ -32,19 +37,18 @@ pub enum Event<'a> {
 #[derive(Clone, Debug, PartialEq)]
 pub enum Tag<'a> {
     /// A part is used to logically separate different sections of the book. The first
-    /// field is the title. If the part is ordered the second field indicates the number
-    /// of the first chapter.
-    Part(Option<CowStr<'a>>, Option<u64>),
+    /// field is the title. If the part contains numbered chapters the second field is
+    /// the top-level number of the first one, like `ChapterNumber::Numbered(vec![3])`.
+    Part(Option<CowStr<'a>>, Option<ChapterNumber>),
 
     /// A chapter represents book content. The first field indicates the status, the
-    /// second field is the name, and the third field is the source. If the part is
-    /// ordered the fourth field indicates the number of the chapter. Chapters can be
-    /// nested.
+    /// second field is the name, and the third field is the source. The fourth field is
+    /// where the chapter is in the book's numbering, if known. Chapters can be nested.
     Chapter(
         ChapterStatus,
         CowStr<'a>,
         Option<ChapterSource<'a>>,
-        Option<u64>,
+        Option<ChapterNumber>,
     ),
     /// The content of the chapter.
     Content(ContentType),
@@ -66,6 +70,31 @@ pub enum ChapterStatus {
     Draft,
 }
 
+/// Where a chapter is in the book's numbering, following `SUMMARY.md`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChapterNumber {
+    /// An unnumbered chapter before the numbered chapters.
+    Prefix,
+    /// A numbered chapter. The field is the section number, one number per level of
+    /// nesting: `[1, 2, 3]` is section 1.2.3.
+    Numbered(Vec<u32>),
+    /// An unnumbered chapter after the numbered chapters.
+    Suffix,
+}
+
+impl core::fmt::Display for ChapterNumber {
+    /// Write the section number the way mdBook does, like `1.2.3.`. Unnumbered
+    /// chapters are written as nothing.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let ChapterNumber::Numbered(numbers) = self {
+            for n in numbers {
+                write!(f, "{n}.")?;
+            }
+        }
+        Ok(())
+    }
+}
+
 /// The type of content.
 #[derive(Clone, Debug, PartialEq, Copy)]
 pub enum ContentType {
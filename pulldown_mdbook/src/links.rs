@@ -0,0 +1,290 @@
+//! Expand mdBook's `{{#include}}`, `{{#rustdoc_include}}`, and `{{#title}}` helpers.
+//!
+//! mdBook's `links` preprocessor expands these before a renderer sees the book, but a
+//! book loaded with [`MDBook::load`](mdbook::MDBook::load) has not been preprocessed.
+//! Expand them with [`ExpandedBook`] and parse the book with
+//! [`Parser::from_expanded`](crate::Parser::from_expanded), so the helpers do not end up
+//! in the output as text:
+//!
+//! ```no_run
+//! let book = mdbook::MDBook::load("path/to/book").unwrap();
+//! let expanded = pulldown_mdbook::links::ExpandedBook::new(&book);
+//! let events = pulldown_mdbook::Parser::from_expanded(&book, &expanded);
+//! ```
+//!
+//! Helpers that cannot be expanded, for example because the file does not exist, are
+//! left in place. Escaped helpers like `\{{#include file.rs}}` lose the backslash.
+
+use mdbook::utils::{
+    take_anchored_lines, take_lines, take_rustdoc_include_anchored_lines,
+    take_rustdoc_include_lines,
+};
+use mdbook::{BookItem, MDBook};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+
+/// How deep included files may include other files, to stop cyclic includes.
+const MAX_DEPTH: usize = 10;
+
+/// Chapter content with its helpers expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expanded {
+    /// The content.
+    pub content: String,
+    /// The title set with `{{#title}}`, if any. mdBook uses it as the title of the
+    /// chapter's web page.
+    pub title: Option<String>,
+}
+
+/// The content of the chapters of a book whose helpers change it when expanded.
+///
+/// The parser borrows the expanded content for as long as the book, so every chapter
+/// is expanded up front. Chapters without helpers are not copied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpandedBook {
+    /// Expanded content by chapter source path.
+    chapters: HashMap<PathBuf, String>,
+}
+
+impl ExpandedBook {
+    /// Expand the helpers in every chapter of `book`. Paths are relative to each
+    /// chapter's source file.
+    pub fn new(book: &MDBook) -> Self {
+        let src = book.root.join(&book.config.book.src);
+        let chapters = book
+            .iter()
+            .filter_map(|item| match item {
+                BookItem::Chapter(ch) if ch.content.contains("{{") => {
+                    let path = ch.source_path.as_ref()?;
+                    let dir = src.join(path.parent().unwrap_or(Path::new("")));
+                    let content = expand(&ch.content, &dir).content;
+                    (content != ch.content).then(|| (path.clone(), content))
+                }
+                _ => None,
+            })
+            .collect();
+        Self { chapters }
+    }
+
+    /// The expanded content of the chapter with the source path `path`, if expanding
+    /// changed it.
+    pub fn content(&self, path: &Path) -> Option<&str> {
+        self.chapters.get(path).map(String::as_str)
+    }
+}
+
+/// Expand the helpers in `content`. Paths are relative to `dir`, the directory of the
+/// chapter's source file.
+pub fn expand(content: &str, dir: &Path) -> Expanded {
+    let mut title = None;
+    let content = expand_at_depth(content, dir, 0, &mut title);
+    Expanded { content, title }
+}
+
+fn expand_at_depth(s: &str, dir: &Path, depth: usize, title: &mut Option<String>) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        let escaped = rest[..start].ends_with('\\');
+        let Some((len, helper)) = parse_helper(&rest[start..], escaped) else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            continue;
+        };
+        let text = &rest[start..start + len];
+        match helper {
+            Helper::Escaped => {
+                out.push_str(&rest[..start - 1]);
+                out.push_str(text);
+            }
+            Helper::Title(t) => {
+                out.push_str(&rest[..start]);
+                *title = Some(t.to_string());
+            }
+            Helper::Include(path, lines) | Helper::RustdocInclude(path, lines) => {
+                out.push_str(&rest[..start]);
+                let target = dir.join(path);
+                match fs::read_to_string(&target) {
+                    Ok(file) if depth < MAX_DEPTH => {
+                        let included = match (&helper, lines) {
+                            (Helper::Include(..), Lines::Range(r)) => take_lines(&file, r),
+                            (Helper::Include(..), Lines::Anchor(a)) => {
+                                take_anchored_lines(&file, a)
+                            }
+                            (_, Lines::Range(r)) => take_rustdoc_include_lines(&file, r),
+                            (_, Lines::Anchor(a)) => take_rustdoc_include_anchored_lines(&file, a),
+                        };
+                        let included_dir = target.parent().unwrap_or(dir);
+                        out.push_str(&expand_at_depth(&included, included_dir, depth + 1, title));
+                    }
+                    _ => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!("could not expand {} ({})", text, target.display());
+                        out.push_str(text);
+                    }
+                }
+            }
+        }
+        rest = &rest[start + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A helper in chapter content.
+#[derive(Debug, Clone, PartialEq)]
+enum Helper<'a> {
+    /// An escaped helper, written without the backslash.
+    Escaped,
+    Include(&'a str, Lines<'a>),
+    RustdocInclude(&'a str, Lines<'a>),
+    Title(&'a str),
+}
+
+/// The lines of a file to include.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lines<'a> {
+    /// Zero-based line range.
+    Range((Bound<usize>, Bound<usize>)),
+    /// The lines between `ANCHOR: name` and `ANCHOR_END: name` comments.
+    Anchor(&'a str),
+}
+
+/// Parse the helper at the start of `s`, which starts with `{{`. Returns its length and
+/// the helper, or `None` if `s` does not start with a supported helper.
+fn parse_helper(s: &str, escaped: bool) -> Option<(usize, Helper<'_>)> {
+    if escaped {
+        // Like mdBook, an escaped helper extends to the last `}}` on its line.
+        let line = &s[..s.find('\n').unwrap_or(s.len())];
+        let end = line.rfind("}}")?;
+        return (s[2..].starts_with('#') && end >= 2).then_some((end + 2, Helper::Escaped));
+    }
+    let inner = s[2..].trim_start();
+    let name_start = s.len() - inner.len() + 1;
+    let name = inner.strip_prefix('#')?;
+    let name_len = name
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(name.len());
+    let args = &name[name_len..];
+    let args_len = args.find('}').filter(|&i| args[i..].starts_with("}}"))?;
+    let trimmed = args[..args_len].trim_start();
+    if name_len == 0 || trimmed.len() == args_len {
+        // The name has to be followed by whitespace and arguments.
+        return None;
+    }
+    let len = name_start + name_len + args_len + 2;
+    let helper = match &name[..name_len] {
+        "title" => Helper::Title(trimmed),
+        kind @ ("include" | "rustdoc_include") => {
+            let file = trimmed.split_whitespace().next()?;
+            let (path, lines) = match file.split_once(':') {
+                Some((path, lines)) => (path, parse_lines(lines)),
+                None => (file, Lines::Range((Bound::Unbounded, Bound::Unbounded))),
+            };
+            if kind == "include" {
+                Helper::Include(path, lines)
+            } else {
+                Helper::RustdocInclude(path, lines)
+            }
+        }
+        _ => return None,
+    };
+    Some((len, helper))
+}
+
+/// Parse a one-based line range like `2:5`, `2:`, `:5`, or `2`, or an anchor name.
+fn parse_lines(s: &str) -> Lines<'_> {
+    let mut parts = s.splitn(2, ':');
+    let first = parts.next().unwrap_or("");
+    let start = match first.parse::<usize>() {
+        Ok(n) => Bound::Included(n.saturating_sub(1)),
+        Err(_) if first.is_empty() => Bound::Unbounded,
+        Err(_) => return Lines::Anchor(first),
+    };
+    let end = match (parts.next().map(str::parse::<usize>), start) {
+        (Some(Ok(end)), _) => Bound::Excluded(end),
+        (Some(Err(_)), _) | (None, Bound::Unbounded) => Bound::Unbounded,
+        // A single line.
+        (None, Bound::Included(n)) => Bound::Excluded(n + 1),
+        (None, Bound::Excluded(_)) => unreachable!("start is never excluded"),
+    };
+    Lines::Range((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use similar_asserts::assert_eq;
+    use tempfile::TempDir;
+
+    /// A directory with source files to include, removed when dropped.
+    fn dir() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        fs::create_dir_all(path.join("sub")).unwrap();
+        fs::write(path.join("lines.txt"), "one\ntwo\nthree\nfour").unwrap();
+        fs::write(
+            path.join("main.rs"),
+            "use std::io;\n// ANCHOR: main\nfn main() {}\n// ANCHOR_END: main\n",
+        )
+        .unwrap();
+        fs::write(path.join("sub/nested.md"), "Nested {{#include inner.txt}}").unwrap();
+        fs::write(path.join("sub/inner.txt"), "inner").unwrap();
+        dir
+    }
+
+    #[test]
+    fn include() {
+        let dir = dir();
+        let expand = |s: &str| expand(s, dir.path()).content;
+        self::assert_eq!(expand("{{#include lines.txt}}"), "one\ntwo\nthree\nfour");
+        self::assert_eq!(expand("{{#include lines.txt:2}}"), "two");
+        self::assert_eq!(expand("{{#include lines.txt:2:3}}"), "two\nthree");
+        self::assert_eq!(expand("{{#include lines.txt:3:}}"), "three\nfour");
+        self::assert_eq!(expand("{{#include lines.txt::2}}"), "one\ntwo");
+        self::assert_eq!(expand("a {{ #include  lines.txt:1 }} b"), "a one b");
+        self::assert_eq!(expand("{{#include main.rs:main}}"), "fn main() {}");
+    }
+
+    #[test]
+    fn rustdoc_include() {
+        let dir = dir();
+        self::assert_eq!(
+            expand("{{#rustdoc_include main.rs:main}}", dir.path()).content,
+            "# use std::io;\nfn main() {}"
+        );
+        self::assert_eq!(
+            expand("{{#rustdoc_include lines.txt:2:3}}", dir.path()).content,
+            "# one\ntwo\nthree\n# four"
+        );
+    }
+
+    #[test]
+    fn nested_include_is_relative_to_included_file() {
+        let dir = dir();
+        self::assert_eq!(
+            expand("{{#include sub/nested.md}}", dir.path()).content,
+            "Nested inner"
+        );
+    }
+
+    #[test]
+    fn title_escaped_and_unknown() {
+        let dir = dir();
+        let content = "\
+{{#title My Page}}
+\\{{#include lines.txt}}
+{{#include missing.txt}}
+{{#playground x.rs}}";
+        self::assert_eq!(
+            expand(content, dir.path()),
+            Expanded {
+                content: "\n{{#include lines.txt}}\n{{#include missing.txt}}\n{{#playground x.rs}}"
+                    .to_string(),
+                title: Some("My Page".to_string()),
+            }
+        );
+    }
+}
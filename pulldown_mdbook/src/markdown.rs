@@ -1,4 +1,9 @@
-pub use pulldown_cmark::{Event, HeadingLevel, LinkType, Parser, Tag};
+pub use pulldown_cmark::{
+    BrokenLinkCallback, Event, HeadingLevel, LinkType, OffsetIter, Options, Parser, Tag,
+};
+
+use std::collections::{HashSet, VecDeque};
+use std::ops::Range;
 
 use crate::CowStr;
 
@@ -70,3 +75,267 @@ where
         }
     }
 }
+
+/// Parse Markdown with footnote definitions before the references to them.
+///
+/// Converters that inline footnote definitions at their references need to see each
+/// definition first. The source is parsed once, holding back each top-level block
+/// until it ends. Only if a block references a footnote that is not defined yet is the
+/// source parsed a second time, to yield the remaining footnote definitions before
+/// that block. They are then skipped where the first pass comes across them.
+pub struct FootnotesFirst<'a> {
+    source: &'a str,
+    options: Options,
+    rest: OffsetIter<'a, 'a>,
+    definitions: Definitions<'a>,
+    /// The held back events of the current top-level block.
+    block: VecDeque<(Event<'a>, Range<usize>)>,
+    /// The labels of the footnote definitions yielded so far.
+    defined: HashSet<CowStr<'a>>,
+    /// Where the footnote definitions yielded by the first pass start.
+    in_place: HashSet<usize>,
+    /// The footnote definition `rest` is in, once the definitions are read.
+    within: WithinDefinition,
+}
+
+/// The second pass over the source.
+enum Definitions<'a> {
+    /// No footnote was referenced before its definition yet. Holds the callback for the
+    /// second pass.
+    Unread(BrokenLinkCallback<'a, 'a>),
+    /// The second pass, yielding the footnote definitions the first pass did not.
+    Reading(Box<OffsetIter<'a, 'a>>, WithinDefinition),
+    /// All footnote definitions have been yielded.
+    Read,
+}
+
+/// Follows the footnote definitions of a pass over the source.
+#[derive(Debug, Default)]
+struct WithinDefinition {
+    depth: usize,
+    start: usize,
+}
+
+impl WithinDefinition {
+    /// Where the outermost footnote definition the event is in starts, if it is in one.
+    fn start(&mut self, event: &Event, range: &Range<usize>) -> Option<usize> {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(_)) => {
+                if self.depth == 0 {
+                    self.start = range.start;
+                }
+                self.depth += 1;
+            }
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                self.depth -= 1;
+                return Some(self.start);
+            }
+            _ => {}
+        }
+        (self.depth > 0).then_some(self.start)
+    }
+}
+
+impl<'a> FootnotesFirst<'a> {
+    pub fn new(source: &'a str, options: Options) -> Self {
+        Self::new_with_broken_link_callbacks(source, options, None, None)
+    }
+
+    /// Parse with callbacks for links to undefined references, as with
+    /// [`Parser::new_with_broken_link_callback`]. Each pass needs its own callback:
+    /// `definitions` is used by the second pass that yields the footnote definitions
+    /// and `rest` by the first pass. `definitions` is only called if a footnote is
+    /// referenced before its definition.
+    pub fn new_with_broken_link_callbacks(
+        source: &'a str,
+        options: Options,
+        definitions: BrokenLinkCallback<'a, 'a>,
+        rest: BrokenLinkCallback<'a, 'a>,
+    ) -> Self {
+        Self {
+            source,
+            options,
+            rest: Parser::new_with_broken_link_callback(source, options, rest).into_offset_iter(),
+            definitions: Definitions::Unread(definitions),
+            block: VecDeque::new(),
+            defined: HashSet::new(),
+            in_place: HashSet::new(),
+            within: WithinDefinition::default(),
+        }
+    }
+
+    /// Yield the source range of each event along with it, like
+    /// [`Parser::into_offset_iter`].
+    pub fn into_offset_iter(self) -> FootnotesFirstOffsets<'a> {
+        FootnotesFirstOffsets(self)
+    }
+
+    fn next_with_range(&mut self) -> Option<(Event<'a>, Range<usize>)> {
+        loop {
+            if let Definitions::Reading(definitions, within) = &mut self.definitions {
+                match definitions.next() {
+                    Some((event, range)) => {
+                        let start = within.start(&event, &range);
+                        if start.is_some_and(|start| !self.in_place.contains(&start)) {
+                            return Some((event, range));
+                        }
+                    }
+                    None => self.definitions = Definitions::Read,
+                }
+                continue;
+            }
+            if let Some(x) = self.block.pop_front() {
+                return Some(x);
+            }
+            if let Definitions::Read = self.definitions {
+                let (event, range) = self.rest.next()?;
+                match self.within.start(&event, &range) {
+                    // Already yielded by the second pass.
+                    Some(start) if !self.in_place.contains(&start) => continue,
+                    _ => return Some((event, range)),
+                }
+            }
+            self.hold_block()?;
+        }
+    }
+
+    /// Hold back the next top-level block of the first pass. If it references a
+    /// footnote that is not defined yet, start the second pass, which yields the
+    /// footnote definitions of the block too.
+    fn hold_block(&mut self) -> Option<()> {
+        let mut depth = 0;
+        let mut within = WithinDefinition::default();
+        let mut starts = vec![];
+        let mut forward = false;
+        for (event, range) in self.rest.by_ref() {
+            match &event {
+                Event::Start(tag) => {
+                    if let Tag::FootnoteDefinition(label) = tag {
+                        self.defined.insert(label.clone());
+                    }
+                    depth += 1;
+                }
+                Event::End(_) => depth -= 1,
+                Event::FootnoteReference(label) => forward |= !self.defined.contains(label),
+                _ => {}
+            }
+            if within.start(&event, &range) == Some(range.start) {
+                starts.push(range.start);
+            }
+            self.block.push_back((event, range));
+            if depth == 0 {
+                break;
+            }
+        }
+        if self.block.is_empty() {
+            return None;
+        }
+        if !forward {
+            self.in_place.extend(starts);
+            return Some(());
+        }
+        let Definitions::Unread(callback) =
+            std::mem::replace(&mut self.definitions, Definitions::Read)
+        else {
+            unreachable!("blocks are only held back before the second pass");
+        };
+        let definitions =
+            Parser::new_with_broken_link_callback(self.source, self.options, callback)
+                .into_offset_iter();
+        self.definitions = Definitions::Reading(Box::new(definitions), WithinDefinition::default());
+        let mut within = WithinDefinition::default();
+        self.block
+            .retain(|(event, range)| within.start(event, range).is_none());
+        Some(())
+    }
+}
+
+impl core::fmt::Debug for FootnotesFirst<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // The broken link callback is not `Debug`.
+        f.debug_struct("FootnotesFirst")
+            .field("rest", &self.rest)
+            .field("block", &self.block)
+            .field("in_place", &self.in_place)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> Iterator for FootnotesFirst<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_range().map(|(event, _)| event)
+    }
+}
+
+/// A [`FootnotesFirst`] that yields the source range of each event along with it.
+/// Created by [`FootnotesFirst::into_offset_iter`].
+#[derive(Debug)]
+pub struct FootnotesFirstOffsets<'a>(FootnotesFirst<'a>);
+
+impl<'a> Iterator for FootnotesFirstOffsets<'a> {
+    type Item = (Event<'a>, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_with_range()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use similar_asserts::assert_eq;
+
+    /// The footnote definition labels and reference labels in the order they are yielded,
+    /// and how often each pass called its broken link callback.
+    fn footnotes(source: &str) -> (Vec<String>, usize, usize) {
+        let mut definitions_calls = 0;
+        let mut rest_calls = 0;
+        let mut definitions = |_| {
+            definitions_calls += 1;
+            None
+        };
+        let mut rest = |_| {
+            rest_calls += 1;
+            None
+        };
+        let labels = FootnotesFirst::new_with_broken_link_callbacks(
+            source,
+            Options::ENABLE_FOOTNOTES,
+            Some(&mut definitions),
+            Some(&mut rest),
+        )
+        .filter_map(|e| match e {
+            Event::Start(Tag::FootnoteDefinition(label)) => Some(format!("{label}:")),
+            Event::FootnoteReference(label) => Some(format!("^{label}")),
+            _ => None,
+        })
+        .collect();
+        (labels, definitions_calls, rest_calls)
+    }
+
+    #[test]
+    fn forward_references() {
+        self::assert_eq!(
+            footnotes("A[^1] [x][y].\n\n[^1]: Note"),
+            (vec!["1:".to_string(), "^1".to_string()], 1, 1)
+        );
+    }
+
+    #[test]
+    fn only_forward_references_parse_twice() {
+        self::assert_eq!(footnotes("No footnotes [x][y]."), (vec![], 0, 1));
+        self::assert_eq!(
+            footnotes("[^1]: Note\n\nA[^1] [x][y]."),
+            (vec!["1:".to_string(), "^1".to_string()], 0, 1)
+        );
+    }
+
+    #[test]
+    fn definitions_are_yielded_once() {
+        let (labels, _, _) =
+            footnotes("[^1]: One\n\nA[^1] B[^2]\n\n[^2]: Two\n\n> C[^3]\n>\n> [^3]: Three");
+        self::assert_eq!(labels, vec!["1:", "2:", "3:", "^1", "^2", "^3"]);
+    }
+}
@@ -1,13 +1,16 @@
 //! Parsers to Convert mdBook into an [`Event`] iterator.
 use crate::*;
 use core::iter;
-use mdbook::{renderer::RenderContext, BookItem, Config, MDBook};
+use mdbook::{book::Chapter, renderer::RenderContext, BookItem, Config, MDBook};
+use std::path::Path;
 
 #[derive(Default, Debug)]
 enum ConfigState {
     #[default]
     Start,
     Title,
+    Description,
+    Language,
     AuthorList,
     Author(usize),
     Done,
@@ -42,6 +45,22 @@ impl<'a> Iterator for ConfigParser<'a> {
                 }
             }
             ConfigState::Title => {
+                self.state = ConfigState::Description;
+                if let Some(description) = self.config.book.description.as_ref() {
+                    Some(Event::Description(description.clone().into()))
+                } else {
+                    self.next()
+                }
+            }
+            ConfigState::Description => {
+                self.state = ConfigState::Language;
+                if let Some(language) = self.config.book.language.as_ref() {
+                    Some(Event::Language(language.clone().into()))
+                } else {
+                    self.next()
+                }
+            }
+            ConfigState::Language => {
                 if !self.config.book.authors.is_empty() {
                     self.state = ConfigState::AuthorList;
                     Some(Event::Start(Tag::AuthorList))
@@ -80,208 +99,245 @@ impl<'a> Iterator for ConfigParser<'a> {
     }
 }
 
-// This is super janky. I have a branch breaking this into iterators but am stuck with
-// lifetimes not living long enough for the embedded markdown events.
-fn events_from_items<'a, 'b>(items: &'a [BookItem]) -> Vec<self::Event<'a>>
-where
-    'a: 'b,
-{
-    let (_, events) = items
-        .iter()
-        .fold((None, vec![]), |mut acc, item| match item {
-            BookItem::Chapter(ch) => {
-                let status = if ch.is_draft_chapter() {
-                    ChapterStatus::Draft
-                } else {
-                    ChapterStatus::Active
-                };
-                let name = ch.name.clone();
-                let source = ch
-                    .source_path
-                    .as_ref()
-                    .map(|x| ChapterSource::Path(x.to_owned()));
-
-                // Chapter start event.
-                acc.1.push(self::Event::Start(self::Tag::Chapter(
-                    status,
-                    name.clone().into(),
-                    source.clone(),
-                    None,
-                )));
-
-                // Chapter content events.
-                if !ch.content.is_empty() {
-                    let p = TextMergeStream::new(pulldown_cmark::Parser::new_ext(
-                        &ch.content,
-                        pulldown_cmark::Options::ENABLE_TABLES,
-                    ));
-                    let p = p.map(Event::MarkdownContentEvent);
-                    acc.1.extend(p);
-                    acc.1.push(Event::End(Tag::Content(ContentType::Markdown)));
-                };
+/// Where the parser is in the book.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum State {
+    #[default]
+    Start,
+    Root,
+    Config,
+    ContentStart,
+    Items,
+    ContentEnd,
+    Done,
+}
 
-                if !ch.sub_items.is_empty() {
-                    let subevents = events_from_items(&ch.sub_items);
-                    acc.1.extend(subevents);
-                }
+/// The Markdown extensions chapters are parsed with.
+const MARKDOWN_OPTIONS: pulldown_cmark::Options = pulldown_cmark::Options::ENABLE_TABLES
+    .union(pulldown_cmark::Options::ENABLE_FOOTNOTES)
+    .union(pulldown_cmark::Options::ENABLE_STRIKETHROUGH)
+    .union(pulldown_cmark::Options::ENABLE_TASKLISTS)
+    .union(pulldown_cmark::Options::ENABLE_HEADING_ATTRIBUTES);
 
-                // Chapter end event.
-                acc.1
-                    .push(Event::End(Tag::Chapter(status, name.into(), source, None)));
-                acc
-            }
-            BookItem::Separator => {
-                acc.1.push(self::Event::Separator);
-                acc
-            }
-            // TODO: numbering.
-            BookItem::PartTitle(x) => {
-                let ev = if let Some(current_title) = acc.0 {
-                    // Close the current part and start a new one.
-                    vec![
-                        self::Event::End(self::Tag::Part(Some(current_title), None)),
-                        self::Event::Start(self::Tag::Part(Some(x.clone().into()), None)),
-                    ]
-                } else {
-                    // Start a new part with a title.
-                    vec![self::Event::Start(self::Tag::Part(
-                        Some(x.clone().into()),
-                        None,
-                    ))]
-                };
-                acc.1.extend(ev);
-                (Some(x.clone().into()), acc.1)
-            }
-        });
-
-    // Post-process to insert `Part` events. The mdbook data model kinda has these,
-    // kinda does not. We'll be consistent and make all chapters contained in parts.
-    let first_start_pos = events
-        .iter()
-        .position(|x| matches!(x, self::Event::Start(self::Tag::Part(_, _))));
-    let first_end_pos = events
-        .iter()
-        .position(|x| matches!(x, self::Event::End(self::Tag::Part(_, _))));
-    let last_start_pos = events
-        .iter()
-        .rposition(|x| matches!(x, self::Event::Start(self::Tag::Part(_, _))));
-    let last_end_pos = events
-        .iter()
-        .rposition(|x| matches!(x, self::Event::End(self::Tag::Part(_, _))));
-
-    match (first_start_pos, first_end_pos, last_start_pos, last_end_pos) {
-        // No parts / titles at all, wrap the whole thing in an untitled part.
-        (None, None, _, _) => iter::once(self::Event::Start(self::Tag::Part(None, None)))
-            .chain(events)
-            .chain(iter::once(self::Event::End(self::Tag::Part(None, None))))
-            .collect(),
-        // Only ends, missing starts.
-        (None, Some(p), None, Some(_)) => {
-            // Add a start for the first end.
-            let start = match &events[p] {
-                Event::End(tag) => Event::Start(tag.clone()),
-                _ => unreachable!(),
-            };
-            iter::once(start).chain(events.iter().cloned()).collect()
-        }
-        // Only starts, missing ends.
-        (Some(first_start), None, Some(last_start), None) => {
-            // Add an end for the last start.
-            let end = match &events[last_start] {
-                Event::Start(tag) => Event::End(tag.clone()),
-                _ => unreachable!(),
-            };
-            if first_start != 0 {
-                // Synthesize starting part, and end the first part where the second
-                // part starts.
-                let (inside, outside) = events.split_at(first_start);
-                return iter::once(Event::Start(Tag::Part(None, None)))
-                    .chain(inside.iter().cloned())
-                    .chain(iter::once(Event::End(Tag::Part(None, None))))
-                    .chain(outside.iter().cloned())
-                    // Put our new end on.
-                    .chain(iter::once(end))
-                    .collect();
-            } else {
-                // Just the end.
-                events.iter().cloned().chain(iter::once(end)).collect()
-            }
-        }
+/// Markdown content of the current chapter.
+#[derive(Debug)]
+enum Content<'a> {
+    /// The content has not been started.
+    Pending(&'a Chapter),
+    /// The content is being parsed.
+    Parsing(Box<TextMergeStream<'a, FootnotesFirst<'a>>>),
+}
 
-        // Contains both starts and ends, we need to make sure they are matched.
-        (Some(first_start), Some(first_end), Some(last_start), Some(last_end)) => {
-            // End before start, so we need to create a start for the first end.
-            if first_end < first_start {
-                let start = match &events[first_end] {
-                    Event::End(tag) => Event::Start(tag.clone()),
-                    _ => unreachable!(),
-                };
-                return iter::once(start).chain(events.iter().cloned()).collect();
-            }
+impl<'a> Iterator for Content<'a> {
+    type Item = pulldown_cmark::Event<'a>;
 
-            // Start after end, so we need to create an end for the last start.
-            if last_start > last_end {
-                let end = match &events[last_start] {
-                    Event::Start(tag) => Event::End(tag.clone()),
-                    _ => unreachable!(),
-                };
-                return events.iter().cloned().chain(iter::once(end)).collect();
-            }
-
-            // Everything is matched, just return what is there.
-            events
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Content::Pending(_) => None,
+            Content::Parsing(parser) => parser.next(),
         }
-        // If we find a part, it will be found forwards and backwards. Therefore, the
-        // other combinations are not possible.
-        _ => unreachable!(),
     }
 }
 
+/// A level of nesting in the book: the top-level items or a chapter and its sub-items.
+#[derive(Debug)]
+struct Frame<'a> {
+    chapter: Option<&'a Chapter>,
+    items: iter::Peekable<core::slice::Iter<'a, BookItem>>,
+}
+
+/// Create the tag of a chapter. Unnumbered chapters are suffix chapters once a numbered
+/// chapter has been seen.
+fn chapter_tag(ch: &Chapter, numbered_seen: bool) -> Tag<'_> {
+    let status = if ch.is_draft_chapter() {
+        ChapterStatus::Draft
+    } else {
+        ChapterStatus::Active
+    };
+    let source = ch
+        .source_path
+        .as_ref()
+        .map(|x| ChapterSource::Path(x.to_owned()));
+    let number = match ch.number {
+        Some(ref n) => ChapterNumber::Numbered(n.0.clone()),
+        None if numbered_seen => ChapterNumber::Suffix,
+        None => ChapterNumber::Prefix,
+    };
+    Tag::Chapter(status, ch.name.as_str().into(), source, Some(number))
+}
+
+/// The top-level number of the first numbered chapter in the part starting at `items`.
+fn first_part_number<'a>(items: impl Iterator<Item = &'a BookItem>) -> Option<ChapterNumber> {
+    items
+        .take_while(|item| !matches!(item, BookItem::PartTitle(_)))
+        .find_map(|item| match item {
+            BookItem::Chapter(ch) => ch
+                .number
+                .as_ref()?
+                .first()
+                .map(|&n| ChapterNumber::Numbered(vec![n])),
+            _ => None,
+        })
+}
+
 /// Parse an mdBook structure into events.
-// TODO: tests
-#[derive(Debug, Clone)]
-pub struct Parser<'a>(Vec<self::Event<'a>>);
+///
+/// Book items are walked lazily and each chapter's Markdown is parsed when the parser
+/// reaches it, so only the current chapter is held in memory. The footnote definitions
+/// of a chapter come before the references to them in its content.
+///
+/// All chapters are contained in parts. Chapters before the first part title are put
+/// in an untitled part.
+#[derive(Debug)]
+pub struct Parser<'a> {
+    state: State,
+    root: &'a Path,
+    /// The chapters whose helpers like `{{#include}}` were expanded.
+    expanded: Option<&'a links::ExpandedBook>,
+    config: ConfigParser<'a>,
+    frames: Vec<Frame<'a>>,
+    content: Option<Content<'a>>,
+    /// The title and number of the open part, if a part is open.
+    part: Option<(Option<CowStr<'a>>, Option<ChapterNumber>)>,
+    parts_seen: bool,
+    numbered_seen: bool,
+}
 
 impl<'a> Parser<'a> {
+    fn new(
+        root: &'a Path,
+        expanded: Option<&'a links::ExpandedBook>,
+        config: &'a Config,
+        items: &'a [BookItem],
+    ) -> Self {
+        Self {
+            state: State::default(),
+            root,
+            expanded,
+            config: ConfigParser::new(config),
+            frames: vec![Frame {
+                chapter: None,
+                items: items.iter().peekable(),
+            }],
+            content: None,
+            part: None,
+            parts_seen: false,
+            numbered_seen: false,
+        }
+    }
+
     /// Create a parser from an `MDBook`. This is available when using `mdbook` as a
     /// library.
+    ///
+    /// A loaded book has not been preprocessed, so helpers like `{{#include}}` are
+    /// parsed as text. Use [`from_expanded`](Self::from_expanded) to expand them.
     pub fn from_mdbook(book: &'a MDBook) -> Self {
-        let config = ConfigParser::new(&book.config);
-        let events = iter::empty()
-            // Configuration
-            .chain(iter::once(self::Event::Start(self::Tag::BookConfiguration)))
-            .chain(iter::once(self::Event::Root(book.root.to_owned().clone())))
-            .chain(config)
-            .chain(iter::once(self::Event::End(self::Tag::BookConfiguration)))
-            // Content
-            .chain(iter::once(self::Event::Start(self::Tag::BookContent)))
-            .chain(events_from_items(&book.book.sections))
-            .chain(iter::once(self::Event::End(self::Tag::BookContent)))
-            .collect();
-        Self(events)
+        Self::new(&book.root, None, &book.config, &book.book.sections)
+    }
+
+    /// Create a parser from an `MDBook` whose `{{#include}}`, `{{#rustdoc_include}}`,
+    /// and `{{#title}}` helpers were expanded the way mdBook's `links` preprocessor
+    /// does. See [`links`].
+    pub fn from_expanded(book: &'a MDBook, expanded: &'a links::ExpandedBook) -> Self {
+        Self::new(
+            &book.root,
+            Some(expanded),
+            &book.config,
+            &book.book.sections,
+        )
     }
 
     /// Create a parser from a `RenderContext`. This is available when using `mdbook` as
-    /// a binary.
+    /// a binary. mdBook has already expanded helpers like `{{#include}}`.
     pub fn from_rendercontext(ctx: &'a RenderContext) -> Self {
-        let config = ConfigParser::new(&ctx.config);
-        let events = iter::empty()
-            // Configuration
-            .chain(iter::once(self::Event::Start(self::Tag::BookConfiguration)))
-            .chain(iter::once(self::Event::Root(ctx.root.clone())))
-            .chain(config)
-            .chain(iter::once(self::Event::End(self::Tag::BookConfiguration)))
-            // Content
-            .chain(iter::once(self::Event::Start(self::Tag::BookContent)))
-            .chain(events_from_items(&ctx.book.sections))
-            .chain(iter::once(self::Event::End(self::Tag::BookContent)))
-            .collect();
+        Self::new(&ctx.root, None, &ctx.config, &ctx.book.sections)
+    }
+
+    /// Start parsing the content of a chapter, with its helpers expanded if they were.
+    fn parse(&self, ch: &'a Chapter) -> Content<'a> {
+        let content = self
+            .expanded
+            .zip(ch.source_path.as_deref())
+            .and_then(|(expanded, path)| expanded.content(path))
+            .unwrap_or(&ch.content);
+        Content::Parsing(Box::new(TextMergeStream::new(FootnotesFirst::new(
+            content,
+            MARKDOWN_OPTIONS,
+        ))))
+    }
 
-        Self(events)
+    fn open_part(&mut self, title: Option<CowStr<'a>>) -> Event<'a> {
+        let number = first_part_number(self.frames[0].items.clone());
+        self.part = Some((title.clone(), number.clone()));
+        self.parts_seen = true;
+        Event::Start(Tag::Part(title, number))
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &self::Event<'a>> {
-        self.0.iter()
+    fn next_item(&mut self) -> Option<Event<'a>> {
+        match self.content.take() {
+            Some(Content::Pending(ch)) => {
+                self.content = Some(self.parse(ch));
+                return Some(Event::Start(Tag::Content(ContentType::Markdown)));
+            }
+            Some(mut content) => {
+                if let Some(event) = content.next() {
+                    self.content = Some(content);
+                    return Some(Event::MarkdownContentEvent(event));
+                }
+                return Some(Event::End(Tag::Content(ContentType::Markdown)));
+            }
+            None => {}
+        }
+
+        let top_level = self.frames.len() == 1;
+        let frame = self.frames.last_mut()?;
+        match frame.items.peek() {
+            Some(BookItem::PartTitle(title)) if top_level => {
+                // Close the open part before starting the next one.
+                if let Some((title, number)) = self.part.take() {
+                    return Some(Event::End(Tag::Part(title, number)));
+                }
+                frame.items.next();
+                Some(self.open_part(Some(title.as_str().into())))
+            }
+            // mdBook only allows part titles at the top level.
+            Some(BookItem::PartTitle(_)) => {
+                frame.items.next();
+                self.next_item()
+            }
+            Some(_) if top_level && self.part.is_none() => Some(self.open_part(None)),
+            Some(BookItem::Separator) => {
+                frame.items.next();
+                Some(Event::Separator)
+            }
+            Some(BookItem::Chapter(_)) => {
+                let Some(BookItem::Chapter(ch)) = frame.items.next() else {
+                    unreachable!("peeked a chapter");
+                };
+                if !ch.content.is_empty() {
+                    self.content = Some(Content::Pending(ch));
+                }
+                let tag = chapter_tag(ch, self.numbered_seen);
+                self.numbered_seen |= ch.number.is_some();
+                self.frames.push(Frame {
+                    chapter: Some(ch),
+                    items: ch.sub_items.iter().peekable(),
+                });
+                Some(Event::Start(tag))
+            }
+            None => match frame.chapter {
+                Some(ch) => {
+                    self.frames.pop();
+                    Some(Event::End(chapter_tag(ch, self.numbered_seen)))
+                }
+                // Books without items still get a part.
+                None if !self.parts_seen => Some(self.open_part(None)),
+                None => self
+                    .part
+                    .take()
+                    .map(|(title, number)| Event::End(Tag::Part(title, number))),
+            },
+        }
     }
 }
 
@@ -289,6 +345,285 @@ impl<'a> Iterator for Parser<'a> {
     type Item = self::Event<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.first().cloned()
+        match self.state {
+            State::Start => {
+                self.state = State::Root;
+                Some(Event::Start(Tag::BookConfiguration))
+            }
+            State::Root => {
+                self.state = State::Config;
+                Some(Event::Root(self.root.to_path_buf()))
+            }
+            State::Config => self.config.next().or_else(|| {
+                self.state = State::ContentStart;
+                Some(Event::End(Tag::BookConfiguration))
+            }),
+            State::ContentStart => {
+                self.state = State::Items;
+                Some(Event::Start(Tag::BookContent))
+            }
+            State::Items => self.next_item().or_else(|| {
+                self.state = State::ContentEnd;
+                self.next()
+            }),
+            State::ContentEnd => {
+                self.state = State::Done;
+                Some(Event::End(Tag::BookContent))
+            }
+            State::Done => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mdbook::book::Book;
+    use pulldown_cmark::Event as MdEvent;
+    use pulldown_cmark::Tag as MdTag;
+    use similar_asserts::assert_eq;
+    use std::path::PathBuf;
+
+    fn chapter(name: &str, content: &str, sub_items: Vec<BookItem>) -> BookItem {
+        let mut ch = Chapter::new(name, content.to_string(), format!("{name}.md"), vec![]);
+        ch.sub_items = sub_items;
+        BookItem::Chapter(ch)
+    }
+
+    fn context(sections: Vec<BookItem>) -> RenderContext {
+        let mut book = Book::new();
+        book.sections = sections;
+        let mut config = Config::default();
+        config.book.title = Some("Title".to_string());
+        config.book.description = Some("About".to_string());
+        config.book.authors = vec!["Ann".to_string(), "Bob".to_string()];
+        RenderContext::new("/book", book, config, "/book/out")
+    }
+
+    fn numbered(name: &str, number: ChapterNumber) -> Tag<'_> {
+        Tag::Chapter(
+            ChapterStatus::Active,
+            name.into(),
+            Some(ChapterSource::Path(PathBuf::from(format!("{name}.md")))),
+            Some(number),
+        )
+    }
+
+    fn active(name: &str) -> Tag<'_> {
+        numbered(name, ChapterNumber::Prefix)
+    }
+
+    #[test]
+    fn configuration_and_chapters() {
+        let ctx = context(vec![
+            chapter("one", "Hi", vec![chapter("two", "", vec![])]),
+            BookItem::Separator,
+        ]);
+        self::assert_eq!(
+            Parser::from_rendercontext(&ctx).collect::<Vec<_>>(),
+            vec![
+                Event::Start(Tag::BookConfiguration),
+                Event::Root(PathBuf::from("/book")),
+                Event::Title("Title".into()),
+                Event::Description("About".into()),
+                Event::Language("en".into()),
+                Event::Start(Tag::AuthorList),
+                Event::Author("Ann".into()),
+                Event::Author("Bob".into()),
+                Event::End(Tag::AuthorList),
+                Event::End(Tag::BookConfiguration),
+                Event::Start(Tag::BookContent),
+                Event::Start(Tag::Part(None, None)),
+                Event::Start(active("one")),
+                Event::Start(Tag::Content(ContentType::Markdown)),
+                Event::MarkdownContentEvent(MdEvent::Start(MdTag::Paragraph)),
+                Event::MarkdownContentEvent(MdEvent::Text("Hi".into())),
+                Event::MarkdownContentEvent(MdEvent::End(MdTag::Paragraph)),
+                Event::End(Tag::Content(ContentType::Markdown)),
+                Event::Start(active("two")),
+                Event::End(active("two")),
+                Event::End(active("one")),
+                Event::Separator,
+                Event::End(Tag::Part(None, None)),
+                Event::End(Tag::BookContent),
+            ]
+        );
+    }
+
+    #[test]
+    fn parts() {
+        let ctx = context(vec![
+            chapter("intro", "", vec![]),
+            BookItem::PartTitle("First".to_string()),
+            chapter("one", "", vec![]),
+            BookItem::PartTitle("Second".to_string()),
+        ]);
+        let events = Parser::from_rendercontext(&ctx)
+            .skip_while(|e| *e != Event::Start(Tag::BookContent))
+            .collect::<Vec<_>>();
+        self::assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::BookContent),
+                Event::Start(Tag::Part(None, None)),
+                Event::Start(active("intro")),
+                Event::End(active("intro")),
+                Event::End(Tag::Part(None, None)),
+                Event::Start(Tag::Part(Some("First".into()), None)),
+                Event::Start(active("one")),
+                Event::End(active("one")),
+                Event::End(Tag::Part(Some("First".into()), None)),
+                Event::Start(Tag::Part(Some("Second".into()), None)),
+                Event::End(Tag::Part(Some("Second".into()), None)),
+                Event::End(Tag::BookContent),
+            ]
+        );
+    }
+
+    #[test]
+    fn numbering() {
+        let number = |name: &str, n: Vec<u32>, sub_items| {
+            let BookItem::Chapter(mut ch) = chapter(name, "", sub_items) else {
+                unreachable!()
+            };
+            ch.number = Some(mdbook::book::SectionNumber(n));
+            BookItem::Chapter(ch)
+        };
+        let ctx = context(vec![
+            chapter("intro", "", vec![]),
+            BookItem::PartTitle("Guide".to_string()),
+            number("one", vec![1], vec![number("two", vec![1, 1], vec![])]),
+            chapter("appendix", "", vec![]),
+        ]);
+        let events = Parser::from_rendercontext(&ctx)
+            .filter(|e| matches!(e, Event::Start(_)))
+            .skip_while(|e| *e != Event::Start(Tag::BookContent))
+            .collect::<Vec<_>>();
+        self::assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::BookContent),
+                Event::Start(Tag::Part(None, None)),
+                Event::Start(numbered("intro", ChapterNumber::Prefix)),
+                Event::Start(Tag::Part(
+                    Some("Guide".into()),
+                    Some(ChapterNumber::Numbered(vec![1]))
+                )),
+                Event::Start(numbered("one", ChapterNumber::Numbered(vec![1]))),
+                Event::Start(numbered("two", ChapterNumber::Numbered(vec![1, 1]))),
+                Event::Start(numbered("appendix", ChapterNumber::Suffix)),
+            ]
+        );
+        self::assert_eq!(ChapterNumber::Numbered(vec![1, 2, 3]).to_string(), "1.2.3.");
+        self::assert_eq!(ChapterNumber::Suffix.to_string(), "");
+    }
+
+    #[test]
+    fn empty_book() {
+        let ctx = context(vec![]);
+        let events = Parser::from_rendercontext(&ctx)
+            .skip_while(|e| *e != Event::Start(Tag::BookContent))
+            .collect::<Vec<_>>();
+        self::assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::BookContent),
+                Event::Start(Tag::Part(None, None)),
+                Event::End(Tag::Part(None, None)),
+                Event::End(Tag::BookContent),
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_extensions() {
+        let ctx = context(vec![chapter(
+            "one",
+            "~~Hi~~[^1]\n\n- [x] Done\n\n[^1]: Note",
+            vec![],
+        )]);
+        let events = Parser::from_rendercontext(&ctx)
+            .filter_map(|e| match e {
+                Event::MarkdownContentEvent(e) => Some(e),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        self::assert_eq!(
+            events,
+            vec![
+                // Footnote definitions come first.
+                MdEvent::Start(MdTag::FootnoteDefinition("1".into())),
+                MdEvent::Start(MdTag::Paragraph),
+                MdEvent::Text("Note".into()),
+                MdEvent::End(MdTag::Paragraph),
+                MdEvent::End(MdTag::FootnoteDefinition("1".into())),
+                MdEvent::Start(MdTag::Paragraph),
+                MdEvent::Start(MdTag::Strikethrough),
+                MdEvent::Text("Hi".into()),
+                MdEvent::End(MdTag::Strikethrough),
+                MdEvent::FootnoteReference("1".into()),
+                MdEvent::End(MdTag::Paragraph),
+                MdEvent::Start(MdTag::List(None)),
+                MdEvent::Start(MdTag::Item),
+                MdEvent::TaskListMarker(true),
+                MdEvent::Text("Done".into()),
+                MdEvent::End(MdTag::Item),
+                MdEvent::End(MdTag::List(None)),
+            ]
+        );
+    }
+
+    #[test]
+    fn advances_and_ends() {
+        let sections = (0..500)
+            .map(|i| chapter(&format!("ch{i}"), "# Heading\n\nText", vec![]))
+            .collect();
+        let ctx = context(sections);
+        let mut parser = Parser::from_rendercontext(&ctx);
+        let chapters = parser
+            .by_ref()
+            .filter(|e| matches!(e, Event::Start(Tag::Chapter(..))))
+            .count();
+        self::assert_eq!(chapters, 500);
+        self::assert_eq!(parser.next(), None);
+    }
+
+    #[test]
+    fn expands_helpers_relative_to_chapter() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::write(src.join("SUMMARY.md"), "- [Chapter](sub/chapter.md)\n").unwrap();
+        std::fs::write(
+            src.join("sub/chapter.md"),
+            "# Heading {#id}\n\n{{#include code.txt}}\n\n{{#include heading.md}}\n",
+        )
+        .unwrap();
+        std::fs::write(src.join("sub/code.txt"), "Included").unwrap();
+        std::fs::write(src.join("sub/heading.md"), "## Included {#inc .big}").unwrap();
+        let book = MDBook::load(dir.path()).unwrap();
+        let expanded = links::ExpandedBook::new(&book);
+        let events = Parser::from_expanded(&book, &expanded)
+            .filter_map(|e| match e {
+                Event::MarkdownContentEvent(e) => Some(e),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let heading = MdTag::Heading(pulldown_cmark::HeadingLevel::H1, Some("id"), vec![]);
+        let included = MdTag::Heading(pulldown_cmark::HeadingLevel::H2, Some("inc"), vec!["big"]);
+        self::assert_eq!(
+            events,
+            vec![
+                MdEvent::Start(heading.clone()),
+                MdEvent::Text("Heading".into()),
+                MdEvent::End(heading),
+                MdEvent::Start(MdTag::Paragraph),
+                MdEvent::Text("Included".into()),
+                MdEvent::End(MdTag::Paragraph),
+                MdEvent::Start(included.clone()),
+                MdEvent::Text("Included".into()),
+                MdEvent::End(included),
+            ]
+        );
     }
 }
@@ -1,5 +1,8 @@
 use std::num::NonZeroU8;
 pub mod markup;
+pub mod parser;
+
+pub use parser::Parser;
 // TODO: remove this.
 use pulldown_cmark::CowStr;
 
@@ -15,6 +18,35 @@ pub enum Event<'a> {
     Text(CowStr<'a>),
     /// An inline code node.
     Code(CowStr<'a>),
+    /// Raw text with an optional language, inline or as a block.
+    ///
+    /// Unlike a [`Tag::CodeBlock`], the text is known up front.
+    ///
+    /// See <https://typst.app/docs/reference/text/raw/>.
+    RawCode(Raw<'a>),
+    /// An equation in Typst math syntax, without the surrounding `$`. The second field
+    /// is how it should be displayed.
+    ///
+    /// See <https://typst.app/docs/reference/math/>.
+    Math(CowStr<'a>, MathDisplay),
+    /// A label for the preceding element, without angle brackets. Labels can be
+    /// referenced by [`LinkType::Label`] links.
+    ///
+    /// See <https://typst.app/docs/reference/foundations/label/>.
+    Label(CowStr<'a>),
+    /// Another reference to the footnote with the given label, shown with the same
+    /// number.
+    ///
+    /// See <https://typst.app/docs/reference/model/footnote/>.
+    FootnoteReference(CowStr<'a>),
+    /// An image placed in running text. Images on their own are shown in a
+    /// [`Tag::Figure`].
+    ///
+    /// See <https://typst.app/docs/reference/visualize/image/>.
+    Image(Image<'a>),
+    /// A task list marker, rendered as a checkbox glyph at the start of a list item.
+    /// The field indicates if the task is checked.
+    TaskListMarker(bool),
     /// A soft line break.
     Linebreak,
     /// A hard line break.
@@ -56,6 +88,13 @@ pub enum Event<'a> {
     /// See <https://typst.app/docs/reference/meta/document>.
     // TODO: make this strongly typed.
     DocumentFunctionCall(Vec<CowStr<'a>>),
+    /// An outline of the document's headings, i.e. a table of contents. The first field
+    /// is the title, which defaults to a localized "Contents" if `None`. The second is
+    /// the deepest heading level to include, all levels if `None`. Headings marked with
+    /// [`TableOfContents::Exclude`] are left out.
+    ///
+    /// See <https://typst.app/docs/reference/model/outline/>.
+    Outline(Option<CowStr<'a>>, Option<NonZeroU8>),
     /// A set rule.
     ///
     /// If setting document metadata, prefer [`DocumentSet`].
@@ -71,6 +110,22 @@ pub enum Event<'a> {
     ///
     /// See <https://typst.app/docs/reference/meta/document>.
     DocumentSet(CowStr<'a>, CowStr<'a>),
+    /// A `page` set rule.
+    ///
+    /// See <https://typst.app/docs/reference/layout/page/>.
+    SetPage(PageSettings<'a>),
+    /// A `text` set rule.
+    ///
+    /// See <https://typst.app/docs/reference/text/text/>.
+    SetText(TextSettings<'a>),
+    /// A `par` set rule.
+    ///
+    /// See <https://typst.app/docs/reference/model/par/>.
+    SetPar(ParSettings<'a>),
+    /// A `heading` set rule.
+    ///
+    /// See <https://typst.app/docs/reference/model/heading/>.
+    SetHeading(HeadingSettings<'a>),
 
     /// Raw string data what will be bassed through directly to typst. Prefer using
     /// other strongly-typed rules.
@@ -98,9 +153,11 @@ pub enum Tag<'a> {
     /// bookmarks.
     Heading(NonZeroU8, TableOfContents, Bookmarks),
 
-    /// A code block. The first argument is the
-    /// fenced value if it exists, the second is how it should be displayed.
-    CodeBlock(Option<CowStr<'a>>, CodeBlockDisplay),
+    /// A code block. The first argument is the language if it exists, the second
+    /// holds attributes from the info string like `ignore` or `should_panic`, and the
+    /// third is how it should be displayed. Attributes are not written to markup but
+    /// let conversions and styling react to them.
+    CodeBlock(Option<CowStr<'a>>, Vec<CowStr<'a>>, CodeBlockDisplay),
 
     /// A bullted list. The first field indicates the marker to use, the second is if
     /// tight is desired. Contains only list items.
@@ -124,18 +181,47 @@ pub enum Tag<'a> {
     Emphasis,
     Strong,
     Strikethrough,
+    /// A footnote. Contains the footnote content, which is shown at the bottom of the
+    /// page and marked where the tag appears.
+    ///
+    /// See <https://typst.app/docs/reference/model/footnote/>.
+    Footnote,
 
     /// A link. The first field is the type and the second is the destination URL.
     Link(LinkType, CowStr<'a>),
 
-    /// A table. The first field is the alignment of each column.
-    Table(Vec<TableCellAlignment>),
-    /// A table header row. Must come after a #[Tag::Table].
+    /// Content aligned in its container. The field is the alignment, like `center` or
+    /// `center + horizon`.
+    ///
+    /// See <https://typst.app/docs/reference/layout/align/>.
+    Align(CowStr<'a>),
+
+    /// Content that is laid out but not shown. Hidden headings still appear in the
+    /// outline.
+    ///
+    /// See <https://typst.app/docs/reference/layout/hide/>.
+    Hide,
+
+    /// A figure showing an image. The first field is the image, the second an optional
+    /// label to reference the figure by. Contains the caption, if any.
+    ///
+    /// See <https://typst.app/docs/reference/model/figure/>.
+    Figure(Image<'a>, Option<CowStr<'a>>),
+
+    /// A table. The first field is the alignment of each column, the second the other
+    /// arguments of the table. Contains only header rows and rows.
+    ///
+    /// See <https://typst.app/docs/reference/model/table/>.
+    Table(Vec<TableCellAlignment>, TableSettings<'a>),
+    /// A table header row. Must come after a #[Tag::Table]. Header rows are written
+    /// together as the table's `table.header`.
     TableHead,
     /// A table row. Must come after a #[Tag::Table].
     TableRow,
-    /// A table row. Must come after a #[Tag::TableRow].
-    TableCell,
+    /// A table cell. Must come after a #[Tag::TableRow] or #[Tag::TableHead]. The field
+    /// is how many columns and rows the cell spans. Cells can contain block content
+    /// like lists and code blocks.
+    TableCell(TableCellSpan),
 }
 
 /// How to display a code block.
@@ -145,6 +231,15 @@ pub enum CodeBlockDisplay {
     Inline,
 }
 
+/// How to display an equation.
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub enum MathDisplay {
+    /// Inline with the surrounding text, written as `$x$`.
+    Inline,
+    /// As a separate block, written as `$ x $`.
+    Block,
+}
+
 /// Item appearance in bookmarks.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Bookmarks {
@@ -165,6 +260,76 @@ pub enum TableOfContents {
 #[derive(Clone, Debug, PartialEq)]
 pub struct NumberingPattern<'a>(&'a str);
 
+/// Raw text, written with backticks or as a `raw` function call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raw<'a> {
+    /// The language to highlight the text as, like `rust`.
+    pub lang: Option<CowStr<'a>>,
+    /// Whether to display the text as a separate block.
+    pub block: bool,
+    /// The text, written as is.
+    pub text: CowStr<'a>,
+}
+
+/// Arguments of an `image` function call. Unset fields are left out of the call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image<'a> {
+    /// The path of the image file.
+    pub path: CowStr<'a>,
+    /// The width, as a Typst relative length like `50%` or `3cm`.
+    pub width: Option<CowStr<'a>>,
+    /// The height, as a Typst relative length.
+    pub height: Option<CowStr<'a>>,
+    /// A short description of the image for readers who cannot see it.
+    pub alt: Option<CowStr<'a>>,
+}
+
+/// Arguments of a `page` set rule. Unset fields are left out of the rule.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct PageSettings<'a> {
+    /// The paper size, like `a4` or `us-letter`.
+    pub paper: Option<CowStr<'a>>,
+    /// The page width, as a Typst length.
+    pub width: Option<CowStr<'a>>,
+    /// The page height, as a Typst length.
+    pub height: Option<CowStr<'a>>,
+    /// The page margins, as a Typst length or dictionary like `(x: 2cm, y: 3cm)`.
+    pub margin: Option<CowStr<'a>>,
+    /// The [numbering pattern](https://typst.app/docs/reference/model/numbering/) of
+    /// page numbers, like `1` or `i`.
+    pub numbering: Option<CowStr<'a>>,
+}
+
+/// Arguments of a `text` set rule. Unset fields are left out of the rule.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct TextSettings<'a> {
+    /// The font family.
+    pub font: Option<CowStr<'a>>,
+    /// The font size, as a Typst length.
+    pub size: Option<CowStr<'a>>,
+    /// The language of the text, as an ISO 639-1/2/3 code like `en`.
+    pub lang: Option<CowStr<'a>>,
+}
+
+/// Arguments of a `par` set rule. Unset fields are left out of the rule.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct ParSettings<'a> {
+    /// Whether to justify text in its line.
+    pub justify: Option<bool>,
+    /// The spacing between lines, as a Typst length.
+    pub leading: Option<CowStr<'a>>,
+    /// The indent of the first line of a paragraph, as a Typst length.
+    pub first_line_indent: Option<CowStr<'a>>,
+}
+
+/// Arguments of a `heading` set rule. Unset fields are left out of the rule.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct HeadingSettings<'a> {
+    /// The [numbering pattern](https://typst.app/docs/reference/model/numbering/) of
+    /// headings, like `1.1`.
+    pub numbering: Option<CowStr<'a>>,
+}
+
 /// Type specifier for Show rules. See [Tag::Show](enum.Tag.html#variant.Show) for
 /// more information.
 // TODO: support different dests.
@@ -184,6 +349,9 @@ pub enum LinkType {
     Content,
     /// Autolink like `http://foo.bar/baz`.
     Autolink,
+    /// Link to a label in the document like `#link(<intro>)[my cool content]`. The
+    /// destination is the label name without angle brackets.
+    Label,
 }
 
 /// Type specifier for a quote.
@@ -210,3 +378,46 @@ pub enum TableCellAlignment {
     Right,
     None,
 }
+
+/// Arguments of a table besides its column alignments. Unset fields are left out of
+/// the table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableSettings<'a> {
+    /// The width of each column, as a Typst track size like `auto`, `1fr`, or `30%`.
+    /// If empty, the columns are sized automatically.
+    pub widths: Vec<CowStr<'a>>,
+    /// The stroke of the cells, like `0.5pt + gray` or `none`.
+    pub stroke: Option<CowStr<'a>>,
+    /// The fill of the cells, as a color or a function like
+    /// `(_, y) => if y == 0 { luma(230) }`.
+    pub fill: Option<CowStr<'a>>,
+    /// Whether the header rows are repeated on each page the table breaks across.
+    pub repeat_header: bool,
+}
+
+impl Default for TableSettings<'_> {
+    fn default() -> Self {
+        Self {
+            widths: vec![],
+            stroke: None,
+            fill: None,
+            repeat_header: true,
+        }
+    }
+}
+
+/// How many columns and rows a table cell spans.
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub struct TableCellSpan {
+    pub colspan: u32,
+    pub rowspan: u32,
+}
+
+impl Default for TableCellSpan {
+    fn default() -> Self {
+        Self {
+            colspan: 1,
+            rowspan: 1,
+        }
+    }
+}
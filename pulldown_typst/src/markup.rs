@@ -1,89 +1,422 @@
-use crate::{Event, LinkType, QuoteQuotes, QuoteType, ShowType, TableCellAlignment, Tag};
-use std::{collections::VecDeque, fmt::Write, io::ErrorKind};
-
-fn typst_escape(s: &str) -> String {
-    s.replace('$', "\\$")
-        .replace('#', "\\#")
-        .replace('<', "\\<")
-        .replace('>', "\\>")
-        .replace('*', "\\*")
-        .replace('_', " \\_")
-        .replace('`', "\\`")
-        .replace('@', "\\@")
+use crate::{
+    Bookmarks, CodeBlockDisplay, Event, Image, LinkType, MathDisplay, QuoteQuotes, QuoteType, Raw,
+    ShowType, TableCellAlignment, TableCellSpan, TableOfContents, TableSettings, Tag,
+};
+use pulldown_cmark::CowStr;
+use std::{collections::VecDeque, fmt::Write};
+
+/// Escape text so it is written as is in markup.
+///
+/// Characters that always have a meaning, like `*` or `#`, are escaped everywhere.
+/// Brackets are escaped too, so text cannot end the content block or table cell it is
+/// in. Others only where they would start something: `//` a comment, `--` or `...` a
+/// shorthand, `@name` a reference, and `=`, `-`, `+`, `/`, and `1.` a heading or list
+/// at `line_start`, i.e. at the start of a line or content block. Text in string
+/// arguments is written with [`string_literal`] instead.
+fn typst_escape(s: &str, mut line_start: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let rest = &s[i + c.len_utf8()..];
+        let next = rest.chars().next();
+        let marker_end = next.is_none_or(char::is_whitespace);
+        let escape = match c {
+            '\\' | '#' | '$' | '*' | '_' | '`' | '<' | '[' | ']' | '~' => true,
+            '@' => next.is_some_and(|n| n.is_alphanumeric() || n == '_'),
+            // `/*` is safe as `*` is escaped.
+            '/' => next == Some('/') || (line_start && marker_end),
+            '-' => matches!(next, Some('-' | '?')) || (line_start && marker_end),
+            '+' => line_start && marker_end,
+            '=' => line_start && (marker_end || next == Some('=')),
+            '.' => rest.starts_with(".."),
+            c if c.is_ascii_digit() && line_start => {
+                // A numbered list marker like `1.`.
+                let digits =
+                    rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let after = &rest[digits..];
+                if after.starts_with('.')
+                    && after[1..].chars().next().is_none_or(char::is_whitespace)
+                {
+                    out.push(c);
+                    out.push_str(&rest[..digits]);
+                    out.push_str("\\.");
+                    for _ in 0..digits + 1 {
+                        chars.next();
+                    }
+                    line_start = false;
+                    continue;
+                }
+                false
+            }
+            _ => false,
+        };
+        if escape {
+            out.push('\\');
+        }
+        out.push(c);
+        line_start = match c {
+            '\n' => true,
+            ' ' | '\t' => line_start,
+            _ => false,
+        };
+    }
+    out
 }
 
-/// Process link URL to better handle markdown file links with anchors.
-/// 
-/// For links like `./file.md#anchor`, this function:
-/// - Removes leading `./` if present
-/// - Converts `.md` extension to `.typ` for Typst compatibility
-/// - Preserves anchor fragments (#anchor)
-#[cfg(test)]
-pub(crate) fn process_link_url(url: &str) -> String {
-    process_link_url_impl(url)
+/// Whether escaped text `s` would continue an embedded expression written right before
+/// it, as arguments like `(a)` or a field access like `.a`. Brackets are always escaped.
+fn continues_expression(s: &str) -> bool {
+    s.starts_with('(')
+        || s.strip_prefix('.')
+            .and_then(|rest| rest.chars().next())
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+}
+
+/// Whether markup ending with `s` leaves the next text at the start of a line or content
+/// block. `None` if `s` is only spaces.
+fn ends_at_line_start(s: &str) -> Option<bool> {
+    // The content of a list item starts right after its marker.
+    if matches!(s, "- " | "+ ") {
+        return Some(true);
+    }
+    let s = s.trim_end_matches([' ', '\t']);
+    s.chars().last().map(|c| matches!(c, '\n' | '['))
+}
+
+/// Write `s` as a Typst string literal.
+fn string_literal(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The length of the longest run of backticks in `s`.
+fn longest_backtick_run(s: &str) -> usize {
+    s.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/// Whether `text` reads back unchanged from a backtick raw block. Typst drops a blank
+/// last line and removes indentation common to all lines if the text does not end
+/// with a newline, and the text must not run into the closing fence.
+fn is_safe_raw_block(text: &str) -> bool {
+    if text.is_empty() || text.ends_with('\n') {
+        return true;
+    }
+    let last = text.rsplit('\n').next().unwrap_or(text);
+    let dedent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    !last.trim().is_empty() && !last.ends_with('`') && dedent == 0
+}
+
+/// Write raw text. Backticks are used where the text reads back unchanged, with a fence
+/// longer than any backtick run in a block, and a `#raw` call otherwise.
+fn raw_markup(raw: &Raw<'_>) -> String {
+    let lang = raw.lang.as_deref().filter(|lang| !lang.is_empty());
+    let text = raw.text.as_ref();
+    let is_tag = |lang: &str| {
+        lang.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && lang
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-'))
+    };
+    if raw.block && lang.is_none_or(is_tag) && is_safe_raw_block(text) {
+        let fence = "`".repeat(longest_backtick_run(text).max(2) + 1);
+        return format!("{fence}{}\n{text}{fence}\n", lang.unwrap_or_default());
+    }
+    // An empty inline raw could run into a following one.
+    if !raw.block && lang.is_none() && !text.is_empty() && !text.contains(['`', '\n']) {
+        return format!("`{text}`");
+    }
+    let mut args = vec![string_literal(text)];
+    if raw.block {
+        args.push("block: true".to_string());
+    }
+    if let Some(lang) = lang {
+        args.push(format!("lang: {}", string_literal(lang)));
+    }
+    let call = format!("#raw({})", args.join(", "));
+    if raw.block {
+        call + "\n"
+    } else {
+        call
+    }
 }
 
-#[cfg(not(test))]
-fn process_link_url(url: &str) -> String {
-    process_link_url_impl(url)
+/// Whether a heading has to be written as a `#heading` function call because it is left
+/// out of the outline or bookmarks, which heading markup cannot express.
+fn is_heading_function(toc: &TableOfContents, bookmarks: &Bookmarks) -> bool {
+    *toc == TableOfContents::Exclude || *bookmarks == Bookmarks::Exclude
 }
 
-fn process_link_url_impl(url: &str) -> String {
-    let mut processed = url.to_string();
-    
-    // Remove leading "./" if present
-    if processed.starts_with("./") {
-        processed = processed[2..].to_string();
-    }
-    
-    // Handle markdown file links with anchors
-    if let Some(anchor_pos) = processed.find('#') {
-        let (file_part, anchor_part) = processed.split_at(anchor_pos);
-        
-        // Convert .md to .typ if it's a markdown file link
-        let file_part = if file_part.ends_with(".md") {
-            file_part.strip_suffix(".md").unwrap_or(file_part).to_string() + ".typ"
+/// Write an `image` function call, without the leading `#`.
+fn image_call(image: &Image<'_>) -> String {
+    let mut args = vec![string_literal(&image.path)];
+    if let Some(width) = &image.width {
+        args.push(format!("width: {width}"));
+    }
+    if let Some(height) = &image.height {
+        args.push(format!("height: {height}"));
+    }
+    if let Some(alt) = &image.alt {
+        args.push(format!("alt: {}", string_literal(alt)));
+    }
+    format!("image({})", args.join(", "))
+}
+
+/// A cell of a table being written.
+struct CellMarkup {
+    span: TableCellSpan,
+    content: String,
+}
+
+impl CellMarkup {
+    fn markup(&self) -> String {
+        let content = self.content.trim();
+        let mut args = vec![];
+        if self.span.colspan > 1 {
+            args.push(format!("colspan: {}", self.span.colspan));
+        }
+        if self.span.rowspan > 1 {
+            args.push(format!("rowspan: {}", self.span.rowspan));
+        }
+        if args.is_empty() {
+            format!("[{content}]")
         } else {
-            file_part.to_string()
+            format!("table.cell({})[{content}]", args.join(", "))
+        }
+    }
+}
+
+/// A table being written. Rows are collected until the table ends, as header rows are
+/// written together and the number of columns can depend on the cell spans.
+struct TableMarkup<'a> {
+    alignments: Vec<TableCellAlignment>,
+    settings: TableSettings<'a>,
+    header: Vec<Vec<CellMarkup>>,
+    rows: Vec<Vec<CellMarkup>>,
+    /// The cells of the open row, if any.
+    row: Option<Vec<CellMarkup>>,
+    /// The open cell, if any.
+    cell: Option<CellMarkup>,
+}
+
+impl<'a> TableMarkup<'a> {
+    fn new(alignments: Vec<TableCellAlignment>, settings: TableSettings<'a>) -> Self {
+        Self {
+            alignments,
+            settings,
+            header: vec![],
+            rows: vec![],
+            row: None,
+            cell: None,
+        }
+    }
+
+    /// The number of columns the cells take up. A cell is placed in the first column
+    /// not taken by a cell spanning rows above it, like Typst does.
+    fn columns(&self) -> usize {
+        // How many more rows each column is taken for.
+        let mut taken: Vec<u32> = vec![];
+        for row in self.header.iter().chain(&self.rows) {
+            let mut column = 0;
+            for cell in row {
+                while taken.get(column).is_some_and(|&rows| rows > 0) {
+                    column += 1;
+                }
+                let end = column + cell.span.colspan.max(1) as usize;
+                if taken.len() < end {
+                    taken.resize(end, 0);
+                }
+                taken[column..end].fill(cell.span.rowspan.max(1));
+                column = end;
+            }
+            for rows in &mut taken {
+                *rows = rows.saturating_sub(1);
+            }
+        }
+        taken.len()
+    }
+
+    fn markup(&self) -> String {
+        let mut args = vec![if self.settings.widths.is_empty() {
+            format!("columns: {}", self.alignments.len().max(self.columns()))
+        } else {
+            format!("columns: ({})", self.settings.widths.join(", "))
+        }];
+        let aligned = |a: &TableCellAlignment| *a != TableCellAlignment::None;
+        if self.alignments.iter().any(aligned) {
+            let alignments = self
+                .alignments
+                .iter()
+                .map(|a| match a {
+                    TableCellAlignment::Left => "left",
+                    TableCellAlignment::Center => "center",
+                    TableCellAlignment::Right => "right",
+                    TableCellAlignment::None => "start",
+                })
+                .collect::<Vec<_>>();
+            args.push(format!("align: ({})", alignments.join(", ")));
+        }
+        if let Some(stroke) = &self.settings.stroke {
+            args.push(format!("stroke: {stroke}"));
+        }
+        if let Some(fill) = &self.settings.fill {
+            args.push(format!("fill: {fill}"));
+        }
+        let row = |cells: &[CellMarkup]| {
+            cells
+                .iter()
+                .map(CellMarkup::markup)
+                .collect::<Vec<_>>()
+                .join(", ")
         };
-        
-        // Reconstruct URL with processed file part and anchor
-        processed = format!("{}{}", file_part, anchor_part);
-    } else if processed.ends_with(".md") {
-        // Convert .md to .typ if no anchor
-        processed = processed.strip_suffix(".md").unwrap_or(&processed).to_string() + ".typ";
-    }
-    
-    processed
+        let mut out = format!("#table(\n  {},\n", args.join(", "));
+        let repeat = format!("repeat: {}", self.settings.repeat_header);
+        match &self.header[..] {
+            [] => {}
+            [cells] => writeln!(out, "  table.header({repeat}, {}),", row(cells)).unwrap(),
+            rows => {
+                writeln!(out, "  table.header(\n    {repeat},").unwrap();
+                for cells in rows {
+                    writeln!(out, "    {},", row(cells)).unwrap();
+                }
+                out.push_str("  ),\n");
+            }
+        }
+        for cells in &self.rows {
+            writeln!(out, "  {},", row(cells)).unwrap();
+        }
+        out.push_str(")\n");
+        out
+    }
+}
+
+/// Write a set rule for `element` with the arguments that are set, or nothing if none
+/// are.
+fn set_rule(element: &str, args: &[(&str, Option<String>)]) -> Option<String> {
+    let args = args
+        .iter()
+        .filter_map(|(k, v)| v.as_ref().map(|v| format!("{k}: {v}")))
+        .collect::<Vec<_>>();
+    if args.is_empty() {
+        None
+    } else {
+        Some(format!("#set {element}({})\n", args.join(", ")))
+    }
+}
+
+/// An error encountered while generating Typst markup.
+///
+/// Each event-related variant records the zero-based `position` of the offending event
+/// in the input iterator and the event itself, formatted with `Debug`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The event does not fit the enclosing tags, e.g. an `End` that does not match the
+    /// open tag or a list item outside of a list. `open` is the innermost open tag.
+    Unbalanced {
+        position: usize,
+        event: String,
+        open: Option<String>,
+    },
+    /// The event is missing data required to generate markup.
+    Malformed {
+        position: usize,
+        event: String,
+        reason: &'static str,
+    },
+    /// Writing the generated markup failed.
+    Write(std::fmt::Error),
+}
+
+impl Error {
+    /// The position of the offending event in the input iterator, if any.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            Error::Unbalanced { position, .. } | Error::Malformed { position, .. } => {
+                Some(*position)
+            }
+            Error::Write(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Unbalanced {
+                position,
+                event,
+                open: Some(open),
+            } => write!(
+                f,
+                "unbalanced event at position {position}: {event} (open tag: {open})"
+            ),
+            Error::Unbalanced {
+                position,
+                event,
+                open: None,
+            } => write!(
+                f,
+                "unbalanced event at position {position}: {event} (no open tag)"
+            ),
+            Error::Malformed {
+                position,
+                event,
+                reason,
+            } => write!(
+                f,
+                "malformed event at position {position}: {reason}: {event}"
+            ),
+            Error::Write(e) => write!(f, "failed to write markup: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Write(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::fmt::Error> for Error {
+    fn from(e: std::fmt::Error) -> Self {
+        Error::Write(e)
+    }
 }
 
 /// Convert Typst events to Typst markup.
 ///
 /// Note: while each item returned by the iterator is a `String`, items may contain
 /// multiple lines.
-// TODO: tests
-pub struct TypstMarkup<'a, T> {
-    tag_queue: VecDeque<Tag<'a>>,
-    codeblock_queue: VecDeque<()>,
-    row_buffer: Option<String>,
-    cell_buffer: Option<String>,
-    paragraph_closed_for_image: bool, // Track if we closed paragraph for an image
-    iter: T,
-}
+///
+/// Panics if an event cannot be converted. For a non-panic version, see
+/// [`TryTypstMarkup`].
+pub struct TypstMarkup<'a, T>(TryTypstMarkup<'a, T>);
 
 impl<'a, T> TypstMarkup<'a, T>
 where
     T: Iterator<Item = self::Event<'a>>,
 {
     pub fn new(iter: T) -> Self {
-        Self {
-            tag_queue: VecDeque::new(),
-            codeblock_queue: VecDeque::new(),
-            row_buffer: None,
-            cell_buffer: None,
-            paragraph_closed_for_image: false,
-            iter,
-        }
+        Self(TryTypstMarkup::new(iter))
     }
 }
 
@@ -94,346 +427,411 @@ where
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // If we have a row buffer and it's not empty, we need to handle buffered output first
-        // But actually, we'll handle it when the row ends
-        
-        match self.iter.next() {
-            None => {
-                // If there's remaining buffer, return it
-                if let Some(mut buf) = self.row_buffer.take() {
-                    if buf.ends_with(", ") {
-                        buf.truncate(buf.len() - 2);
-                    }
-                    Some(buf)
-                } else {
-                    None
+        self.0.next().map(|x| x.unwrap_or_else(|e| panic!("{e}")))
+    }
+}
+
+/// Convert Typst events to Typst markup, reporting events that cannot be converted.
+///
+/// Yields an [`Error`] for the first offending event and stops.
+pub struct TryTypstMarkup<'a, T> {
+    tag_queue: VecDeque<Tag<'a>>,
+    /// The text of each open code block, written once the block ends.
+    codeblock_queue: VecDeque<String>,
+    /// The open tables, innermost last.
+    tables: Vec<TableMarkup<'a>>,
+    /// The captions of the open figures, innermost last, with the number of tables
+    /// open when the figure started.
+    captions: Vec<(usize, String)>,
+    /// A label for the open `#heading` function call, written after its content.
+    heading_label: Option<String>,
+    /// Whether the markup written so far ends at the start of a line or content block.
+    line_start: bool,
+    /// Whether the markup written so far ends with a closing bracket of an embedded
+    /// expression, like `#strong[a]`, which text written next could continue.
+    after_expression: bool,
+    position: usize,
+    failed: bool,
+    iter: T,
+}
+
+impl<'a, T> TryTypstMarkup<'a, T>
+where
+    T: Iterator<Item = self::Event<'a>>,
+{
+    pub fn new(iter: T) -> Self {
+        Self {
+            tag_queue: VecDeque::new(),
+            codeblock_queue: VecDeque::new(),
+            tables: vec![],
+            captions: vec![],
+            heading_label: None,
+            line_start: true,
+            after_expression: false,
+            position: 0,
+            failed: false,
+            iter,
+        }
+    }
+
+    /// Whether text written next starts a line or content block.
+    fn at_line_start(&self) -> bool {
+        if let Some(caption) = self.caption() {
+            // Captions are wrapped in brackets.
+            return ends_at_line_start(caption).unwrap_or(true);
+        }
+        match self.tables.last() {
+            // Cells are wrapped in brackets.
+            Some(table) => table
+                .cell
+                .as_ref()
+                .and_then(|cell| ends_at_line_start(&cell.content))
+                .unwrap_or(true),
+            None => self.line_start,
+        }
+    }
+
+    /// Write raw text. Blocks are started on their own line, as Typst only reads a
+    /// backtick raw block that starts a line or content block as a block.
+    fn raw_markup(&self, raw: &Raw<'_>) -> String {
+        let markup = raw_markup(raw);
+        if raw.block && !self.at_line_start() {
+            format!("\n{markup}")
+        } else {
+            markup
+        }
+    }
+
+    /// The caption of the innermost figure, if content is written to it.
+    fn caption(&self) -> Option<&String> {
+        match self.captions.last() {
+            Some((tables, caption)) if *tables == self.tables.len() => Some(caption),
+            _ => None,
+        }
+    }
+
+    /// Add `content` to the open figure caption or table cell, if any, or return it to
+    /// be written.
+    fn buffered(&mut self, content: String) -> Option<String> {
+        if let Some((tables, caption)) = self.captions.last_mut() {
+            if *tables == self.tables.len() {
+                caption.push_str(&content);
+                return None;
+            }
+        }
+        let Some(table) = self.tables.last_mut() else {
+            return Some(content);
+        };
+        match (&mut table.cell, &mut table.row) {
+            (Some(cell), _) => cell.content.push_str(&content),
+            // Like whitespace between cells.
+            (None, _) if content.trim().is_empty() => {}
+            // Content outside of cells gets a cell of its own.
+            (None, row) => {
+                let cell = CellMarkup {
+                    span: TableCellSpan::default(),
+                    content,
+                };
+                match row {
+                    Some(cells) => cells.push(cell),
+                    None => table.rows.push(vec![cell]),
                 }
             }
-            Some(Event::Start(x)) => {
+        }
+        None
+    }
+
+    fn markup(&mut self, event: Event<'a>, position: usize) -> Result<String, Error> {
+        let text = matches!(event, Event::Text(_));
+        let ret = match event {
+            Event::Start(x) => {
                 let ret = match x {
                     Tag::Paragraph => Some("#par()[".to_string()),
                     Tag::Show(ty, ref selector, ref set, ref func) => match ty {
                         ShowType::ShowSet => {
-                            let (ele, k, v) = set.as_ref().expect("set data for show-set");
+                            let Some((ele, k, v)) = set.as_ref() else {
+                                return Err(Error::Malformed {
+                                    position,
+                                    event: format!("{:?}", Event::Start(x.clone())),
+                                    reason: "show-set rule without set data",
+                                });
+                            };
                             Some(
                                 format!("#show {}: set {}({}:{})", selector, ele, k, v).to_string(),
                             )
                         }
-                        ShowType::Function => Some(
-                            format!(
-                                "#show {}:{}",
-                                selector,
-                                func.as_ref().expect("function body"),
-                            )
-                            .to_string(),
-                        ),
+                        ShowType::Function => {
+                            let Some(func) = func.as_ref() else {
+                                return Err(Error::Malformed {
+                                    position,
+                                    event: format!("{:?}", Event::Start(x.clone())),
+                                    reason: "show rule without function body",
+                                });
+                            };
+                            Some(format!("#show {}:{}", selector, func))
+                        }
                     },
+                    Tag::Heading(n, ref toc, ref bookmarks)
+                        if is_heading_function(toc, bookmarks) =>
+                    {
+                        let mut args = vec![format!("level: {n}")];
+                        if *toc == TableOfContents::Exclude {
+                            args.push("outlined: false".to_string());
+                        }
+                        if *bookmarks == Bookmarks::Exclude {
+                            args.push("bookmarked: false".to_string());
+                        }
+                        Some(format!("#heading({})[", args.join(", ")))
+                    }
                     Tag::Heading(n, _, _) => Some(format!("{} ", "=".repeat(n.get().into()))),
-                    // TODO: get the number of backticks / tildes somehow.
-                    Tag::CodeBlock(ref fence, ref _display) => {
-                        let depth = self.codeblock_queue.len();
-                        self.codeblock_queue.push_back(());
-                        Some(format!(
-                            "{}{}\n",
-                            "`".repeat(6 + depth),
-                            fence
-                                .clone()
-                                .map(|x| x.into_string())
-                                .unwrap_or_else(|| "".to_string())
-                        ))
+                    Tag::Align(ref alignment) => Some(format!("#align({alignment})[")),
+                    Tag::Hide => Some("#hide[".to_string()),
+                    Tag::CodeBlock(_, _, _) => {
+                        self.codeblock_queue.push_back(String::new());
+                        None
                     }
                     Tag::BulletList(_, _) => None,
                     Tag::NumberedList(_, _, _) => None,
-                    Tag::Item => {
-                        let list = self.tag_queue.back().expect("list item contained in list");
-
-                        match list {
-                            Tag::BulletList(_, _) => Some("- ".to_string()),
-                            Tag::NumberedList(_, _, _) => Some("+ ".to_string()),
-                            _ => unreachable!(),
+                    Tag::Item => match self.tag_queue.back() {
+                        Some(Tag::BulletList(_, _)) => Some("- ".to_string()),
+                        Some(Tag::NumberedList(_, _, _)) => Some("+ ".to_string()),
+                        // List items must be contained in a list.
+                        open => {
+                            return Err(Error::Unbalanced {
+                                position,
+                                event: format!("{:?}", Event::Start(x.clone())),
+                                open: open.map(|t| format!("{t:?}")),
+                            })
                         }
-                    }
+                    },
                     Tag::Emphasis => Some("#emph[".to_string()),
                     Tag::Strong => Some("#strong[".to_string()),
-                    Tag::Link(ref ty, ref url) => {
-                        let processed_url = process_link_url(url);
-                        match ty {
-                            LinkType::Content => Some(format!("#link(\"{processed_url}\")[")),
-                            LinkType::Url | LinkType::Autolink => Some(format!("#link(\"{processed_url}\")[")),
+                    Tag::Footnote => Some("#footnote[".to_string()),
+                    Tag::Link(ref ty, ref url) => match ty {
+                        LinkType::Content | LinkType::Url | LinkType::Autolink => {
+                            Some(format!("#link({})[", string_literal(url)))
                         }
+                        LinkType::Label => Some(format!("#link(<{url}>)[")),
                     },
                     Tag::Quote(ref ty, ref quotes, ref attribution) => {
-                        let block = match ty {
-                            &QuoteType::Block => "block: true,",
-                            &QuoteType::Inline => "block: false,",
+                        let block = match *ty {
+                            QuoteType::Block => "block: true,",
+                            QuoteType::Inline => "block: false,",
                         };
-                        let quotes = match quotes {
-                            &QuoteQuotes::DoNotWrapInDoubleQuotes => "quotes: false,",
-                            &QuoteQuotes::WrapInDoubleQuotes => "quotes: true,",
-                            &QuoteQuotes::Auto => "quotes: auto,",
+                        let quotes = match *quotes {
+                            QuoteQuotes::DoNotWrapInDoubleQuotes => "quotes: false,",
+                            QuoteQuotes::WrapInDoubleQuotes => "quotes: true,",
+                            QuoteQuotes::Auto => "quotes: auto,",
                         };
                         match attribution {
+                            // The attribution is a content block of its own.
                             Some(attribution) => Some(format!(
                                 "#quote({} {} attribution: [{}])[",
-                                block, quotes, attribution
+                                block,
+                                quotes,
+                                typst_escape(attribution, true)
                             )),
                             None => Some(format!("#quote({} {})[", block, quotes)),
                         }
                     }
-                    Tag::Table(ref alignment) => {
-                        let num_columns = alignment.len();
-                        let alignments: Vec<String> = alignment
-                            .iter()
-                            .map(|a| match a {
-                                TableCellAlignment::Left => "left".to_string(),
-                                TableCellAlignment::Center => "center".to_string(),
-                                TableCellAlignment::Right => "right".to_string(),
-                                TableCellAlignment::None => "start".to_string(),
-                            })
-                            .collect();
-                        
-                        // Build the table parameters
-                        let mut params = vec![format!("columns: {}", num_columns)];
-                        if !alignments.iter().all(|a| a == "start") {
-                            params.push(format!("align: ({})", alignments.join(", ")));
-                        }
-                        
-                        Some(format!("#table(\n  {},\n", params.join(", ")))
+                    Tag::Table(ref alignments, ref settings) => {
+                        self.tables
+                            .push(TableMarkup::new(alignments.clone(), settings.clone()));
+                        None
                     }
-                    Tag::TableRow => {
-                        self.row_buffer = Some(String::new());
-                        Some("".to_string())
+                    Tag::TableRow | Tag::TableHead => {
+                        // Rows must be contained in a table.
+                        let Some(table) = self
+                            .tables
+                            .last_mut()
+                            .filter(|_| matches!(self.tag_queue.back(), Some(Tag::Table(..))))
+                        else {
+                            return Err(Error::Unbalanced {
+                                position,
+                                event: format!("{:?}", Event::Start(x.clone())),
+                                open: self.tag_queue.back().map(|t| format!("{t:?}")),
+                            });
+                        };
+                        table.row = Some(vec![]);
+                        None
                     }
-                    Tag::TableHead => {
-                        self.row_buffer = Some(String::new());
-                        Some("".to_string())
+                    Tag::TableCell(span) => {
+                        // Cells must be contained in a row.
+                        let Some(table) = self.tables.last_mut().filter(|_| {
+                            matches!(self.tag_queue.back(), Some(Tag::TableRow | Tag::TableHead))
+                        }) else {
+                            return Err(Error::Unbalanced {
+                                position,
+                                event: format!("{:?}", Event::Start(x.clone())),
+                                open: self.tag_queue.back().map(|t| format!("{t:?}")),
+                            });
+                        };
+                        table.cell = Some(CellMarkup {
+                            span,
+                            content: String::new(),
+                        });
+                        None
                     }
-                    Tag::TableCell => {
-                        self.cell_buffer = Some(String::new());
-                        Some("".to_string())
+                    Tag::Figure(_, _) => {
+                        self.captions.push((self.tables.len(), String::new()));
+                        None
                     }
-                    _ => todo!(),
+                    Tag::Strikethrough => Some("#strike[".to_string()),
                 };
 
                 // Set the current tag for later processing and return optional event.
                 self.tag_queue.push_back(x);
-                if ret.is_none() {
-                    return Some("".to_string());
-                }
-                // If we're in a cell buffer (which means we're in a table cell), accumulate to cell buffer
-                if let Some(ref mut cell_buf) = self.cell_buffer {
-                    cell_buf.push_str(&ret.as_ref().unwrap());
-                    Some("".to_string())
-                } else if let Some(ref mut buf) = self.row_buffer {
-                    // If we're in a row buffer but not in a cell, accumulate to row buffer
-                    buf.push_str(&ret.as_ref().unwrap());
-                    Some("".to_string())
-                } else {
-                    ret
-                }
+                ret
             }
-            Some(Event::End(x)) => {
-                // If we closed paragraph for an image and this is the End(Paragraph) event,
-                // skip it since we already closed it (and removed it from tag_queue)
-                if matches!(x, Tag::Paragraph) && self.paragraph_closed_for_image {
-                    self.paragraph_closed_for_image = false;
-                    // We already removed the paragraph from tag_queue when processing FunctionCall,
-                    // so we just skip this End(Paragraph) event
-                    return Some("".to_string());
-                }
+            Event::End(x) => {
                 let ret = match x {
                     Tag::Paragraph => Some("]\n".to_string()),
+                    Tag::Heading(_, ref toc, ref bookmarks)
+                        if is_heading_function(toc, bookmarks) =>
+                    {
+                        let label = self.heading_label.take().unwrap_or_default();
+                        Some(format!("]{label}\n"))
+                    }
                     Tag::Heading(_, _, _) => Some("\n".to_string()),
+                    Tag::Align(_) | Tag::Hide => Some("]\n".to_string()),
                     Tag::Item => Some("\n".to_string()),
                     Tag::Emphasis => Some("]".to_string()),
                     Tag::Strong => Some("]".to_string()),
+                    Tag::Footnote => Some("]".to_string()),
                     Tag::BulletList(_, _) => Some("".to_string()),
                     Tag::NumberedList(_, _, _) => Some("".to_string()),
-                    Tag::CodeBlock(_, _) => {
-                        let _ = self.codeblock_queue.pop_back();
-                        let depth = self.codeblock_queue.len();
-                        Some(format!("{}\n", "`".repeat(6 + depth)))
+                    Tag::CodeBlock(ref lang, _, ref display) => {
+                        let raw = Raw {
+                            lang: lang.clone(),
+                            block: *display == CodeBlockDisplay::Block,
+                            text: self.codeblock_queue.pop_back().unwrap_or_default().into(),
+                        };
+                        // A nested code block is part of the text of the outer one.
+                        match self.codeblock_queue.back_mut() {
+                            Some(outer) => {
+                                outer.push_str(&raw_markup(&raw));
+                                None
+                            }
+                            None => Some(self.raw_markup(&raw)),
+                        }
                     }
                     Tag::Link(ty, _) => match ty {
                         LinkType::Content => Some("]".to_string()),
-                        LinkType::Url | LinkType::Autolink => Some("]".to_string()),
+                        LinkType::Url | LinkType::Autolink | LinkType::Label => {
+                            Some("]".to_string())
+                        }
                     },
                     Tag::Show(_, _, _, _) => Some("\n".to_string()),
                     Tag::Quote(quote_type, _, _) => Some(match quote_type {
                         QuoteType::Inline => "]".to_string(),
                         QuoteType::Block => "]\n".to_string(),
                     }),
-                    Tag::Table(_) => Some(")\n".to_string()),
-                    Tag::TableHead => {
-                        if let Some(mut buf) = self.row_buffer.take() {
-                            // Remove trailing ", " if present
-                            if buf.ends_with(", ") {
-                                buf.truncate(buf.len() - 2);
+                    Tag::Table(..) => self.tables.pop().map(|table| table.markup()),
+                    Tag::TableHead | Tag::TableRow => {
+                        if let Some(table) = self.tables.last_mut() {
+                            let cells = table.row.take().unwrap_or_default();
+                            if x == Tag::TableHead {
+                                table.header.push(cells);
+                            } else {
+                                table.rows.push(cells);
                             }
-                            // Output row with cells on same line: [cell1], [cell2], ...
-                            Some(format!("  {},\n", buf))
-                        } else {
-                            Some("\n".to_string())
                         }
+                        None
                     }
-                    Tag::TableRow => {
-                        if let Some(mut buf) = self.row_buffer.take() {
-                            // Remove trailing ", " if present
-                            if buf.ends_with(", ") {
-                                buf.truncate(buf.len() - 2);
+                    Tag::TableCell(_) => {
+                        if let Some(table) = self.tables.last_mut() {
+                            if let (Some(cell), Some(cells)) = (table.cell.take(), &mut table.row) {
+                                cells.push(cell);
                             }
-                            // Output row with cells on same line: [cell1], [cell2], ...
-                            Some(format!("  {},\n", buf))
-                        } else {
-                            Some("\n".to_string())
                         }
+                        None
                     }
-                    Tag::TableCell => {
-                        // Get cell content and decide if it needs quotes
-                        if let Some(mut cell_content) = self.cell_buffer.take() {
-                            // Replace <br> with \ + newline (Typst line break)
-                            cell_content = cell_content.replace("<br>", "\\\n").replace("<br/>", "\\\n").replace("<br />", "\\\n");
-                            
-                            // Restore underscores that were escaped by typst_escape ( \\_ -> _)
-                            cell_content = cell_content.replace(" \\_", "_");
-                            
-                            // Escape // to \/\/ for table cells
-                            cell_content = cell_content.replace("//", "\\/\\/");
-                            
-                            // Escape * to \* for table cells, but avoid double-escaping
-                            // If content came from Event::Text, * is already escaped as \*
-                            // If content came from Event::Raw, * needs to be escaped
-                            // We replace * only if it's not already escaped (not preceded by \)
-                            let mut result = String::with_capacity(cell_content.len() * 2);
-                            let mut chars = cell_content.chars().peekable();
-                            while let Some(ch) = chars.next() {
-                                if ch == '*' {
-                                    // Check if previous char was a backslash
-                                    if result.ends_with('\\') {
-                                        // Already escaped, keep as is
-                                        result.push(ch);
-                                    } else {
-                                        // Not escaped, escape it
-                                        result.push_str("\\*");
-                                    }
-                                } else {
-                                    result.push(ch);
-                                }
-                            }
-                            cell_content = result;
-                            
-                            // Trim whitespace
-                            cell_content = cell_content.trim().to_string();
-                            
-                            // Check if cell content contains Typst markup (starts with #)
-                            // If it's plain text, wrap in quotes; otherwise use as-is
-                            let _needs_quotes = !cell_content.trim_start().starts_with('#') 
-                                && !cell_content.contains("#emph")
-                                && !cell_content.contains("#strong")
-                                && !cell_content.contains("#link");
-                            
-                            // Wrap cell content in square brackets: [content]
-                            let formatted_cell = if cell_content.is_empty() {
-                                "[]".to_string()
-                            } else {
-                                format!("[{}]", cell_content)
-                            };
-                            
-                            // Append to row buffer with ", " separator
-                            if let Some(ref mut buf) = self.row_buffer {
-                                if !buf.is_empty() {
-                                    buf.push_str(", ");
-                                }
-                                buf.push_str(&formatted_cell);
-                            } else {
-                                // If row_buffer doesn't exist, this is an error state
-                                // But we'll still output the cell content
-                                eprintln!("Warning: TableCell ended but row_buffer is None");
-                            }
-                        } else {
-                            // Empty cell
-                            if let Some(ref mut buf) = self.row_buffer {
-                                if !buf.is_empty() {
-                                    buf.push_str(", ");
-                                }
-                                buf.push_str("[]");
+                    Tag::Figure(ref image, ref label) => {
+                        let mut args = vec![image_call(image)];
+                        if let Some((_, caption)) = self.captions.pop() {
+                            if !caption.trim().is_empty() {
+                                args.push(format!("caption: [{}]", caption.trim()));
                             }
                         }
-                        Some("".to_string())
+                        let label = label
+                            .as_ref()
+                            .map(|l| format!(" <{l}>"))
+                            .unwrap_or_default();
+                        Some(format!("#figure({}){label}\n", args.join(", ")))
                     }
-                    _ => todo!(),
+                    Tag::Strikethrough => Some("]".to_string()),
                 };
 
                 let in_tag = self.tag_queue.pop_back();
 
                 // Make sure we are in a good state.
-                assert_eq!(in_tag, Some(x.clone()));
-                
-                // If we're in a cell buffer (which means we're in a table cell), accumulate to cell buffer
-                if let Some(ref mut cell_buf) = self.cell_buffer {
-                    cell_buf.push_str(&ret.as_ref().unwrap_or(&"".to_string()));
-                    Some("".to_string())
-                } else if self.row_buffer.is_some() && !matches!(&x, Tag::TableRow | Tag::TableHead | Tag::TableCell) {
-                    // If we're in a row buffer but not in a cell, accumulate to row buffer
-                    if let Some(ref mut buf) = self.row_buffer {
-                        buf.push_str(&ret.as_ref().unwrap_or(&"".to_string()));
-                        Some("".to_string())
-                    } else {
-                        ret
-                    }
-                } else {
-                    ret
+                if in_tag.as_ref() != Some(&x) {
+                    return Err(Error::Unbalanced {
+                        position,
+                        event: format!("{:?}", Event::End(x)),
+                        open: in_tag.map(|t| format!("{t:?}")),
+                    });
                 }
+                ret
             }
-            Some(Event::Raw(x)) => {
-                let content = x.into_string();
-                if let Some(ref mut cell_buf) = self.cell_buffer {
-                    cell_buf.push_str(&content);
-                    Some("".to_string())
-                } else if let Some(ref mut buf) = self.row_buffer {
-                    buf.push_str(&content);
-                    Some("".to_string())
-                } else {
-                    Some(content)
+            Event::Raw(x) => {
+                if let Some(code) = self.codeblock_queue.back_mut() {
+                    code.push_str(&x);
+                    return Ok(String::new());
                 }
+                Some(x.into_string())
             }
-            Some(Event::Text(x)) => {
-                let content = if self.codeblock_queue.is_empty() {
-                    typst_escape(&x)
-                } else {
-                    x.into_string()
-                };
-                if let Some(ref mut cell_buf) = self.cell_buffer {
-                    cell_buf.push_str(&content);
-                    Some("".to_string())
-                } else if let Some(ref mut buf) = self.row_buffer {
-                    buf.push_str(&content);
-                    Some("".to_string())
-                } else {
-                    Some(content)
+            Event::Text(x) => {
+                if let Some(code) = self.codeblock_queue.back_mut() {
+                    code.push_str(&x);
+                    return Ok(String::new());
+                }
+                let mut escaped = typst_escape(&x, self.at_line_start());
+                if self.after_expression && continues_expression(&escaped) {
+                    escaped.insert(0, '\\');
                 }
+                Some(escaped)
             }
-            Some(Event::Code(x)) => {
-                let content = format!(
-                    "#raw(\"{}\")",
-                    x
-                        // "Raw" still needs forward slashes escaped or they will break out of
-                        // the tag.
-                        .replace('\\', r#"\\"#)
-                        // "Raw" still needs quotes escaped or they will prematurely end the tag.
-                        .replace('"', r#"\""#)
-                );
-                if let Some(ref mut buf) = self.row_buffer {
-                    buf.push_str(&content);
-                    Some("".to_string())
-                } else {
-                    Some(content)
+            Event::Code(x) => Some(raw_markup(&Raw {
+                lang: None,
+                block: false,
+                text: x,
+            })),
+            Event::RawCode(raw) => Some(self.raw_markup(&raw)),
+            Event::Math(x, display) => Some(match display {
+                MathDisplay::Inline => format!("${x}$"),
+                MathDisplay::Block => format!("$ {x} $"),
+            }),
+            Event::Label(x) => {
+                let content = format!(" <{x}>");
+                if let Some(Tag::Heading(_, toc, bookmarks)) = self.tag_queue.back() {
+                    if is_heading_function(toc, bookmarks) {
+                        self.heading_label = Some(content);
+                        return Ok(String::new());
+                    }
                 }
+                Some(content)
             }
-            Some(Event::Linebreak) => Some("#linebreak()\n".to_string()),
-            Some(Event::Parbreak) => Some("#parbreak()\n".to_string()),
-            Some(Event::PageBreak) => Some("#pagebreak()\n".to_string()),
-            Some(Event::Line(start, end, length, angle, stroke)) => {
+            Event::FootnoteReference(x) => Some(format!("#footnote(<{x}>)")),
+            // Boxed so the image stays in the line.
+            Event::Image(image) => Some(format!("#box({})", image_call(&image))),
+            Event::TaskListMarker(checked) => {
+                Some(if checked { "\u{2612} " } else { "\u{2610} " }.to_string())
+            }
+            Event::Linebreak => Some("#linebreak()\n".to_string()),
+            Event::Parbreak => Some("#parbreak()\n".to_string()),
+            Event::PageBreak => Some("#pagebreak()\n".to_string()),
+            Event::Outline(title, depth) => {
+                let mut args = vec![];
+                if let Some(title) = title {
+                    args.push(format!("title: [{}]", typst_escape(&title, true)));
+                }
+                if let Some(depth) = depth {
+                    args.push(format!("depth: {depth}"));
+                }
+                Some(format!("#outline({})\n", args.join(", ")))
+            }
+            Event::Line(start, end, length, angle, stroke) => {
                 let mut parts = vec![];
 
                 if let Some(start) = start {
@@ -454,34 +852,82 @@ where
 
                 Some(format!("#line({})\n", parts.join(", ")))
             }
-            Some(Event::Let(lhs, rhs)) => Some(format!("#let {lhs} = {rhs}\n")),
-            Some(Event::FunctionCall(v, f, args)) => {
+            Event::Let(lhs, rhs) => Some(format!("#let {lhs} = {rhs}\n")),
+            Event::FunctionCall(v, f, args) => {
                 let args = args.join(", ");
-                // If this is an image function call and we're in a paragraph, close the paragraph first
-                let mut result = String::new();
-                if f.as_ref() == "image" && self.tag_queue.back().map(|t| matches!(t, Tag::Paragraph)).unwrap_or(false) {
-                    // Close the paragraph before the image
-                    result.push_str("]\n");
-                    // Remove the paragraph from tag_queue since we're closing it
-                    if let Some(Tag::Paragraph) = self.tag_queue.pop_back() {
-                        // Mark that we closed paragraph for an image, so we can skip the next End(Paragraph) event
-                        self.paragraph_closed_for_image = true;
-                    }
-                }
                 if let Some(v) = v {
-                    result.push_str(&format!("#{v}.{f}({args})\n"));
+                    Some(format!("#{v}.{f}({args})\n"))
                 } else {
-                    result.push_str(&format!("#{f}({args})\n"));
+                    Some(format!("#{f}({args})\n"))
                 }
-                Some(result)
             }
-            Some(Event::DocumentFunctionCall(args)) => {
+            Event::DocumentFunctionCall(args) => {
                 let args = args.join(", ");
                 Some(format!("#document({args})\n"))
             }
-            Some(Event::Set(ele, k, v)) => Some(format!("#set {ele}({k}: {v})\n")),
-            Some(Event::DocumentSet(k, v)) => Some(format!("#set document({k}: {v})\n")),
+            Event::Set(ele, k, v) => Some(format!("#set {ele}({k}: {v})\n")),
+            Event::DocumentSet(k, v) => Some(format!("#set document({k}: {v})\n")),
+            Event::SetPage(page) => set_rule(
+                "page",
+                &[
+                    ("paper", page.paper.map(|x| string_literal(&x))),
+                    ("width", page.width.map(CowStr::into_string)),
+                    ("height", page.height.map(CowStr::into_string)),
+                    ("margin", page.margin.map(CowStr::into_string)),
+                    ("numbering", page.numbering.map(|x| string_literal(&x))),
+                ],
+            ),
+            Event::SetText(text) => set_rule(
+                "text",
+                &[
+                    ("font", text.font.map(|x| string_literal(&x))),
+                    ("size", text.size.map(CowStr::into_string)),
+                    ("lang", text.lang.map(|x| string_literal(&x))),
+                ],
+            ),
+            Event::SetPar(par) => set_rule(
+                "par",
+                &[
+                    ("justify", par.justify.map(|x| x.to_string())),
+                    ("leading", par.leading.map(CowStr::into_string)),
+                    (
+                        "first-line-indent",
+                        par.first_line_indent.map(CowStr::into_string),
+                    ),
+                ],
+            ),
+            Event::SetHeading(heading) => set_rule(
+                "heading",
+                &[("numbering", heading.numbering.map(|x| string_literal(&x)))],
+            ),
+        };
+        if let Some(ret) = ret.as_deref().filter(|ret| !ret.is_empty()) {
+            self.after_expression = !text && ret.ends_with([']', ')']);
         }
+        // Content in a table is written once the table ends.
+        Ok(ret.and_then(|ret| self.buffered(ret)).unwrap_or_default())
+    }
+}
+
+impl<'a, T> Iterator for TryTypstMarkup<'a, T>
+where
+    T: Iterator<Item = self::Event<'a>>,
+{
+    type Item = Result<String, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let event = self.iter.next()?;
+        let position = self.position;
+        self.position += 1;
+        let ret = self.markup(event, position);
+        self.failed = ret.is_err();
+        if let Some(line_start) = ret.as_deref().ok().and_then(ends_at_line_start) {
+            self.line_start = line_start;
+        }
+        Some(ret)
     }
 }
 
@@ -502,8 +948,23 @@ where
     W: Write,
 {
     for e in TypstMarkup::new(iter) {
-        w.write_str(&e)
-            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+        w.write_str(&e).map_err(std::io::Error::other)?;
+    }
+    Ok(())
+}
+
+/// Iterate over an Iterator of Typst [`Event`]s, generate Typst markup for each
+/// [`Event`], and write it to a `Write`r.
+///
+/// Unlike [`write_markup`], events that cannot be converted are reported as an
+/// [`Error`] instead of panicking.
+pub fn try_write_markup<'a, T, W>(w: &mut W, iter: T) -> Result<(), Error>
+where
+    T: Iterator<Item = Event<'a>>,
+    W: Write,
+{
+    for e in TryTypstMarkup::new(iter) {
+        w.write_str(&e?)?;
     }
     Ok(())
 }
@@ -535,7 +996,7 @@ mod tests {
                 Event::End(Tag::Emphasis),
             ];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            let expected = "#emph[ \\_whatever \\_]";
+            let expected = "#emph[\\_whatever\\_]";
             assert_eq!(&output, &expected);
         }
 
@@ -554,14 +1015,239 @@ mod tests {
         }
     }
 
+    mod raw {
+        use super::*;
+
+        fn block(lang: Option<&'static str>, text: &'static str) -> Vec<Event<'static>> {
+            let tag = Tag::CodeBlock(lang.map(Into::into), vec![], CodeBlockDisplay::Block);
+            vec![
+                Event::Start(tag.clone()),
+                Event::Text(text.into()),
+                Event::End(tag),
+            ]
+        }
+
+        #[test]
+        fn fence_longer_than_backticks() {
+            let output = TypstMarkup::new(block(Some("md"), "a ``` b ```` c\n").into_iter())
+                .collect::<String>();
+            assert_eq!(&output, "`````md\na ``` b ```` c\n`````\n");
+        }
+
+        #[test]
+        fn nested_code_blocks() {
+            let outer = Tag::CodeBlock(None, vec![], CodeBlockDisplay::Block);
+            let mut input = vec![Event::Start(outer.clone())];
+            input.extend(block(Some("rust"), "fn main() {}\n"));
+            input.push(Event::End(outer));
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(&output, "````\n```rust\nfn main() {}\n```\n````\n");
+        }
+
+        #[test]
+        fn unsafe_block_uses_function() {
+            let output = TypstMarkup::new(block(Some("rust"), "    a\n    b").into_iter())
+                .collect::<String>();
+            assert_eq!(
+                &output,
+                "#raw(\"    a\\n    b\", block: true, lang: \"rust\")\n"
+            );
+            let output =
+                TypstMarkup::new(block(Some("c++"), "x\n").into_iter()).collect::<String>();
+            assert_eq!(&output, "#raw(\"x\\n\", block: true, lang: \"c++\")\n");
+        }
+
+        #[test]
+        fn inline() {
+            let code = |s: &'static str| {
+                TypstMarkup::new(vec![Event::Code(s.into())].into_iter()).collect::<String>()
+            };
+            assert_eq!(code("a \"b\""), "`a \"b\"`");
+            assert_eq!(code("a`b"), "#raw(\"a`b\")");
+            assert_eq!(code(""), "#raw(\"\")");
+        }
+
+        #[test]
+        fn typed() {
+            let input = vec![
+                Event::RawCode(Raw {
+                    lang: Some("rust".into()),
+                    block: false,
+                    text: "let x = 1;".into(),
+                }),
+                Event::RawCode(Raw {
+                    lang: None,
+                    block: true,
+                    text: "x\n".into(),
+                }),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(
+                &output,
+                "#raw(\"let x = 1;\", lang: \"rust\")\n```\nx\n```\n"
+            );
+        }
+
+        #[test]
+        fn block_starts_line() {
+            let block = Tag::CodeBlock(None, vec![], CodeBlockDisplay::Block);
+            let input = vec![
+                Event::Start(Tag::BulletList(None, true)),
+                Event::Start(Tag::Item),
+                Event::Start(block.clone()),
+                Event::Text("x\n".into()),
+                Event::End(block.clone()),
+                Event::End(Tag::Item),
+                Event::Start(Tag::Item),
+                Event::Text("see".into()),
+                Event::Start(block.clone()),
+                Event::Text("y\n".into()),
+                Event::End(block),
+                Event::End(Tag::Item),
+                Event::End(Tag::BulletList(None, true)),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(&output, "- ```\nx\n```\n\n- see\n```\ny\n```\n\n");
+        }
+
+        #[test]
+        fn in_table_cell() {
+            let input = vec![
+                Event::Start(Tag::Table(
+                    vec![TableCellAlignment::None],
+                    TableSettings::default(),
+                )),
+                Event::Start(Tag::TableRow),
+                Event::Start(Tag::TableCell(TableCellSpan::default())),
+                Event::Text("see ".into()),
+                Event::Code("a*b // c".into()),
+                Event::End(Tag::TableCell(TableCellSpan::default())),
+                Event::End(Tag::TableRow),
+                Event::End(Tag::Table(
+                    vec![TableCellAlignment::None],
+                    TableSettings::default(),
+                )),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(&output, "#table(\n  columns: 1,\n  [see `a*b // c`],\n)\n");
+        }
+    }
+
     mod escape {
         use super::*;
 
+        fn paragraph(text: &'static str) -> String {
+            let input = vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text(text.into()),
+                Event::End(Tag::Paragraph),
+            ];
+            TypstMarkup::new(input.into_iter()).collect::<String>()
+        }
+
+        #[test]
+        fn line_start_markers() {
+            assert_eq!(paragraph("= Not a heading"), "#par()[\\= Not a heading]\n");
+            assert_eq!(paragraph("- not a list"), "#par()[\\- not a list]\n");
+            assert_eq!(paragraph("+ nor"), "#par()[\\+ nor]\n");
+            assert_eq!(paragraph("/ Term: no"), "#par()[\\/ Term: no]\n");
+            assert_eq!(paragraph("12. no"), "#par()[12\\. no]\n");
+            assert_eq!(paragraph("a\n - b"), "#par()[a\n \\- b]\n");
+            // Only at the start of a line.
+            assert_eq!(
+                paragraph("a = b - c + d / 2. e"),
+                "#par()[a = b - c + d / 2. e]\n"
+            );
+        }
+
+        #[test]
+        fn line_start_after_markup() {
+            let input = vec![
+                Event::Start(Tag::Strong),
+                Event::Text("a".into()),
+                Event::End(Tag::Strong),
+                Event::Text("- b".into()),
+                Event::Linebreak,
+                Event::Text("- c".into()),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(&output, "#strong[a]- b#linebreak()\n\\- c");
+        }
+
+        #[test]
+        fn line_start_in_list_item() {
+            let input = vec![
+                Event::Start(Tag::BulletList(None, true)),
+                Event::Start(Tag::Item),
+                Event::Text("- a".into()),
+                Event::End(Tag::Item),
+                Event::End(Tag::BulletList(None, true)),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(&output, "- \\- a\n");
+        }
+
+        #[test]
+        fn after_expression() {
+            let strong = |text: &'static str| {
+                let input = vec![
+                    Event::Start(Tag::Strong),
+                    Event::Text("a".into()),
+                    Event::End(Tag::Strong),
+                    Event::Text(text.into()),
+                ];
+                TypstMarkup::new(input.into_iter()).collect::<String>()
+            };
+            assert_eq!(strong("(see x)"), "#strong[a]\\(see x)");
+            assert_eq!(strong(".b"), "#strong[a]\\.b");
+            assert_eq!(strong("[b]"), "#strong[a]\\[b\\]");
+            // Only what would continue the expression.
+            assert_eq!(strong(". b"), "#strong[a]. b");
+            assert_eq!(strong(" (b)"), "#strong[a] (b)");
+
+            let link = Tag::Link(LinkType::Content, "u".into());
+            let input = vec![
+                Event::Start(link.clone()),
+                Event::Text("docs".into()),
+                Event::End(link),
+                Event::Text("(v2)".into()),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(&output, "#link(\"u\")[docs]\\(v2)");
+
+            // Text is not an expression.
+            assert_eq!(paragraph("a](b)"), "#par()[a\\](b)]\n");
+        }
+
+        #[test]
+        fn syntax() {
+            assert_eq!(
+                paragraph("a_b_ *c* $d$ #e `f` <g> @h ~ [i] \\"),
+                "#par()[a\\_b\\_ \\*c\\* \\$d\\$ \\#e \\`f\\` \\<g> \\@h \\~ \\[i\\] \\\\]\n"
+            );
+            assert_eq!(
+                paragraph("a // b /* c -- d -? e... f@ g"),
+                "#par()[a \\// b /\\* c \\-- d \\-? e\\... f@ g]\n"
+            );
+        }
+
+        #[test]
+        fn link_url_is_a_string() {
+            let tag = Tag::Link(LinkType::Url, "https://example.com/?q=\"a\"".into());
+            let input = vec![
+                Event::Start(tag.clone()),
+                Event::Text("a".into()),
+                Event::End(tag),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(&output, "#link(\"https://example.com/?q=\\\"a\\\"\")[a]");
+        }
+
         #[test]
         fn raw_encodes_code() {
             let input = vec![Event::Code("*foo*".into())];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            let expected = "#raw(\"*foo*\")";
+            let expected = "`*foo*`";
             assert_eq!(&output, &expected);
         }
 
@@ -570,7 +1256,7 @@ mod tests {
         fn raw_escapes_forward_slash() {
             let input = vec![Event::Code(r#"\"#.into())];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            let expected = r####"#raw("\\")"####;
+            let expected = r####"`\`"####;
             assert_eq!(&output, &expected);
 
             let input = vec![
@@ -581,19 +1267,19 @@ mod tests {
                 Event::End(Tag::Paragraph),
             ];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            let expected = r####"#par()[before #raw("\\") after]"####.to_string() + "\n";
+            let expected = r####"#par()[before `\` after]"####.to_string() + "\n";
             assert_eq!(&output, &expected);
         }
 
         #[test]
         fn doesnt_escape_codeblock() {
             let input = vec![
-                Event::Start(Tag::CodeBlock(None, crate::CodeBlockDisplay::Block)),
+                Event::Start(Tag::CodeBlock(None, vec![], crate::CodeBlockDisplay::Block)),
                 Event::Text("*blah*".into()),
-                Event::End(Tag::CodeBlock(None, crate::CodeBlockDisplay::Block)),
+                Event::End(Tag::CodeBlock(None, vec![], crate::CodeBlockDisplay::Block)),
             ];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            let expected = "``````\n*blah*``````\n";
+            let expected = "```\n*blah*```\n";
             assert_eq!(&output, &expected);
         }
 
@@ -610,62 +1296,46 @@ mod tests {
         }
 
         #[test]
-        fn processes_markdown_file_link_with_anchor() {
+        fn label() {
             let input = vec![
-                Event::Start(Tag::Link(LinkType::Content, "./tcp附录.md#附录五-分拣机机器类型表".into())),
-                Event::Text("附录五-分拣机机器类型表".into()),
-                Event::End(Tag::Link(LinkType::Content, "./tcp附录.md#附录五-分拣机机器类型表".into())),
+                Event::Start(Tag::Link(LinkType::Label, "intro-setup".into())),
+                Event::Text("Setup".into()),
+                Event::End(Tag::Link(LinkType::Label, "intro-setup".into())),
             ];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            let expected = "#link(\"tcp附录.typ#附录五-分拣机机器类型表\")[附录五-分拣机机器类型表]";
+            let expected = "#link(<intro-setup>)[Setup]";
             assert_eq!(&output, &expected);
         }
 
         #[test]
-        fn processes_markdown_file_link_without_anchor() {
+        fn markdown_file_url_is_unchanged() {
             let input = vec![
-                Event::Start(Tag::Link(LinkType::Content, "./file.md".into())),
+                Event::Start(Tag::Link(LinkType::Content, "./file.md#anchor".into())),
                 Event::Text("link text".into()),
-                Event::End(Tag::Link(LinkType::Content, "./file.md".into())),
+                Event::End(Tag::Link(LinkType::Content, "./file.md#anchor".into())),
             ];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            let expected = "#link(\"file.typ\")[link text]";
-            assert_eq!(&output, &expected);
-        }
-
-        #[test]
-        fn processes_markdown_file_link_with_anchor_no_leading_dot() {
-            let input = vec![
-                Event::Start(Tag::Link(LinkType::Content, "tcp附录.md#附录五-分拣机机器类型表".into())),
-                Event::Text("附录五-分拣机机器类型表".into()),
-                Event::End(Tag::Link(LinkType::Content, "tcp附录.md#附录五-分拣机机器类型表".into())),
-            ];
-            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            let expected = "#link(\"tcp附录.typ#附录五-分拣机机器类型表\")[附录五-分拣机机器类型表]";
+            let expected = "#link(\"./file.md#anchor\")[link text]";
             assert_eq!(&output, &expected);
         }
 
         #[test]
         fn processes_regular_http_link() {
             let input = vec![
-                Event::Start(Tag::Link(LinkType::Content, "https://example.com/page".into())),
+                Event::Start(Tag::Link(
+                    LinkType::Content,
+                    "https://example.com/page".into(),
+                )),
                 Event::Text("Example".into()),
-                Event::End(Tag::Link(LinkType::Content, "https://example.com/page".into())),
+                Event::End(Tag::Link(
+                    LinkType::Content,
+                    "https://example.com/page".into(),
+                )),
             ];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
             let expected = "#link(\"https://example.com/page\")[Example]";
             assert_eq!(&output, &expected);
         }
-
-        #[test]
-        fn test_process_link_url_function() {
-            // Test the process_link_url function directly
-            assert_eq!(process_link_url("./tcp附录.md#附录五-分拣机机器类型表"), "tcp附录.typ#附录五-分拣机机器类型表");
-            assert_eq!(process_link_url("./file.md"), "file.typ");
-            assert_eq!(process_link_url("file.md#anchor"), "file.typ#anchor");
-            assert_eq!(process_link_url("https://example.com/page"), "https://example.com/page");
-            assert_eq!(process_link_url("./path/to/file.md#section"), "path/to/file.typ#section");
-        }
     }
 
     mod quote {
@@ -741,6 +1411,27 @@ mod tests {
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
             assert!(output.contains('\n'));
         }
+
+        #[test]
+        fn escapes_attribution() {
+            let input = vec![
+                Event::Start(Tag::Quote(
+                    QuoteType::Block,
+                    QuoteQuotes::Auto,
+                    Some("#emph[x] ]".into()),
+                )),
+                Event::Text("whatever".into()),
+                Event::End(Tag::Quote(
+                    QuoteType::Block,
+                    QuoteQuotes::Auto,
+                    Some("#emph[x] ]".into()),
+                )),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected =
+                "#quote(block: true, quotes: auto, attribution: [\\#emph\\[x\\] \\]])[whatever]\n";
+            assert_eq!(&output, &expected);
+        }
     }
 
     mod line {
@@ -824,22 +1515,22 @@ mod tests {
     #[test]
     fn table_conversion() {
         let input = vec![
-            Event::Start(Tag::Table(vec![
-                TableCellAlignment::Left,
-                TableCellAlignment::Center,
-            ])),
+            Event::Start(Tag::Table(
+                vec![TableCellAlignment::Left, TableCellAlignment::Center],
+                TableSettings::default(),
+            )),
             Event::Start(Tag::TableRow),
-            Event::Start(Tag::TableCell),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("Header 1".into()),
-            Event::End(Tag::TableCell),
-            Event::Start(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("Header 2".into()),
-            Event::End(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
             Event::End(Tag::TableRow),
-            Event::End(Tag::Table(vec![
-                TableCellAlignment::Left,
-                TableCellAlignment::Center,
-            ])),
+            Event::End(Tag::Table(
+                vec![TableCellAlignment::Left, TableCellAlignment::Center],
+                TableSettings::default(),
+            )),
         ];
 
         let output = TypstMarkup::new(input.into_iter()).collect::<String>();
@@ -851,146 +1542,687 @@ mod tests {
     #[test]
     fn table_multiple_cells() {
         let input = vec![
-            Event::Start(Tag::Table(vec![
-                TableCellAlignment::None,
-                TableCellAlignment::None,
-                TableCellAlignment::None,
-            ])),
+            Event::Start(Tag::Table(
+                vec![
+                    TableCellAlignment::None,
+                    TableCellAlignment::None,
+                    TableCellAlignment::None,
+                ],
+                TableSettings::default(),
+            )),
             Event::Start(Tag::TableHead),
-            Event::Start(Tag::TableCell),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("序号".into()),
-            Event::End(Tag::TableCell),
-            Event::Start(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("版本".into()),
-            Event::End(Tag::TableCell),
-            Event::Start(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("版本号".into()),
-            Event::End(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
             Event::End(Tag::TableHead),
             Event::Start(Tag::TableRow),
-            Event::Start(Tag::TableCell),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("1".into()),
-            Event::End(Tag::TableCell),
-            Event::Start(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("V1.0".into()),
-            Event::End(Tag::TableCell),
-            Event::Start(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("1".into()),
-            Event::End(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
             Event::End(Tag::TableRow),
-            Event::End(Tag::Table(vec![
-                TableCellAlignment::None,
-                TableCellAlignment::None,
-                TableCellAlignment::None,
-            ])),
+            Event::End(Tag::Table(
+                vec![
+                    TableCellAlignment::None,
+                    TableCellAlignment::None,
+                    TableCellAlignment::None,
+                ],
+                TableSettings::default(),
+            )),
         ];
 
         let output = TypstMarkup::new(input.into_iter()).collect::<String>();
         // Each cell should be in separate array elements
-        let expected = "#table(\n  columns: 3,\n  [序号], [版本], [版本号],\n  [1], [V1.0], [1],\n)\n";
+        let expected = "#table(\n  columns: 3,\n  \
+            table.header(repeat: true, [序号], [版本], [版本号]),\n  [1], [V1.0], [1],\n)\n";
         assert_eq!(output, expected, "Cells should be properly separated");
     }
 
     #[test]
     fn table_escapes_forward_slash() {
         let input = vec![
-            Event::Start(Tag::Table(vec![TableCellAlignment::None, TableCellAlignment::None])),
+            Event::Start(Tag::Table(
+                vec![TableCellAlignment::None, TableCellAlignment::None],
+                TableSettings::default(),
+            )),
             Event::Start(Tag::TableRow),
-            Event::Start(Tag::TableCell),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("comment // test".into()),
-            Event::End(Tag::TableCell),
-            Event::Start(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("path/to/file".into()),
-            Event::End(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
             Event::End(Tag::TableRow),
-            Event::End(Tag::Table(vec![TableCellAlignment::None, TableCellAlignment::None])),
+            Event::End(Tag::Table(
+                vec![TableCellAlignment::None, TableCellAlignment::None],
+                TableSettings::default(),
+            )),
         ];
 
         let output = TypstMarkup::new(input.into_iter()).collect::<String>();
         // Only // should be escaped to \/\/, single / should remain unchanged
-        let expected = "#table(\n  columns: 2,\n  [comment \\/\\/ test], [path/to/file],\n)\n";
-        assert_eq!(output, expected, "Double forward slashes should be escaped in table cells");
+        let expected = "#table(\n  columns: 2,\n  [comment \\// test], [path/to/file],\n)\n";
+        assert_eq!(
+            output, expected,
+            "Double forward slashes should be escaped in table cells"
+        );
     }
 
     #[test]
     fn table_escapes_asterisk() {
         let input = vec![
-            Event::Start(Tag::Table(vec![TableCellAlignment::None, TableCellAlignment::None])),
+            Event::Start(Tag::Table(
+                vec![TableCellAlignment::None, TableCellAlignment::None],
+                TableSettings::default(),
+            )),
             Event::Start(Tag::TableRow),
-            Event::Start(Tag::TableCell),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("bold *text*".into()),
-            Event::End(Tag::TableCell),
-            Event::Start(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("item*1".into()),
-            Event::End(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
             Event::End(Tag::TableRow),
-            Event::End(Tag::Table(vec![TableCellAlignment::None, TableCellAlignment::None])),
+            Event::End(Tag::Table(
+                vec![TableCellAlignment::None, TableCellAlignment::None],
+                TableSettings::default(),
+            )),
         ];
 
         let output = TypstMarkup::new(input.into_iter()).collect::<String>();
         let expected = "#table(\n  columns: 2,\n  [bold \\*text\\*], [item\\*1],\n)\n";
-        assert_eq!(output, expected, "Asterisks should be escaped in table cells");
+        assert_eq!(
+            output, expected,
+            "Asterisks should be escaped in table cells"
+        );
     }
 
     #[test]
     fn table_escapes_both_forward_slash_and_asterisk() {
         let input = vec![
-            Event::Start(Tag::Table(vec![TableCellAlignment::None])),
+            Event::Start(Tag::Table(
+                vec![TableCellAlignment::None],
+                TableSettings::default(),
+            )),
             Event::Start(Tag::TableRow),
-            Event::Start(Tag::TableCell),
+            Event::Start(Tag::TableCell(TableCellSpan::default())),
             Event::Text("comment // test *bold*".into()),
-            Event::End(Tag::TableCell),
+            Event::End(Tag::TableCell(TableCellSpan::default())),
             Event::End(Tag::TableRow),
-            Event::End(Tag::Table(vec![TableCellAlignment::None])),
+            Event::End(Tag::Table(
+                vec![TableCellAlignment::None],
+                TableSettings::default(),
+            )),
         ];
 
         let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-        let expected = "#table(\n  columns: 1,\n  [comment \\/\\/ test \\*bold\\*],\n)\n";
-        assert_eq!(output, expected, "Both forward slashes and asterisks should be escaped in table cells");
+        let expected = "#table(\n  columns: 1,\n  [comment \\// test \\*bold\\*],\n)\n";
+        assert_eq!(
+            output, expected,
+            "Both forward slashes and asterisks should be escaped in table cells"
+        );
     }
 
-    mod images {
+    mod tables {
         use super::*;
 
+        fn cell(text: &str, colspan: u32, rowspan: u32) -> Vec<Event<'_>> {
+            let span = TableCellSpan { colspan, rowspan };
+            vec![
+                Event::Start(Tag::TableCell(span)),
+                Event::Text(text.into()),
+                Event::End(Tag::TableCell(span)),
+            ]
+        }
+
+        fn row<'a>(tag: Tag<'a>, cells: Vec<Vec<Event<'a>>>) -> Vec<Event<'a>> {
+            let mut events = vec![Event::Start(tag.clone())];
+            events.extend(cells.into_iter().flatten());
+            events.push(Event::End(tag));
+            events
+        }
+
+        fn table<'a>(settings: TableSettings<'a>, rows: Vec<Vec<Event<'a>>>) -> String {
+            let tag = Tag::Table(vec![], settings);
+            let mut events = vec![Event::Start(tag.clone())];
+            events.extend(rows.into_iter().flatten());
+            events.push(Event::End(tag));
+            TypstMarkup::new(events.into_iter()).collect()
+        }
+
+        #[test]
+        fn spans() {
+            let output = table(
+                TableSettings::default(),
+                vec![
+                    row(Tag::TableRow, vec![cell("a", 2, 1), cell("b", 1, 2)]),
+                    row(Tag::TableRow, vec![cell("c", 1, 1), cell("d", 1, 1)]),
+                ],
+            );
+            // The column count includes the spans.
+            let expected = "#table(\n  columns: 3,\n  table.cell(colspan: 2)[a], \
+                            table.cell(rowspan: 2)[b],\n  [c], [d],\n)\n";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn header_rows() {
+            let output = table(
+                TableSettings::default(),
+                vec![
+                    row(Tag::TableHead, vec![cell("a", 2, 1)]),
+                    row(Tag::TableHead, vec![cell("b", 1, 1), cell("c", 1, 1)]),
+                    row(Tag::TableRow, vec![cell("d", 1, 1), cell("e", 1, 1)]),
+                ],
+            );
+            let expected = "#table(\n  columns: 2,\n  table.header(\n    repeat: true,\n    \
+                            table.cell(colspan: 2)[a],\n    [b], [c],\n  ),\n  [d], [e],\n)\n";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn settings() {
+            let settings = TableSettings {
+                widths: vec!["1fr".into(), "30%".into()],
+                stroke: Some("0.5pt + gray".into()),
+                fill: Some("(_, y) => if y == 0 { luma(230) }".into()),
+                repeat_header: false,
+            };
+            let output = table(
+                settings,
+                vec![
+                    row(Tag::TableHead, vec![cell("a", 1, 1), cell("b", 1, 1)]),
+                    row(Tag::TableRow, vec![cell("c", 1, 1), cell("d", 1, 1)]),
+                ],
+            );
+            let expected = "#table(\n  columns: (1fr, 30%), stroke: 0.5pt + gray, \
+                            fill: (_, y) => if y == 0 { luma(230) },\n  \
+                            table.header(repeat: false, [a], [b]),\n  [c], [d],\n)\n";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn block_content() {
+            let span = TableCellSpan::default();
+            let list = Tag::BulletList(None, true);
+            let code = Tag::CodeBlock(Some("rust".into()), vec![], CodeBlockDisplay::Block);
+            let cells = vec![
+                vec![
+                    Event::Start(Tag::TableCell(span)),
+                    Event::Start(list.clone()),
+                    Event::Start(Tag::Item),
+                    Event::Text("a".into()),
+                    Event::End(Tag::Item),
+                    Event::Start(Tag::Item),
+                    Event::Text("b".into()),
+                    Event::End(Tag::Item),
+                    Event::End(list),
+                    Event::End(Tag::TableCell(span)),
+                ],
+                vec![
+                    Event::Start(Tag::TableCell(span)),
+                    Event::Start(code.clone()),
+                    Event::Text("let x = [1];\n".into()),
+                    Event::End(code),
+                    Event::End(Tag::TableCell(span)),
+                ],
+            ];
+            let output = table(TableSettings::default(), vec![row(Tag::TableRow, cells)]);
+            let expected = "#table(\n  columns: 2,\n  [- a\n- b], \
+                            [```rust\nlet x = [1];\n```],\n)\n";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn nested() {
+            let inner = table(
+                TableSettings::default(),
+                vec![row(Tag::TableRow, vec![cell("b", 1, 1)])],
+            );
+            let tag = Tag::Table(vec![], TableSettings::default());
+            let span = TableCellSpan::default();
+            let mut events = vec![
+                Event::Start(Tag::Table(vec![], TableSettings::default())),
+                Event::Start(Tag::TableRow),
+                Event::Start(Tag::TableCell(span)),
+                Event::Text("a".into()),
+                Event::End(Tag::TableCell(span)),
+                Event::Start(Tag::TableCell(span)),
+                Event::Start(tag.clone()),
+            ];
+            events.extend(row(Tag::TableRow, vec![cell("b", 1, 1)]));
+            events.extend([
+                Event::End(tag),
+                Event::End(Tag::TableCell(span)),
+                Event::End(Tag::TableRow),
+                Event::End(Tag::Table(vec![], TableSettings::default())),
+            ]);
+            let output = TypstMarkup::new(events.into_iter()).collect::<String>();
+            let expected = format!("#table(\n  columns: 2,\n  [a], [{}],\n)\n", inner.trim());
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn cell_outside_row() {
+            let table = Tag::Table(vec![], TableSettings::default());
+            let span = TableCellSpan::default();
+            let input = vec![
+                Event::Start(table.clone()),
+                Event::Start(Tag::TableCell(span)),
+            ];
+            let err = TryTypstMarkup::new(input.into_iter())
+                .collect::<Result<String, _>>()
+                .unwrap_err();
+            assert_eq!(
+                err,
+                Error::Unbalanced {
+                    position: 1,
+                    event: "Start(TableCell(TableCellSpan { colspan: 1, rowspan: 1 }))".to_string(),
+                    open: Some(format!("{table:?}")),
+                }
+            );
+        }
+    }
+
+    mod figures {
+        use super::*;
+
+        fn image(width: Option<&str>, alt: Option<&str>) -> Image<'static> {
+            Image {
+                path: "cat.png".into(),
+                width: width.map(|w| w.to_string().into()),
+                height: None,
+                alt: alt.map(|a| a.to_string().into()),
+            }
+        }
+
         #[test]
-        fn image_not_in_paragraph() {
+        fn caption_and_label() {
+            let figure = Tag::Figure(image(Some("50%"), Some("A cat")), Some("fig:cat".into()));
+            let input = vec![
+                Event::Start(figure.clone()),
+                Event::Text("The ".into()),
+                Event::Start(Tag::Emphasis),
+                Event::Text("cat".into()),
+                Event::End(Tag::Emphasis),
+                Event::End(figure),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "#figure(image(\"cat.png\", width: 50%, alt: \"A cat\"), \
+                            caption: [The #emph[cat]]) <fig:cat>\n";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn without_caption() {
+            let figure = Tag::Figure(image(None, None), None);
+            let input = vec![Event::Start(figure.clone()), Event::End(figure)];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(&output, "#figure(image(\"cat.png\"))\n");
+        }
+
+        #[test]
+        fn inline_image() {
             let input = vec![
                 Event::Start(Tag::Paragraph),
-                Event::Text("Some text".into()),
-                Event::FunctionCall(None, "image".into(), vec!["\"images/spx/image1.png\"".into()]),
+                Event::Text("A ".into()),
+                Event::Image(image(Some("1em"), Some("cat"))),
+                Event::Text(".".into()),
                 Event::End(Tag::Paragraph),
             ];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            // Image should not be wrapped in paragraph, paragraph should be closed before image
-            let expected = "#par()[Some text]\n#image(\"images/spx/image1.png\")\n";
-            assert_eq!(output, expected, "Image should not be wrapped in paragraph");
+            assert_eq!(
+                &output,
+                "#par()[A #box(image(\"cat.png\", width: 1em, alt: \"cat\")).]\n"
+            );
         }
 
         #[test]
-        fn image_between_paragraphs() {
+        fn caption_escapes_line_start() {
+            let figure = Tag::Figure(image(None, None), None);
+            let input = vec![
+                Event::Start(figure.clone()),
+                Event::Text("- not a list".into()),
+                Event::End(figure),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(
+                &output,
+                "#figure(image(\"cat.png\"), caption: [\\- not a list])\n"
+            );
+        }
+    }
+
+    mod images {
+        use super::*;
+
+        #[test]
+        fn figure_between_paragraphs() {
+            let figure = Tag::Figure(
+                Image {
+                    path: "images/spx/image1.png".into(),
+                    width: None,
+                    height: None,
+                    alt: None,
+                },
+                None,
+            );
             let input = vec![
                 Event::Start(Tag::Paragraph),
                 Event::Text("Some text".into()),
                 Event::End(Tag::Paragraph),
-                Event::FunctionCall(None, "image".into(), vec!["\"images/spx/image1.png\"".into()]),
+                Event::Start(figure.clone()),
+                Event::End(figure),
                 Event::Start(Tag::Paragraph),
                 Event::Text(" more text".into()),
                 Event::End(Tag::Paragraph),
             ];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            // Image should be standalone between paragraphs
-            let expected = "#par()[Some text]\n#image(\"images/spx/image1.png\")\n#par()[ more text]\n";
-            assert_eq!(output, expected, "Image should be standalone between paragraphs");
+            let expected = "#par()[Some text]\n#figure(image(\"images/spx/image1.png\"))\n\
+                            #par()[ more text]\n";
+            assert_eq!(output, expected);
+        }
+    }
+
+    mod strikethrough {
+        use super::*;
+
+        #[test]
+        fn inline() {
+            let input = vec![
+                Event::Start(Tag::Strikethrough),
+                Event::Text("gone".into()),
+                Event::End(Tag::Strikethrough),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "#strike[gone]";
+            assert_eq!(&output, &expected);
+        }
+    }
+
+    mod task_lists {
+        use super::*;
+
+        #[test]
+        fn checkboxes() {
+            let list = Tag::BulletList(None, true);
+            let input = vec![
+                Event::Start(list.clone()),
+                Event::Start(Tag::Item),
+                Event::TaskListMarker(true),
+                Event::Text("done".into()),
+                Event::End(Tag::Item),
+                Event::Start(Tag::Item),
+                Event::TaskListMarker(false),
+                Event::Text("todo".into()),
+                Event::End(Tag::Item),
+                Event::End(list),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "- \u{2612} done\n- \u{2610} todo\n";
+            assert_eq!(&output, &expected);
+        }
+    }
+
+    mod footnote {
+        use super::*;
+
+        #[test]
+        fn inline() {
+            let input = vec![
+                Event::Text("Claim.".into()),
+                Event::Start(Tag::Footnote),
+                Event::Text("Source".into()),
+                Event::End(Tag::Footnote),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "Claim.#footnote[Source]";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn reference() {
+            let input = vec![
+                Event::Text("A".into()),
+                Event::Start(Tag::Footnote),
+                Event::Text("Source".into()),
+                Event::End(Tag::Footnote),
+                Event::Label("fn".into()),
+                Event::Text(" and B".into()),
+                Event::FootnoteReference("fn".into()),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "A#footnote[Source] <fn> and B#footnote(<fn>)";
+            assert_eq!(&output, &expected);
+        }
+    }
+
+    mod math {
+        use super::*;
+
+        #[test]
+        fn inline() {
+            let input = vec![
+                Event::Text("area ".into()),
+                Event::Math("pi r^2".into(), MathDisplay::Inline),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "area $pi r^2$";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn block() {
+            let input = vec![Event::Math("sum_(i=1)^n i".into(), MathDisplay::Block)];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "$ sum_(i=1)^n i $";
+            assert_eq!(&output, &expected);
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/styling/#set-rules
+    mod set_rules {
+        use super::*;
+        use crate::{HeadingSettings, PageSettings, ParSettings, TextSettings};
+
+        #[test]
+        fn typed() {
+            let input = vec![
+                Event::SetPage(PageSettings {
+                    paper: Some("us-letter".into()),
+                    margin: Some("2.5cm".into()),
+                    numbering: Some("1".into()),
+                    ..Default::default()
+                }),
+                Event::SetText(TextSettings {
+                    font: Some("New \"Computer\" Modern".into()),
+                    lang: Some("en".into()),
+                    ..Default::default()
+                }),
+                Event::SetPar(ParSettings {
+                    justify: Some(true),
+                    first_line_indent: Some("1em".into()),
+                    ..Default::default()
+                }),
+                Event::SetHeading(HeadingSettings {
+                    numbering: Some("1.1".into()),
+                }),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "\
+#set page(paper: \"us-letter\", margin: 2.5cm, numbering: \"1\")
+#set text(font: \"New \\\"Computer\\\" Modern\", lang: \"en\")
+#set par(justify: true, first-line-indent: 1em)
+#set heading(numbering: \"1.1\")
+";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn empty() {
+            let input = vec![Event::SetPage(PageSettings::default())];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            assert_eq!(&output, "");
+        }
+    }
+
+    mod outline {
+        use super::*;
+        use crate::{Bookmarks, TableOfContents};
+        use std::num::NonZeroU8;
+
+        #[test]
+        fn outline() {
+            let input = vec![
+                Event::Outline(None, None),
+                Event::Outline(Some("Table of *contents*".into()), NonZeroU8::new(2)),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "#outline()\n#outline(title: [Table of \\*contents\\*], depth: 2)\n";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn excluded_heading() {
+            let tag = Tag::Heading(
+                NonZeroU8::new(1).unwrap(),
+                TableOfContents::Exclude,
+                Bookmarks::Exclude,
+            );
+            let input = vec![
+                Event::Start(tag.clone()),
+                Event::Text("Title".into()),
+                Event::Label("title".into()),
+                Event::End(tag),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected =
+                "#heading(level: 1, outlined: false, bookmarked: false)[Title] <title>\n";
+            assert_eq!(&output, &expected);
+        }
+
+        #[test]
+        fn align() {
+            let tag = Tag::Align("center + horizon".into());
+            let input = vec![
+                Event::Start(tag.clone()),
+                Event::Start(Tag::Paragraph),
+                Event::Text("Centered".into()),
+                Event::End(Tag::Paragraph),
+                Event::End(tag),
+            ];
+            let output = TypstMarkup::new(input.into_iter()).collect::<String>();
+            let expected = "#align(center + horizon)[#par()[Centered]\n]\n";
+            assert_eq!(&output, &expected);
         }
 
         #[test]
-        fn image_standalone() {
+        fn hide() {
+            let heading = Tag::Heading(
+                NonZeroU8::new(1).unwrap(),
+                TableOfContents::Include,
+                Bookmarks::Include,
+            );
             let input = vec![
-                Event::FunctionCall(None, "image".into(), vec!["\"images/spx/image1.png\"".into()]),
+                Event::Start(Tag::Hide),
+                Event::Start(heading.clone()),
+                Event::Text("Draft".into()),
+                Event::End(heading),
+                Event::End(Tag::Hide),
             ];
             let output = TypstMarkup::new(input.into_iter()).collect::<String>();
-            let expected = "#image(\"images/spx/image1.png\")\n";
-            assert_eq!(output, expected, "Standalone image should not be wrapped");
+            assert_eq!(&output, "#hide[= Draft\n]\n");
+        }
+    }
+
+    mod errors {
+        use super::*;
+
+        #[test]
+        fn malformed() {
+            let show = Tag::Show(ShowType::ShowSet, "heading".into(), None, None);
+            let input = vec![
+                Event::Text("text".into()),
+                Event::Start(show.clone()),
+                Event::End(show),
+            ];
+            let output = TryTypstMarkup::new(input.into_iter()).collect::<Vec<_>>();
+            let expected = vec![
+                Ok("text".to_string()),
+                Err(Error::Malformed {
+                    position: 1,
+                    event: "Start(Show(ShowSet, Borrowed(\"heading\"), None, None))".to_string(),
+                    reason: "show-set rule without set data",
+                }),
+            ];
+            assert_eq!(output, expected);
+        }
+
+        #[test]
+        fn unbalanced() {
+            let input = vec![
+                Event::Start(Tag::Strong),
+                Event::Text("bold".into()),
+                Event::End(Tag::Emphasis),
+            ];
+            let err = TryTypstMarkup::new(input.into_iter())
+                .collect::<Result<String, _>>()
+                .unwrap_err();
+            assert_eq!(
+                err,
+                Error::Unbalanced {
+                    position: 2,
+                    event: "End(Emphasis)".to_string(),
+                    open: Some("Strong".to_string()),
+                }
+            );
+            assert_eq!(err.position(), Some(2));
+            assert_eq!(
+                err.to_string(),
+                "unbalanced event at position 2: End(Emphasis) (open tag: Strong)"
+            );
+        }
+
+        #[test]
+        fn item_outside_list() {
+            let input = vec![Event::Start(Tag::Item)];
+            let mut output = String::new();
+            let err = try_write_markup(&mut output, input.into_iter()).unwrap_err();
+            assert_eq!(
+                err,
+                Error::Unbalanced {
+                    position: 0,
+                    event: "Start(Item)".to_string(),
+                    open: None,
+                }
+            );
+        }
+
+        #[test]
+        fn write() {
+            let input = vec![Event::Text("ok".into())];
+            let mut output = String::new();
+            assert_eq!(try_write_markup(&mut output, input.into_iter()), Ok(()));
+            assert_eq!(output, "ok");
+        }
+
+        #[test]
+        #[should_panic(expected = "unbalanced event at position 0")]
+        fn panics_without_try() {
+            let input = vec![Event::Start(Tag::Item)];
+            let _ = TypstMarkup::new(input.into_iter()).collect::<String>();
         }
     }
 }
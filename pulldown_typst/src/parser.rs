@@ -0,0 +1,2519 @@
+//! Parse Typst markup into an [`Event`] iterator.
+use crate::{
+    Bookmarks, CodeBlockDisplay, Event, HeadingSettings, Image, LinkType, MathDisplay,
+    PageSettings, ParSettings, QuoteQuotes, QuoteType, Raw, ShowType, TableCellAlignment,
+    TableCellSpan, TableOfContents, TableSettings, Tag, TextSettings,
+};
+use pulldown_cmark::CowStr;
+use std::{collections::VecDeque, num::NonZeroU8};
+
+/// Functions that are treated as blocks when they start a line. They are not wrapped
+/// in an implicit paragraph and they end any paragraph before them.
+const BLOCK_FUNCTIONS: &[&str] = &[
+    "align",
+    "block",
+    "colbreak",
+    "document",
+    "figure",
+    "grid",
+    "heading",
+    "image",
+    "line",
+    "outline",
+    "pagebreak",
+    "par",
+    "parbreak",
+    "quote",
+    "table",
+    "v",
+];
+
+/// How deeply content blocks and lists may nest before they are passed through
+/// unparsed, so deeply nested input cannot overflow the stack.
+const MAX_NESTING: usize = 64;
+
+/// Keywords that start a statement when prefixed with `#`.
+const STATEMENTS: &[&str] = &["set", "show", "let", "import", "include"];
+
+/// When inline parsing should stop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stop {
+    /// At the end of the line, e.g. for headings.
+    Line,
+    /// At the end of a list item with the given marker indentation.
+    Item(usize),
+    /// At a blank line or at the start of a block.
+    Paragraph,
+    /// At the unmatched `]` closing a content block.
+    Bracket,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ListKind {
+    Bullet,
+    Numbered(u64),
+}
+
+/// Parse Typst markup into events.
+///
+/// Only markup mode is understood. Code expressions that do not map to a typed
+/// [`Event`] are passed through as [`Event::FunctionCall`] or [`Event::Raw`] so they
+/// can be written back out unchanged.
+///
+/// Text that does not need unescaping is borrowed from the input.
+#[derive(Debug, Clone)]
+pub struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// How many block-level content brackets we are inside of.
+    depth: usize,
+    /// How many content blocks and lists we are inside of.
+    nesting: usize,
+    /// A position and its column, to find columns without rescanning lines.
+    column: (usize, usize),
+    buf: VecDeque<Event<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            pos: 0,
+            depth: 0,
+            nesting: 0,
+            column: (0, 0),
+            buf: VecDeque::new(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    fn prev(&self) -> Option<char> {
+        self.text[..self.pos].chars().next_back()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Push text. Adjacent text events are merged once the block is complete, see
+    /// [`merge_text`].
+    fn push_text(&self, out: &mut Vec<Event<'a>>, text: CowStr<'a>) {
+        if !text.is_empty() {
+            out.push(Event::Text(text));
+        }
+    }
+
+    /// The offset of borrowed text in the input.
+    fn offset(&self, s: &CowStr<'a>) -> Option<usize> {
+        match s {
+            CowStr::Borrowed(s) => {
+                let start = self.text.as_ptr() as usize;
+                let ptr = s.as_ptr() as usize;
+                (ptr >= start && ptr + s.len() <= start + self.text.len()).then(|| ptr - start)
+            }
+            _ => None,
+        }
+    }
+
+    /// Merge adjacent text events. Adjacent borrowed slices of the input stay
+    /// borrowed, other runs of text are collected into one buffer.
+    fn merge_text(&self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+        let mut merged = Vec::with_capacity(events.len());
+        let mut borrowed: Option<CowStr<'a>> = None;
+        let mut owned: Option<String> = None;
+        for event in events {
+            match event {
+                Event::Text(text) => match (&mut owned, borrowed.take()) {
+                    (Some(buf), _) => buf.push_str(&text),
+                    (None, None) => borrowed = Some(text),
+                    (None, Some(prev)) => match (self.offset(&prev), self.offset(&text)) {
+                        (Some(a), Some(b)) if a + prev.len() == b => {
+                            borrowed = Some(CowStr::Borrowed(&self.text[a..b + text.len()]));
+                        }
+                        _ => {
+                            let mut buf = prev.into_string();
+                            buf.push_str(&text);
+                            owned = Some(buf);
+                        }
+                    },
+                },
+                event => {
+                    merged.extend(borrowed.take().map(Event::Text));
+                    merged.extend(owned.take().map(|t| Event::Text(t.into())));
+                    merged.push(event);
+                }
+            }
+        }
+        merged.extend(borrowed.map(Event::Text));
+        merged.extend(owned.map(|t| Event::Text(t.into())));
+        merged
+    }
+
+    /// Parse the next block into `out`.
+    fn block(&mut self, out: &mut Vec<Event<'a>>) {
+        self.skip_whitespace();
+        let rest = self.rest();
+        if rest.is_empty() || (self.depth > 0 && rest.starts_with(']')) {
+            return;
+        }
+        let indent = self.column();
+
+        if let Some(level) = heading_level(rest) {
+            self.heading(out, level);
+        } else if rest.starts_with("```") {
+            self.raw(out, CodeBlockDisplay::Block);
+        } else if list_marker(rest).is_some() {
+            self.list(out, indent);
+        } else if rest.starts_with("//") || rest.starts_with("/*") {
+            self.comment();
+        } else if is_block_code(rest) {
+            self.bump();
+            self.code(out);
+        } else {
+            self.paragraph(out);
+        }
+    }
+
+    /// Parse blocks until the `]` closing the current content block.
+    fn blocks(&mut self, out: &mut Vec<Event<'a>>) {
+        self.depth += 1;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None | Some(']') => break,
+                _ => {
+                    let before = self.pos;
+                    self.block(out);
+                    // Guarantee progress on malformed input.
+                    if self.pos == before {
+                        self.bump();
+                    }
+                }
+            }
+        }
+        self.depth -= 1;
+    }
+
+    /// The column of the current position in its line. Only the text since the last
+    /// position asked for is scanned when moving forward.
+    fn column(&mut self) -> usize {
+        let (from, column) = match self.column {
+            (pos, column) if pos <= self.pos => (pos, column),
+            _ => (0, 0),
+        };
+        let skipped = &self.text[from..self.pos];
+        let column = match skipped.rfind('\n') {
+            Some(i) => skipped[i + 1..].chars().count(),
+            None => column + skipped.chars().count(),
+        };
+        self.column = (self.pos, column);
+        column
+    }
+
+    fn heading(&mut self, out: &mut Vec<Event<'a>>, level: usize) {
+        self.pos += level;
+        self.skip_spaces();
+        let level = NonZeroU8::new(level.min(u8::MAX.into()) as u8).expect("non-zero");
+        let tag = Tag::Heading(level, TableOfContents::Include, Bookmarks::Include);
+        out.push(Event::Start(tag.clone()));
+        self.inline(out, Stop::Line);
+        // A trailing label belongs to the heading.
+        let label = match out.last() {
+            Some(Event::Label(_)) => out.pop(),
+            _ => None,
+        };
+        trim_end(out);
+        out.extend(label);
+        out.push(Event::End(tag));
+    }
+
+    fn paragraph(&mut self, out: &mut Vec<Event<'a>>) {
+        let start = out.len();
+        out.push(Event::Start(Tag::Paragraph));
+        self.inline(out, Stop::Paragraph);
+        trim_end(out);
+        if out.len() == start + 1 {
+            // Nothing but comments or whitespace.
+            out.pop();
+        } else {
+            out.push(Event::End(Tag::Paragraph));
+        }
+    }
+
+    fn list(&mut self, out: &mut Vec<Event<'a>>, indent: usize) {
+        let kind = match list_marker(self.rest()) {
+            Some((kind, _)) => kind,
+            None => return,
+        };
+        let open = out.len();
+        out.push(Event::Start(Tag::Paragraph));
+        let mut tight = true;
+
+        while let Some((item_kind, len)) = list_marker(self.rest()) {
+            if !same_list(kind, item_kind) {
+                break;
+            }
+            self.pos += len;
+            self.skip_spaces();
+            out.push(Event::Start(Tag::Item));
+            self.inline(out, Stop::Item(indent));
+            trim_end(out);
+
+            // Nested lists.
+            loop {
+                let (next, blank) = self.next_line();
+                match next {
+                    Some(line)
+                        if indentation(line) > indent
+                            && list_marker(line.trim_start()).is_some()
+                            && self.nesting < MAX_NESTING =>
+                    {
+                        tight &= !blank;
+                        self.pos = line.as_ptr() as usize - self.text.as_ptr() as usize;
+                        let nested = indentation(line);
+                        self.skip_spaces();
+                        self.nesting += 1;
+                        self.list(out, nested);
+                        self.nesting -= 1;
+                    }
+                    _ => break,
+                }
+            }
+            out.push(Event::End(Tag::Item));
+
+            // Next item.
+            let (next, blank) = self.next_line();
+            match next {
+                Some(line)
+                    if indentation(line) == indent
+                        && list_marker(line.trim_start())
+                            .is_some_and(|(k, _)| same_list(kind, k)) =>
+                {
+                    tight &= !blank;
+                    self.pos = line.as_ptr() as usize - self.text.as_ptr() as usize;
+                    self.skip_spaces();
+                }
+                _ => break,
+            }
+        }
+
+        let tag = match kind {
+            ListKind::Bullet => Tag::BulletList(None, tight),
+            ListKind::Numbered(start) => Tag::NumberedList(start, None, tight),
+        };
+        out[open] = Event::Start(tag.clone());
+        out.push(Event::End(tag));
+    }
+
+    /// The next non-blank line after the current one and whether blank lines were
+    /// skipped to get there.
+    fn next_line(&self) -> (Option<&'a str>, bool) {
+        let rest = self.rest();
+        let mut rest = match rest.find('\n') {
+            Some(i) => &rest[i + 1..],
+            None => return (None, false),
+        };
+        let mut blank = false;
+        loop {
+            let line = rest.split('\n').next().unwrap_or("");
+            if !line.trim().is_empty() {
+                return (Some(line), blank);
+            }
+            blank = true;
+            match rest.find('\n') {
+                Some(i) => rest = &rest[i + 1..],
+                None => return (None, blank),
+            }
+        }
+    }
+
+    fn comment(&mut self) {
+        if self.eat("//") {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+        } else if self.eat("/*") {
+            match self.rest().find("*/") {
+                Some(i) => self.pos += i + 2,
+                None => self.pos = self.text.len(),
+            }
+        }
+    }
+
+    /// Parse raw text delimited by backticks.
+    fn raw(&mut self, out: &mut Vec<Event<'a>>, display: CodeBlockDisplay) {
+        let fence = self.rest().chars().take_while(|&c| c == '`').count();
+        self.pos += fence;
+        if fence == 2 {
+            out.push(Event::Code(CowStr::Borrowed("")));
+            return;
+        }
+        if fence == 1 {
+            let rest = self.rest();
+            let end = rest.find('`').unwrap_or(rest.len());
+            out.push(Event::Code(CowStr::Borrowed(&rest[..end])));
+            self.pos += (end + 1).min(rest.len());
+            return;
+        }
+
+        let rest = self.rest();
+        let lang_len = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '+' | '.')))
+            .unwrap_or(rest.len());
+        let lang = (lang_len > 0).then(|| CowStr::Borrowed(&rest[..lang_len]));
+        self.pos += lang_len;
+
+        let rest = self.rest();
+        let closing = "`".repeat(fence);
+        let end = rest.find(&closing).unwrap_or(rest.len());
+        let mut body = &rest[..end];
+        // Like Typst, drop the rest of the opening line if it is blank and the
+        // indentation before the closing fence.
+        if let Some(i) = body.find('\n') {
+            if body[..i].trim().is_empty() {
+                body = &body[i + 1..];
+            }
+        }
+        if let Some(i) = body.rfind('\n') {
+            if body[i + 1..].trim().is_empty() {
+                body = &body[..i + 1];
+            }
+        }
+        self.pos += (end + fence).min(rest.len());
+
+        let tag = Tag::CodeBlock(lang, vec![], display);
+        out.push(Event::Start(tag.clone()));
+        if !body.is_empty() {
+            out.push(Event::Text(CowStr::Borrowed(body)));
+        }
+        out.push(Event::End(tag));
+    }
+
+    /// Parse inline content until `stop`.
+    fn inline(&mut self, out: &mut Vec<Event<'a>>, stop: Stop) {
+        // Open strong / emphasis delimiters.
+        let mut open: Vec<Tag<'a>> = vec![];
+        // Unmatched literal `[` that a `]` should balance.
+        let mut brackets = 0;
+        let mut text_start = self.pos;
+
+        macro_rules! flush {
+            () => {
+                if text_start < self.pos {
+                    self.push_text(out, CowStr::Borrowed(&self.text[text_start..self.pos]));
+                }
+            };
+        }
+
+        while let Some(c) = self.peek() {
+            match c {
+                '\n' => {
+                    let (next, blank) = self.next_line();
+                    let stop_here = match stop {
+                        Stop::Bracket => false,
+                        Stop::Line => true,
+                        Stop::Paragraph => {
+                            blank || next.is_none_or(|l| is_block_start(l.trim_start()))
+                        }
+                        Stop::Item(indent) => {
+                            blank
+                                || next.is_none_or(|l| {
+                                    indentation(l) <= indent
+                                        || list_marker(l.trim_start()).is_some()
+                                })
+                        }
+                    };
+                    if stop_here {
+                        break;
+                    }
+                    self.bump();
+                }
+                ']' if brackets > 0 => {
+                    brackets -= 1;
+                    self.bump();
+                }
+                ']' if stop == Stop::Bracket || self.depth > 0 => break,
+                '[' => {
+                    brackets += 1;
+                    self.bump();
+                }
+                '\\' => {
+                    flush!();
+                    self.bump();
+                    match self.peek() {
+                        None => self.push_text(out, CowStr::Borrowed("\\")),
+                        Some(' ' | '\t' | '\r' | '\n') => out.push(Event::Linebreak),
+                        Some('u') if self.peek_nth(1) == Some('{') => {
+                            let rest = self.rest();
+                            match rest.find('}').and_then(|end| {
+                                u32::from_str_radix(&rest[2..end], 16)
+                                    .ok()
+                                    .and_then(char::from_u32)
+                                    .map(|c| (c, end))
+                            }) {
+                                Some((c, end)) => {
+                                    self.push_text(out, CowStr::Boxed(c.to_string().into()));
+                                    self.pos += end + 1;
+                                }
+                                None => self.push_text(out, CowStr::Borrowed("\\")),
+                            }
+                        }
+                        Some(c) => {
+                            self.push_text(out, CowStr::Borrowed(&self.rest()[..c.len_utf8()]));
+                            self.bump();
+                        }
+                    }
+                    text_start = self.pos;
+                }
+                '*' | '_' if !self.in_word() => {
+                    flush!();
+                    self.bump();
+                    let tag = if c == '*' { Tag::Strong } else { Tag::Emphasis };
+                    match open.iter().rposition(|t| *t == tag) {
+                        Some(i) => {
+                            while open.len() > i {
+                                out.push(Event::End(open.pop().expect("open tag")));
+                            }
+                        }
+                        None => {
+                            out.push(Event::Start(tag.clone()));
+                            open.push(tag);
+                        }
+                    }
+                    text_start = self.pos;
+                }
+                '`' => {
+                    flush!();
+                    self.raw(out, CodeBlockDisplay::Inline);
+                    text_start = self.pos;
+                }
+                '$' => {
+                    flush!();
+                    let rest = self.rest();
+                    // Math does not continue past the end of its paragraph.
+                    match find_unescaped(&rest[1..], '$')
+                        .filter(|&i| !has_blank_line(&rest[1..i + 1]))
+                    {
+                        Some(i) => {
+                            let inner = &rest[1..i + 1];
+                            // Whitespace on both sides of the content makes a block
+                            // equation.
+                            let display = if inner.starts_with(char::is_whitespace)
+                                && inner.ends_with(char::is_whitespace)
+                                && !inner.trim().is_empty()
+                            {
+                                MathDisplay::Block
+                            } else {
+                                MathDisplay::Inline
+                            };
+                            out.push(Event::Math(CowStr::Borrowed(inner.trim()), display));
+                            self.pos += i + 2;
+                        }
+                        None => {
+                            self.push_text(out, CowStr::Borrowed("$"));
+                            self.bump();
+                        }
+                    }
+                    text_start = self.pos;
+                }
+                '#' if self.peek_nth(1).is_some_and(is_ident_start) => {
+                    flush!();
+                    self.bump();
+                    self.code(out);
+                    text_start = self.pos;
+                }
+                // Code and content blocks and parenthesized expressions.
+                '#' if matches!(self.peek_nth(1), Some('{' | '[' | '(')) => {
+                    flush!();
+                    let start = self.pos;
+                    self.bump();
+                    match self.peek() {
+                        Some('{') => self.balanced('{', '}', true),
+                        Some('[') => self.balanced('[', ']', false),
+                        _ => self.balanced('(', ')', true),
+                    };
+                    out.push(Event::Raw(CowStr::Borrowed(&self.text[start..self.pos])));
+                    text_start = self.pos;
+                }
+                '<' if label_len(self.rest()).is_some() => {
+                    flush!();
+                    // The space the writer puts before a label is not part of the text.
+                    if let Some(Event::Text(text)) = out.last_mut() {
+                        if text.ends_with(' ') {
+                            *text = match &*text {
+                                CowStr::Borrowed(t) => CowStr::Borrowed(&t[..t.len() - 1]),
+                                t => CowStr::Boxed(t[..t.len() - 1].into()),
+                            };
+                            if text.is_empty() {
+                                out.pop();
+                            }
+                        }
+                    }
+                    let len = label_len(self.rest()).expect("label");
+                    out.push(Event::Label(CowStr::Borrowed(&self.rest()[1..len - 1])));
+                    self.pos += len;
+                    text_start = self.pos;
+                }
+                '@' if self.peek_nth(1).is_some_and(is_ident_start) => {
+                    flush!();
+                    let rest = self.rest();
+                    let len = 1 + ident_len(&rest[1..]);
+                    out.push(Event::Raw(CowStr::Borrowed(&rest[..len])));
+                    self.pos += len;
+                    text_start = self.pos;
+                }
+                'h' if !self.in_word()
+                    && (self.rest().starts_with("http://")
+                        || self.rest().starts_with("https://")) =>
+                {
+                    flush!();
+                    let url = url_slice(self.rest());
+                    let tag = Tag::Link(LinkType::Autolink, CowStr::Borrowed(url));
+                    out.push(Event::Start(tag.clone()));
+                    out.push(Event::Text(CowStr::Borrowed(url)));
+                    out.push(Event::End(tag));
+                    self.pos += url.len();
+                    text_start = self.pos;
+                }
+                '/' if matches!(self.peek_nth(1), Some('/' | '*')) => {
+                    flush!();
+                    self.comment();
+                    text_start = self.pos;
+                }
+                '~' => {
+                    flush!();
+                    self.bump();
+                    self.push_text(out, CowStr::Borrowed("\u{a0}"));
+                    text_start = self.pos;
+                }
+                _ => {
+                    self.bump();
+                }
+            }
+        }
+        flush!();
+        while let Some(tag) = open.pop() {
+            out.push(Event::End(tag));
+        }
+    }
+
+    /// Whether the character at the current position is surrounded by word characters.
+    fn in_word(&self) -> bool {
+        let after = self.peek_nth(1);
+        self.prev().is_some_and(char::is_alphanumeric) && after.is_some_and(char::is_alphanumeric)
+    }
+
+    /// Parse a code expression. The leading `#` has already been consumed.
+    fn code(&mut self, out: &mut Vec<Event<'a>>) {
+        let start = self.pos - 1;
+        let rest = self.rest();
+        let len = ident_len(rest);
+        let name = &rest[..len];
+        self.pos += len;
+
+        if STATEMENTS.contains(&name) {
+            self.statement(out, name, start);
+            return;
+        }
+
+        // Field access, e.g. `#table.cell(...)`.
+        let mut target = None;
+        let mut name = name;
+        while self.peek() == Some('.') && self.peek_nth(1).is_some_and(is_ident_start) {
+            target = Some(&self.text[start + 1..self.pos]);
+            self.bump();
+            let rest = self.rest();
+            let len = ident_len(rest);
+            name = &rest[..len];
+            self.pos += len;
+        }
+
+        let args = if self.peek() == Some('(') {
+            let inner = self.balanced('(', ')', true);
+            split_args(inner)
+        } else {
+            vec![]
+        };
+        let has_content = self.peek() == Some('[');
+
+        // Past the nesting limit content blocks are passed through unparsed.
+        if target.is_none()
+            && self.nesting < MAX_NESTING
+            && self.typed(out, name, &args, has_content)
+        {
+            return;
+        }
+
+        // Untyped function call. Content blocks are passed as trailing arguments.
+        let mut args: Vec<CowStr<'a>> = args.into_iter().map(CowStr::Borrowed).collect();
+        while self.peek() == Some('[') {
+            let from = self.pos;
+            self.balanced('[', ']', false);
+            args.push(CowStr::Borrowed(&self.text[from..self.pos]));
+        }
+        if args.is_empty() && !self.text[start..self.pos].ends_with(')') {
+            // Not a call, e.g. a variable.
+            out.push(Event::Raw(CowStr::Borrowed(&self.text[start..self.pos])));
+        } else {
+            out.push(Event::FunctionCall(
+                target.map(CowStr::Borrowed),
+                CowStr::Borrowed(name),
+                args,
+            ));
+        }
+    }
+
+    /// Map known functions to typed events. Returns `false` if the call was not
+    /// handled.
+    fn typed(
+        &mut self,
+        out: &mut Vec<Event<'a>>,
+        name: &'a str,
+        args: &[&'a str],
+        has_content: bool,
+    ) -> bool {
+        let positional = || args.iter().copied().find(|a| named(a).is_none());
+        let arg = |key: &str| {
+            args.iter()
+                .filter_map(|a| named(a))
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v)
+        };
+
+        match name {
+            "emph" | "strong" | "strike" if has_content => {
+                let tag = match name {
+                    "emph" => Tag::Emphasis,
+                    "strong" => Tag::Strong,
+                    _ => Tag::Strikethrough,
+                };
+                self.content(out, tag, false);
+            }
+            "par" if has_content => self.content(out, Tag::Paragraph, false),
+            "footnote" if has_content && args.is_empty() => self.content(out, Tag::Footnote, false),
+            // Another reference to a labelled footnote.
+            "footnote" => match args {
+                [label] if label_len(label) == Some(label.len()) => out.push(
+                    Event::FootnoteReference(CowStr::Borrowed(&label[1..label.len() - 1])),
+                ),
+                _ => return false,
+            },
+            "heading" if has_content => {
+                let level = arg("level")
+                    .and_then(|l| l.parse::<u8>().ok())
+                    .and_then(NonZeroU8::new)
+                    .unwrap_or(NonZeroU8::MIN);
+                let toc = match arg("outlined") {
+                    Some("false") => TableOfContents::Exclude,
+                    _ => TableOfContents::Include,
+                };
+                let bookmarks = match arg("bookmarked") {
+                    Some("false") => Bookmarks::Exclude,
+                    _ => Bookmarks::Include,
+                };
+                self.content(out, Tag::Heading(level, toc, bookmarks), false);
+                // A label after the call belongs to the heading.
+                let rest = self.rest().trim_start_matches([' ', '\t']);
+                if let Some(len) = label_len(rest) {
+                    let end = out.pop();
+                    out.push(Event::Label(CowStr::Borrowed(&rest[1..len - 1])));
+                    out.extend(end);
+                    self.pos = self.text.len() - rest.len() + len;
+                }
+            }
+            "align" if has_content => match positional() {
+                Some(alignment) => self.content(out, Tag::Align(CowStr::Borrowed(alignment)), true),
+                None => return false,
+            },
+            "outline" if !has_content => {
+                let title = arg("title").map(|t| {
+                    match t.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+                        Some(content) => CowStr::Borrowed(content),
+                        None => string_literal(t).unwrap_or(CowStr::Borrowed(t)),
+                    }
+                });
+                let depth = arg("depth")
+                    .and_then(|d| d.parse::<u8>().ok())
+                    .and_then(NonZeroU8::new);
+                out.push(Event::Outline(title, depth));
+            }
+            "quote" if has_content => {
+                let ty = match arg("block") {
+                    Some("true") => QuoteType::Block,
+                    _ => QuoteType::Inline,
+                };
+                let quotes = match arg("quotes") {
+                    Some("true") => QuoteQuotes::WrapInDoubleQuotes,
+                    Some("false") => QuoteQuotes::DoNotWrapInDoubleQuotes,
+                    _ => QuoteQuotes::Auto,
+                };
+                let attribution = arg("attribution").map(|a| {
+                    match a.strip_prefix('[').and_then(|a| a.strip_suffix(']')) {
+                        Some(content) => CowStr::Borrowed(content),
+                        None => string_literal(a).unwrap_or(CowStr::Borrowed(a)),
+                    }
+                });
+                let block = ty == QuoteType::Block;
+                self.content(out, Tag::Quote(ty, quotes, attribution), block);
+            }
+            "link"
+                if has_content && positional().is_some_and(|l| label_len(l) == Some(l.len())) =>
+            {
+                let label = positional().expect("label");
+                let tag = Tag::Link(
+                    LinkType::Label,
+                    CowStr::Borrowed(&label[1..label.len() - 1]),
+                );
+                self.content(out, tag, false);
+            }
+            "link" => match positional().and_then(string_literal) {
+                Some(url) if has_content => {
+                    self.content(out, Tag::Link(LinkType::Content, url), false)
+                }
+                Some(url) => {
+                    let tag = Tag::Link(LinkType::Url, url.clone());
+                    out.push(Event::Start(tag.clone()));
+                    out.push(Event::Text(url));
+                    out.push(Event::End(tag));
+                }
+                None => return false,
+            },
+            "raw" if !has_content => match positional().and_then(string_literal) {
+                Some(text) if arg("block") == Some("true") => {
+                    let lang = arg("lang").and_then(string_literal);
+                    let tag = Tag::CodeBlock(lang, vec![], CodeBlockDisplay::Block);
+                    out.push(Event::Start(tag.clone()));
+                    out.push(Event::Text(text));
+                    out.push(Event::End(tag));
+                }
+                Some(text) => match arg("lang").and_then(string_literal) {
+                    Some(lang) => out.push(Event::RawCode(Raw {
+                        lang: Some(lang),
+                        block: false,
+                        text,
+                    })),
+                    None => out.push(Event::Code(text)),
+                },
+                None => return false,
+            },
+            "linebreak" if args.is_empty() && !has_content => out.push(Event::Linebreak),
+            "parbreak" if args.is_empty() && !has_content => out.push(Event::Parbreak),
+            "pagebreak" if args.is_empty() && !has_content => out.push(Event::PageBreak),
+            "line" if !has_content && args.iter().all(|a| named(a).is_some()) => {
+                let point = |key| {
+                    arg(key).and_then(|v| {
+                        let inner = v.strip_prefix('(')?.strip_suffix(')')?;
+                        match split_args(inner)[..] {
+                            [x, y] => Some((CowStr::Borrowed(x), CowStr::Borrowed(y))),
+                            _ => None,
+                        }
+                    })
+                };
+                out.push(Event::Line(
+                    point("start"),
+                    point("end"),
+                    arg("length").map(CowStr::Borrowed),
+                    arg("angle").map(CowStr::Borrowed),
+                    arg("stroke").map(CowStr::Borrowed),
+                ));
+            }
+            "image" if !has_content => match image(args) {
+                Some(image) => out.push(Event::Image(image)),
+                None => return false,
+            },
+            // Like the writer puts images in running text.
+            "box" if !has_content => {
+                let inner = match args {
+                    [call] => call
+                        .strip_prefix("image(")
+                        .and_then(|c| c.strip_suffix(')')),
+                    _ => None,
+                };
+                match inner.and_then(|inner| image(&split_args(inner))) {
+                    Some(image) => out.push(Event::Image(image)),
+                    None => return false,
+                }
+            }
+            "figure" if !has_content => match self.figure(args) {
+                Some(events) => out.extend(events),
+                None => return false,
+            },
+            "table" if !has_content => match self.table(args) {
+                Some(events) => out.extend(events),
+                None => return false,
+            },
+            "document" if !has_content => out.push(Event::DocumentFunctionCall(
+                args.iter().copied().map(CowStr::Borrowed).collect(),
+            )),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Parse a `[...]` content block wrapped in `tag`.
+    fn content(&mut self, out: &mut Vec<Event<'a>>, tag: Tag<'a>, block: bool) {
+        out.push(Event::Start(tag.clone()));
+        if self.eat("[") {
+            self.nesting += 1;
+            if block {
+                self.blocks(out);
+            } else {
+                let depth = std::mem::take(&mut self.depth);
+                self.inline(out, Stop::Bracket);
+                self.depth = depth;
+            }
+            self.nesting -= 1;
+            self.eat("]");
+        }
+        out.push(Event::End(tag));
+    }
+
+    /// Parse `arg`, a `[...]` content block in the input, as blocks or inline content.
+    /// Returns `None` if the block does not end with the argument.
+    fn content_arg(&mut self, arg: &'a str, block: bool) -> Option<Vec<Event<'a>>> {
+        let start = self.offset(&CowStr::Borrowed(arg))?;
+        let (pos, depth) = (self.pos, self.depth);
+        self.pos = start;
+        let mut out = vec![];
+        if self.eat("[") {
+            self.nesting += 1;
+            if block {
+                self.blocks(&mut out);
+            } else {
+                self.depth = 0;
+                self.inline(&mut out, Stop::Bracket);
+            }
+            self.nesting -= 1;
+        }
+        let closed = self.eat("]") && self.pos == start + arg.len();
+        (self.pos, self.depth) = (pos, depth);
+        closed.then_some(out)
+    }
+
+    /// Parse the arguments of a `figure` call showing an image, and a label after it.
+    /// Returns `None` if there are arguments a [`Tag::Figure`] cannot hold.
+    fn figure(&mut self, args: &[&'a str]) -> Option<Vec<Event<'a>>> {
+        let mut image_arg = None;
+        let mut caption = vec![];
+        for &arg in args {
+            match named(arg) {
+                None if image_arg.is_none() => {
+                    let inner = arg.strip_prefix("image(")?.strip_suffix(')')?;
+                    image_arg = Some(image(&split_args(inner))?);
+                }
+                Some(("caption", v)) => caption = self.content_arg(v, false)?,
+                _ => return None,
+            }
+        }
+        let rest = self.rest().trim_start_matches([' ', '\t']);
+        let label = label_len(rest).map(|len| {
+            self.pos = self.text.len() - rest.len() + len;
+            CowStr::Borrowed(&rest[1..len - 1])
+        });
+        let tag = Tag::Figure(image_arg?, label);
+        let mut out = vec![Event::Start(tag.clone())];
+        out.extend(caption);
+        out.push(Event::End(tag));
+        Some(out)
+    }
+
+    /// Parse the arguments of a `table` call into rows of cells. Returns `None` if there
+    /// are arguments a [`Tag::Table`] cannot hold.
+    fn table(&mut self, args: &[&'a str]) -> Option<Vec<Event<'a>>> {
+        let mut columns = None;
+        let mut alignments = None;
+        let mut settings = TableSettings::default();
+        let mut header = vec![];
+        let mut cells = vec![];
+        for &arg in args {
+            match named(arg) {
+                Some(("columns", v)) => match v.strip_prefix('(').and_then(|v| v.strip_suffix(')'))
+                {
+                    Some(widths) => {
+                        settings.widths = split_args(widths)
+                            .into_iter()
+                            .map(CowStr::Borrowed)
+                            .collect();
+                        columns = Some(settings.widths.len());
+                    }
+                    None => columns = Some(v.parse().ok()?),
+                },
+                Some(("align", v)) => {
+                    let inner = v.strip_prefix('(')?.strip_suffix(')')?;
+                    alignments = Some(
+                        split_args(inner)
+                            .into_iter()
+                            .map(|a| match a {
+                                "left" => Some(TableCellAlignment::Left),
+                                "center" => Some(TableCellAlignment::Center),
+                                "right" => Some(TableCellAlignment::Right),
+                                "start" => Some(TableCellAlignment::None),
+                                _ => None,
+                            })
+                            .collect::<Option<Vec<_>>>()?,
+                    );
+                }
+                Some(("stroke", v)) => settings.stroke = Some(CowStr::Borrowed(v)),
+                Some(("fill", v)) => settings.fill = Some(CowStr::Borrowed(v)),
+                Some(_) => return None,
+                None => match arg
+                    .strip_prefix("table.header(")
+                    .and_then(|a| a.strip_suffix(')'))
+                {
+                    Some(inner) => {
+                        for arg in split_args(inner) {
+                            match named(arg) {
+                                Some(("repeat", v)) => settings.repeat_header = v.parse().ok()?,
+                                Some(_) => return None,
+                                None => header.push(self.cell(arg)?),
+                            }
+                        }
+                    }
+                    None => cells.push(self.cell(arg)?),
+                },
+            }
+        }
+        let columns = columns?.max(1);
+        let alignments = alignments.unwrap_or_else(|| vec![TableCellAlignment::None; columns]);
+
+        let tag = Tag::Table(alignments, settings);
+        let mut out = vec![Event::Start(tag.clone())];
+        for (row_tag, cells) in [(Tag::TableHead, header), (Tag::TableRow, cells)] {
+            for row in table_rows(cells, columns) {
+                out.push(Event::Start(row_tag.clone()));
+                for (span, content) in row {
+                    out.push(Event::Start(Tag::TableCell(span)));
+                    out.extend(content);
+                    out.push(Event::End(Tag::TableCell(span)));
+                }
+                out.push(Event::End(row_tag.clone()));
+            }
+        }
+        out.push(Event::End(tag));
+        Some(out)
+    }
+
+    /// Parse a table cell, `[...]` or `table.cell(...)[...]`. Content that is a single
+    /// paragraph is unwrapped, like the writer writes inline cell content.
+    fn cell(&mut self, arg: &'a str) -> Option<Cell<'a>> {
+        let mut span = TableCellSpan::default();
+        let content = match arg.strip_prefix("table.cell(") {
+            Some(rest) => {
+                let close = find_top_level(rest, ')')?;
+                for arg in split_args(&rest[..close]) {
+                    match named(arg)? {
+                        ("colspan", v) => span.colspan = v.parse().ok()?,
+                        ("rowspan", v) => span.rowspan = v.parse().ok()?,
+                        _ => return None,
+                    }
+                }
+                &rest[close + 1..]
+            }
+            None => arg,
+        };
+        if !content.starts_with('[') {
+            return None;
+        }
+        let mut events = self.content_arg(content, true)?;
+        let paragraphs = events
+            .iter()
+            .filter(|e| matches!(e, Event::Start(Tag::Paragraph)))
+            .count();
+        if paragraphs == 1
+            && events.first() == Some(&Event::Start(Tag::Paragraph))
+            && events.last() == Some(&Event::End(Tag::Paragraph))
+        {
+            events.pop();
+            events.remove(0);
+        }
+        Some((span, events))
+    }
+
+    /// Parse a `set`, `show`, or `let` statement, or pass other statements through as
+    /// raw markup.
+    fn statement(&mut self, out: &mut Vec<Event<'a>>, keyword: &str, start: usize) {
+        self.skip_spaces();
+        let body_start = self.pos;
+        self.expression_end();
+        let body = self.text[body_start..self.pos].trim_end();
+        self.eat(";");
+
+        let handled = match keyword {
+            "set" => set_rule(body).map(|(ele, args)| {
+                if let Some(event) = typed_set_rule(ele, &args) {
+                    out.push(event);
+                    return;
+                }
+                for (k, v) in args {
+                    out.push(if ele == "document" {
+                        Event::DocumentSet(CowStr::Borrowed(k), CowStr::Borrowed(v))
+                    } else {
+                        Event::Set(
+                            CowStr::Borrowed(ele),
+                            CowStr::Borrowed(k),
+                            CowStr::Borrowed(v),
+                        )
+                    });
+                }
+            }),
+            "show" => find_top_level(body, ':').map(|i| {
+                let selector = CowStr::Borrowed(body[..i].trim());
+                let rule = body[i + 1..].trim();
+                let tag = match rule.strip_prefix("set ").and_then(set_rule) {
+                    Some((ele, args)) if args.len() == 1 => {
+                        let (k, v) = args[0];
+                        Tag::Show(
+                            ShowType::ShowSet,
+                            selector,
+                            Some((ele.into(), k.into(), v.into())),
+                            None,
+                        )
+                    }
+                    _ => Tag::Show(ShowType::Function, selector, None, Some(rule.into())),
+                };
+                out.push(Event::Start(tag.clone()));
+                out.push(Event::End(tag));
+            }),
+            "let" => find_top_level(body, '=').map(|i| {
+                out.push(Event::Let(
+                    CowStr::Borrowed(body[..i].trim()),
+                    CowStr::Borrowed(body[i + 1..].trim()),
+                ));
+            }),
+            _ => None,
+        };
+        if handled.is_none() {
+            out.push(Event::Raw(CowStr::Borrowed(
+                self.text[start..self.pos].trim_end(),
+            )));
+        }
+    }
+
+    /// Advance to the end of a code expression: a newline or `;` outside of any
+    /// brackets.
+    fn expression_end(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                '\n' | ';' => break,
+                '(' => {
+                    self.balanced('(', ')', true);
+                }
+                '[' => {
+                    self.balanced('[', ']', false);
+                }
+                '{' => {
+                    self.balanced('{', '}', true);
+                }
+                '"' => {
+                    self.string();
+                }
+                _ => {
+                    self.bump();
+                }
+            }
+        }
+    }
+
+    /// Skip a string literal starting at the current position.
+    fn string(&mut self) {
+        self.bump();
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '"' => break,
+                _ => {}
+            }
+        }
+    }
+
+    /// Skip a bracketed region starting at the current position and return its inner
+    /// text. In code mode string literals are skipped over, in markup mode escapes and
+    /// raw text are.
+    fn balanced(&mut self, open: char, close: char, code: bool) -> &'a str {
+        self.bump();
+        let start = self.pos;
+        let mut depth = 1;
+        while let Some(c) = self.peek() {
+            match c {
+                '"' if code => {
+                    self.string();
+                    continue;
+                }
+                '\\' if !code => {
+                    self.bump();
+                }
+                '`' if !code => {
+                    let mut raw = Vec::new();
+                    self.raw(&mut raw, CodeBlockDisplay::Inline);
+                    continue;
+                }
+                c if c == open => depth += 1,
+                c if c == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let inner = &self.text[start..self.pos];
+                        self.bump();
+                        return inner;
+                    }
+                }
+                _ => {}
+            }
+            self.bump();
+        }
+        &self.text[start..]
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.buf.pop_front() {
+                return Some(event);
+            }
+            if self.pos >= self.text.len() {
+                return None;
+            }
+            let before = self.pos;
+            let mut out = vec![];
+            self.block(&mut out);
+            if self.pos == before {
+                // A stray `]` at the top level.
+                let rest = self.rest();
+                let len = rest.chars().next().map_or(0, char::len_utf8);
+                out.push(Event::Text(CowStr::Borrowed(&rest[..len])));
+                self.pos += len;
+            }
+            let out = self.merge_text(out);
+            self.buf.extend(out);
+        }
+    }
+}
+
+/// Remove trailing whitespace from the trailing text events.
+fn trim_end(out: &mut Vec<Event<'_>>) {
+    while let Some(Event::Text(text)) = out.last_mut() {
+        let trimmed = text.trim_end();
+        if trimmed.is_empty() {
+            out.pop();
+            continue;
+        }
+        if trimmed.len() != text.len() {
+            *text = match &*text {
+                CowStr::Borrowed(t) => CowStr::Borrowed(t.trim_end()),
+                t => CowStr::Boxed(t.trim_end().into()),
+            };
+        }
+        break;
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn ident_len(s: &str) -> usize {
+    s.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(s.len())
+}
+
+/// The level of the heading at the start of `s`, if any.
+fn heading_level(s: &str) -> Option<usize> {
+    let level = s.chars().take_while(|&c| c == '=').count();
+    match s[level..].chars().next() {
+        _ if level == 0 => None,
+        None | Some(' ' | '\t' | '\n' | '\r') => Some(level),
+        _ => None,
+    }
+}
+
+/// The kind and length of the list marker at the start of `s`, if any.
+fn list_marker(s: &str) -> Option<(ListKind, usize)> {
+    let followed_by_space = |len: usize| {
+        matches!(
+            s[len..].chars().next(),
+            None | Some(' ' | '\t' | '\n' | '\r')
+        )
+    };
+    if s.starts_with('-') && !s.starts_with("--") && followed_by_space(1) {
+        return Some((ListKind::Bullet, 1));
+    }
+    if s.starts_with('+') && followed_by_space(1) {
+        return Some((ListKind::Numbered(1), 1));
+    }
+    let digits = s.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && s[digits..].starts_with('.') && followed_by_space(digits + 1) {
+        let start = s[..digits].parse().ok()?;
+        return Some((ListKind::Numbered(start), digits + 1));
+    }
+    None
+}
+
+fn same_list(a: ListKind, b: ListKind) -> bool {
+    matches!(
+        (a, b),
+        (ListKind::Bullet, ListKind::Bullet) | (ListKind::Numbered(_), ListKind::Numbered(_))
+    )
+}
+
+fn indentation(line: &str) -> usize {
+    line.chars().take_while(|c| matches!(c, ' ' | '\t')).count()
+}
+
+/// Whether `s` starts with a block-level code expression.
+fn is_block_code(s: &str) -> bool {
+    match s.strip_prefix('#') {
+        Some(rest) => {
+            let name = &rest[..ident_len(rest)];
+            STATEMENTS.contains(&name)
+                || BLOCK_FUNCTIONS.contains(&name)
+                || (name == "raw" && is_raw_block(&rest[name.len()..]))
+        }
+        None => false,
+    }
+}
+
+/// Whether `s`, the text after `#raw`, starts with arguments that include `block: true`.
+fn is_raw_block(s: &str) -> bool {
+    let Some(args) = s.strip_prefix('(') else {
+        return false;
+    };
+    let end = find_top_level(args, ')').unwrap_or(args.len());
+    split_args(&args[..end])
+        .into_iter()
+        .filter_map(named)
+        .any(|(k, v)| k == "block" && v == "true")
+}
+
+/// Whether a line starting with `s` ends a paragraph.
+fn is_block_start(s: &str) -> bool {
+    heading_level(s).is_some()
+        || list_marker(s).is_some()
+        || s.starts_with("```")
+        || is_block_code(s)
+}
+
+/// The length of the label at the start of `s`, e.g. `<intro>`.
+fn label_len(s: &str) -> Option<usize> {
+    let inner = s.strip_prefix('<')?;
+    let len = inner
+        .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '.')))
+        .unwrap_or(inner.len());
+    (len > 0 && inner[len..].starts_with('>')).then_some(len + 2)
+}
+
+/// The URL at the start of `s`, without trailing punctuation.
+fn url_slice(s: &str) -> &str {
+    let mut parens = 0;
+    let mut end = s.len();
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => parens += 1,
+            ')' if parens > 0 => parens -= 1,
+            c if c.is_whitespace() || matches!(c, ')' | ']' | '<' | '>' | '"') => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    s[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', '\''])
+}
+
+/// Whether `s` contains a blank line between two line breaks.
+fn has_blank_line(s: &str) -> bool {
+    let mut lines = s.split('\n').skip(1).peekable();
+    while let Some(line) = lines.next() {
+        if lines.peek().is_some() && line.trim().is_empty() {
+            return true;
+        }
+    }
+    false
+}
+
+fn find_unescaped(s: &str, needle: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            c if c == needle && !escaped => return Some(i),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Find `needle` outside of any brackets or strings.
+fn find_top_level(s: &str, needle: char) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if in_string {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => in_string = false,
+                _ => escaped = false,
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            c if c == needle && depth == 0 => return Some(i),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split function call arguments on top-level commas.
+fn split_args(s: &str) -> Vec<&str> {
+    let mut args = vec![];
+    let mut rest = s;
+    while let Some(i) = find_top_level(rest, ',') {
+        args.push(rest[..i].trim());
+        rest = &rest[i + 1..];
+    }
+    args.push(rest.trim());
+    args.retain(|a| !a.is_empty());
+    args
+}
+
+/// Split a named argument into its name and value.
+fn named(arg: &str) -> Option<(&str, &str)> {
+    let i = find_top_level(arg, ':')?;
+    let name = arg[..i].trim();
+    (ident_len(name) == name.len() && !name.is_empty()).then(|| (name, arg[i + 1..].trim()))
+}
+
+/// Parse `ele(k: v, ...)` from a set rule.
+fn set_rule(s: &str) -> Option<(&str, Vec<(&str, &str)>)> {
+    let open = s.find('(')?;
+    let ele = s[..open].trim();
+    let close = open + s[open..].rfind(')')?;
+    let args = split_args(&s[open + 1..close])
+        .into_iter()
+        .map(named)
+        .collect::<Option<Vec<_>>>()?;
+    (!ele.is_empty() && !args.is_empty()).then_some((ele, args))
+}
+
+/// A typed event for `page`, `text`, `par`, and `heading` set rules. Returns `None` if
+/// the rule has arguments the typed event cannot hold, so it is kept as generic
+/// [`Event::Set`]s.
+fn typed_set_rule<'a>(ele: &str, args: &[(&'a str, &'a str)]) -> Option<Event<'a>> {
+    let length = |v: &'a str| Some(Some(CowStr::Borrowed(v)));
+    let string = |v: &'a str| string_literal(v).map(Some);
+    match ele {
+        "page" => {
+            let mut page = PageSettings::default();
+            for &(k, v) in args {
+                match k {
+                    "paper" => page.paper = string(v)?,
+                    "width" => page.width = length(v)?,
+                    "height" => page.height = length(v)?,
+                    "margin" => page.margin = length(v)?,
+                    "numbering" => page.numbering = string(v)?,
+                    _ => return None,
+                }
+            }
+            Some(Event::SetPage(page))
+        }
+        "text" => {
+            let mut text = TextSettings::default();
+            for &(k, v) in args {
+                match k {
+                    "font" => text.font = string(v)?,
+                    "size" => text.size = length(v)?,
+                    "lang" => text.lang = string(v)?,
+                    _ => return None,
+                }
+            }
+            Some(Event::SetText(text))
+        }
+        "par" => {
+            let mut par = ParSettings::default();
+            for &(k, v) in args {
+                match k {
+                    "justify" => par.justify = Some(v.parse().ok()?),
+                    "leading" => par.leading = length(v)?,
+                    "first-line-indent" => par.first_line_indent = length(v)?,
+                    _ => return None,
+                }
+            }
+            Some(Event::SetPar(par))
+        }
+        "heading" => match args {
+            [("numbering", v)] => Some(Event::SetHeading(HeadingSettings {
+                numbering: string(v)?,
+            })),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A table cell: its span and content.
+type Cell<'a> = (TableCellSpan, Vec<Event<'a>>);
+
+/// Group table cells into rows of `columns` columns. Like Typst, a cell is placed in the
+/// first column not taken by a cell spanning rows above it.
+fn table_rows(cells: Vec<Cell<'_>>, columns: usize) -> Vec<Vec<Cell<'_>>> {
+    // How many more rows each column is taken for, including the current one.
+    let mut taken = vec![0u32; columns];
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut column = 0;
+    let mut end_row = |row: &mut Vec<_>, taken: &mut Vec<u32>| {
+        rows.push(std::mem::take(row));
+        for rows in taken.iter_mut() {
+            *rows = rows.saturating_sub(1);
+        }
+    };
+    for (span, content) in cells {
+        while taken[column] > 0 {
+            column += 1;
+            if column == columns {
+                end_row(&mut row, &mut taken);
+                column = 0;
+            }
+        }
+        let end = (column + span.colspan.max(1) as usize).min(columns);
+        taken[column..end].fill(span.rowspan.max(1));
+        row.push((span, content));
+        column = end;
+        while column < columns && taken[column] > 0 {
+            column += 1;
+        }
+        if column == columns {
+            end_row(&mut row, &mut taken);
+            column = 0;
+        }
+    }
+    if !row.is_empty() {
+        end_row(&mut row, &mut taken);
+    }
+    rows
+}
+
+/// Parse the arguments of an `image` call. `None` if there are arguments an [`Image`]
+/// cannot hold.
+fn image<'a>(args: &[&'a str]) -> Option<Image<'a>> {
+    let mut path = None;
+    let mut image = Image {
+        path: CowStr::Borrowed(""),
+        width: None,
+        height: None,
+        alt: None,
+    };
+    for &arg in args {
+        match named(arg) {
+            None if path.is_none() => path = Some(string_literal(arg)?),
+            Some(("width", v)) => image.width = Some(CowStr::Borrowed(v)),
+            Some(("height", v)) => image.height = Some(CowStr::Borrowed(v)),
+            Some(("alt", v)) => image.alt = Some(string_literal(v)?),
+            _ => return None,
+        }
+    }
+    image.path = path?;
+    Some(image)
+}
+
+/// The value of a Typst string literal. Borrowed when no escapes are present.
+fn string_literal(s: &str) -> Option<CowStr<'_>> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    if !inner.contains('\\') {
+        return Some(CowStr::Borrowed(inner));
+    }
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('u') => {
+                let hex: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                out.extend(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32));
+            }
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    Some(CowStr::Boxed(out.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Vec<Event<'_>> {
+        Parser::new(s).collect()
+    }
+
+    fn h(level: u8) -> Tag<'static> {
+        Tag::Heading(
+            NonZeroU8::new(level).unwrap(),
+            TableOfContents::Include,
+            Bookmarks::Include,
+        )
+    }
+
+    mod headings {
+        use super::*;
+
+        #[test]
+        fn levels() {
+            assert_eq!(
+                parse("= One\n\n== Two *bold*\n"),
+                vec![
+                    Event::Start(h(1)),
+                    Event::Text("One".into()),
+                    Event::End(h(1)),
+                    Event::Start(h(2)),
+                    Event::Text("Two ".into()),
+                    Event::Start(Tag::Strong),
+                    Event::Text("bold".into()),
+                    Event::End(Tag::Strong),
+                    Event::End(h(2)),
+                ]
+            );
+        }
+
+        #[test]
+        fn label() {
+            assert_eq!(
+                parse("= Intro <intro>\n"),
+                vec![
+                    Event::Start(h(1)),
+                    Event::Text("Intro".into()),
+                    Event::Label("intro".into()),
+                    Event::End(h(1)),
+                ]
+            );
+        }
+
+        #[test]
+        fn excluded() {
+            let tag = Tag::Heading(
+                NonZeroU8::new(2).unwrap(),
+                TableOfContents::Exclude,
+                Bookmarks::Include,
+            );
+            assert_eq!(
+                parse("#heading(level: 2, outlined: false)[Title] <title>\n"),
+                vec![
+                    Event::Start(tag.clone()),
+                    Event::Text("Title".into()),
+                    Event::Label("title".into()),
+                    Event::End(tag),
+                ]
+            );
+        }
+
+        #[test]
+        fn not_a_heading() {
+            assert_eq!(
+                parse("==not"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("==not".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+    }
+
+    mod paragraphs {
+        use super::*;
+
+        #[test]
+        fn split_on_blank_lines() {
+            assert_eq!(
+                parse("foo\nbar\n\nbaz"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("foo\nbar".into()),
+                    Event::End(Tag::Paragraph),
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("baz".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn text_is_borrowed() {
+            let events = parse("hello world");
+            assert!(matches!(
+                events[1],
+                Event::Text(CowStr::Borrowed("hello world"))
+            ));
+        }
+
+        #[test]
+        fn explicit_par() {
+            assert_eq!(
+                parse("#par()[one]\n#par()[two]\n"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("one".into()),
+                    Event::End(Tag::Paragraph),
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("two".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn escapes_and_comments() {
+            assert_eq!(
+                parse("a \\*b\\* // comment\nc /* block */ d"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("a *b* \nc  d".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn linebreak() {
+            assert_eq!(
+                parse("a \\\nb"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("a ".into()),
+                    Event::Linebreak,
+                    Event::Text("\nb".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+    }
+
+    mod inline {
+        use super::*;
+
+        #[test]
+        fn strong_and_emphasis() {
+            assert_eq!(
+                parse("*a _b_* #emph[c] #strong[d]"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Start(Tag::Strong),
+                    Event::Text("a ".into()),
+                    Event::Start(Tag::Emphasis),
+                    Event::Text("b".into()),
+                    Event::End(Tag::Emphasis),
+                    Event::End(Tag::Strong),
+                    Event::Text(" ".into()),
+                    Event::Start(Tag::Emphasis),
+                    Event::Text("c".into()),
+                    Event::End(Tag::Emphasis),
+                    Event::Text(" ".into()),
+                    Event::Start(Tag::Strong),
+                    Event::Text("d".into()),
+                    Event::End(Tag::Strong),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn underscore_in_word() {
+            assert_eq!(
+                parse("snake_case"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("snake_case".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn raw() {
+            assert_eq!(
+                parse("use `foo` and #raw(\"a\\\"b\")"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("use ".into()),
+                    Event::Code("foo".into()),
+                    Event::Text(" and ".into()),
+                    Event::Code("a\"b".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn links() {
+            let url = Tag::Link(LinkType::Url, "https://a.com".into());
+            let content = Tag::Link(LinkType::Content, "https://b.com".into());
+            let auto = Tag::Link(LinkType::Autolink, "https://c.com/x".into());
+            assert_eq!(
+                parse("#link(\"https://a.com\") #link(\"https://b.com\")[B] https://c.com/x."),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Start(url.clone()),
+                    Event::Text("https://a.com".into()),
+                    Event::End(url),
+                    Event::Text(" ".into()),
+                    Event::Start(content.clone()),
+                    Event::Text("B".into()),
+                    Event::End(content),
+                    Event::Text(" ".into()),
+                    Event::Start(auto.clone()),
+                    Event::Text("https://c.com/x".into()),
+                    Event::End(auto),
+                    Event::Text(".".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn label_link() {
+            let link = Tag::Link(LinkType::Label, "intro".into());
+            assert_eq!(
+                parse("See #link(<intro>)[the intro]."),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("See ".into()),
+                    Event::Start(link.clone()),
+                    Event::Text("the intro".into()),
+                    Event::End(link),
+                    Event::Text(".".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn function_calls() {
+            assert_eq!(
+                parse("#box(width: 1em)[x] #table.cell(colspan: 2)[y]"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::FunctionCall(
+                        None,
+                        "box".into(),
+                        vec!["width: 1em".into(), "[x]".into()]
+                    ),
+                    Event::Text(" ".into()),
+                    Event::FunctionCall(
+                        Some("table".into()),
+                        "cell".into(),
+                        vec!["colspan: 2".into(), "[y]".into()]
+                    ),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn images() {
+            let image = |path, width: Option<&'static str>, alt: Option<&'static str>| Image {
+                path: CowStr::Borrowed(path),
+                width: width.map(CowStr::Borrowed),
+                height: None,
+                alt: alt.map(CowStr::Borrowed),
+            };
+            assert_eq!(
+                parse("and #image(\"a.png\", alt: \"A\") #box(image(\"b.png\", width: 1em))"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("and ".into()),
+                    Event::Image(image("a.png", None, Some("A"))),
+                    Event::Text(" ".into()),
+                    Event::Image(image("b.png", Some("1em"), None)),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+            // Other arguments are kept in a function call.
+            assert_eq!(
+                parse("a #image(\"a.png\", fit: \"cover\")"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("a ".into()),
+                    Event::FunctionCall(
+                        None,
+                        "image".into(),
+                        vec!["\"a.png\"".into(), "fit: \"cover\"".into()]
+                    ),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn strike() {
+            assert_eq!(
+                parse("#strike[old] new"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Start(Tag::Strikethrough),
+                    Event::Text("old".into()),
+                    Event::End(Tag::Strikethrough),
+                    Event::Text(" new".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn footnote() {
+            assert_eq!(
+                parse("Claim.#footnote[See *this*.]"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("Claim.".into()),
+                    Event::Start(Tag::Footnote),
+                    Event::Text("See ".into()),
+                    Event::Start(Tag::Strong),
+                    Event::Text("this".into()),
+                    Event::End(Tag::Strong),
+                    Event::Text(".".into()),
+                    Event::End(Tag::Footnote),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn math() {
+            assert_eq!(
+                parse("$x^2$ and $ sum_(i=1)^n i $ or $a \\$ b$"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Math("x^2".into(), MathDisplay::Inline),
+                    Event::Text(" and ".into()),
+                    Event::Math("sum_(i=1)^n i".into(), MathDisplay::Block),
+                    Event::Text(" or ".into()),
+                    Event::Math("a \\$ b".into(), MathDisplay::Inline),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn unknown_code_is_raw() {
+            assert_eq!(
+                parse("value: #x and $a + b$"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("value: ".into()),
+                    Event::Raw("#x".into()),
+                    Event::Text(" and ".into()),
+                    Event::Math("a + b".into(), MathDisplay::Inline),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+        #[test]
+        fn unterminated_math_is_text() {
+            assert_eq!(
+                parse("costs $5\n\nand $x$"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("costs $5".into()),
+                    Event::End(Tag::Paragraph),
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("and ".into()),
+                    Event::Math("x".into(), MathDisplay::Inline),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn code_and_content_blocks_are_raw() {
+            assert_eq!(
+                parse("a #{ let x = \"}\" } b #[*c*] d #(1 + 2)"),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("a ".into()),
+                    Event::Raw("#{ let x = \"}\" }".into()),
+                    Event::Text(" b ".into()),
+                    Event::Raw("#[*c*]".into()),
+                    Event::Text(" d ".into()),
+                    Event::Raw("#(1 + 2)".into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn many_escapes_are_one_text() {
+            let input = "\\*".repeat(1000);
+            assert_eq!(
+                parse(&input),
+                vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text("*".repeat(1000).into()),
+                    Event::End(Tag::Paragraph),
+                ]
+            );
+        }
+    }
+
+    mod nesting {
+        use super::*;
+
+        #[test]
+        fn deep_content_is_passed_through() {
+            let input = format!("{}x{}", "#strong[".repeat(1000), "]".repeat(1000));
+            let events = parse(&input);
+            let starts = events
+                .iter()
+                .filter(|e| matches!(e, Event::Start(Tag::Strong)))
+                .count();
+            assert_eq!(starts, MAX_NESTING);
+            assert!(matches!(
+                events.iter().find(|e| !matches!(e, Event::Start(_))),
+                Some(Event::FunctionCall(None, name, _)) if &**name == "strong"
+            ));
+        }
+
+        #[test]
+        fn deep_blocks_do_not_overflow() {
+            for (open, close) in [
+                ("#align(center)[", "]"),
+                ("#quote(block: true)[", "]"),
+                ("#heading[", "]"),
+                ("#figure(image(\"a.png\"), caption: [", "])"),
+            ] {
+                let input = format!("{}x{}", open.repeat(1000), close.repeat(1000));
+                assert!(!parse(&input).is_empty());
+            }
+        }
+
+        #[test]
+        fn deep_lists_do_not_overflow() {
+            let input: String = (0..1000)
+                .map(|i| format!("{}- x\n", " ".repeat(i)))
+                .collect();
+            let lists = parse(&input)
+                .iter()
+                .filter(|e| matches!(e, Event::Start(Tag::BulletList(..))))
+                .count();
+            assert!(lists > MAX_NESTING);
+        }
+    }
+
+    mod lists {
+        use super::*;
+
+        #[test]
+        fn bullet() {
+            assert_eq!(
+                parse("- one\n- two\n  more\n"),
+                vec![
+                    Event::Start(Tag::BulletList(None, true)),
+                    Event::Start(Tag::Item),
+                    Event::Text("one".into()),
+                    Event::End(Tag::Item),
+                    Event::Start(Tag::Item),
+                    Event::Text("two\n  more".into()),
+                    Event::End(Tag::Item),
+                    Event::End(Tag::BulletList(None, true)),
+                ]
+            );
+        }
+
+        #[test]
+        fn numbered_and_nested() {
+            assert_eq!(
+                parse("3. a\n  + b\n\n4. c"),
+                vec![
+                    Event::Start(Tag::NumberedList(3, None, false)),
+                    Event::Start(Tag::Item),
+                    Event::Text("a".into()),
+                    Event::Start(Tag::NumberedList(1, None, true)),
+                    Event::Start(Tag::Item),
+                    Event::Text("b".into()),
+                    Event::End(Tag::Item),
+                    Event::End(Tag::NumberedList(1, None, true)),
+                    Event::End(Tag::Item),
+                    Event::Start(Tag::Item),
+                    Event::Text("c".into()),
+                    Event::End(Tag::Item),
+                    Event::End(Tag::NumberedList(3, None, false)),
+                ]
+            );
+        }
+    }
+
+    mod raw_blocks {
+        use super::*;
+
+        #[test]
+        fn with_lang() {
+            let tag = Tag::CodeBlock(Some("rust".into()), vec![], CodeBlockDisplay::Block);
+            assert_eq!(
+                parse("```rust\nfn main() {}\n```\n"),
+                vec![
+                    Event::Start(tag.clone()),
+                    Event::Text("fn main() {}\n".into()),
+                    Event::End(tag),
+                ]
+            );
+        }
+
+        #[test]
+        fn longer_fence() {
+            let tag = Tag::CodeBlock(None, vec![], CodeBlockDisplay::Block);
+            assert_eq!(
+                parse("``````\n```\n``````"),
+                vec![
+                    Event::Start(tag.clone()),
+                    Event::Text("```\n".into()),
+                    Event::End(tag),
+                ]
+            );
+        }
+    }
+
+    mod tables {
+        use super::*;
+
+        fn cell(text: &'static str) -> Vec<Event<'static>> {
+            let span = TableCellSpan::default();
+            vec![
+                Event::Start(Tag::TableCell(span)),
+                Event::Text(text.into()),
+                Event::End(Tag::TableCell(span)),
+            ]
+        }
+
+        #[test]
+        fn rows_by_column() {
+            let tag = Tag::Table(vec![TableCellAlignment::None; 2], TableSettings::default());
+            let mut expected = vec![Event::Start(tag.clone()), Event::Start(Tag::TableRow)];
+            expected.extend(cell("a"));
+            expected.extend(cell("b"));
+            expected.extend([Event::End(Tag::TableRow), Event::Start(Tag::TableRow)]);
+            expected.extend(cell("c"));
+            expected.extend([Event::End(Tag::TableRow), Event::End(tag)]);
+            assert_eq!(parse("#table(columns: 2, [a], [b],\n  [c])"), expected);
+        }
+
+        #[test]
+        fn untyped() {
+            // Arguments a table event cannot hold keep the call as is.
+            assert_eq!(
+                parse("#table(columns: 2, gutter: 1em, [a], [b])"),
+                vec![Event::FunctionCall(
+                    None,
+                    "table".into(),
+                    vec![
+                        "columns: 2".into(),
+                        "gutter: 1em".into(),
+                        "[a]".into(),
+                        "[b]".into()
+                    ]
+                )]
+            );
+            assert_eq!(
+                parse("#figure(rect(), caption: [A])"),
+                vec![Event::FunctionCall(
+                    None,
+                    "figure".into(),
+                    vec!["rect()".into(), "caption: [A]".into()]
+                )]
+            );
+        }
+    }
+
+    mod rules {
+        use super::*;
+
+        #[test]
+        fn set() {
+            assert_eq!(
+                parse("#set document(title: \"Book\")\n#set text(fill: red, size: 11pt)\n"),
+                vec![
+                    Event::DocumentSet("title".into(), "\"Book\"".into()),
+                    Event::Set("text".into(), "fill".into(), "red".into()),
+                    Event::Set("text".into(), "size".into(), "11pt".into()),
+                ]
+            );
+        }
+
+        #[test]
+        fn set_unbalanced() {
+            assert_eq!(parse("#set x)("), vec![Event::Raw("#set x)(".into())]);
+        }
+
+        #[test]
+        fn typed_set() {
+            let input = "\
+#set page(paper: \"a4\", margin: (x: 2cm, y: 3cm))
+#set text(font: \"Inter\", size: 11pt, lang: \"de\")
+#set par(justify: true)
+#set heading(numbering: \"1.1\")
+";
+            assert_eq!(
+                parse(input),
+                vec![
+                    Event::SetPage(PageSettings {
+                        paper: Some("a4".into()),
+                        margin: Some("(x: 2cm, y: 3cm)".into()),
+                        ..Default::default()
+                    }),
+                    Event::SetText(TextSettings {
+                        font: Some("Inter".into()),
+                        size: Some("11pt".into()),
+                        lang: Some("de".into()),
+                    }),
+                    Event::SetPar(ParSettings {
+                        justify: Some(true),
+                        ..Default::default()
+                    }),
+                    Event::SetHeading(HeadingSettings {
+                        numbering: Some("1.1".into()),
+                    }),
+                ]
+            );
+        }
+
+        #[test]
+        fn show() {
+            let set = Tag::Show(
+                ShowType::ShowSet,
+                "heading".into(),
+                Some(("text".into(), "fill".into(), "blue".into())),
+                None,
+            );
+            let func = Tag::Show(
+                ShowType::Function,
+                "link".into(),
+                None,
+                Some("underline".into()),
+            );
+            assert_eq!(
+                parse("#show heading: set text(fill: blue)\n#show link: underline\n"),
+                vec![
+                    Event::Start(set.clone()),
+                    Event::End(set),
+                    Event::Start(func.clone()),
+                    Event::End(func),
+                ]
+            );
+        }
+
+        #[test]
+        fn let_binding() {
+            assert_eq!(
+                parse("#let name = \"a = b\"\n#import \"x.typ\": y"),
+                vec![
+                    Event::Let("name".into(), "\"a = b\"".into()),
+                    Event::Raw("#import \"x.typ\": y".into()),
+                ]
+            );
+        }
+    }
+
+    mod roundtrip {
+        use super::*;
+        use crate::markup::TypstMarkup;
+
+        #[test]
+        fn writer_output_parses_back() {
+            let input = vec![
+                Event::Start(h(1)),
+                Event::Text("Title".into()),
+                Event::End(h(1)),
+                Event::Start(Tag::Paragraph),
+                Event::Text("Some ".into()),
+                Event::Start(Tag::Emphasis),
+                Event::Text("text".into()),
+                Event::End(Tag::Emphasis),
+                Event::Text(" ".into()),
+                Event::Image(Image {
+                    path: "a.png".into(),
+                    width: Some("50%".into()),
+                    height: None,
+                    alt: Some("A \"b\"".into()),
+                }),
+                Event::End(Tag::Paragraph),
+                Event::Start(Tag::Quote(QuoteType::Block, QuoteQuotes::Auto, None)),
+                Event::Start(Tag::Paragraph),
+                Event::Text("quoted".into()),
+                Event::End(Tag::Paragraph),
+                Event::End(Tag::Quote(QuoteType::Block, QuoteQuotes::Auto, None)),
+            ];
+            let markup = TypstMarkup::new(input.clone().into_iter()).collect::<String>();
+            assert_eq!(parse(&markup), input);
+        }
+
+        #[test]
+        fn raw() {
+            let texts = [
+                "fn main() {}\n",
+                "```\n",
+                "a ``` b\n````",
+                "say \"hi\" \\ \\n\n",
+                "ends with `",
+                "  indented\n  too",
+                "trailing\n  ",
+                "\n\nblank first\n",
+            ];
+            for text in texts {
+                for lang in [None, Some("rust")] {
+                    let tag = Tag::CodeBlock(lang.map(Into::into), vec![], CodeBlockDisplay::Block);
+                    let input = vec![
+                        Event::Start(tag.clone()),
+                        Event::Text(text.into()),
+                        Event::End(tag),
+                    ];
+                    let markup = TypstMarkup::new(input.clone().into_iter()).collect::<String>();
+                    assert_eq!(parse(&markup), input, "{markup}");
+                }
+            }
+            for text in ["a", "`", "``x", "\"q\"", "a\nb", "", "\\"] {
+                let input = vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Code(text.into()),
+                    Event::End(Tag::Paragraph),
+                ];
+                let markup = TypstMarkup::new(input.clone().into_iter()).collect::<String>();
+                assert_eq!(parse(&markup), input, "{markup}");
+            }
+        }
+
+        fn assert_roundtrip(input: Vec<Event<'_>>) {
+            let markup = TypstMarkup::new(input.clone().into_iter()).collect::<String>();
+            assert_eq!(parse(&markup), input, "{markup}");
+        }
+
+        #[test]
+        fn inline_raw_with_lang() {
+            assert_roundtrip(vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text("See ".into()),
+                Event::RawCode(Raw {
+                    lang: Some("rust".into()),
+                    block: false,
+                    text: "let x = \"`\";".into(),
+                }),
+                Event::End(Tag::Paragraph),
+            ]);
+        }
+
+        #[test]
+        fn tables() {
+            let cell = |colspan, rowspan, text: &'static str| {
+                let span = TableCellSpan { colspan, rowspan };
+                vec![
+                    Event::Start(Tag::TableCell(span)),
+                    Event::Text(text.into()),
+                    Event::End(Tag::TableCell(span)),
+                ]
+            };
+            let row = |tag: Tag<'static>, cells: Vec<Vec<Event<'static>>>| {
+                let mut row = vec![Event::Start(tag.clone())];
+                row.extend(cells.into_iter().flatten());
+                row.push(Event::End(tag));
+                row
+            };
+            let table = |tag: Tag<'static>, rows: Vec<Vec<Event<'static>>>| {
+                let mut table = vec![Event::Start(tag.clone())];
+                table.extend(rows.into_iter().flatten());
+                table.push(Event::End(tag));
+                table
+            };
+
+            // A header, and cells spanning rows and columns.
+            assert_roundtrip(table(
+                Tag::Table(
+                    vec![
+                        TableCellAlignment::Left,
+                        TableCellAlignment::None,
+                        TableCellAlignment::Right,
+                    ],
+                    TableSettings::default(),
+                ),
+                vec![
+                    row(Tag::TableHead, vec![cell(1, 1, "a"), cell(2, 1, "b")]),
+                    row(
+                        Tag::TableRow,
+                        vec![cell(1, 2, "c"), cell(1, 1, "d"), cell(1, 1, "e")],
+                    ),
+                    row(Tag::TableRow, vec![cell(2, 1, "f")]),
+                    row(
+                        Tag::TableRow,
+                        vec![cell(1, 1, "g"), cell(1, 1, "h"), cell(1, 1, "i")],
+                    ),
+                ],
+            ));
+
+            // Column widths, strokes, fills, and header rows that are not repeated.
+            assert_roundtrip(table(
+                Tag::Table(
+                    vec![TableCellAlignment::None; 2],
+                    TableSettings {
+                        widths: vec!["1fr".into(), "30%".into()],
+                        stroke: Some("0.5pt + gray".into()),
+                        fill: Some("(_, y) => if y == 0 { luma(230) }".into()),
+                        repeat_header: false,
+                    },
+                ),
+                vec![
+                    row(Tag::TableHead, vec![cell(1, 1, "a"), cell(1, 1, "b")]),
+                    row(Tag::TableHead, vec![cell(1, 1, "c"), cell(1, 1, "d")]),
+                    row(Tag::TableRow, vec![cell(1, 1, "e *f*"), cell(1, 1, "g")]),
+                ],
+            ));
+        }
+
+        #[test]
+        fn table_cell_with_blocks() {
+            let span = TableCellSpan::default();
+            let list = Tag::BulletList(None, true);
+            let code = Tag::CodeBlock(Some("rust".into()), vec![], CodeBlockDisplay::Block);
+            let tag = Tag::Table(vec![TableCellAlignment::None], TableSettings::default());
+            assert_roundtrip(vec![
+                Event::Start(tag.clone()),
+                Event::Start(Tag::TableRow),
+                Event::Start(Tag::TableCell(span)),
+                Event::Start(list.clone()),
+                Event::Start(Tag::Item),
+                Event::Text("a".into()),
+                Event::End(Tag::Item),
+                Event::End(list),
+                Event::Start(code.clone()),
+                Event::Text("x\n".into()),
+                Event::End(code),
+                Event::End(Tag::TableCell(span)),
+                Event::End(Tag::TableRow),
+                Event::End(tag),
+            ]);
+        }
+
+        #[test]
+        fn figures() {
+            let image = Image {
+                path: "cat.png".into(),
+                width: Some("50%".into()),
+                height: None,
+                alt: Some("A \"cat\"".into()),
+            };
+            let figure = Tag::Figure(image.clone(), Some("fig:cat".into()));
+            assert_roundtrip(vec![
+                Event::Start(figure.clone()),
+                Event::Text("The ".into()),
+                Event::Start(Tag::Emphasis),
+                Event::Text("cat".into()),
+                Event::End(Tag::Emphasis),
+                Event::End(figure),
+            ]);
+            let figure = Tag::Figure(image, None);
+            assert_roundtrip(vec![Event::Start(figure.clone()), Event::End(figure)]);
+        }
+
+        #[test]
+        fn footnotes() {
+            assert_roundtrip(vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text("Claim".into()),
+                Event::Start(Tag::Footnote),
+                Event::Text("The note.".into()),
+                Event::End(Tag::Footnote),
+                Event::Label("footnote-1".into()),
+                Event::Text(" and again".into()),
+                Event::FootnoteReference("footnote-1".into()),
+                Event::Text(".".into()),
+                Event::End(Tag::Paragraph),
+            ]);
+        }
+
+        #[test]
+        fn escaped_text() {
+            let texts = [
+                "= a",
+                "- b",
+                "+ c",
+                "/ d: e",
+                "1. f",
+                "g_h_ *i* $j$ #k `l` <m> @n ~ [o] \\ p",
+                "q // r /* s */ t",
+            ];
+            for text in texts {
+                let input = vec![
+                    Event::Start(Tag::Paragraph),
+                    Event::Text(text.into()),
+                    Event::End(Tag::Paragraph),
+                ];
+                let markup = TypstMarkup::new(input.clone().into_iter()).collect::<String>();
+                assert_eq!(parse(&markup), input, "{markup}");
+            }
+        }
+
+        #[test]
+        fn title_page() {
+            let heading =
+                Tag::Heading(NonZeroU8::MIN, TableOfContents::Exclude, Bookmarks::Exclude);
+            let input = vec![
+                Event::Start(Tag::Align("center + horizon".into())),
+                Event::Start(heading.clone()),
+                Event::Text("Book".into()),
+                Event::End(heading),
+                Event::Start(Tag::Paragraph),
+                Event::Text("About things".into()),
+                Event::End(Tag::Paragraph),
+                Event::End(Tag::Align("center + horizon".into())),
+                Event::PageBreak,
+                Event::Outline(Some("Contents".into()), NonZeroU8::new(2)),
+                Event::PageBreak,
+            ];
+            let markup = TypstMarkup::new(input.clone().into_iter()).collect::<String>();
+            assert_eq!(parse(&markup), input);
+        }
+    }
+}
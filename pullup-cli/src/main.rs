@@ -0,0 +1,1016 @@
+//! Convert between markup formats from the command line.
+//!
+//! ```sh
+//! pullup --from markdown --to typst README.md -o README.typ
+//! cat notes.typ | pullup --from typst --to markdown
+//! pullup --from mdbook --to typst path/to/book --no-images
+//! ```
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use clap::{ArgAction, Args, Parser, ValueEnum};
+use pullup::convert::Errors;
+use pullup::markdown::{self, MarkdownFilter};
+use pullup::mdbook::to::typst::{
+    ChapterNumbering, Conversion, DraftPolicy, PartStyle, Preamble, SeparatorStyle,
+};
+use pullup::typst::{self, TypstFilter, TypstIter};
+use pullup::{IteratorExt, ParserEvent};
+
+/// Convert between markup formats.
+#[derive(Debug, Parser)]
+#[command(name = "pullup", version, about)]
+struct Cli {
+    /// The format of the input.
+    #[arg(long, value_enum)]
+    from: Format,
+    /// The format of the output.
+    #[arg(long, value_enum)]
+    to: OutputFormat,
+    /// The input file, or the book directory for mdBook. Reads from stdin if omitted or
+    /// `-`.
+    input: Option<PathBuf>,
+    /// The output file. Writes to stdout if omitted or `-`.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// What to do with Typst constructs that have no Markdown equivalent.
+    #[arg(long, value_enum, default_value_t = TypstOnly::Drop)]
+    typst_only: TypstOnly,
+    /// How to render draft chapters (mdBook only).
+    #[arg(long, value_enum, default_value_t = Drafts::Title)]
+    drafts: Drafts,
+    /// What to use as the caption of image figures.
+    #[arg(long, value_enum, default_value_t = ImageCaptions::TitleOrAlt)]
+    image_captions: ImageCaptions,
+    /// How to convert links to references without a definition (Markdown only).
+    #[arg(long, value_enum, default_value_t = UnknownLinks::Marker)]
+    unknown_links: UnknownLinks,
+    #[command(flatten)]
+    conversions: Conversions,
+}
+
+/// An input markup format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Markdown,
+    Mdbook,
+    Typst,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::Markdown => "Markdown",
+            Format::Mdbook => "mdBook",
+            Format::Typst => "Typst",
+        }
+    }
+}
+
+/// An output markup format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Markdown,
+    Typst,
+}
+
+impl OutputFormat {
+    fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "Markdown",
+            OutputFormat::Typst => "Typst",
+        }
+    }
+}
+
+/// See [`pullup::typst::to::markdown::TypstOnly`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TypstOnly {
+    /// Drop them.
+    Drop,
+    /// Keep their Typst markup in an HTML comment.
+    Comment,
+}
+
+/// See [`pullup::mdbook::to::typst::DraftPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Drafts {
+    /// Leave them out.
+    Skip,
+    /// Show their title, marked as a draft.
+    Title,
+    /// Only list them in the table of contents.
+    Outline,
+}
+
+/// See [`pullup::markdown::to::typst::ImageCaptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ImageCaptions {
+    /// The image title if it has one, otherwise the alt text.
+    TitleOrAlt,
+    /// Only the image title.
+    Title,
+    /// Only the alt text.
+    Alt,
+    /// No caption.
+    None,
+}
+
+impl ImageCaptions {
+    fn policy(self) -> pullup::markdown::to::typst::ImageCaptions {
+        use pullup::markdown::to::typst::ImageCaptions as Policy;
+        match self {
+            ImageCaptions::TitleOrAlt => Policy::TitleOrAlt,
+            ImageCaptions::Title => Policy::Title,
+            ImageCaptions::Alt => Policy::Alt,
+            ImageCaptions::None => Policy::None,
+        }
+    }
+}
+
+/// See [`pullup::markdown::UnknownLinks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum UnknownLinks {
+    /// Keep the source text of the link as plain text.
+    Text,
+    /// Keep the link content in brackets followed by the reference, like Markdown
+    /// renders it.
+    Marker,
+    /// Fail with an error.
+    Error,
+}
+
+impl UnknownLinks {
+    fn policy(self) -> markdown::UnknownLinks {
+        use markdown::UnknownLinks as Policy;
+        match self {
+            UnknownLinks::Text => Policy::Text,
+            UnknownLinks::Marker => Policy::Marker,
+            UnknownLinks::Error => Policy::Error,
+        }
+    }
+}
+
+/// Constructs to convert. All are converted by default; events of constructs that are
+/// not converted are left out of the output.
+#[derive(Debug, Clone, Args)]
+#[command(next_help_heading = "Conversions")]
+struct Conversions {
+    /// Do not add a title page (mdBook only).
+    #[arg(long = "no-title-page", action = ArgAction::SetFalse)]
+    title_page: bool,
+    /// Do not add a table of contents (mdBook only).
+    #[arg(long = "no-outline", action = ArgAction::SetFalse)]
+    outline: bool,
+    /// Do not convert the book title (mdBook only).
+    #[arg(long = "no-title", action = ArgAction::SetFalse)]
+    title: bool,
+    /// Do not convert the book authors (mdBook only).
+    #[arg(long = "no-authors", action = ArgAction::SetFalse)]
+    authors: bool,
+    /// Do not set up the page and text (mdBook only).
+    #[arg(long = "no-preamble", action = ArgAction::SetFalse)]
+    preamble: bool,
+    /// Do not label headings or convert links between chapters (mdBook only).
+    #[arg(long = "no-labels", action = ArgAction::SetFalse)]
+    labels: bool,
+    /// Do not convert part titles (mdBook only).
+    #[arg(long = "no-parts", action = ArgAction::SetFalse)]
+    parts: bool,
+    /// Do not convert separators (mdBook only).
+    #[arg(long = "no-separators", action = ArgAction::SetFalse)]
+    separators: bool,
+    /// Do not convert chapters to headings (mdBook only).
+    #[arg(long = "no-chapters", action = ArgAction::SetFalse)]
+    chapters: bool,
+    /// Do not number chapters (mdBook only).
+    #[arg(long = "no-chapter-numbering", action = ArgAction::SetFalse)]
+    chapter_numbering: bool,
+    /// Do not convert chapter content (mdBook only).
+    #[arg(long = "no-content", action = ArgAction::SetFalse)]
+    content: bool,
+    /// Do not convert footnotes.
+    #[arg(long = "no-footnotes", action = ArgAction::SetFalse)]
+    footnotes: bool,
+    /// Do not convert headings.
+    #[arg(long = "no-headings", action = ArgAction::SetFalse)]
+    headings: bool,
+    /// Do not convert paragraphs.
+    #[arg(long = "no-paragraphs", action = ArgAction::SetFalse)]
+    paragraphs: bool,
+    /// Do not convert math.
+    #[arg(long = "no-math", action = ArgAction::SetFalse)]
+    math: bool,
+    /// Do not convert soft breaks.
+    #[arg(long = "no-soft-breaks", action = ArgAction::SetFalse)]
+    soft_breaks: bool,
+    /// Do not convert hard breaks.
+    #[arg(long = "no-hard-breaks", action = ArgAction::SetFalse)]
+    hard_breaks: bool,
+    /// Strip hidden lines from Rust code blocks. The default for mdBook.
+    #[arg(long = "hidden-lines", overrides_with = "no_hidden_lines")]
+    hidden_lines: bool,
+    /// Do not strip hidden lines from Rust code blocks. The default for Markdown.
+    #[arg(long = "no-hidden-lines", overrides_with = "hidden_lines")]
+    no_hidden_lines: bool,
+    /// Do not convert text.
+    #[arg(long = "no-text", action = ArgAction::SetFalse)]
+    text: bool,
+    /// Do not convert strong text.
+    #[arg(long = "no-strong", action = ArgAction::SetFalse)]
+    strong: bool,
+    /// Do not convert emphasized text.
+    #[arg(long = "no-emphasis", action = ArgAction::SetFalse)]
+    emphasis: bool,
+    /// Do not convert strikethrough.
+    #[arg(long = "no-strikethrough", action = ArgAction::SetFalse)]
+    strikethrough: bool,
+    /// Do not convert task list markers.
+    #[arg(long = "no-task-lists", action = ArgAction::SetFalse)]
+    task_lists: bool,
+    /// Do not convert block quotes.
+    #[arg(long = "no-blockquotes", action = ArgAction::SetFalse)]
+    blockquotes: bool,
+    /// Do not convert lists.
+    #[arg(long = "no-lists", action = ArgAction::SetFalse)]
+    lists: bool,
+    /// Do not convert code.
+    #[arg(long = "no-code", action = ArgAction::SetFalse)]
+    code: bool,
+    /// Do not convert links.
+    #[arg(long = "no-links", action = ArgAction::SetFalse)]
+    links: bool,
+    /// Do not convert images.
+    #[arg(long = "no-images", action = ArgAction::SetFalse)]
+    images: bool,
+    /// Do not convert tables.
+    #[arg(long = "no-tables", action = ArgAction::SetFalse)]
+    tables: bool,
+}
+
+/// The flags that apply when converting Markdown to Typst, also as chapter content of
+/// a book.
+const MARKDOWN_TO_TYPST: &[&str] = &[
+    "--no-footnotes",
+    "--no-headings",
+    "--no-paragraphs",
+    "--no-math",
+    "--no-soft-breaks",
+    "--no-hard-breaks",
+    "--hidden-lines",
+    "--no-hidden-lines",
+    "--no-text",
+    "--no-strong",
+    "--no-emphasis",
+    "--no-strikethrough",
+    "--no-task-lists",
+    "--no-blockquotes",
+    "--no-lists",
+    "--no-code",
+    "--no-links",
+    "--no-images",
+    "--no-tables",
+    "--image-captions",
+];
+
+/// The flags that only apply when converting an mdBook to Typst.
+const MDBOOK_TO_TYPST: &[&str] = &[
+    "--no-title-page",
+    "--no-outline",
+    "--no-title",
+    "--no-authors",
+    "--no-preamble",
+    "--no-labels",
+    "--no-parts",
+    "--no-separators",
+    "--no-chapters",
+    "--no-chapter-numbering",
+    "--no-content",
+    "--drafts",
+];
+
+/// The flags that apply when converting Typst to Markdown.
+const TYPST_TO_MARKDOWN: &[&str] = &[
+    "--no-footnotes",
+    "--no-headings",
+    "--no-paragraphs",
+    "--no-math",
+    "--no-hard-breaks",
+    "--no-text",
+    "--no-strong",
+    "--no-emphasis",
+    "--no-strikethrough",
+    "--no-task-lists",
+    "--no-blockquotes",
+    "--no-lists",
+    "--no-code",
+    "--no-links",
+    "--no-images",
+    "--no-tables",
+    "--typst-only",
+];
+
+impl Cli {
+    /// Fail if a flag is used that does not apply when converting between the input
+    /// and output formats. Value flags count as used when they are not the default.
+    fn check(&self) -> anyhow::Result<()> {
+        let c = &self.conversions;
+        let flags = [
+            (!c.title_page, "--no-title-page"),
+            (!c.outline, "--no-outline"),
+            (!c.title, "--no-title"),
+            (!c.authors, "--no-authors"),
+            (!c.preamble, "--no-preamble"),
+            (!c.labels, "--no-labels"),
+            (!c.parts, "--no-parts"),
+            (!c.separators, "--no-separators"),
+            (!c.chapters, "--no-chapters"),
+            (!c.chapter_numbering, "--no-chapter-numbering"),
+            (!c.content, "--no-content"),
+            (!c.footnotes, "--no-footnotes"),
+            (!c.headings, "--no-headings"),
+            (!c.paragraphs, "--no-paragraphs"),
+            (!c.math, "--no-math"),
+            (!c.soft_breaks, "--no-soft-breaks"),
+            (!c.hard_breaks, "--no-hard-breaks"),
+            (c.hidden_lines, "--hidden-lines"),
+            (c.no_hidden_lines, "--no-hidden-lines"),
+            (!c.text, "--no-text"),
+            (!c.strong, "--no-strong"),
+            (!c.emphasis, "--no-emphasis"),
+            (!c.strikethrough, "--no-strikethrough"),
+            (!c.task_lists, "--no-task-lists"),
+            (!c.blockquotes, "--no-blockquotes"),
+            (!c.lists, "--no-lists"),
+            (!c.code, "--no-code"),
+            (!c.links, "--no-links"),
+            (!c.images, "--no-images"),
+            (!c.tables, "--no-tables"),
+            (self.typst_only != TypstOnly::Drop, "--typst-only"),
+            (self.drafts != Drafts::Title, "--drafts"),
+            (
+                self.image_captions != ImageCaptions::TitleOrAlt,
+                "--image-captions",
+            ),
+            (
+                self.unknown_links != UnknownLinks::Marker,
+                "--unknown-links",
+            ),
+        ];
+        // mdBook does not report links to undefined references, it keeps them as text.
+        let supported: &[&[&str]] = match (self.from, self.to) {
+            (Format::Markdown, OutputFormat::Typst) => &[MARKDOWN_TO_TYPST, &["--unknown-links"]],
+            (Format::Mdbook, OutputFormat::Typst) => &[MARKDOWN_TO_TYPST, MDBOOK_TO_TYPST],
+            (Format::Typst, OutputFormat::Markdown) => &[TYPST_TO_MARKDOWN],
+            // Converting to Markdown keeps every construct, including hidden lines.
+            (Format::Markdown, OutputFormat::Markdown) => &[&["--no-hidden-lines"]],
+            (Format::Mdbook, OutputFormat::Markdown) => &[&["--no-title", "--no-hidden-lines"]],
+            (Format::Typst, OutputFormat::Typst) => &[],
+        };
+        let unsupported = flags
+            .into_iter()
+            .filter(|(used, flag)| *used && !supported.iter().any(|s| s.contains(flag)))
+            .map(|(_, flag)| flag)
+            .collect::<Vec<_>>();
+        if !unsupported.is_empty() {
+            bail!(
+                "{} cannot be used when converting {} to {}",
+                unsupported.join(", "),
+                self.from.name(),
+                self.to.name()
+            );
+        }
+        Ok(())
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    cli.check()?;
+    let input = cli.input.as_deref().filter(|p| *p != Path::new("-"));
+    let output = match (cli.from, input) {
+        (Format::Mdbook, Some(dir)) => {
+            let book = load_book(dir)?;
+            match cli.to {
+                OutputFormat::Typst => mdbook_to_typst(&cli, &book)?,
+                OutputFormat::Markdown => mdbook_to_markdown(&cli, &book)?,
+            }
+        }
+        (Format::Mdbook, None) => bail!("mdBook input must be a book directory"),
+        (_, Some(path)) => {
+            let source = fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            convert(&cli, &source)?
+        }
+        (_, None) => {
+            let mut source = String::new();
+            io::stdin()
+                .read_to_string(&mut source)
+                .context("failed to read stdin")?;
+            convert(&cli, &source)?
+        }
+    };
+    match cli.output.as_deref().filter(|p| *p != Path::new("-")) {
+        Some(path) => {
+            fs::write(path, output).with_context(|| format!("failed to write {}", path.display()))
+        }
+        None => io::stdout()
+            .write_all(output.as_bytes())
+            .context("failed to write stdout"),
+    }
+}
+
+/// The Markdown extensions Markdown input is parsed with.
+const MARKDOWN_OPTIONS: markdown::Options = markdown::Options::ENABLE_TABLES
+    .union(markdown::Options::ENABLE_FOOTNOTES)
+    .union(markdown::Options::ENABLE_STRIKETHROUGH)
+    .union(markdown::Options::ENABLE_TASKLISTS)
+    .union(markdown::Options::ENABLE_HEADING_ATTRIBUTES);
+
+/// Convert a Markdown or Typst source to the output format.
+fn convert(cli: &Cli, source: &str) -> anyhow::Result<String> {
+    match (cli.from, cli.to) {
+        (Format::Markdown, OutputFormat::Typst) => {
+            // Accept links to undefined references so the unknown links policy applies.
+            let mut unknown = markdown::unknown_reference;
+            let mut unknown_in_definitions = markdown::unknown_reference;
+            let errors = Errors::new();
+            let events = markdown::UnknownReferences::new(
+                source,
+                markdown::FootnotesFirst::new_with_broken_link_callbacks(
+                    source,
+                    MARKDOWN_OPTIONS,
+                    Some(&mut unknown_in_definitions),
+                    Some(&mut unknown),
+                )
+                .into_offset_iter(),
+                cli.unknown_links.policy(),
+                errors.clone(),
+            );
+            let c = &cli.conversions;
+            let events = markdown::to::typst::Conversion::builder()
+                .events(events)
+                .footnotes(c.footnotes)
+                .headings(c.headings)
+                .paragraphs(c.paragraphs)
+                .math(c.math)
+                .soft_breaks(c.soft_breaks)
+                .hard_breaks(c.hard_breaks)
+                .hidden_lines(c.hidden_lines)
+                .text(c.text)
+                .strong(c.strong)
+                .emphasis(c.emphasis)
+                .strikethrough(c.strikethrough)
+                .task_lists(c.task_lists)
+                .blockquotes(c.blockquotes)
+                .lists(c.lists)
+                .code(c.code)
+                .links(c.links)
+                .images(c.images)
+                .image_captions(cli.image_captions.policy())
+                .tables(c.tables)
+                .build();
+            let mut error = None;
+            let markup = typst_markup(
+                errors
+                    .check(events)
+                    .map_while(|e| e.map_err(|e| error = Some(e)).ok().map(ParserEvent::Typst)),
+            )?;
+            match error {
+                Some(e) => Err(e.into()),
+                None => Ok(markup),
+            }
+        }
+        (Format::Markdown, OutputFormat::Markdown) => {
+            let mut markup = String::new();
+            markdown::to::markup::write_markup(
+                &mut markup,
+                markdown::Parser::new_ext(source, MARKDOWN_OPTIONS),
+            )?;
+            Ok(markup)
+        }
+        (Format::Typst, OutputFormat::Typst) => typst_markup(TypstIter(typst::Parser::new(source))),
+        (Format::Typst, OutputFormat::Markdown) => {
+            let events = TypstIter(typst::Parser::new(source));
+            let events = typst_to_markdown(events, &cli.conversions, cli.typst_only);
+            let mut markup = String::new();
+            markdown::to::markup::write_markup(&mut markup, MarkdownFilter(events))?;
+            Ok(markup)
+        }
+        (Format::Mdbook, _) => bail!("mdBook input must be a book directory"),
+    }
+}
+
+/// Load the book in `dir`.
+fn load_book(dir: &Path) -> anyhow::Result<mdbook::MDBook> {
+    mdbook::MDBook::load(dir).with_context(|| format!("failed to load book from {}", dir.display()))
+}
+
+/// Convert an mdBook to Markdown: the book title followed by the content of each
+/// chapter.
+fn mdbook_to_markdown(cli: &Cli, book: &mdbook::MDBook) -> anyhow::Result<String> {
+    use pullup::markdown::{Event as MdEvent, HeadingLevel, Tag as MdTag};
+    use pullup::mdbook::Event;
+
+    let expanded = pullup::mdbook::links::ExpandedBook::new(book);
+    let events =
+        pullup::mdbook::Parser::from_expanded(book, &expanded).flat_map(|event| match event {
+            Event::Title(title) if cli.conversions.title => {
+                let heading = MdTag::Heading(HeadingLevel::H1, None, vec![]);
+                vec![
+                    MdEvent::Start(heading.clone()),
+                    MdEvent::Text(title),
+                    MdEvent::End(heading),
+                ]
+            }
+            Event::MarkdownContentEvent(event) => vec![event],
+            _ => vec![],
+        });
+    let mut markup = String::new();
+    markdown::to::markup::write_markup(&mut markup, events)?;
+    Ok(markup)
+}
+
+/// Convert an mdBook to Typst.
+fn mdbook_to_typst(cli: &Cli, book: &mdbook::MDBook) -> anyhow::Result<String> {
+    let expanded = pullup::mdbook::links::ExpandedBook::new(book);
+    let c = &cli.conversions;
+    let events = Conversion::builder()
+        .events(pullup::mdbook::Parser::from_expanded(book, &expanded))
+        .title_page(c.title_page)
+        .outline(c.outline)
+        .title(c.title)
+        .authors(c.authors)
+        .preamble(c.preamble.then(Preamble::default))
+        .labels(c.labels)
+        .parts(c.parts.then(PartStyle::default))
+        .separators(c.separators.then(SeparatorStyle::default))
+        .chapters(c.chapters)
+        .chapter_numbering(c.chapter_numbering.then(ChapterNumbering::default))
+        .drafts(match cli.drafts {
+            Drafts::Skip => DraftPolicy::Skip,
+            Drafts::Title => DraftPolicy::default(),
+            Drafts::Outline => DraftPolicy::Outline,
+        })
+        .content(c.content)
+        .footnotes(c.footnotes)
+        .headings(c.headings)
+        .paragraphs(c.paragraphs)
+        .math(c.math)
+        .soft_breaks(c.soft_breaks)
+        .hard_breaks(c.hard_breaks)
+        .hidden_lines(!c.no_hidden_lines)
+        .text(c.text)
+        .strong(c.strong)
+        .emphasis(c.emphasis)
+        .strikethrough(c.strikethrough)
+        .task_lists(c.task_lists)
+        .blockquotes(c.blockquotes)
+        .lists(c.lists)
+        .code(c.code)
+        .links(c.links)
+        .images(c.images)
+        .image_captions(cli.image_captions.policy())
+        .tables(c.tables)
+        .build();
+    typst_markup(events)
+}
+
+/// Apply the Typst to Markdown converters. Code is converted before text as it collects
+/// the text of inline raw blocks.
+fn typst_to_markdown<'a>(
+    events: impl Iterator<Item = ParserEvent<'a>> + 'a,
+    c: &Conversions,
+    typst_only: TypstOnly,
+) -> impl Iterator<Item = ParserEvent<'a>> + 'a {
+    use pullup::typst::to::markdown::{
+        ConvertBreaks, ConvertCode, ConvertEmphasis, ConvertFigures, ConvertFootnotes,
+        ConvertHeadings, ConvertImages, ConvertLines, ConvertLinks, ConvertLists, ConvertMath,
+        ConvertParagraphs, ConvertQuotes, ConvertStrikethrough, ConvertStrong, ConvertTables,
+        ConvertTaskLists, ConvertText, ConvertTypstOnly,
+    };
+
+    let policy = match typst_only {
+        TypstOnly::Drop => pullup::typst::to::markdown::TypstOnly::Drop,
+        TypstOnly::Comment => pullup::typst::to::markdown::TypstOnly::Comment,
+    };
+    events
+        .convert_with(c.footnotes.then(ConvertFootnotes::new))
+        .convert_with(c.images.then(ConvertFigures::new))
+        .convert_with(c.code.then(ConvertCode::new))
+        .convert_with(c.blockquotes.then(ConvertQuotes::new))
+        .convert_with(c.tables.then(ConvertTables::new))
+        .convert_with(c.headings.then(ConvertHeadings::new))
+        .convert_with(c.lists.then(ConvertLists::new))
+        .convert_with(c.task_lists.then(ConvertTaskLists::new))
+        .convert_with(c.links.then(ConvertLinks::new))
+        .convert_with(c.images.then(ConvertImages::new))
+        .convert_with(c.math.then(ConvertMath::new))
+        .convert_with(c.strong.then(ConvertStrong::new))
+        .convert_with(c.emphasis.then(ConvertEmphasis::new))
+        .convert_with(c.strikethrough.then(ConvertStrikethrough::new))
+        .convert_with(c.paragraphs.then(ConvertParagraphs::new))
+        .convert_with(c.hard_breaks.then(ConvertBreaks::new))
+        .convert_with(ConvertLines::new())
+        .convert_with(c.text.then(ConvertText::new))
+        .convert_with(ConvertTypstOnly::new(policy))
+}
+
+/// Generate Typst markup from the Typst events, leaving out any other events.
+fn typst_markup<'a>(events: impl Iterator<Item = ParserEvent<'a>>) -> anyhow::Result<String> {
+    let mut markup = String::new();
+    typst::to::markup::try_write_markup(&mut markup, TypstFilter(events))?;
+    Ok(markup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str], source: &str) -> String {
+        let cli = Cli::try_parse_from([&["pullup"], args].concat()).unwrap();
+        convert(&cli, source).unwrap()
+    }
+
+    #[test]
+    fn markdown_to_typst() {
+        assert_eq!(
+            run(
+                &["--from", "markdown", "--to", "typst"],
+                "# Hi\n\nSome **bold** text."
+            ),
+            "= Hi\n#par()[Some #strong[bold] text.]\n"
+        );
+    }
+
+    #[test]
+    fn typst_to_markdown() {
+        assert_eq!(
+            run(
+                &["--from", "typst", "--to", "markdown"],
+                "= Hi\n\nSome *bold* text."
+            ),
+            "# Hi\n\nSome **bold** text.\n"
+        );
+        assert_eq!(
+            run(
+                &["--from", "typst", "--to", "markdown"],
+                "See #image(\"a.png\", alt: \"A\")."
+            ),
+            "See ![A](a.png).\n"
+        );
+        assert_eq!(
+            run(
+                &["--from", "typst", "--to", "markdown"],
+                "Text#footnote[The note.] <footnote-1> #strike[gone] $x^2$.\n\n\
+                 #figure(image(\"c.png\", alt: \"cat\"), caption: [Cat])"
+            ),
+            "Text[^footnote-1] ~~gone~~ $x^2$.\n\n[^footnote-1]: The note.\n\n\
+             ![cat](c.png \"Cat\")\n"
+        );
+    }
+
+    #[test]
+    fn toggles() {
+        assert_eq!(
+            run(
+                &["--from", "markdown", "--to", "typst", "--no-strong"],
+                "Some **bold** text."
+            ),
+            "#par()[Some bold text.]\n"
+        );
+    }
+
+    #[test]
+    fn hidden_lines() {
+        let md = "```rust\n# fn main() {}\nlet x = 1;\n```";
+        assert_eq!(
+            run(&["--from", "markdown", "--to", "typst"], md),
+            "```rust\n# fn main() {}\nlet x = 1;\n```\n"
+        );
+        assert_eq!(
+            run(
+                &["--from", "markdown", "--to", "typst", "--hidden-lines"],
+                md
+            ),
+            "```rust\nlet x = 1;\n```\n"
+        );
+    }
+
+    #[test]
+    fn image_captions() {
+        let md = "![A cat](cat.png \"My cat\")";
+        assert_eq!(
+            run(&["--from", "markdown", "--to", "typst"], md),
+            "#figure(image(\"cat.png\", alt: \"A cat\"), caption: [My cat])\n"
+        );
+        assert_eq!(
+            run(
+                &[
+                    "--from",
+                    "markdown",
+                    "--to",
+                    "typst",
+                    "--image-captions",
+                    "none"
+                ],
+                md
+            ),
+            "#figure(image(\"cat.png\", alt: \"A cat\"))\n"
+        );
+    }
+
+    #[test]
+    fn unknown_links() {
+        let md = "See [*it*][Missing] and [^nope].";
+        let args = ["--from", "markdown", "--to", "typst"];
+        assert_eq!(
+            run(&args, md),
+            "#par()[See \\[#emph[it]\\]\\[Missing\\] and \\[^nope\\].]\n"
+        );
+        assert_eq!(
+            run(&[&args[..], &["--unknown-links", "text"]].concat(), md),
+            "#par()[See \\[\\*it\\*\\]\\[Missing\\] and \\[^nope\\].]\n"
+        );
+        let cli =
+            Cli::try_parse_from([&["pullup"], &args[..], &["--unknown-links", "error"]].concat())
+                .unwrap();
+        let err = convert(&cli, md).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("link to unknown reference at offset 4"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn brackets_are_not_unknown_links() {
+        let md = "Use a[0] here and [sic].";
+        for policy in ["text", "marker", "error"] {
+            assert_eq!(
+                run(
+                    &[
+                        "--from",
+                        "markdown",
+                        "--to",
+                        "typst",
+                        "--unknown-links",
+                        policy
+                    ],
+                    md
+                ),
+                "#par()[Use a\\[0\\] here and \\[sic\\].]\n"
+            );
+        }
+    }
+
+    #[test]
+    fn same_format() {
+        assert_eq!(
+            run(
+                &["--from", "markdown", "--to", "markdown"],
+                "# Hi\n\nText[^1].\n\n[^1]: Note"
+            ),
+            "# Hi\n\nText[^1].\n\n[^1]: Note\n"
+        );
+        assert_eq!(
+            run(
+                &["--from", "typst", "--to", "typst"],
+                "= Hi\n\nSome *bold* text."
+            ),
+            "= Hi\n#par()[Some #strong[bold] text.]\n"
+        );
+    }
+
+    #[test]
+    fn markdown_to_markdown_toggles() {
+        let cli = Cli::try_parse_from([
+            "pullup",
+            "--from",
+            "markdown",
+            "--to",
+            "markdown",
+            "--no-strong",
+            "--hidden-lines",
+        ])
+        .unwrap();
+        let err = cli.check().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--hidden-lines, --no-strong cannot be used when converting Markdown to Markdown"
+        );
+        assert_eq!(
+            run(
+                &[
+                    "--from",
+                    "markdown",
+                    "--to",
+                    "markdown",
+                    "--no-hidden-lines"
+                ],
+                "Some **bold** text."
+            ),
+            "Some **bold** text.\n"
+        );
+    }
+
+    /// A book whose index page sets a `{{#title}}` and whose second chapter includes a
+    /// file next to it.
+    fn book() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(dir.path().join("book.toml"), "[book]\ntitle = \"Book\"\n").unwrap();
+        fs::write(
+            src.join("SUMMARY.md"),
+            "- [Intro](intro.md)\n- [Sub](sub/ch.md)\n",
+        )
+        .unwrap();
+        fs::write(
+            src.join("intro.md"),
+            "{{#title Index}}\n# Intro\n\nHello.\n",
+        )
+        .unwrap();
+        fs::write(src.join("sub/ch.md"), "# Sub\n\n{{#include inc.txt}}\n").unwrap();
+        fs::write(src.join("sub/inc.txt"), "Included").unwrap();
+        dir
+    }
+
+    #[test]
+    fn mdbook_to_markdown() {
+        let dir = book();
+        let cli = Cli::try_parse_from(["pullup", "--from", "mdbook", "--to", "markdown"]).unwrap();
+        let book = load_book(dir.path()).unwrap();
+        assert_eq!(
+            super::mdbook_to_markdown(&cli, &book).unwrap(),
+            "# Book\n\n# Intro\n\nHello.\n\n# Sub\n\nIncluded\n"
+        );
+
+        let cli = Cli::try_parse_from([
+            "pullup",
+            "--from",
+            "mdbook",
+            "--to",
+            "markdown",
+            "--no-title",
+        ])
+        .unwrap();
+        assert_eq!(
+            super::mdbook_to_markdown(&cli, &book).unwrap(),
+            "# Intro\n\nHello.\n\n# Sub\n\nIncluded\n"
+        );
+        let cli = Cli::try_parse_from([
+            "pullup",
+            "--from",
+            "mdbook",
+            "--to",
+            "markdown",
+            "--no-tables",
+        ])
+        .unwrap();
+        assert!(cli.check().is_err());
+    }
+
+    #[test]
+    fn mdbook_page_title_is_not_book_title() {
+        let dir = book();
+        let cli = Cli::try_parse_from(["pullup", "--from", "mdbook", "--to", "typst"]).unwrap();
+        let book = load_book(dir.path()).unwrap();
+        let typst = super::mdbook_to_typst(&cli, &book).unwrap();
+        assert!(
+            typst.starts_with("#set document(title: \"Book\")"),
+            "{typst}"
+        );
+    }
+
+    /// The error for flags that do not apply when converting between the formats.
+    fn rejected(args: &[&str]) -> Option<String> {
+        let cli = Cli::try_parse_from([&["pullup"], args].concat()).unwrap();
+        cli.check().err().map(|e| e.to_string())
+    }
+
+    #[test]
+    fn rejected_flags() {
+        assert_eq!(
+            rejected(&["--from", "typst", "--to", "markdown", "--no-title-page", "--no-soft-breaks"]),
+            Some(
+                "--no-title-page, --no-soft-breaks cannot be used when converting Typst to Markdown"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            rejected(&[
+                "--from",
+                "typst",
+                "--to",
+                "markdown",
+                "--image-captions",
+                "alt"
+            ]),
+            Some("--image-captions cannot be used when converting Typst to Markdown".to_string())
+        );
+        assert_eq!(
+            rejected(&[
+                "--from",
+                "mdbook",
+                "--to",
+                "typst",
+                "--unknown-links",
+                "error"
+            ]),
+            Some("--unknown-links cannot be used when converting mdBook to Typst".to_string())
+        );
+        assert_eq!(
+            rejected(&[
+                "--from",
+                "markdown",
+                "--to",
+                "typst",
+                "--no-chapters",
+                "--drafts",
+                "skip"
+            ]),
+            Some(
+                "--no-chapters, --drafts cannot be used when converting Markdown to Typst"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            rejected(&[
+                "--from",
+                "markdown",
+                "--to",
+                "typst",
+                "--typst-only",
+                "comment"
+            ]),
+            Some("--typst-only cannot be used when converting Markdown to Typst".to_string())
+        );
+        assert_eq!(
+            rejected(&["--from", "typst", "--to", "typst", "--no-strong"]),
+            Some("--no-strong cannot be used when converting Typst to Typst".to_string())
+        );
+    }
+
+    #[test]
+    fn accepted_flags() {
+        assert_eq!(
+            rejected(&[
+                "--from",
+                "markdown",
+                "--to",
+                "typst",
+                "--no-strong",
+                "--unknown-links",
+                "text"
+            ]),
+            None
+        );
+        assert_eq!(
+            rejected(&[
+                "--from",
+                "mdbook",
+                "--to",
+                "typst",
+                "--no-title-page",
+                "--drafts",
+                "skip",
+                "--no-tables"
+            ]),
+            None
+        );
+        assert_eq!(
+            rejected(&[
+                "--from",
+                "typst",
+                "--to",
+                "markdown",
+                "--typst-only",
+                "comment",
+                "--no-math"
+            ]),
+            None
+        );
+        // Flags set to their default are not rejected.
+        assert_eq!(
+            rejected(&[
+                "--from",
+                "mdbook",
+                "--to",
+                "typst",
+                "--unknown-links",
+                "marker"
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(Cli::try_parse_from(["pullup", "--from", "html", "--to", "typst"]).is_err());
+        assert!(Cli::try_parse_from(["pullup", "--from", "typst", "--to", "mdbook"]).is_err());
+    }
+}
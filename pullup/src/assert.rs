@@ -1,9 +1,10 @@
-//! Iterator adaptors that convert markup-specific iterators into iterators that emit
-//! [`ParserEvent`](crate::ParserEvent).
+//! Iterator adaptors that convert [`ParserEvent`](crate::ParserEvent) iterators to
+//! markup-specific iterators, either panicking or reporting an [`Error`](crate::Error)
+//! when an event of another markup format is encountered.
 
 #[cfg(feature = "markdown")]
-pub use crate::markdown::AssertMarkdown;
+pub use crate::markdown::{AssertMarkdown, TryAssertMarkdown};
 #[cfg(feature = "mdbook")]
-pub use crate::mdbook::AssertMdbook;
+pub use crate::mdbook::{AssertMdbook, TryAssertMdbook};
 #[cfg(feature = "typst")]
-pub use crate::typst::AssertTypst;
+pub use crate::typst::{AssertTypst, TryAssertTypst};
@@ -0,0 +1,490 @@
+//! Write conversion passes and compose them into pipelines.
+//!
+//! A [`Converter`] is handed one event at a time and emits any number of events in its
+//! place. It can look ahead at, and consume, the events that follow through its
+//! [`Input`]. Run a converter over an event iterator with
+//! [`IteratorExt::convert_with`]; chaining calls builds a pipeline whose stages run in
+//! the order they are written, without boxing each stage:
+//!
+//! ```
+//! # #[cfg(feature = "markdown")]
+//! # {
+//! use pullup::convert::{self, Input, Output};
+//! use pullup::markdown::{Event, MarkdownIter, Parser};
+//! use pullup::{IteratorExt, ParserEvent};
+//!
+//! // Shout all text.
+//! let upper = convert::from_fn(|event, _: &mut Input, out: &mut Output| match event {
+//!     ParserEvent::Markdown(Event::Text(t)) => {
+//!         out.emit(ParserEvent::Markdown(Event::Text(t.to_uppercase().into())))
+//!     }
+//!     e => out.emit(e),
+//! });
+//! // Drop paragraphs, keeping their content.
+//! let unwrap = convert::from_fn(|event, _: &mut Input, out: &mut Output| match event {
+//!     ParserEvent::Markdown(Event::Start(_) | Event::End(_)) => {}
+//!     e => out.emit(e),
+//! });
+//!
+//! let events = MarkdownIter(Parser::new("hi"))
+//!     .convert_with(upper)
+//!     .convert_with(unwrap)
+//!     .collect::<Vec<_>>();
+//! assert_eq!(events, vec![ParserEvent::Markdown(Event::Text("HI".into()))]);
+//! # }
+//! ```
+//!
+//! Converters that can fail report to an [`Errors`] handle, which yields the errors
+//! alongside the events at the end of the pipeline.
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::{Error, ParserEvent};
+
+/// A conversion pass over [`ParserEvent`]s.
+///
+/// Events that a converter does not handle should be emitted unchanged so later passes
+/// see them.
+pub trait Converter<'a> {
+    /// Convert `event`, emitting the result to `output`. Events that follow can be
+    /// inspected or consumed through `input`.
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    );
+
+    /// Called once the input is exhausted, to emit any events the converter is still
+    /// holding on to.
+    fn finish(&mut self, output: &mut Output<'_, 'a>) {
+        let _ = output;
+    }
+}
+
+impl<'a, C> Converter<'a> for &mut C
+where
+    C: Converter<'a> + ?Sized,
+{
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        (**self).convert(event, input, output)
+    }
+
+    fn finish(&mut self, output: &mut Output<'_, 'a>) {
+        (**self).finish(output)
+    }
+}
+
+impl<'a, C> Converter<'a> for Box<C>
+where
+    C: Converter<'a> + ?Sized,
+{
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        (**self).convert(event, input, output)
+    }
+
+    fn finish(&mut self, output: &mut Output<'_, 'a>) {
+        (**self).finish(output)
+    }
+}
+
+/// An optional pass. `None` passes all events through unchanged, which makes it easy to
+/// turn passes on and off without changing the type of the pipeline.
+impl<'a, C> Converter<'a> for Option<C>
+where
+    C: Converter<'a>,
+{
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match self {
+            Some(c) => c.convert(event, input, output),
+            None => output.emit(event),
+        }
+    }
+
+    fn finish(&mut self, output: &mut Output<'_, 'a>) {
+        if let Some(c) = self {
+            c.finish(output)
+        }
+    }
+}
+
+/// The events following the one being converted.
+///
+/// Iterating consumes events so they are not passed to the converter again. Peeking
+/// leaves them in place.
+pub struct Input<'i, 'a> {
+    lookahead: &'i mut VecDeque<ParserEvent<'a>>,
+    iter: &'i mut dyn Iterator<Item = ParserEvent<'a>>,
+}
+
+impl<'i, 'a> Input<'i, 'a> {
+    /// Look at the next event without consuming it.
+    pub fn peek(&mut self) -> Option<&ParserEvent<'a>> {
+        self.peek_nth(0)
+    }
+
+    /// Look at the event `n` places ahead without consuming it. `peek_nth(0)` is the
+    /// same as [`peek`](Self::peek).
+    pub fn peek_nth(&mut self, n: usize) -> Option<&ParserEvent<'a>> {
+        while self.lookahead.len() <= n {
+            let event = self.iter.next()?;
+            self.lookahead.push_back(event);
+        }
+        self.lookahead.get(n)
+    }
+
+    /// Consume the next event if `f` returns `true` for it.
+    pub fn next_if(&mut self, f: impl FnOnce(&ParserEvent<'a>) -> bool) -> Option<ParserEvent<'a>> {
+        if f(self.peek()?) {
+            self.next()
+        } else {
+            None
+        }
+    }
+}
+
+impl<'i, 'a> Iterator for Input<'i, 'a> {
+    type Item = ParserEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lookahead.pop_front().or_else(|| self.iter.next())
+    }
+}
+
+/// Where a converter emits its events. Events are yielded in the order they are
+/// emitted.
+pub struct Output<'o, 'a>(&'o mut VecDeque<ParserEvent<'a>>);
+
+impl<'o, 'a> Output<'o, 'a> {
+    /// Emit a single event.
+    pub fn emit(&mut self, event: ParserEvent<'a>) {
+        self.0.push_back(event);
+    }
+
+    /// Emit several events.
+    pub fn emit_many(&mut self, events: impl IntoIterator<Item = ParserEvent<'a>>) {
+        self.0.extend(events);
+    }
+}
+
+impl<'o, 'a> Extend<ParserEvent<'a>> for Output<'o, 'a> {
+    fn extend<T: IntoIterator<Item = ParserEvent<'a>>>(&mut self, iter: T) {
+        self.emit_many(iter);
+    }
+}
+
+/// An iterator that runs a [`Converter`] over events. Created by
+/// [`IteratorExt::convert_with`].
+#[derive(Debug, Clone)]
+pub struct Convert<'a, I, C> {
+    iter: I,
+    converter: C,
+    lookahead: VecDeque<ParserEvent<'a>>,
+    output: VecDeque<ParserEvent<'a>>,
+    finished: bool,
+}
+
+impl<'a, I, C> Convert<'a, I, C>
+where
+    I: Iterator<Item = ParserEvent<'a>>,
+    C: Converter<'a>,
+{
+    pub fn new(iter: I, converter: C) -> Self {
+        Self {
+            iter,
+            converter,
+            lookahead: VecDeque::new(),
+            output: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+impl<'a, I, C> Iterator for Convert<'a, I, C>
+where
+    I: Iterator<Item = ParserEvent<'a>>,
+    C: Converter<'a>,
+{
+    type Item = ParserEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.output.pop_front() {
+                return Some(event);
+            }
+            if self.finished {
+                return None;
+            }
+            let mut output = Output(&mut self.output);
+            match self.lookahead.pop_front().or_else(|| self.iter.next()) {
+                Some(event) => {
+                    let mut input = Input {
+                        lookahead: &mut self.lookahead,
+                        iter: &mut self.iter,
+                    };
+                    self.converter.convert(event, &mut input, &mut output);
+                }
+                None => {
+                    self.finished = true;
+                    self.converter.finish(&mut output);
+                }
+            }
+        }
+    }
+}
+
+/// Errors reported by converters in a pipeline.
+///
+/// Clones share the same errors, so a converter can report to one clone while
+/// [`check`](Errors::check) yields them at the end of the pipeline.
+#[derive(Debug, Clone, Default)]
+pub struct Errors(Rc<RefCell<VecDeque<Error>>>);
+
+impl Errors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report an error.
+    pub fn report(&self, error: Error) {
+        self.0.borrow_mut().push_back(error);
+    }
+
+    fn take(&self) -> Option<Error> {
+        self.0.borrow_mut().pop_front()
+    }
+
+    /// Yield the items of `iter` as `Ok`, and each error reported while producing an
+    /// item as an `Err` before it.
+    pub fn check<I>(self, iter: I) -> Checked<I>
+    where
+        I: Iterator,
+    {
+        Checked {
+            iter: iter.fuse(),
+            errors: self,
+            pending: None,
+        }
+    }
+}
+
+/// An iterator over events and the errors reported while producing them. Created by
+/// [`Errors::check`].
+#[derive(Debug)]
+pub struct Checked<I: Iterator> {
+    iter: core::iter::Fuse<I>,
+    errors: Errors,
+    /// The item produced along with the error that was just yielded.
+    pending: Option<I::Item>,
+}
+
+impl<I> Iterator for Checked<I>
+where
+    I: Iterator,
+{
+    type Item = Result<I::Item, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.errors.take() {
+            return Some(Err(error));
+        }
+        let item = self.pending.take().or_else(|| self.iter.next());
+        match self.errors.take() {
+            Some(error) => {
+                self.pending = item;
+                Some(Err(error))
+            }
+            None => item.map(Ok),
+        }
+    }
+}
+
+/// Create a [`Converter`] from a closure.
+pub fn from_fn<'a, F>(f: F) -> FromFn<F>
+where
+    F: FnMut(ParserEvent<'a>, &mut Input<'_, 'a>, &mut Output<'_, 'a>),
+{
+    FromFn(f)
+}
+
+/// A [`Converter`] created from a closure by [`from_fn`].
+#[derive(Debug, Clone)]
+pub struct FromFn<F>(F);
+
+impl<'a, F> Converter<'a> for FromFn<F>
+where
+    F: FnMut(ParserEvent<'a>, &mut Input<'_, 'a>, &mut Output<'_, 'a>),
+{
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        (self.0)(event, input, output)
+    }
+}
+
+/// Extends [`ParserEvent`] iterators with [`convert_with`](IteratorExt::convert_with).
+pub trait IteratorExt<'a>: Iterator<Item = ParserEvent<'a>> + Sized {
+    /// Run `converter` over the events of this iterator.
+    fn convert_with<C>(self, converter: C) -> Convert<'a, Self, C>
+    where
+        C: Converter<'a>,
+    {
+        Convert::new(self, converter)
+    }
+}
+
+impl<'a, T> IteratorExt<'a> for T where T: Iterator<Item = ParserEvent<'a>> {}
+
+#[cfg(all(test, feature = "markdown"))]
+mod tests {
+    use super::*;
+    use crate::markdown::{Event, MarkdownIter, Parser, Tag};
+    use similar_asserts::assert_eq;
+
+    use ParserEvent::*;
+
+    /// Merges adjacent text events.
+    struct MergeText;
+
+    impl<'a> Converter<'a> for MergeText {
+        fn convert(
+            &mut self,
+            event: ParserEvent<'a>,
+            input: &mut Input<'_, 'a>,
+            output: &mut Output<'_, 'a>,
+        ) {
+            match event {
+                Markdown(Event::Text(t)) => {
+                    let mut text = t.into_string();
+                    while let Some(Markdown(Event::Text(t))) =
+                        input.next_if(|e| matches!(e, Markdown(Event::Text(_))))
+                    {
+                        text.push_str(&t);
+                    }
+                    output.emit(Markdown(Event::Text(text.into())));
+                }
+                e => output.emit(e),
+            }
+        }
+    }
+
+    /// Holds back all events until the end.
+    #[derive(Default)]
+    struct Reverse<'a>(Vec<ParserEvent<'a>>);
+
+    impl<'a> Converter<'a> for Reverse<'a> {
+        fn convert(
+            &mut self,
+            event: ParserEvent<'a>,
+            _: &mut Input<'_, 'a>,
+            _: &mut Output<'_, 'a>,
+        ) {
+            self.0.push(event);
+        }
+
+        fn finish(&mut self, output: &mut Output<'_, 'a>) {
+            output.emit_many(self.0.drain(..).rev());
+        }
+    }
+
+    #[test]
+    fn lookahead() {
+        // Escapes split text events.
+        let i = MarkdownIter(Parser::new(r"a\*b")).convert_with(MergeText);
+        self::assert_eq!(
+            i.collect::<Vec<_>>(),
+            vec![
+                Markdown(Event::Start(Tag::Paragraph)),
+                Markdown(Event::Text("a*b".into())),
+                Markdown(Event::End(Tag::Paragraph)),
+            ]
+        );
+    }
+
+    #[test]
+    fn peek_nth() {
+        let mut seen = vec![];
+        let i = MarkdownIter(Parser::new("a")).convert_with(from_fn(|e, input, output| {
+            seen.push(input.peek_nth(1).is_some());
+            output.emit(e)
+        }));
+        self::assert_eq!(i.count(), 3);
+        self::assert_eq!(seen, vec![true, false, false]);
+    }
+
+    #[test]
+    fn emit_many_and_finish() {
+        let double = from_fn(|e: ParserEvent, _: &mut Input, output: &mut Output| {
+            output.emit_many([e.clone(), e])
+        });
+        let i = MarkdownIter(Parser::new("a"))
+            .convert_with(double)
+            .convert_with(Reverse::default());
+        self::assert_eq!(
+            i.collect::<Vec<_>>(),
+            vec![
+                Markdown(Event::End(Tag::Paragraph)),
+                Markdown(Event::End(Tag::Paragraph)),
+                Markdown(Event::Text("a".into())),
+                Markdown(Event::Text("a".into())),
+                Markdown(Event::Start(Tag::Paragraph)),
+                Markdown(Event::Start(Tag::Paragraph)),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let errors = Errors::new();
+        let reporter = errors.clone();
+        let i = MarkdownIter(Parser::new("a")).convert_with(from_fn(move |e, _, output| {
+            if let Markdown(Event::Text(_)) = e {
+                reporter.report(Error::UnexpectedEvent {
+                    position: 1,
+                    expected: "typst",
+                    found: "markdown",
+                    event: format!("{e:?}"),
+                });
+            }
+            output.emit(e)
+        }));
+        let results = errors.check(i).collect::<Vec<_>>();
+        self::assert_eq!(results.len(), 4);
+        self::assert_eq!(results[1].as_ref().unwrap_err().position(), Some(1));
+        self::assert_eq!(results[2], Ok(Markdown(Event::Text("a".into()))));
+    }
+
+    #[test]
+    fn optional() {
+        let i = MarkdownIter(Parser::new("a"))
+            .convert_with(None::<Reverse>)
+            .convert_with(Some(Reverse::default()));
+        self::assert_eq!(
+            i.collect::<Vec<_>>(),
+            vec![
+                Markdown(Event::End(Tag::Paragraph)),
+                Markdown(Event::Text("a".into())),
+                Markdown(Event::Start(Tag::Paragraph)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Errors reported by the fallible adaptors in this crate.
+
+/// An error encountered while processing markup events.
+///
+/// Event-related variants record the zero-based `position` of the offending event in
+/// the input iterator and the event itself, formatted with `Debug`. Source-related
+/// variants record the byte `offset` in the source and the offending source text
+/// instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// An event of one markup format was encountered where only events of another
+    /// format are allowed.
+    UnexpectedEvent {
+        position: usize,
+        expected: &'static str,
+        found: &'static str,
+        event: String,
+    },
+    /// A Markdown link refers to a reference without a definition.
+    #[cfg(feature = "markdown")]
+    UnknownLink { offset: usize, source: String },
+    /// Generating Typst markup failed.
+    #[cfg(feature = "typst")]
+    TypstMarkup(crate::typst::to::markup::Error),
+}
+
+impl Error {
+    /// The position of the offending event in the input iterator, if any.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            Error::UnexpectedEvent { position, .. } => Some(*position),
+            #[cfg(feature = "markdown")]
+            Error::UnknownLink { .. } => None,
+            #[cfg(feature = "typst")]
+            Error::TypstMarkup(e) => e.position(),
+        }
+    }
+
+    /// The byte offset of the offending markup in the source, if known.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            #[cfg(feature = "markdown")]
+            Error::UnknownLink { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::UnexpectedEvent {
+                position,
+                expected,
+                found,
+                event,
+            } => write!(
+                f,
+                "unexpected {found} event at position {position} (expected {expected}): {event}"
+            ),
+            #[cfg(feature = "markdown")]
+            Error::UnknownLink { offset, source } => {
+                write!(f, "link to unknown reference at offset {offset}: {source}")
+            }
+            #[cfg(feature = "typst")]
+            Error::TypstMarkup(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::UnexpectedEvent { .. } => None,
+            #[cfg(feature = "markdown")]
+            Error::UnknownLink { .. } => None,
+            #[cfg(feature = "typst")]
+            Error::TypstMarkup(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "typst")]
+impl From<crate::typst::to::markup::Error> for Error {
+    fn from(e: crate::typst::to::markup::Error) -> Self {
+        Error::TypstMarkup(e)
+    }
+}
+
+#[cfg(all(test, feature = "markdown", feature = "typst"))]
+mod tests {
+    use super::*;
+    use crate::assert::{TryAssertMarkdown, TryAssertTypst};
+    use crate::{markdown, typst, ParserEvent};
+    use similar_asserts::assert_eq;
+
+    #[test]
+    fn unexpected_event() {
+        let input = vec![
+            ParserEvent::Typst(typst::Event::Text("a".into())),
+            ParserEvent::Markdown(markdown::Event::Text("b".into())),
+            ParserEvent::Typst(typst::Event::Parbreak),
+        ];
+        let output = TryAssertTypst::new(input.clone().into_iter()).collect::<Vec<_>>();
+        let err = Error::UnexpectedEvent {
+            position: 1,
+            expected: "typst",
+            found: "markdown",
+            event: "Text(Borrowed(\"b\"))".to_string(),
+        };
+        self::assert_eq!(
+            output,
+            vec![
+                Ok(typst::Event::Text("a".into())),
+                Err(err.clone()),
+                Ok(typst::Event::Parbreak),
+            ]
+        );
+        self::assert_eq!(err.position(), Some(1));
+        self::assert_eq!(
+            err.to_string(),
+            "unexpected markdown event at position 1 (expected typst): Text(Borrowed(\"b\"))"
+        );
+
+        let output = TryAssertMarkdown::new(input.into_iter())
+            .filter_map(Result::err)
+            .map(|e| e.position())
+            .collect::<Vec<_>>();
+        self::assert_eq!(output, vec![Some(0), Some(2)]);
+    }
+
+    #[test]
+    fn typst_markup() {
+        let input = vec![
+            typst::Event::Start(typst::Tag::Strong),
+            typst::Event::End(typst::Tag::Emphasis),
+        ];
+        let mut output = String::new();
+        let err: Error = typst::to::markup::try_write_markup(&mut output, input.into_iter())
+            .unwrap_err()
+            .into();
+        self::assert_eq!(err.position(), Some(1));
+        self::assert_eq!(
+            err.to_string(),
+            "unbalanced event at position 1: End(Emphasis) (open tag: Strong)"
+        );
+    }
+}
@@ -1,4 +1,6 @@
 pub mod assert;
+pub mod convert;
+mod error;
 pub mod filter;
 
 #[cfg(feature = "markdown")]
@@ -8,6 +10,9 @@ pub mod mdbook;
 #[cfg(feature = "typst")]
 pub mod typst;
 
+pub use convert::{Converter, IteratorExt};
+pub use error::Error;
+
 /// Represents all the types of markup events this crate can operate on.
 ///
 /// Markup adapters:
@@ -27,49 +32,45 @@ pub enum ParserEvent<'a> {
 }
 
 #[macro_export]
-/// Convert between markup events without a buffer or lookahead.
+/// Define a [`Converter`] that converts one event at a time, without a buffer or
+/// lookahead. The body maps each event to the event to emit in its place, or `None` to
+/// drop it.
+///
+/// For passes that need lookahead or emit several events per input event, implement
+/// [`Converter`] instead.
 macro_rules! converter {
     (
         $(#[$attr:meta])*
         $struct_name:ident,
-        $in:ty => $out:ty,
         $body:expr
     ) => {
         // Define the struct with the given name
-        #[derive(Debug, Clone)]
+        #[derive(Debug, Clone, Copy, Default)]
         $(#[$attr])*
-        pub struct $struct_name<'a, I> {
-            iter: I,
-            p: core::marker::PhantomData<&'a ()>,
-        }
+        pub struct $struct_name;
 
-        // Define an implementation for the struct
-        impl<'a, I> $struct_name<'a, I>
-        where
-            I: Iterator<Item = ParserEvent<'a>>,
-        {
+        impl $struct_name {
             #[allow(dead_code)]
-            pub fn new(iter: I) -> Self {
-                Self {
-                    iter,
-                    p: core::marker::PhantomData,
-                }
+            pub fn new() -> Self {
+                Self
             }
         }
 
-        impl<'a, I> Iterator for $struct_name<'a, I>
-        where
-            I: Iterator<Item = $in>,
-        {
-            type Item = $out;
-
-            fn next(&mut self) -> Option<Self::Item> {
+        impl<'a> $crate::Converter<'a> for $struct_name {
+            fn convert(
+                &mut self,
+                event: $crate::ParserEvent<'a>,
+                _: &mut $crate::convert::Input<'_, 'a>,
+                output: &mut $crate::convert::Output<'_, 'a>,
+            ) {
                 #[cfg(feature = "tracing")]
-                let span = tracing::span!(tracing::Level::TRACE, &"next");
+                let span = tracing::span!(tracing::Level::TRACE, &"convert");
                 #[cfg(feature = "tracing")]
                 let _enter = span.enter();
                 #[allow(clippy::redundant_closure_call)]
-                $body(self)
+                if let Some(event) = $body(event) {
+                    output.emit(event);
+                }
             }
         }
     };
@@ -1,6 +1,9 @@
 //! Support for [Markdown](https://commonmark.org/).
 
-use crate::ParserEvent;
+use std::ops::Range;
+
+use crate::convert::Errors;
+use crate::{Error, ParserEvent};
 pub use pulldown_cmark::*;
 
 pub mod strip;
@@ -9,7 +12,7 @@ pub mod to;
 /// Assert that an iterator only contains Markdown events. Panics if another type of
 /// event is encountered.
 ///
-/// For a non-panic version, see [`MarkdownFilter`].
+/// For non-panic versions, see [`TryAssertMarkdown`] and [`MarkdownFilter`].
 pub struct AssertMarkdown<T>(pub T);
 impl<'a, T> Iterator for AssertMarkdown<T>
 where
@@ -29,6 +32,50 @@ where
     }
 }
 
+/// Check that an iterator only contains Markdown events. Yields an [`Error`] for each
+/// event of another type instead of panicking.
+///
+/// For a panicking version, see [`AssertMarkdown`].
+pub struct TryAssertMarkdown<T> {
+    iter: T,
+    position: usize,
+}
+
+impl<T> TryAssertMarkdown<T> {
+    pub fn new(iter: T) -> Self {
+        Self { iter, position: 0 }
+    }
+}
+
+impl<'a, T> Iterator for TryAssertMarkdown<T>
+where
+    T: Iterator<Item = ParserEvent<'a>>,
+{
+    type Item = Result<self::Event<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.iter.next()?;
+        self.position += 1;
+        match event {
+            ParserEvent::Markdown(x) => Some(Ok(x)),
+            #[cfg(feature = "mdbook")]
+            ParserEvent::Mdbook(x) => Some(Err(Error::UnexpectedEvent {
+                position: self.position - 1,
+                expected: "markdown",
+                found: "mdbook",
+                event: format!("{x:?}"),
+            })),
+            #[cfg(feature = "typst")]
+            ParserEvent::Typst(x) => Some(Err(Error::UnexpectedEvent {
+                position: self.position - 1,
+                expected: "markdown",
+                found: "typst",
+                event: format!("{x:?}"),
+            })),
+        }
+    }
+}
+
 /// An iterator that only contains Markdown events. Other types of events will be
 /// filtered out.
 ///
@@ -65,3 +112,284 @@ where
         self.0.next().map(ParserEvent::Markdown)
     }
 }
+
+/// How to convert links to a reference without a definition, see
+/// [`UnknownReferences`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum UnknownLinks {
+    /// Keep the source text of the link, like `[*text*][ref]`, as plain text.
+    Text,
+    /// Keep the link content in brackets followed by the reference, like Markdown
+    /// renders an unresolved reference.
+    #[default]
+    Marker,
+    /// Report an [`Error`] and keep the link content.
+    Error,
+}
+
+/// A broken link callback that accepts links to references without a definition, so
+/// the parser yields them with an `*Unknown` link type for [`UnknownReferences`]:
+///
+/// ```
+/// # use pullup::markdown::{self, Parser, Options};
+/// let mut callback = markdown::unknown_reference;
+/// let parser = Parser::new_with_broken_link_callback("[a][b]", Options::empty(), Some(&mut callback));
+/// ```
+///
+/// Only full (`[text][ref]`) and collapsed (`[text][]`) references are accepted. A
+/// shortcut reference (`[text]`) without a definition is indistinguishable from text
+/// in brackets, like `a[0]` or `[sic]`, so it stays text. Accepted links have an empty
+/// destination and title.
+pub fn unknown_reference<'a>(link: BrokenLink<'a>) -> Option<(CowStr<'a>, CowStr<'a>)> {
+    matches!(link.link_type, LinkType::Reference | LinkType::Collapsed)
+        .then(|| (CowStr::Borrowed(""), CowStr::Borrowed("")))
+}
+
+/// Convert links to a reference without a definition according to an
+/// [`UnknownLinks`] policy, using the source range of each event.
+///
+/// The events must come from parsing `source` with [`unknown_reference`] as the broken
+/// link callback, with their offsets:
+///
+/// ```
+/// # use pullup::convert::Errors;
+/// # use pullup::markdown::{self, Parser, Options, UnknownLinks, UnknownReferences};
+/// let source = "[text][ref]";
+/// let mut callback = markdown::unknown_reference;
+/// let parser = Parser::new_with_broken_link_callback(source, Options::empty(), Some(&mut callback));
+/// let errors = Errors::new();
+/// let events = UnknownReferences::new(source, parser.into_offset_iter(), UnknownLinks::Error, errors.clone());
+/// let results = errors.check(events).collect::<Vec<_>>();
+/// assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+/// ```
+///
+/// The resulting events contain no links to undefined references. Errors are reported
+/// to `errors` with the byte offset of the link in `source`.
+pub struct UnknownReferences<'a, I> {
+    source: &'a str,
+    iter: I,
+    policy: UnknownLinks,
+    errors: Errors,
+    /// Whether the content of an unknown link is left out, as its source text was
+    /// written instead.
+    skip: bool,
+}
+
+impl<'a, I> UnknownReferences<'a, I>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    pub fn new(source: &'a str, iter: I, policy: UnknownLinks, errors: Errors) -> Self {
+        Self {
+            source,
+            iter,
+            policy,
+            errors,
+            skip: false,
+        }
+    }
+
+    /// The source text of a link. The range of a collapsed reference can end before
+    /// its `[]`.
+    fn link_source(&self, kind: LinkType, range: Range<usize>) -> &'a str {
+        let (source, end) = (self.source, range.end.min(self.source.len()));
+        let end = match kind {
+            LinkType::CollapsedUnknown
+                if !source[..end].ends_with("][]") && source[end..].starts_with("[]") =>
+            {
+                end + 2
+            }
+            _ => end,
+        };
+        &source[range.start..end]
+    }
+}
+
+impl<'a, I> Iterator for UnknownReferences<'a, I>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (event, range) = self.iter.next()?;
+            let (start, kind) = match &event {
+                Event::Start(Tag::Link(kind, ..)) => (true, *kind),
+                Event::End(Tag::Link(kind, ..)) => (false, *kind),
+                _ if self.skip => continue,
+                _ => return Some(event),
+            };
+            if !matches!(
+                kind,
+                LinkType::ReferenceUnknown | LinkType::CollapsedUnknown | LinkType::ShortcutUnknown
+            ) {
+                return Some(event);
+            }
+            let text = |s: &'a str| Some(Event::Text(CowStr::Borrowed(s)));
+            match (self.policy, start) {
+                (UnknownLinks::Text, true) => {
+                    self.skip = true;
+                    return text(self.link_source(kind, range));
+                }
+                (UnknownLinks::Text, false) => self.skip = false,
+                (UnknownLinks::Marker, true) => return text("["),
+                (UnknownLinks::Marker, false) => {
+                    return match kind {
+                        LinkType::ReferenceUnknown => {
+                            let source = self.link_source(kind, range);
+                            // The `]` closing the content and the label.
+                            let label = reference_label(source);
+                            text(&source[(source.len() - label.len()).saturating_sub(1)..])
+                        }
+                        LinkType::CollapsedUnknown => text("][]"),
+                        _ => text("]"),
+                    };
+                }
+                (UnknownLinks::Error, true) => self.errors.report(Error::UnknownLink {
+                    offset: range.start,
+                    source: self.link_source(kind, range).to_string(),
+                }),
+                (UnknownLinks::Error, false) => {}
+            }
+        }
+    }
+}
+
+/// The trailing `[ref]` of the source text of a full reference link. A reference label
+/// cannot contain unescaped brackets, so it starts at the last one.
+fn reference_label(source: &str) -> &str {
+    let escaped = |i: usize| {
+        let backslashes = source[..i].bytes().rev().take_while(|&b| b == b'\\');
+        backslashes.count() % 2 == 1
+    };
+    source
+        .match_indices('[')
+        .rev()
+        .map(|(i, _)| i)
+        .find(|&i| !escaped(i))
+        .map_or("", |i| &source[i..])
+}
+
+/// Parse Markdown with footnote definitions before the references to them. The mdBook
+/// parser parses chapters with it too.
+pub use pulldown_mdbook::markdown::{FootnotesFirst, FootnotesFirstOffsets};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use similar_asserts::assert_eq;
+
+    const MD: &str = "See [it][Missing  Ref], [*this*][] and a[0] [sic].";
+
+    /// The text of the events of `md` with `policy`, with `_` for emphasis, and the
+    /// offsets of the reported errors.
+    fn convert(md: &str, policy: UnknownLinks) -> (String, Vec<Option<usize>>) {
+        let mut callback = unknown_reference;
+        let parser =
+            Parser::new_with_broken_link_callback(md, Options::empty(), Some(&mut callback));
+        let errors = Errors::new();
+        let events = UnknownReferences::new(md, parser.into_offset_iter(), policy, errors.clone());
+        let (mut text, mut offsets) = (String::new(), vec![]);
+        for result in errors.check(events) {
+            match result {
+                Ok(Event::Text(t)) => text.push_str(&t),
+                Ok(Event::Start(Tag::Emphasis) | Event::End(Tag::Emphasis)) => text.push('_'),
+                Ok(Event::Start(Tag::Link(kind, ..))) => panic!("unexpected {kind:?} link"),
+                Ok(_) => {}
+                Err(e) => offsets.push(e.offset()),
+            }
+        }
+        (text, offsets)
+    }
+
+    #[test]
+    fn text() {
+        self::assert_eq!(
+            convert(MD, UnknownLinks::Text),
+            (
+                "See [it][Missing  Ref], [*this*][] and a[0] [sic].".to_string(),
+                vec![]
+            )
+        );
+    }
+
+    #[test]
+    fn marker() {
+        self::assert_eq!(
+            convert(MD, UnknownLinks::Marker),
+            (
+                "See [it][Missing  Ref], [_this_][] and a[0] [sic].".to_string(),
+                vec![]
+            )
+        );
+    }
+
+    #[test]
+    fn marker_with_brackets() {
+        self::assert_eq!(
+            convert(r"[a [b\]] c][x\[y]", UnknownLinks::Marker).0,
+            r"[a [b]] c][x\[y]"
+        );
+    }
+
+    #[test]
+    fn error() {
+        self::assert_eq!(
+            convert(MD, UnknownLinks::Error),
+            (
+                "See it, _this_ and a[0] [sic].".to_string(),
+                vec![Some(4), Some(24)]
+            )
+        );
+
+        let mut callback = unknown_reference;
+        let parser =
+            Parser::new_with_broken_link_callback(MD, Options::empty(), Some(&mut callback));
+        let errors = Errors::new();
+        let events = UnknownReferences::new(
+            MD,
+            parser.into_offset_iter(),
+            UnknownLinks::Error,
+            errors.clone(),
+        );
+        let error = errors.check(events).find_map(Result::err).unwrap();
+        self::assert_eq!(
+            error.to_string(),
+            "link to unknown reference at offset 4: [it][Missing  Ref]"
+        );
+    }
+
+    #[test]
+    fn shortcuts_are_text() {
+        self::assert_eq!(
+            convert("Use a[0] here and [sic].", UnknownLinks::Error),
+            ("Use a[0] here and [sic].".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn footnotes_first() {
+        let md = "A[^1] [x][y].\n\n[^1]: See [z][].\n";
+        let mut rest = unknown_reference;
+        let mut definitions = unknown_reference;
+        let parser = FootnotesFirst::new_with_broken_link_callbacks(
+            md,
+            Options::ENABLE_FOOTNOTES,
+            Some(&mut definitions),
+            Some(&mut rest),
+        );
+        let errors = Errors::new();
+        let events = UnknownReferences::new(
+            md,
+            parser.into_offset_iter(),
+            UnknownLinks::Error,
+            errors.clone(),
+        );
+        let offsets = errors
+            .check(events)
+            .filter_map(|r| r.err().and_then(|e| e.offset()))
+            .collect::<Vec<_>>();
+        self::assert_eq!(offsets, vec![25, 6]);
+    }
+}
@@ -5,13 +5,10 @@ use crate::ParserEvent;
 converter!(
     /// Strip out Markdown HTML.
     StripHtml,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Markdown(markdown::Event::Html(_))) => {
-                this.iter.find(|x| !matches!(x, ParserEvent::Markdown(markdown::Event::Html(_))))
-            },
-            x => x,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Html(_)) => None,
+            x => Some(x),
     }
 });
 
@@ -20,6 +17,7 @@ mod tests {
     use super::*;
     use crate::markdown::CowStr;
     use crate::markdown::*;
+    use crate::IteratorExt;
     use similar_asserts::assert_eq;
 
     // Set up type names so they are clearer and more succint.
@@ -42,7 +40,8 @@ is anybody in there?
 *just nod if you can hear me*
 <del>*foo*</del>
 ";
-            let i = AssertMarkdown(super::StripHtml::new(MarkdownIter(Parser::new(&md))));
+            let i =
+                AssertMarkdown(MarkdownIter(Parser::new(md)).convert_with(super::StripHtml::new()));
             self::assert_eq!(
                 i.collect::<Vec<markdown::Event>>(),
                 vec![
@@ -0,0 +1,1164 @@
+//! Convert Markdown events to Markdown text.
+//!
+//! The output is [CommonMark](https://spec.commonmark.org/) with the [GitHub Flavored
+//! Markdown](https://github.github.com/gfm/) extensions supported by
+//! [`pulldown_cmark`]: tables, strikethrough, task lists, and footnotes. Text is escaped
+//! so parsing the output with [`Parser`](crate::markdown::Parser) yields the same
+//! events. Some events have more than one spelling and come back in a normalized form:
+//!
+//! * Reference, collapsed, and shortcut links are written as inline links, as their
+//!   definitions are not part of the event stream.
+//! * Indented code blocks are written as fenced code blocks when indenting them would
+//!   change their meaning, for example directly after a list.
+//! * Adjacent text events may be merged or split differently.
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use crate::markdown::{Alignment, CodeBlockKind, Event, LinkType, Tag};
+
+/// A block that prefixes every line of its content.
+#[derive(Debug)]
+enum Container {
+    BlockQuote,
+    List {
+        tight: bool,
+        number: Option<u64>,
+        delimiter: char,
+    },
+    Item {
+        tight: bool,
+        marker: Option<String>,
+        indent: usize,
+    },
+    Footnote {
+        marker: Option<String>,
+    },
+}
+
+/// Nesting used to find which lists are loose.
+enum Scan {
+    List(usize),
+    Item,
+    Other,
+}
+
+/// Convert Markdown events to Markdown text.
+///
+/// Note: while each item returned by the iterator is a `String`, items may contain
+/// multiple lines or be empty.
+pub struct MarkdownMarkup<'a, T> {
+    containers: Vec<Container>,
+    /// Whether the next block must be separated from the previous one.
+    separate: bool,
+    /// Whether nothing but container prefixes may be written on the current line.
+    line_start: bool,
+    /// Whether the next text starts a line of inline content.
+    content_start: bool,
+    /// Whether a footnote reference started the current line of inline content, so a
+    /// colon after it would make it read as a definition.
+    footnote_start: bool,
+    /// Delimiter of a list that directly precedes the next block.
+    previous_list: Option<char>,
+    /// The emphasis delimiter written last, if nothing was written after it, and
+    /// whether it was strong.
+    opener: Option<(char, bool)>,
+    delimiters: Vec<char>,
+    in_heading: bool,
+    /// Underline character of a setext heading being written.
+    setext: Option<char>,
+    in_cell: bool,
+    in_html_block: bool,
+    in_autolink: bool,
+    code: Option<String>,
+    table: Vec<Alignment>,
+    /// Looseness of upcoming lists, in the order they start.
+    loose: VecDeque<bool>,
+    lookahead: VecDeque<Event<'a>>,
+    iter: T,
+}
+
+impl<'a, T> MarkdownMarkup<'a, T>
+where
+    T: Iterator<Item = Event<'a>>,
+{
+    pub fn new(iter: T) -> Self {
+        Self {
+            containers: vec![],
+            separate: false,
+            line_start: true,
+            content_start: false,
+            footnote_start: false,
+            previous_list: None,
+            opener: None,
+            delimiters: vec![],
+            in_heading: false,
+            setext: None,
+            in_cell: false,
+            in_html_block: false,
+            in_autolink: false,
+            code: None,
+            table: vec![],
+            loose: VecDeque::new(),
+            lookahead: VecDeque::new(),
+            iter,
+        }
+    }
+
+    fn pull(&mut self) -> Option<Event<'a>> {
+        self.lookahead.pop_front().or_else(|| self.iter.next())
+    }
+
+    fn peek(&mut self) -> Option<&Event<'a>> {
+        if self.lookahead.is_empty() {
+            let next = self.iter.next()?;
+            self.lookahead.push_back(next);
+        }
+        self.lookahead.front()
+    }
+
+    /// Looks ahead to the end of a list that just started and records which of it and
+    /// its nested lists are loose, i.e. have paragraphs directly inside their items.
+    fn scan_lists(&mut self) {
+        let mut stack = vec![Scan::List(0)];
+        let mut loose = vec![false];
+        let mut i = 0;
+        while !stack.is_empty() {
+            if i == self.lookahead.len() {
+                match self.iter.next() {
+                    Some(event) => self.lookahead.push_back(event),
+                    None => break,
+                }
+            }
+            match &self.lookahead[i] {
+                Event::Start(Tag::List(_)) => {
+                    loose.push(false);
+                    stack.push(Scan::List(loose.len() - 1));
+                }
+                Event::Start(Tag::Item) => stack.push(Scan::Item),
+                Event::Start(Tag::Paragraph) => {
+                    if let [.., Scan::List(list), Scan::Item] = stack.as_slice() {
+                        loose[*list] = true;
+                    }
+                    stack.push(Scan::Other);
+                }
+                Event::Start(_) => stack.push(Scan::Other),
+                Event::End(_) => {
+                    stack.pop();
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        self.loose.extend(loose);
+    }
+
+    /// Looks ahead to the end of a heading that just started and returns whether it
+    /// contains line breaks.
+    fn heading_has_breaks(&mut self) -> bool {
+        let mut i = 0;
+        loop {
+            if i == self.lookahead.len() {
+                match self.iter.next() {
+                    Some(event) => self.lookahead.push_back(event),
+                    None => return false,
+                }
+            }
+            match &self.lookahead[i] {
+                Event::SoftBreak | Event::HardBreak => return true,
+                Event::End(Tag::Heading(..)) => return false,
+                _ => i += 1,
+            }
+        }
+    }
+
+    /// Whether blocks in the innermost container are separated by a line break instead
+    /// of a blank line.
+    fn tight(&self) -> bool {
+        matches!(
+            self.containers.last(),
+            Some(
+                Container::List { tight: true, .. }
+                    | Container::Item { tight: true, .. }
+                    | Container::Footnote { .. }
+            )
+        )
+    }
+
+    /// Whether a list item or footnote marker has not been written yet.
+    fn marker_pending(&self) -> bool {
+        self.containers.iter().any(|c| {
+            matches!(
+                c,
+                Container::Item {
+                    marker: Some(_),
+                    ..
+                } | Container::Footnote { marker: Some(_) }
+            )
+        })
+    }
+
+    fn prefix(&mut self, blank: bool) -> String {
+        let mut prefix = String::new();
+        for container in self.containers.iter_mut() {
+            match container {
+                Container::BlockQuote => prefix.push_str("> "),
+                Container::List { .. } => {}
+                Container::Item { marker, indent, .. } => match marker.take() {
+                    Some(marker) => prefix.push_str(&marker),
+                    None => prefix.push_str(&" ".repeat(*indent)),
+                },
+                Container::Footnote { marker } => {
+                    if let Some(marker) = marker.take() {
+                        prefix.push_str(&marker);
+                    }
+                }
+            }
+        }
+        if blank {
+            prefix.truncate(prefix.trim_end().len());
+        }
+        prefix
+    }
+
+    /// Write `s`, prefixing each line with the prefixes of the open containers.
+    fn write(&mut self, out: &mut String, s: &str) {
+        for line in s.split_inclusive('\n') {
+            if self.line_start {
+                let prefix = self.prefix(line == "\n");
+                out.push_str(&prefix);
+            }
+            out.push_str(line);
+            self.line_start = line.ends_with('\n');
+        }
+    }
+
+    /// End the current line, if anything was written on it.
+    fn end_line(&mut self, out: &mut String) {
+        if !self.line_start {
+            self.write(out, "\n");
+        }
+    }
+
+    /// Separate a new block from the previous one. Footnote definitions that start
+    /// with anything but a paragraph get their label on a line of its own.
+    fn block(&mut self, out: &mut String) {
+        self.end_line(out);
+        if let Some(Container::Footnote { marker: Some(_) }) = self.containers.last() {
+            self.write(out, "\n");
+        }
+        self.paragraph(out);
+    }
+
+    /// Separate a new paragraph from the previous block.
+    fn paragraph(&mut self, out: &mut String) {
+        self.end_line(out);
+        if self.separate && !self.tight() {
+            self.write(out, "\n");
+        }
+        self.separate = false;
+        self.previous_list = None;
+    }
+
+    fn end_block(&mut self, out: &mut String) {
+        self.end_line(out);
+        self.separate = true;
+        self.content_start = false;
+    }
+
+    fn text(&mut self, text: &str) -> String {
+        let trailing = matches!(
+            self.peek(),
+            None | Some(
+                Event::SoftBreak
+                    | Event::HardBreak
+                    | Event::End(Tag::Paragraph | Tag::Heading(..) | Tag::TableCell | Tag::Item)
+            )
+        );
+        let before_link = matches!(
+            self.peek(),
+            Some(Event::Start(Tag::Link(..)) | Event::FootnoteReference(_))
+        );
+        let mut escaped = String::with_capacity(text.len());
+        let ordered_marker = match self.content_start {
+            true => ordered_list_marker(text),
+            false => None,
+        };
+        for (i, c) in text.char_indices() {
+            let rest = &text[i + c.len_utf8()..];
+            // A character needs at most one backslash, even if several rules apply.
+            let mut escape = false;
+            if i == 0 && self.content_start {
+                let next = rest.chars().next();
+                match c {
+                    ' ' => {
+                        escaped.push_str("&#32;");
+                        continue;
+                    }
+                    '\t' => {
+                        escaped.push_str("&#9;");
+                        continue;
+                    }
+                    '>' | '=' | '#' => escape = true,
+                    '-' if matches!(next, None | Some(' ' | '\t' | '-')) => escape = true,
+                    '+' if matches!(next, None | Some(' ' | '\t')) => escape = true,
+                    _ => {}
+                }
+            }
+            match c {
+                '\n' => {
+                    escaped.push_str("&#10;");
+                    continue;
+                }
+                '\r' => {
+                    escaped.push_str("&#13;");
+                    continue;
+                }
+                // A pipe can make the line read as a table row once it is indented
+                // differently, so it is escaped everywhere.
+                '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '~' | '|' => escape = true,
+                ':' if i == 0 && self.footnote_start => escape = true,
+                '#' | '{' if self.in_heading => escape = true,
+                '&' if is_entity(rest) => escape = true,
+                '!' if rest.is_empty() && before_link => escape = true,
+                '.' | ')' if ordered_marker == Some(i) => escape = true,
+                ' ' if rest.is_empty() && trailing => {
+                    escaped.push_str("&#32;");
+                    continue;
+                }
+                '\t' if rest.is_empty() && trailing => {
+                    escaped.push_str("&#9;");
+                    continue;
+                }
+                _ => {}
+            }
+            if escape {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
+    fn start(&mut self, tag: Tag<'a>, out: &mut String) {
+        match tag {
+            Tag::Paragraph => {
+                self.paragraph(out);
+                self.content_start = true;
+            }
+            Tag::Heading(level, _, _) => {
+                self.block(out);
+                // Only setext headings can contain line breaks.
+                self.setext = match level as usize {
+                    1 if self.heading_has_breaks() => Some('='),
+                    2 if self.heading_has_breaks() => Some('-'),
+                    _ => None,
+                };
+                if self.setext.is_none() {
+                    self.write(out, &format!("{} ", "#".repeat(level as usize)));
+                }
+                self.in_heading = true;
+                self.content_start = true;
+            }
+            Tag::BlockQuote => {
+                self.block(out);
+                self.containers.push(Container::BlockQuote);
+            }
+            Tag::CodeBlock(_) => {
+                self.code = Some(String::new());
+            }
+            Tag::List(number) => {
+                let previous = self.previous_list;
+                self.block(out);
+                if self.loose.is_empty() {
+                    self.scan_lists();
+                }
+                let loose = self.loose.pop_front().unwrap_or_default();
+                let delimiter = match (number, previous) {
+                    (None, Some('-')) => '*',
+                    (None, _) => '-',
+                    (Some(_), Some('.')) => ')',
+                    (Some(_), _) => '.',
+                };
+                self.containers.push(Container::List {
+                    tight: !loose,
+                    number,
+                    delimiter,
+                });
+            }
+            Tag::Item => {
+                self.block(out);
+                let Some(Container::List {
+                    tight,
+                    number,
+                    delimiter,
+                }) = self.containers.last_mut()
+                else {
+                    panic!("list item outside of a list");
+                };
+                let marker = match number {
+                    Some(n) => {
+                        let marker = format!("{n}{delimiter} ");
+                        *n += 1;
+                        marker
+                    }
+                    None => format!("{delimiter} "),
+                };
+                let tight = *tight;
+                self.containers.push(Container::Item {
+                    tight,
+                    indent: marker.len(),
+                    marker: Some(marker),
+                });
+                self.content_start = true;
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.block(out);
+                self.containers.push(Container::Footnote {
+                    marker: Some(format!("[^{label}]: ")),
+                });
+            }
+            Tag::Table(alignment) => {
+                self.block(out);
+                self.table = alignment;
+            }
+            Tag::TableHead | Tag::TableRow => {}
+            Tag::TableCell => {
+                self.write(out, "| ");
+                self.in_cell = true;
+                self.content_start = true;
+            }
+            Tag::Emphasis | Tag::Strong => {
+                let strong = matches!(tag, Tag::Strong);
+                // `***` reads as emphasis around strong, other nestings of the same
+                // delimiter change meaning.
+                let c = match self.opener {
+                    Some(('*', false)) if strong => '*',
+                    Some(('*', _)) => '_',
+                    _ => '*',
+                };
+                let n = if strong { 2 } else { 1 };
+                self.write(out, &c.to_string().repeat(n));
+                self.delimiters.push(c);
+                self.opener = Some((c, strong));
+            }
+            Tag::Strikethrough => self.write(out, "~~"),
+            Tag::Link(ty, dest, _) => match autolink(ty, &dest) {
+                true => {
+                    self.write(out, &format!("<{dest}>"));
+                    self.in_autolink = true;
+                }
+                false => self.write(out, "["),
+            },
+            Tag::Image(..) => self.write(out, "!["),
+        }
+    }
+
+    fn end(&mut self, tag: Tag<'a>, out: &mut String) {
+        match tag {
+            Tag::Paragraph => self.end_block(out),
+            Tag::Heading(_, id, classes) => {
+                if id.is_some() || !classes.is_empty() {
+                    let attributes = id
+                        .map(|id| format!("#{id}"))
+                        .into_iter()
+                        .chain(classes.iter().map(|c| format!(".{c}")))
+                        .collect::<Vec<_>>();
+                    self.write(out, &format!(" {{{}}}", attributes.join(" ")));
+                }
+                self.in_heading = false;
+                if let Some(c) = self.setext.take() {
+                    self.end_line(out);
+                    self.write(out, &c.to_string().repeat(3));
+                }
+                self.end_block(out);
+            }
+            Tag::BlockQuote => {
+                if !self.separate {
+                    // Empty block quote.
+                    self.write(out, "\n");
+                }
+                self.containers.pop();
+                self.end_block(out);
+            }
+            Tag::CodeBlock(kind) => {
+                let code = self.code.take().unwrap_or_default();
+                self.code_block(kind, &code, out);
+                self.end_block(out);
+            }
+            Tag::List(_) => {
+                let list = self.containers.pop();
+                self.end_block(out);
+                if let Some(Container::List { delimiter, .. }) = list {
+                    self.previous_list = Some(delimiter);
+                }
+            }
+            Tag::Item | Tag::FootnoteDefinition(_) => {
+                if self.marker_pending() {
+                    // Empty item or definition.
+                    self.write(out, "\n");
+                }
+                self.containers.pop();
+                self.end_block(out);
+            }
+            Tag::Table(_) => {
+                self.table.clear();
+                self.end_block(out);
+            }
+            Tag::TableHead => {
+                self.write(out, "|\n");
+                let mut delimiter_row = String::from("|");
+                for alignment in &self.table {
+                    delimiter_row.push_str(match alignment {
+                        Alignment::None => " --- |",
+                        Alignment::Left => " :-- |",
+                        Alignment::Center => " :-: |",
+                        Alignment::Right => " --: |",
+                    });
+                }
+                delimiter_row.push('\n');
+                self.write(out, &delimiter_row);
+            }
+            Tag::TableRow => self.write(out, "|\n"),
+            Tag::TableCell => {
+                if !self.content_start {
+                    self.write(out, " ");
+                }
+                self.in_cell = false;
+            }
+            Tag::Emphasis | Tag::Strong => {
+                let c = self.delimiters.pop().unwrap_or('*');
+                let n = if matches!(tag, Tag::Strong) { 2 } else { 1 };
+                self.write(out, &c.to_string().repeat(n));
+            }
+            Tag::Strikethrough => self.write(out, "~~"),
+            Tag::Link(ty, dest, title) => match autolink(ty, &dest) {
+                true => self.in_autolink = false,
+                false => self.write(out, &format!("]({})", destination(&dest, &title))),
+            },
+            Tag::Image(_, dest, title) => {
+                self.write(out, &format!("]({})", destination(&dest, &title)))
+            }
+        }
+    }
+
+    fn code_block(&mut self, kind: CodeBlockKind<'a>, code: &str, out: &mut String) {
+        let first_blank = code.lines().next().is_none_or(|l| l.trim().is_empty());
+        let last_blank = code.lines().last().is_none_or(|l| l.trim().is_empty());
+        let indented = matches!(kind, CodeBlockKind::Indented)
+            && !first_blank
+            && !last_blank
+            && !self.tight()
+            && !self.marker_pending()
+            && self.previous_list.is_none();
+        self.block(out);
+        if indented {
+            for line in code.lines() {
+                self.write(out, &format!("    {line}\n"));
+            }
+            return;
+        }
+        let info = match kind {
+            CodeBlockKind::Fenced(info) => info,
+            CodeBlockKind::Indented => "".into(),
+        };
+        let c = if info.contains('`') { '~' } else { '`' };
+        let fence = c.to_string().repeat(longest_run(code, c).max(2) + 1);
+        // An info string starting with the fence character would lengthen the fence.
+        let space = if info.starts_with(c) { " " } else { "" };
+        self.write(out, &format!("{fence}{space}{}\n", escape_info(&info)));
+        self.write(out, code);
+        if !code.is_empty() && !code.ends_with('\n') {
+            self.write(out, "\n");
+        }
+        self.write(out, &format!("{fence}\n"));
+    }
+}
+
+impl<'a, T> Iterator for MarkdownMarkup<'a, T>
+where
+    T: Iterator<Item = Event<'a>>,
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.pull()?;
+        let mut out = String::new();
+        let opener = self.opener.take();
+        let footnote_start = core::mem::take(&mut self.footnote_start);
+        let in_html_block = core::mem::take(&mut self.in_html_block);
+        match event {
+            Event::Text(text) if self.code.is_some() => {
+                self.code.as_mut().expect("in code block").push_str(&text);
+            }
+            Event::Text(_) if self.in_autolink => {}
+            Event::Text(text) => {
+                let mut text = text.to_string();
+                while let Some(Event::Text(more)) = self.peek() {
+                    text.push_str(more);
+                    self.pull();
+                }
+                self.footnote_start = footnote_start;
+                let escaped = self.text(&text);
+                self.footnote_start = false;
+                self.write(&mut out, &escaped);
+                self.content_start = false;
+            }
+            Event::Start(tag) => {
+                self.opener = opener;
+                self.start(tag, &mut out);
+                if self.opener == opener {
+                    self.opener = None;
+                }
+            }
+            Event::End(tag) => self.end(tag, &mut out),
+            Event::Code(code) => {
+                let span = match code.is_empty() && !self.in_heading && !self.in_cell {
+                    // Line endings in code spans read as spaces, except for a lone one.
+                    true => "`\n`".to_string(),
+                    false => code_span(&code),
+                };
+                self.write(&mut out, &span);
+                self.content_start = false;
+            }
+            Event::Html(html) => {
+                let block = self.line_start && !self.in_heading && !self.in_cell && {
+                    !self.content_start || self.marker_pending()
+                };
+                if block {
+                    if !in_html_block {
+                        self.block(&mut out);
+                    }
+                    self.write(&mut out, &html);
+                    self.separate = true;
+                    self.in_html_block = true;
+                } else {
+                    self.write(&mut out, &html);
+                    self.content_start = false;
+                }
+            }
+            Event::FootnoteReference(label) => {
+                self.write(&mut out, &format!("[^{label}]"));
+                self.footnote_start = self.content_start;
+                self.content_start = false;
+            }
+            Event::SoftBreak | Event::HardBreak
+                if (self.in_heading && self.setext.is_none()) || self.in_cell =>
+            {
+                self.write(&mut out, " ");
+            }
+            Event::SoftBreak => {
+                self.write(&mut out, "\n");
+                self.content_start = true;
+            }
+            Event::HardBreak => {
+                self.write(&mut out, "\\\n");
+                self.content_start = true;
+            }
+            Event::Rule => {
+                self.block(&mut out);
+                self.write(&mut out, "***");
+                self.end_block(&mut out);
+            }
+            Event::TaskListMarker(checked) => {
+                self.write(&mut out, if checked { "[x] " } else { "[ ] " });
+            }
+        }
+        Some(out)
+    }
+}
+
+/// Whether a link can be written as an autolink.
+fn autolink(ty: LinkType, dest: &str) -> bool {
+    let plain = !dest.is_empty()
+        && !dest
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | '\\'));
+    match ty {
+        LinkType::Autolink => {
+            let scheme = dest.split(':').next().unwrap_or_default();
+            plain
+                && dest.contains(':')
+                && (2..=32).contains(&scheme.len())
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+        }
+        LinkType::Email => plain && dest.contains('@'),
+        _ => false,
+    }
+}
+
+/// Format the destination and title of an inline link or image.
+fn destination(dest: &str, title: &str) -> String {
+    let mut s = String::new();
+    if dest.is_empty() || dest.contains(|c: char| c == ' ' || c.is_control() || c == '<') {
+        s.push('<');
+        for (i, c) in dest.char_indices() {
+            match c {
+                '<' | '>' | '\\' => s.push('\\'),
+                '&' if is_entity(&dest[i + 1..]) => s.push('\\'),
+                _ => {}
+            }
+            s.push(c);
+        }
+        s.push('>');
+    } else {
+        for (i, c) in dest.char_indices() {
+            match c {
+                '(' | ')' | '\\' => s.push('\\'),
+                '&' if is_entity(&dest[i + 1..]) => s.push('\\'),
+                _ => {}
+            }
+            s.push(c);
+        }
+    }
+    if !title.is_empty() {
+        s.push_str(" \"");
+        for (i, c) in title.char_indices() {
+            match c {
+                '"' | '\\' => s.push('\\'),
+                '&' if is_entity(&title[i + 1..]) => s.push('\\'),
+                _ => {}
+            }
+            s.push(c);
+        }
+        s.push('"');
+    }
+    s
+}
+
+/// Format an inline code span, choosing a backtick string that does not occur in the
+/// code.
+fn code_span(code: &str) -> String {
+    let mut n = 1;
+    while code.split(|c| c != '`').any(|run| run.len() == n) {
+        n += 1;
+    }
+    let fence = "`".repeat(n);
+    let pad = code.starts_with('`')
+        || code.ends_with('`')
+        || (code.starts_with(' ') && code.ends_with(' ') && code.contains(|c| c != ' '));
+    let pad = if pad || code.is_empty() { " " } else { "" };
+    format!("{fence}{pad}{code}{pad}{fence}")
+}
+
+/// Escape a code block info string.
+fn escape_info(info: &str) -> String {
+    let mut s = String::with_capacity(info.len());
+    for (i, c) in info.char_indices() {
+        match c {
+            '\\' => s.push('\\'),
+            '&' if is_entity(&info[i + 1..]) => s.push('\\'),
+            _ => {}
+        }
+        s.push(c);
+    }
+    s
+}
+
+/// Whether the text after an `&` could be read as an entity or numeric character
+/// reference.
+fn is_entity(rest: &str) -> bool {
+    let name = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#'))
+        .unwrap_or(rest.len());
+    name > 0 && rest[name..].starts_with(';')
+}
+
+/// If `s` starts with what reads as an ordered list marker, return the offset of its
+/// delimiter.
+fn ordered_list_marker(s: &str) -> Option<usize> {
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    if !(1..=9).contains(&digits) {
+        return None;
+    }
+    let rest = &s[digits..];
+    (rest.starts_with(['.', ')']) && matches!(rest[1..].chars().next(), None | Some(' ' | '\t')))
+        .then_some(digits)
+}
+
+fn longest_run(s: &str, c: char) -> usize {
+    s.split(|x| x != c)
+        .map(|run| run.len() / c.len_utf8())
+        .max()
+        .unwrap_or_default()
+}
+
+/// Iterate over an Iterator of Markdown [`Event`]s, generate Markdown text for each
+/// [`Event`], and push it to a `String`.
+pub fn push_markup<'a, T>(s: &mut String, iter: T)
+where
+    T: Iterator<Item = Event<'a>>,
+{
+    s.extend(MarkdownMarkup::new(iter));
+}
+
+/// Iterate over an Iterator of Markdown [`Event`]s, generate Markdown text for each
+/// [`Event`], and write it to a `Write`r.
+pub fn write_markup<'a, T, W>(w: &mut W, iter: T) -> std::io::Result<()>
+where
+    T: Iterator<Item = Event<'a>>,
+    W: Write,
+{
+    for e in MarkdownMarkup::new(iter) {
+        w.write_str(&e).map_err(std::io::Error::other)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::{Options, Parser};
+    use similar_asserts::assert_eq;
+
+    fn options() -> Options {
+        Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+    }
+
+    /// Parse Markdown, merging adjacent text events so differently escaped text
+    /// compares equal.
+    fn parse(md: &str) -> Vec<Event<'_>> {
+        let mut events: Vec<Event> = vec![];
+        for event in Parser::new_ext(md, options()) {
+            match (events.last_mut(), event) {
+                (Some(Event::Text(previous)), Event::Text(text)) => {
+                    *previous = format!("{previous}{text}").into();
+                }
+                (_, event) => events.push(event),
+            }
+        }
+        events
+    }
+
+    fn markup(md: &str) -> String {
+        MarkdownMarkup::new(Parser::new_ext(md, options())).collect()
+    }
+
+    /// Assert that writing the events of `md` and parsing the result gives back the
+    /// same events.
+    fn assert_roundtrip(md: &str) {
+        let written = markup(md);
+        self::assert_eq!(parse(md), parse(&written), "written as:\n{written}");
+    }
+
+    mod blocks {
+        use super::*;
+
+        #[test]
+        fn paragraphs_and_headings() {
+            let md = "\
+# Title
+
+Some text
+over lines.
+
+## Sub {#id .class}
+";
+            self::assert_eq!(
+                markup(md),
+                "\
+# Title
+
+Some text
+over lines.
+
+## Sub \\{\\#id .class}
+"
+            );
+            assert_roundtrip(md);
+        }
+
+        #[test]
+        fn block_quotes() {
+            let md = "\
+> quoted
+>
+> > nested
+>
+> - item
+";
+            self::assert_eq!(markup(md), md);
+            assert_roundtrip(md);
+        }
+
+        #[test]
+        fn rules_and_html() {
+            let md = "\
+a
+
+---
+
+<div>
+html
+</div>
+
+b
+";
+            self::assert_eq!(
+                markup(md),
+                "\
+a
+
+***
+
+<div>
+html
+</div>
+
+b
+"
+            );
+            assert_roundtrip(md);
+        }
+    }
+
+    /// Markdown docs:
+    /// * https://spec.commonmark.org/0.31.2/#lists
+    mod lists {
+        use super::*;
+
+        #[test]
+        fn tight() {
+            let md = "\
+- a
+- b
+  - c
+
+3. d
+4. e
+";
+            self::assert_eq!(markup(md), md);
+            assert_roundtrip(md);
+        }
+
+        #[test]
+        fn loose() {
+            let md = "\
+- a
+
+  more
+
+- b
+";
+            self::assert_eq!(markup(md), md);
+            assert_roundtrip(md);
+        }
+
+        #[test]
+        fn adjacent_lists() {
+            assert_roundtrip("- a\n- b\n* c\n\n1. d\n2) e\n");
+        }
+
+        #[test]
+        fn code_first_in_loose_list() {
+            assert_roundtrip("- ```\n  a\n  ```\n\n- b\n");
+        }
+
+        #[test]
+        fn empty_items() {
+            assert_roundtrip("-\n- a\n-\n");
+        }
+
+        #[test]
+        fn task_lists() {
+            let md = "\
+- [ ] todo
+- [x] done
+";
+            self::assert_eq!(markup(md), md);
+            assert_roundtrip(md);
+        }
+    }
+
+    /// Markdown docs:
+    /// * https://spec.commonmark.org/0.31.2/#fenced-code-blocks
+    /// * https://spec.commonmark.org/0.31.2/#indented-code-blocks
+    mod code {
+        use super::*;
+
+        #[test]
+        fn fence_longer_than_content() {
+            let md = "\
+````rust
+```
+not the end
+```
+````
+";
+            self::assert_eq!(markup(md), md);
+            assert_roundtrip(md);
+        }
+
+        #[test]
+        fn tilde_fence_for_backtick_info() {
+            assert_roundtrip("~~~ a`b\ncode\n~~~\n");
+        }
+
+        #[test]
+        fn info_starting_with_fence_character() {
+            assert_roundtrip("~~~ ~```a\ncode\n~~~\n");
+            self::assert_eq!(markup("~~~ ~a`\ncode\n~~~\n"), "~~~ ~a`\ncode\n~~~\n");
+        }
+
+        #[test]
+        fn indented() {
+            let md = "\
+para
+
+    indented
+    code
+";
+            self::assert_eq!(markup(md), md);
+            assert_roundtrip(md);
+            assert_roundtrip("- a\n\n<!-- -->\n\n    code\n");
+        }
+
+        #[test]
+        fn inline() {
+            let md = "`` a`b `` and ` `` ` and `x`\n";
+            assert_roundtrip(md);
+            self::assert_eq!(markup("`x`"), "`x`\n");
+        }
+
+        #[test]
+        fn in_lists_and_quotes() {
+            assert_roundtrip("> ```\n> a\n>\n> b\n> ```\n\n- ```\n  c\n  ```\n");
+        }
+    }
+
+    /// Markdown docs:
+    /// * https://github.github.com/gfm/#tables-extension-
+    mod tables {
+        use super::*;
+
+        #[test]
+        fn aligned() {
+            let md = "\
+| a | b | c | d |
+| --- | :-- | :-: | --: |
+| 1 | `x\\|y` | a\\|b | |
+";
+            self::assert_eq!(markup(md), md);
+            assert_roundtrip(md);
+        }
+    }
+
+    mod inline {
+        use super::*;
+
+        #[test]
+        fn emphasis_and_links() {
+            let md = "\
+*a* **b** ~~c~~ ***d*** [e](http://x.com \"t\") ![f](<a b.png>) <http://y.com>
+<me@example.com>[^1]
+
+[^1]: Note
+";
+            self::assert_eq!(
+                markup(md),
+                "\
+*a* **b** ~~c~~ ***d*** [e](http://x.com \"t\") ![f](<a b.png>) <http://y.com>
+<me@example.com>[^1]
+
+[^1]: Note
+"
+            );
+            assert_roundtrip(md);
+        }
+
+        #[test]
+        fn reference_links_become_inline() {
+            self::assert_eq!(markup("[a][r]\n\n[r]: /url\n"), "[a](/url)\n");
+        }
+
+        #[test]
+        fn breaks() {
+            let md = "a\\\nb  \nc\n";
+            self::assert_eq!(markup(md), "a\\\nb\\\nc\n");
+            assert_roundtrip(md);
+        }
+    }
+
+    /// Markdown docs:
+    /// * https://spec.commonmark.org/0.31.2/#backslash-escapes
+    mod escaping {
+        use super::*;
+
+        #[test]
+        fn punctuation() {
+            assert_roundtrip(r"\*a\* \_b\_ \`c\` \[d\]\(e\) \<f> \~\~g\~\~ h\\i &amp;amp; \&copy;");
+        }
+
+        #[test]
+        fn line_starts() {
+            assert_roundtrip(
+                "\
+a
+\\# not heading
+\\> not quote
+\\- not item
+\\+ not item
+1\\. not item
+2\\) not item
+\\===
+\\---
+",
+            );
+        }
+
+        #[test]
+        fn pipes() {
+            assert_roundtrip("- a\n\n  b\\|c\n  \\-|-\n");
+            assert_roundtrip("a \\| b\n\\- | -\n");
+        }
+
+        #[test]
+        fn footnote_reference_before_colon() {
+            let md = "a\n[^1]\\: b\n\n[^1]: Note\n";
+            assert_roundtrip(md);
+            self::assert_eq!(markup(md), "a\n[^1]\\: b\n\n[^1]: Note\n");
+        }
+
+        #[test]
+        fn heading_starts() {
+            assert_roundtrip("## \\#\n\n# \\## a #\n\nb\n\\#\n===\n");
+            self::assert_eq!(markup("## \\#"), "## \\#\n");
+        }
+
+        #[test]
+        fn leading_and_trailing_whitespace() {
+            assert_roundtrip("&#32;  a &#32;\nb&#9;\n\n# &#32;c&#32;\n");
+        }
+
+        #[test]
+        fn from_events() {
+            let events = vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text("# *not* [markup]!".into()),
+                Event::Start(Tag::Link(LinkType::Inline, "a(b)".into(), "".into())),
+                Event::Text("x".into()),
+                Event::End(Tag::Link(LinkType::Inline, "a(b)".into(), "".into())),
+                Event::End(Tag::Paragraph),
+            ];
+            let md: String = MarkdownMarkup::new(events.clone().into_iter()).collect();
+            self::assert_eq!(md, "\\# \\*not\\* \\[markup\\]\\![x](a\\(b\\))\n");
+            self::assert_eq!(parse(&md), events);
+        }
+    }
+
+    #[test]
+    fn push_and_write() {
+        let mut s = String::from("> ");
+        push_markup(&mut s, Parser::new("*a*"));
+        self::assert_eq!(s, "> *a*\n");
+
+        let mut w = String::new();
+        write_markup(&mut w, Parser::new("*a*")).unwrap();
+        self::assert_eq!(w, "*a*\n");
+    }
+}
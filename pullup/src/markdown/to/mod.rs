@@ -1,4 +1,5 @@
 //! Convert Markdown _to_ other formats.
 
+pub mod markup;
 #[cfg(feature = "typst")]
 pub mod typst;
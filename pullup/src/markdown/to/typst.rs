@@ -1,353 +1,924 @@
 //! Convert Markdown to Typst.
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
+use crate::convert::{Converter, Input, Output};
 use crate::converter;
 use crate::markdown;
 use crate::typst;
 use crate::ParserEvent;
 
+pub mod math;
+
+#[cfg(feature = "builder")]
+mod builder;
+
+#[cfg(feature = "builder")]
+pub use builder::Conversion;
+
 converter!(
     /// Convert Markdown paragraphs to Typst paragraphs.
     ConvertParagraphs,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Paragraph))) => {
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Paragraph)) => {
                 Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Paragraph)))
             },
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Paragraph))) => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Paragraph)) => {
                 Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Paragraph)))
             },
-            x => x,
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Markdown text to Typst text.
+    ///
+    /// The Typst writer escapes the text and math is handled by [`ConvertMath`].
+    ConvertText,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Text(t)) => {
+                Some(ParserEvent::Typst(typst::Event::Text(t)))
+            },
+            x => Some(x),
     }
 });
 
-/// Convert Markdown text to Typst text.
-pub struct ConvertText<T> {
-    code: VecDeque<()>,
-    iter: T,
+/// Convert LaTeX math in Markdown text to Typst math.
+///
+/// Recognizes `$...$` and `\(...\)` as inline math and `$$...$$` and `\[...\]` as
+/// block math, like mdBook's MathJax support. Note Markdown escapes are processed
+/// first, so `\(` must be written as `\\(` in the Markdown source. Equations are
+/// translated with [`math::latex_to_typst`].
+///
+/// Must run before the Markdown text and soft breaks it looks at are converted.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertMath {
+    code: bool,
+}
+
+impl ConvertMath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Convert a run of text and soft breaks, which may contain math split across
+    /// events.
+    fn run<'a>(run: Vec<markdown::Event<'a>>, output: &mut Output<'_, 'a>) {
+        let text = run
+            .iter()
+            .map(|e| match e {
+                markdown::Event::Text(t) => t.as_ref(),
+                _ => "\n",
+            })
+            .collect::<String>();
+        let spans = math_spans(&text);
+        if spans.is_empty() {
+            output.emit_many(run.into_iter().map(ParserEvent::Markdown));
+            return;
+        }
+
+        let mut text_start = 0;
+        for (start, end, inner, display) in spans {
+            Self::text(&text[text_start..start], output);
+            output.emit(ParserEvent::Typst(typst::Event::Math(
+                math::latex_to_typst(inner).into(),
+                display,
+            )));
+            text_start = end;
+        }
+        Self::text(&text[text_start..], output);
+    }
+
+    fn text(text: &str, output: &mut Output<'_, '_>) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                output.emit(ParserEvent::Markdown(markdown::Event::SoftBreak));
+            }
+            if !line.is_empty() {
+                output.emit(ParserEvent::Markdown(markdown::Event::Text(
+                    line.to_string().into(),
+                )));
+            }
+        }
+    }
 }
 
-impl<'a, T> ConvertText<T>
-where
-    T: Iterator<Item = ParserEvent<'a>>,
-{
-    pub fn new(iter: T) -> Self {
-        ConvertText {
-            code: VecDeque::new(),
-            iter,
+impl<'a> Converter<'a> for ConvertMath {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            event @ ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::CodeBlock(_))) => {
+                self.code = true;
+                output.emit(event)
+            }
+            event @ ParserEvent::Markdown(markdown::Event::End(markdown::Tag::CodeBlock(_))) => {
+                self.code = false;
+                output.emit(event)
+            }
+            ParserEvent::Markdown(markdown::Event::Text(t)) if !self.code => {
+                // Math may span several text events and lines.
+                let mut run = vec![markdown::Event::Text(t)];
+                while let Some(ParserEvent::Markdown(e)) = input.next_if(|e| {
+                    matches!(
+                        e,
+                        ParserEvent::Markdown(
+                            markdown::Event::Text(_) | markdown::Event::SoftBreak
+                        )
+                    )
+                }) {
+                    run.push(e);
+                }
+                Self::run(run, output);
+            }
+            x => output.emit(x),
         }
     }
 }
 
-impl<'a, T> Iterator for ConvertText<T>
-where
-    T: Iterator<Item = ParserEvent<'a>>,
-{
-    type Item = ParserEvent<'a>;
+/// Convert Markdown footnotes to Typst footnotes.
+///
+/// Footnote definitions are removed and their content is placed in a labelled Typst
+/// footnote at the first reference. Later references to the same definition refer to
+/// that footnote, so it is shown with the same number. References without a definition
+/// are kept as text.
+///
+/// Definitions must come before their references, which
+/// [`FootnotesFirst`](markdown::FootnotesFirst) and the mdBook parser take care of.
+/// Nothing is buffered, so a reference to a definition that comes later is kept as
+/// text too.
+///
+/// Must run before the Markdown events inside definitions are converted.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertFootnotes<'a> {
+    definitions: HashMap<markdown::CowStr<'a>, Vec<ParserEvent<'a>>>,
+    /// The Typst labels of the footnotes written so far, by definition label.
+    labels: HashMap<markdown::CowStr<'a>, String>,
+    /// The number of footnotes written, to keep labels unique across chapters.
+    count: usize,
+}
+
+impl<'a> ConvertFootnotes<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match (self.code.pop_back(), self.iter.next()) {
-            // In code, include the unescaped text.
-            (Some(_), Some(ParserEvent::Markdown(markdown::Event::Text(t)))) => {
-                Some(ParserEvent::Typst(typst::Event::Text(t)))
+    /// Collect the content of a footnote definition up to its end.
+    fn definition(input: &mut Input<'_, 'a>) -> Vec<ParserEvent<'a>> {
+        let mut body = vec![];
+        let mut depth = 0;
+        for event in input {
+            match &event {
+                ParserEvent::Markdown(markdown::Event::Start(
+                    markdown::Tag::FootnoteDefinition(_),
+                )) => depth += 1,
+                ParserEvent::Markdown(markdown::Event::End(markdown::Tag::FootnoteDefinition(
+                    _,
+                ))) => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                _ => {}
             }
-            // Not in code, escape the text using typist escaping rules.
-            (None, Some(ParserEvent::Markdown(markdown::Event::Text(t)))) => {
-                if t.trim().starts_with("\\[") && t.trim().ends_with("\\]") {
-                    // Strip out mdbook's non-standard MathJax.
-                    // TODO: Translate to typst math and/or expose this as a typed
-                    // markdown event.
-                    self.next()
-                } else {
-                    Some(ParserEvent::Typst(typst::Event::Text(t)))
+            body.push(event);
+        }
+
+        // A single paragraph is inlined into the footnote.
+        let paragraphs = body
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Paragraph))
+                )
+            })
+            .count();
+        if paragraphs == 1
+            && matches!(
+                body.first(),
+                Some(ParserEvent::Markdown(markdown::Event::Start(
+                    markdown::Tag::Paragraph
+                )))
+            )
+            && matches!(
+                body.last(),
+                Some(ParserEvent::Markdown(markdown::Event::End(
+                    markdown::Tag::Paragraph
+                )))
+            )
+        {
+            body.pop();
+            body.remove(0);
+        }
+        body
+    }
+
+    /// Emit a footnote for a reference, or the reference as text if it has no
+    /// definition.
+    fn footnote(
+        &mut self,
+        label: markdown::CowStr<'a>,
+        stack: &mut Vec<markdown::CowStr<'a>>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        if let Some(written) = self.labels.get(&label) {
+            let written = written.clone().into();
+            output.emit(ParserEvent::Typst(typst::Event::FootnoteReference(written)));
+            return;
+        }
+        let Some(body) = self
+            .definitions
+            .get(&label)
+            .filter(|_| !stack.contains(&label))
+            .cloned()
+        else {
+            output.emit(ParserEvent::Markdown(markdown::Event::Text(
+                format!("[^{label}]").into(),
+            )));
+            return;
+        };
+        stack.push(label.clone());
+        output.emit(ParserEvent::Typst(typst::Event::Start(
+            typst::Tag::Footnote,
+        )));
+        for event in body {
+            match event {
+                // Footnotes can reference other footnotes.
+                ParserEvent::Markdown(markdown::Event::FootnoteReference(label)) => {
+                    self.footnote(label, stack, output)
                 }
+                event => output.emit(event),
+            }
+        }
+        output.emit(ParserEvent::Typst(typst::Event::End(typst::Tag::Footnote)));
+        stack.pop();
+        self.count += 1;
+        let written = format!("footnote-{}", self.count);
+        output.emit(ParserEvent::Typst(typst::Event::Label(
+            written.clone().into(),
+        )));
+        self.labels.insert(label, written);
+    }
+}
+
+impl<'a> Converter<'a> for ConvertFootnotes<'a> {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::FootnoteDefinition(
+                label,
+            ))) => {
+                let body = Self::definition(input);
+                self.definitions.insert(label, body);
             }
-            // Track code start.
-            (
-                _,
-                event @ Some(ParserEvent::Markdown(markdown::Event::Start(
-                    markdown::Tag::CodeBlock(_),
-                ))),
-            ) => {
-                self.code.push_back(());
-                event
+            // Footnotes do not cross mdBook chapters.
+            #[cfg(feature = "mdbook")]
+            event @ ParserEvent::Mdbook(_) => {
+                self.definitions.clear();
+                self.labels.clear();
+                output.emit(event);
             }
-            // Track code end.
-            (
-                _,
-                event @ Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::CodeBlock(
-                    _,
-                )))),
-            ) => {
-                let _ = self.code.pop_back();
-                event
+            ParserEvent::Markdown(markdown::Event::FootnoteReference(label)) => {
+                self.footnote(label, &mut vec![], output)
+            }
+            event => output.emit(event),
+        }
+    }
+}
+
+/// Find math in text. Returns the start and end of each equation including its
+/// delimiters, the LaTeX inside, and how it is displayed.
+fn math_spans(text: &str) -> Vec<(usize, usize, &str, typst::MathDisplay)> {
+    let mut spans = vec![];
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        // An escaped dollar sign.
+        if rest.starts_with("\\$") {
+            i += 2;
+            continue;
+        }
+        let found = if let Some(r) = rest.strip_prefix("\\[") {
+            r.find("\\]")
+                .map(|end| (2, end, 2, typst::MathDisplay::Block))
+        } else if let Some(r) = rest.strip_prefix("\\(") {
+            r.find("\\)")
+                .map(|end| (2, end, 2, typst::MathDisplay::Inline))
+        } else if let Some(r) = rest.strip_prefix("$$") {
+            r.find("$$")
+                .map(|end| (2, end, 2, typst::MathDisplay::Block))
+        } else if let Some(r) = rest.strip_prefix('$') {
+            // Like Pandoc, the opening `$` must be followed by a non-space and the
+            // closing `$` preceded by a non-space and not followed by a digit, so
+            // prices like $5 and $10 stay text.
+            r.find('$')
+                .filter(|&end| {
+                    !r.starts_with(char::is_whitespace)
+                        && !r[..end].ends_with(char::is_whitespace)
+                        && !r[end + 1..].starts_with(|c: char| c.is_ascii_digit())
+                })
+                .map(|end| (1, end, 1, typst::MathDisplay::Inline))
+        } else {
+            None
+        };
+        match found {
+            Some((open, end, close, display)) if !rest[open..open + end].trim().is_empty() => {
+                spans.push((i, i + open + end + close, &rest[open..open + end], display));
+                i += open + end + close;
             }
-            (_, x) => x,
+            _ => i += rest.chars().next().map_or(1, char::len_utf8),
         }
     }
+    spans
 }
 
 converter!(
     /// Convert Markdown links to Typst links.
+    ///
+    /// Reference, collapsed, and shortcut links use the destination of their
+    /// definition. Links to undefined references have no destination, so only their
+    /// content is kept. Use [`UnknownReferences`](markdown::UnknownReferences) to
+    /// convert them by policy first.
     ConvertLinks,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Link(kind, url, _)))) => {
-                match kind {
-                    markdown::LinkType::Inline => Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Link(typst::LinkType::Content, url)))),
-                    /*
-                    markdown::LinkType::Reference => unimplemented!(),
-                    markdown::LinkType::ReferenceUnknown => unimplemented!(),
-                    markdown::LinkType::Collapsed => unimplemented!(),
-                    markdown::LinkType::CollapsedUnknown => unimplemented!(),
-                    markdown::LinkType::Shortcut => unimplemented!(),
-                    markdown::LinkType::ShortcutUnknown => unimplemented!(),
-                    */
-                    markdown::LinkType::Autolink => {
-                        Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Link(typst::LinkType::Autolink, url))))
-                    },
-                    markdown::LinkType::Email => {
-                        let url = "mailto:".to_string() + url.as_ref();
-                        Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Link(typst::LinkType::Url, url.into()))))
-                    },
-                    _ => this.iter.next()
-                }
-            },
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Link(kind, url, _)))) => {
-                match kind {
-                    markdown::LinkType::Inline => Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Link(typst::LinkType::Content, url)))),
-                    /*
-                    markdown::LinkType::Reference => unimplemented!(),
-                    markdown::LinkType::ReferenceUnknown => unimplemented!(),
-                    markdown::LinkType::Collapsed => unimplemented!(),
-                    markdown::LinkType::CollapsedUnknown => unimplemented!(),
-                    markdown::LinkType::Shortcut => unimplemented!(),
-                    markdown::LinkType::ShortcutUnknown => unimplemented!(),
-                    */
-                    markdown::LinkType::Autolink => {
-                        Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Link(typst::LinkType::Autolink, url))))
-                    },
-                    markdown::LinkType::Email => {
-                        let url = "mailto:".to_string() + url.as_ref();
-                        Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Link(typst::LinkType::Url, url.into()))))
-                    },
-                    _ => this.iter.next()
-                }
-            },
-            x => x,
-    }
+    |event: ParserEvent<'a>| {
+        let (start, kind, url) = match event {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Link(kind, url, _))) => {
+                (true, kind, url)
+            }
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Link(kind, url, _))) => {
+                (false, kind, url)
+            }
+            x => return Some(x),
+        };
+        let tag = match kind {
+            markdown::LinkType::Inline
+            | markdown::LinkType::Reference
+            | markdown::LinkType::Collapsed
+            | markdown::LinkType::Shortcut => typst::Tag::Link(typst::LinkType::Content, url),
+            markdown::LinkType::Autolink => typst::Tag::Link(typst::LinkType::Autolink, url),
+            markdown::LinkType::Email => {
+                let url = "mailto:".to_string() + url.as_ref();
+                typst::Tag::Link(typst::LinkType::Url, url.into())
+            }
+            markdown::LinkType::ReferenceUnknown
+            | markdown::LinkType::CollapsedUnknown
+            | markdown::LinkType::ShortcutUnknown => return None,
+        };
+        Some(ParserEvent::Typst(if start {
+            typst::Event::Start(tag)
+        } else {
+            typst::Event::End(tag)
+        }))
 });
 
-/// Convert Markdown images to Typst image function calls.
-/// This converter skips the alt text content inside image tags.
-/// It also ensures images are not inside paragraphs by closing the paragraph
-/// before the image and reopening it after if needed.
-pub struct ConvertImages<'a, T> {
-    in_image: bool,
+/// What to use as the caption of a figure converted from a Markdown image.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ImageCaptions {
+    /// The image title if it has one, otherwise the alt text.
+    #[default]
+    TitleOrAlt,
+    /// Only the image title.
+    Title,
+    /// Only the alt text.
+    Alt,
+    /// No caption.
+    None,
+}
+
+/// Convert Markdown images to Typst images.
+///
+/// An image that is the only content of its paragraph, or an HTML block, becomes a
+/// figure in place of the paragraph. The image title or alt text becomes the caption,
+/// according to the [`ImageCaptions`] policy. Alt text that is not used as the caption
+/// is kept as the image's `alt`. Other images stay in the running text, with their alt
+/// text as the image's `alt`.
+///
+/// Attributes right after the image, like `![Cat](cat.png){#fig-cat width=50%}`, set the
+/// label and the image width and height. HTML `<img>` tags with a `src` are converted
+/// too, using their `width`, `height`, `alt`, and `title` attributes.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertImages {
+    captions: ImageCaptions,
     in_paragraph: bool,
-    buffer: VecDeque<ParserEvent<'a>>,
-    iter: T,
 }
 
-impl<'a, T> ConvertImages<'a, T>
-where
-    T: Iterator<Item = ParserEvent<'a>>,
-{
-    pub fn new(iter: T) -> Self {
+/// An image read from Markdown events.
+struct ParsedImage<'a> {
+    path: String,
+    title: String,
+    /// The events of the alt text.
+    alt: Vec<ParserEvent<'a>>,
+    /// The plain text of the alt text.
+    alt_text: String,
+    attrs: ImageAttrs,
+}
+
+impl ParsedImage<'_> {
+    /// Read an `<img>` tag.
+    fn html(tag: &HtmlTag) -> Self {
+        let attr = |name: &str| {
+            tag.attrs
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        };
+        let alt_text = attr("alt").unwrap_or("").to_string();
+        let alt = if alt_text.is_empty() {
+            vec![]
+        } else {
+            vec![ParserEvent::Typst(typst::Event::Text(
+                alt_text.clone().into(),
+            ))]
+        };
+        ParsedImage {
+            path: attr("src").unwrap_or("").to_string(),
+            title: attr("title").unwrap_or("").to_string(),
+            alt,
+            alt_text,
+            attrs: ImageAttrs {
+                label: attr("id").filter(|id| !id.is_empty()).map(str::to_string),
+                width: attr("width").and_then(length),
+                height: attr("height").and_then(length),
+            },
+        }
+    }
+
+    fn image(&self, alt: bool) -> typst::Image<'static> {
+        let path = &self.path;
+        typst::Image {
+            path: path.strip_prefix("./").unwrap_or(path).to_string().into(),
+            width: self.attrs.width.clone().map(Into::into),
+            height: self.attrs.height.clone().map(Into::into),
+            alt: (alt && !self.alt_text.is_empty()).then(|| self.alt_text.clone().into()),
+        }
+    }
+}
+
+impl ConvertImages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_captions(captions: ImageCaptions) -> Self {
         ConvertImages {
-            in_image: false,
+            captions,
             in_paragraph: false,
-            buffer: VecDeque::new(),
-            iter,
         }
     }
-}
 
-impl<'a, T> Iterator for ConvertImages<'a, T>
-where
-    T: Iterator<Item = ParserEvent<'a>>,
-{
-    type Item = ParserEvent<'a>;
+    /// Read a Markdown image up to its end. Returns the image and the text after its
+    /// attributes, if any.
+    fn read<'a>(
+        input: &mut Input<'_, 'a>,
+        url: markdown::CowStr<'a>,
+        title: markdown::CowStr<'a>,
+    ) -> (ParsedImage<'a>, Option<ParserEvent<'a>>) {
+        let mut alt = vec![];
+        let mut alt_text = String::new();
+        loop {
+            match input.next() {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Image(..))))
+                | None => break,
+                Some(event) => {
+                    if let ParserEvent::Markdown(
+                        markdown::Event::Text(t) | markdown::Event::Code(t),
+                    )
+                    | ParserEvent::Typst(typst::Event::Text(t) | typst::Event::Code(t)) = &event
+                    {
+                        alt_text.push_str(t);
+                    }
+                    alt.push(event);
+                }
+            }
+        }
+        // Attributes are the start of the text after the image.
+        let next = input.next_if(|e| {
+            matches!(
+                e,
+                ParserEvent::Markdown(markdown::Event::Text(_))
+                    | ParserEvent::Typst(typst::Event::Text(_))
+            )
+        });
+        let (attrs, rest) = match next {
+            Some(ParserEvent::Markdown(markdown::Event::Text(t))) => {
+                let (attrs, rest) = ImageAttrs::parse_prefix(&t);
+                let rest = markdown::Event::Text(rest.to_string().into());
+                (attrs, Some(ParserEvent::Markdown(rest)))
+            }
+            Some(ParserEvent::Typst(typst::Event::Text(t))) => {
+                let (attrs, rest) = ImageAttrs::parse_prefix(&t);
+                let rest = typst::Event::Text(rest.to_string().into());
+                (attrs, Some(ParserEvent::Typst(rest)))
+            }
+            _ => (ImageAttrs::default(), None),
+        };
+        let image = ParsedImage {
+            path: url.to_string(),
+            title: title.to_string(),
+            alt,
+            alt_text,
+            attrs,
+        };
+        (image, rest)
+    }
+
+    /// Read the next image, if one follows. Returns the image and the text after its
+    /// attributes, if any.
+    fn read_next<'a>(
+        input: &mut Input<'_, 'a>,
+    ) -> Option<(ParsedImage<'a>, Option<ParserEvent<'a>>)> {
+        let image = input.next_if(|e| match e {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Image(..))) => true,
+            ParserEvent::Markdown(markdown::Event::Html(html)) => img_tag(html).is_some(),
+            _ => false,
+        });
+        match image? {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Image(_, url, title))) => {
+                Some(Self::read(input, url, title))
+            }
+            ParserEvent::Markdown(markdown::Event::Html(html)) => {
+                img_tag(&html).map(|tag| (ParsedImage::html(&tag), None))
+            }
+            _ => None,
+        }
+    }
+
+    /// Emit a figure for the image.
+    fn figure<'a>(&self, image: ParsedImage<'a>, output: &mut Output<'_, 'a>) {
+        let use_title = !image.title.is_empty()
+            && matches!(
+                self.captions,
+                ImageCaptions::TitleOrAlt | ImageCaptions::Title
+            );
+        let use_alt = !use_title
+            && !image.alt_text.is_empty()
+            && matches!(
+                self.captions,
+                ImageCaptions::TitleOrAlt | ImageCaptions::Alt
+            );
+        let tag = typst::Tag::Figure(image.image(!use_alt), image.attrs.label.map(Into::into));
+        output.emit(ParserEvent::Typst(typst::Event::Start(tag.clone())));
+        if use_title {
+            output.emit(ParserEvent::Typst(typst::Event::Text(image.title.into())));
+        } else if use_alt {
+            output.emit_many(image.alt);
+        }
+        output.emit(ParserEvent::Typst(typst::Event::End(tag)));
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // If we have buffered events, return them first
-        if let Some(event) = self.buffer.pop_front() {
-            return Some(event);
+    /// Emit the image in the running text, followed by `rest`.
+    fn inline<'a>(
+        image: ParsedImage<'a>,
+        rest: Option<ParserEvent<'a>>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        output.emit(ParserEvent::Typst(typst::Event::Image(image.image(true))));
+        if let Some(label) = image.attrs.label {
+            output.emit(ParserEvent::Typst(typst::Event::Label(label.into())));
         }
+        output.emit_many(rest.filter(|event| {
+            !matches!(event, ParserEvent::Markdown(markdown::Event::Text(t))
+                | ParserEvent::Typst(typst::Event::Text(t)) if t.is_empty())
+        }));
+    }
+}
 
-        match self.iter.next() {
-            Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Paragraph))) => {
+impl<'a> Converter<'a> for ConvertImages {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            start @ (ParserEvent::Typst(typst::Event::Start(typst::Tag::Paragraph))
+            | ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Paragraph))) => {
                 self.in_paragraph = true;
-                Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Paragraph)))
-            },
-            Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Paragraph))) => {
-                self.in_paragraph = false;
-                Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Paragraph)))
-            },
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Image(_, url, _)))) => {
-                self.in_image = true;
-                // If we're in a paragraph, close it before the image
-                if self.in_paragraph {
-                    self.buffer.push_back(ParserEvent::Typst(typst::Event::End(typst::Tag::Paragraph)));
+                let Some((image, rest)) = Self::read_next(input) else {
+                    output.emit(start);
+                    return;
+                };
+                // Whitespace before the end of the paragraph is dropped.
+                let rest = rest.filter(|event| {
+                    !matches!(event, ParserEvent::Markdown(markdown::Event::Text(t))
+                        | ParserEvent::Typst(typst::Event::Text(t)) if t.trim().is_empty())
+                });
+                let end = input.next_if(|e| {
+                    rest.is_none()
+                        && matches!(
+                            e,
+                            ParserEvent::Typst(typst::Event::End(typst::Tag::Paragraph))
+                                | ParserEvent::Markdown(markdown::Event::End(
+                                    markdown::Tag::Paragraph
+                                ))
+                        )
+                });
+                if end.is_some() {
+                    // The figure takes the place of the paragraph.
                     self.in_paragraph = false;
+                    self.figure(image, output);
+                } else {
+                    output.emit(start);
+                    Self::inline(image, rest, output);
                 }
-                // Convert image start to FunctionCall event
-                // The URL needs to be wrapped in quotes for the function call
-                // Remove leading "./" if present
-                let url_str = url.as_ref().strip_prefix("./").unwrap_or(url.as_ref());
-                let url_str = format!("\"{}\"", url_str);
-                Some(ParserEvent::Typst(typst::Event::FunctionCall(None, "image".into(), vec![url_str.into()])))
+            }
+            end @ (ParserEvent::Typst(typst::Event::End(typst::Tag::Paragraph))
+            | ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Paragraph))) => {
+                self.in_paragraph = false;
+                output.emit(end)
+            }
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Image(_, url, title))) => {
+                let (image, rest) = Self::read(input, url, title);
+                Self::inline(image, rest, output);
+            }
+            ParserEvent::Markdown(markdown::Event::Html(html)) => match img_tag(&html) {
+                // HTML blocks are not in a paragraph.
+                Some(tag) if !self.in_paragraph => self.figure(ParsedImage::html(&tag), output),
+                Some(tag) => Self::inline(ParsedImage::html(&tag), None, output),
+                None => output.emit(ParserEvent::Markdown(markdown::Event::Html(html))),
             },
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Image(_, _, _)))) => {
-                self.in_image = false;
-                // Skip the end tag and check if there's a following paragraph end that we should also skip
-                // (since we already closed the paragraph before the image)
-                match self.iter.next() {
-                    Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Paragraph))) if !self.in_paragraph => {
-                        // This paragraph end was for the paragraph that contained the image
-                        // We already closed it, so skip this one
-                        self.next()
-                    },
-                    other => other,
+            x => output.emit(x),
+        }
+    }
+}
+
+/// Parse `html` if it is a single `<img>` tag with a `src`.
+fn img_tag(html: &str) -> Option<HtmlTag> {
+    HtmlTag::parse(html.trim())
+        .filter(|tag| tag.name == "img" && !tag.closing)
+        .filter(|tag| tag.attrs.iter().any(|(k, _)| k.eq_ignore_ascii_case("src")))
+}
+
+/// Attributes of an image, written like `{#label width=50% height=3cm}`.
+#[derive(Debug, Default)]
+struct ImageAttrs {
+    label: Option<String>,
+    width: Option<String>,
+    height: Option<String>,
+}
+
+impl ImageAttrs {
+    /// Parse the attributes at the start of `s`. Returns the attributes and the rest of
+    /// `s`, or no attributes and all of `s` if it does not start with valid attributes.
+    fn parse_prefix(s: &str) -> (Self, &str) {
+        let mut attrs = Self::default();
+        let Some((inner, rest)) = s.strip_prefix('{').and_then(|s| s.split_once('}')) else {
+            return (attrs, s);
+        };
+        for attr in inner.split_whitespace() {
+            if let Some(label) = attr.strip_prefix('#').filter(|l| !l.is_empty()) {
+                attrs.label = Some(label.to_string());
+            } else if attr.starts_with('.') {
+                // Classes have no Typst equivalent.
+            } else if let Some((key, value)) = attr.split_once('=') {
+                let value = value.trim_matches(|c| c == '"' || c == '\'');
+                match key {
+                    "width" => attrs.width = length(value),
+                    "height" => attrs.height = length(value),
+                    _ => (),
                 }
-            },
-            Some(event) if self.in_image => {
-                // Skip all content inside image tags (alt text)
-                self.next()
-            },
-            x => x,
+            } else {
+                return (Self::default(), s);
+            }
         }
+        (attrs, rest)
     }
 }
 
 converter!(
     /// Convert Markdown **strong** tags to Typst strong tags.
     ConvertStrong,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Strong))) => {
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Strong)) => {
                 Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Strong)))
             },
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Strong))) => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Strong)) => {
                 Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Strong)))
             },
-            x => x,
+            x => Some(x),
     }
 });
 
 converter!(
     /// Convert Markdown _emphasis_ tags to Typst emphasis tags.
     ConvertEmphasis,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Emphasis))) => {
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Emphasis)) => {
                 Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Emphasis)))
             },
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Emphasis))) => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Emphasis)) => {
                 Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Emphasis)))
             },
-            x => x,
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Markdown ~~strikethrough~~ tags to Typst strikethrough tags.
+    ConvertStrikethrough,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Strikethrough)) => {
+                Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Strikethrough)))
+            },
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Strikethrough)) => {
+                Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Strikethrough)))
+            },
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Markdown task list markers to Typst checkbox glyphs.
+    ConvertTaskLists,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::TaskListMarker(checked)) => {
+                Some(ParserEvent::Typst(typst::Event::TaskListMarker(checked)))
+            },
+            x => Some(x),
     }
 });
 
 converter!(
     /// Convert Markdown soft breaks to Typst line breaks.
     ConvertSoftBreaks,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Markdown(markdown::Event::SoftBreak)) => {
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::SoftBreak) => {
                 Some(ParserEvent::Typst(typst::Event::Text(" ".into())))
             },
-            x => x,
+            x => Some(x),
     }
 });
 
 converter!(
     /// Convert Markdown hard breaks to Typst line breaks.
     ConvertHardBreaks,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Markdown(markdown::Event::HardBreak)) => {
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::HardBreak) => {
                 Some(ParserEvent::Typst(typst::Event::Linebreak))
             },
-            x => x,
+            x => Some(x),
     }
 });
 
 converter!(
     /// Convert Markdown blockquotes to Typst quotes.
     ConvertBlockQuotes,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::BlockQuote))) => {
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::BlockQuote)) => {
                 Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Quote(typst::QuoteType::Block, typst::QuoteQuotes::Auto, None))))
             },
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::BlockQuote))) => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::BlockQuote)) => {
                 Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Quote(typst::QuoteType::Block, typst::QuoteQuotes::Auto, None))))
             },
-            x => x,
+            x => Some(x),
     }
 });
 
+/// Split a code block info string like `rust,ignore` or `rust editable` into the
+/// language and its attributes.
+fn info_string(
+    info: markdown::CowStr<'_>,
+) -> (Option<markdown::CowStr<'_>>, Vec<markdown::CowStr<'_>>) {
+    let separator = |c: char| c == ',' || c.is_whitespace();
+    let mut tokens: VecDeque<_> = match info {
+        markdown::CowStr::Borrowed(s) => s
+            .split(separator)
+            .filter(|x| !x.is_empty())
+            .map(markdown::CowStr::Borrowed)
+            .collect(),
+        s => s
+            .split(separator)
+            .filter(|x| !x.is_empty())
+            .map(|x| markdown::CowStr::from(x.to_string()))
+            .collect(),
+    };
+    let lang = tokens.pop_front();
+    (lang, tokens.into())
+}
+
 converter!(
     /// Convert Markdown code tags to Typst raw tags.
+    ///
+    /// The info string of fenced code blocks is split into the language and its
+    /// attributes, so `rust,ignore` becomes the language `rust` with the attribute
+    /// `ignore`.
     ConvertCode,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
+    |event: ParserEvent<'a>| {
+        let block = |kind: markdown::CodeBlockKind<'a>| match kind {
+            markdown::CodeBlockKind::Indented => typst::Tag::CodeBlock(None, vec![], typst::CodeBlockDisplay::Block),
+            markdown::CodeBlockKind::Fenced(val) => {
+                let (lang, attributes) = info_string(val);
+                typst::Tag::CodeBlock(lang, attributes, typst::CodeBlockDisplay::Block)
+            },
+        };
+        match event {
             // Inline.
-            Some(ParserEvent::Markdown(markdown::Event::Code(x))) => {
+            ParserEvent::Markdown(markdown::Event::Code(x)) => {
                 Some(ParserEvent::Typst(typst::Event::Code(x)))
             },
             // Block.
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::CodeBlock(kind)))) => {
-                match kind {
-                    markdown::CodeBlockKind::Indented => Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::CodeBlock(None, typst::CodeBlockDisplay::Block)))),
-                    markdown::CodeBlockKind::Fenced(val) => {
-                        let val = if val.as_ref() == "" {
-                            None
-                        } else {
-                            Some(val)
-                        };
-                        Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::CodeBlock(val, typst::CodeBlockDisplay::Block))))
-                    },
-                }
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::CodeBlock(kind))) => {
+                Some(ParserEvent::Typst(typst::Event::Start(block(kind))))
             },
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::CodeBlock(kind)))) => {
-                match kind {
-                    markdown::CodeBlockKind::Indented => Some(ParserEvent::Typst(typst::Event::End(typst::Tag::CodeBlock(None, typst::CodeBlockDisplay::Block)))),
-                    markdown::CodeBlockKind::Fenced(val) => {
-                        let val = if val.as_ref() == "" {
-                            None
-                        } else {
-                            Some(val)
-                        };
-                        Some(ParserEvent::Typst(typst::Event::End(typst::Tag::CodeBlock(val, typst::CodeBlockDisplay::Block))))
-                    },
-                }
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::CodeBlock(kind))) => {
+                Some(ParserEvent::Typst(typst::Event::End(block(kind))))
             },
-            x => x,
+            x => Some(x),
     }
 });
 
+/// Strip hidden lines from Rust code blocks.
+///
+/// Like mdBook, lines in Rust code blocks that start with `#` are hidden, unless the
+/// `#` is followed by `!` or `[` as in attributes. A line starting with `##` is shown
+/// with a single `#`.
+///
+/// Must run before the Markdown text in code blocks is converted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConvertHiddenLines;
+
+impl ConvertHiddenLines {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Remove hidden lines from the text of a Rust code block.
+fn strip_hidden_lines(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let code = line.trim_start();
+        let indent = &line[..line.len() - code.len()];
+        match code.strip_prefix('#') {
+            Some(rest) if rest.starts_with('#') => {
+                out.push_str(indent);
+                out.push_str(rest);
+            }
+            Some(rest) if !rest.starts_with(['!', '[']) => {}
+            _ => out.push_str(line),
+        }
+    }
+    out
+}
+
+impl<'a> Converter<'a> for ConvertHiddenLines {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::CodeBlock(
+                markdown::CodeBlockKind::Fenced(info),
+            ))) if info_string(info.clone()).0.as_deref() == Some("rust") => {
+                output.emit(ParserEvent::Markdown(markdown::Event::Start(
+                    markdown::Tag::CodeBlock(markdown::CodeBlockKind::Fenced(info)),
+                )));
+                // Collect the text of the block, which may be split into several events.
+                let mut text = String::new();
+                while let Some(ParserEvent::Markdown(markdown::Event::Text(t))) =
+                    input.next_if(|e| matches!(e, ParserEvent::Markdown(markdown::Event::Text(_))))
+                {
+                    text.push_str(&t);
+                }
+                let text = strip_hidden_lines(&text);
+                if !text.is_empty() {
+                    output.emit(ParserEvent::Markdown(markdown::Event::Text(text.into())));
+                }
+            }
+            x => output.emit(x),
+        }
+    }
+}
+
 converter!(
     /// Convert Markdown lists to Typst lists.
     ConvertLists,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
+    |event: ParserEvent<'a>| {
         // TODO: Handle tight.
 
         // TODO: Allow changing the marker and number format.
-        match this.iter.next() {
+        match event {
             // List start.
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::List(number)))) => {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::List(number))) => {
                 if let Some(start) = number {
                     // Numbered list
                     Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::NumberedList(start, None, false))))
@@ -358,7 +929,7 @@ converter!(
 
             },
             // List end.
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::List(number)))) => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::List(number))) => {
                 if let Some(start) = number {
                     // Numbered list
                     Some(ParserEvent::Typst(typst::Event::End(typst::Tag::NumberedList(start, None, false))))
@@ -369,14 +940,14 @@ converter!(
 
             },
             // List item start.
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Item))) => {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Item)) => {
                 Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Item)))
             },
             // List item end.
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Item))) => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Item)) => {
                 Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Item)))
             },
-            x => x,
+            x => Some(x),
         }
    }
 );
@@ -384,8 +955,7 @@ converter!(
 converter!(
     /// Convert Markdown headings to Typst headings.
     ConvertHeadings,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
+    |event: ParserEvent<'a>| {
         struct TypstLevel(std::num::NonZeroU8);
 
         impl std::ops::Deref for TypstLevel {
@@ -407,22 +977,22 @@ converter!(
                 }
             }
         }
-        match this.iter.next() {
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Heading(level, _, _)))) => {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Heading(level, _, _))) => {
                 let level: TypstLevel = level.into();
                 Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Heading(*level,
                     typst::TableOfContents::Include,
                     typst::Bookmarks::Include,
                 ))))
             },
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Heading(level, _, _))))  => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Heading(level, _, _)))  => {
                 let level: TypstLevel = level.into();
                 Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Heading(*level,
                     typst::TableOfContents::Include,
                     typst::Bookmarks::Include,
                 ))))
             },
-            x => x,
+            x => Some(x),
         }
    }
 );
@@ -430,11 +1000,10 @@ converter!(
 converter!(
     /// Convert Markdown tables to Typst tables.
     ConvertTables,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
+    |event: ParserEvent<'a>| {
+        match event {
             // Handle starting a table
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Table(alignment)))) => {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Table(alignment))) => {
                 Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::Table(
                     alignment.iter().map(|&a| match a {
                         markdown::Alignment::Left => typst::TableCellAlignment::Left,
@@ -442,10 +1011,11 @@ converter!(
                         markdown::Alignment::Right => typst::TableCellAlignment::Right,
                         markdown::Alignment::None => typst::TableCellAlignment::None,
                     }).collect(),
+                    typst::TableSettings::default(),
                 ))))
             },
             // Handle ending a table
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Table(alignment)))) => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Table(alignment))) => {
                 Some(ParserEvent::Typst(typst::Event::End(typst::Tag::Table(
                     alignment.iter().map(|&a| match a {
                         markdown::Alignment::Left => typst::TableCellAlignment::Left,
@@ -453,42 +1023,469 @@ converter!(
                         markdown::Alignment::Right => typst::TableCellAlignment::Right,
                         markdown::Alignment::None => typst::TableCellAlignment::None,
                     }).collect(),
+                    typst::TableSettings::default(),
                 ))))
             },
             // Handle header row
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableHead))) => {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableHead)) => {
                 Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::TableHead)))
             },
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableHead))) => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableHead)) => {
                 Some(ParserEvent::Typst(typst::Event::End(typst::Tag::TableHead)))
             },
             // Handle starting a row
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableRow))) => {
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableRow)) => {
                 Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::TableRow)))
             },
             // Handle ending a row
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableRow))) => {
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableRow)) => {
                 Some(ParserEvent::Typst(typst::Event::End(typst::Tag::TableRow)))
             },
             // Handle starting a cell
-            Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableCell))) => {
-                Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::TableCell)))
+            ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableCell)) => {
+                Some(ParserEvent::Typst(typst::Event::Start(typst::Tag::TableCell(
+                    typst::TableCellSpan::default(),
+                ))))
             },
             // Handle ending a cell
-            Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableCell))) => {
-                Some(ParserEvent::Typst(typst::Event::End(typst::Tag::TableCell)))
+            ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableCell)) => {
+                Some(ParserEvent::Typst(typst::Event::End(typst::Tag::TableCell(
+                    typst::TableCellSpan::default(),
+                ))))
             },
             // Pass through any other events
-            x => x,
+            x => Some(x),
         }
     }
 );
 
+/// Convert HTML tables in Markdown to Typst tables.
+///
+/// Rows in `<thead>`, and rows starting with a `<th>` cell before the first body row,
+/// become header rows. `colspan` and `rowspan` attributes become cell spans, and the
+/// widths of `<col>` elements become column widths. Text in cells is kept, as is
+/// Markdown content separated from the tags by blank lines, like lists or code blocks.
+/// `<br>` in cells, including cells of Markdown tables, becomes a line break. Other
+/// HTML is left as is.
+#[derive(Default)]
+pub struct ConvertHtmlTables<'a> {
+    /// The open tables, innermost last.
+    tables: Vec<HtmlTable<'a>>,
+    /// How many Markdown table cells are open.
+    markdown_cells: usize,
+    /// The start of a tag that continues in the next HTML event.
+    partial: String,
+}
+
+/// An open HTML table.
+#[derive(Default)]
+struct HtmlTable<'a> {
+    /// The table's arguments, or `None` once its start has been emitted. The start is
+    /// held back until the `<col>` elements are read.
+    settings: Option<typst::TableSettings<'a>>,
+    /// The arguments the start was emitted with, for the end.
+    started: typst::TableSettings<'a>,
+    in_head: bool,
+    /// Whether a body row was emitted.
+    has_body: bool,
+    /// The open row, if any. Its tag is `None` until its first cell decides whether it
+    /// is a header row.
+    row: Option<Option<typst::Tag<'a>>>,
+    /// The open cell, if any.
+    cell: Option<typst::TableCellSpan>,
+}
+
+impl<'a> ConvertHtmlTables<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Emit the start of the innermost table if it is held back.
+    fn start_table(&mut self, output: &mut Output<'_, 'a>) {
+        let Some(settings) = self.tables.last_mut().and_then(|t| t.settings.take()) else {
+            return;
+        };
+        self.tables.last_mut().expect("open table").started = settings.clone();
+        output.emit(ParserEvent::Typst(typst::Event::Start(typst::Tag::Table(
+            vec![],
+            settings,
+        ))));
+    }
+
+    fn end_cell(&mut self, output: &mut Output<'_, 'a>) {
+        if let Some(span) = self.tables.last_mut().and_then(|t| t.cell.take()) {
+            output.emit(ParserEvent::Typst(typst::Event::End(
+                typst::Tag::TableCell(span),
+            )));
+        }
+    }
+
+    fn end_row(&mut self, output: &mut Output<'_, 'a>) {
+        self.end_cell(output);
+        if let Some(Some(tag)) = self.tables.last_mut().and_then(|t| t.row.take()) {
+            output.emit(ParserEvent::Typst(typst::Event::End(tag)));
+        }
+    }
+
+    fn start_cell(
+        &mut self,
+        header: bool,
+        span: typst::TableCellSpan,
+        output: &mut Output<'_, 'a>,
+    ) {
+        self.start_table(output);
+        self.end_cell(output);
+        let table = self.tables.last_mut().expect("open table");
+        let row = match table.row.get_or_insert(None) {
+            Some(_) => None,
+            row => {
+                let tag = if table.in_head || (header && !table.has_body) {
+                    typst::Tag::TableHead
+                } else {
+                    table.has_body = true;
+                    typst::Tag::TableRow
+                };
+                *row = Some(tag.clone());
+                Some(tag)
+            }
+        };
+        table.cell = Some(span);
+        if let Some(tag) = row {
+            output.emit(ParserEvent::Typst(typst::Event::Start(tag)));
+        }
+        output.emit(ParserEvent::Typst(typst::Event::Start(
+            typst::Tag::TableCell(span),
+        )));
+    }
+
+    /// Convert the tags and text of an HTML event in or starting a table.
+    fn html(&mut self, html: &str, output: &mut Output<'_, 'a>) {
+        let html = std::mem::take(&mut self.partial) + html;
+        let mut rest = html.as_str();
+        while !rest.is_empty() {
+            if self.tables.is_empty() {
+                // HTML before a table is left as is.
+                let start = find_ignore_case(rest, "<table").unwrap_or(rest.len());
+                if !rest[..start].trim().is_empty() {
+                    let html = rest[..start].to_string();
+                    output.emit(ParserEvent::Markdown(markdown::Event::Html(html.into())));
+                }
+                rest = &rest[start..];
+                if rest.is_empty() {
+                    break;
+                }
+            }
+            let text_len = rest.find('<').unwrap_or(rest.len());
+            self.text(&rest[..text_len], output);
+            rest = &rest[text_len..];
+            if rest.is_empty() {
+                break;
+            }
+            let Some(tag_len) = rest.find('>').map(|i| i + 1) else {
+                self.partial = rest.to_string();
+                break;
+            };
+            self.tag(&rest[..tag_len], output);
+            rest = &rest[tag_len..];
+        }
+    }
+
+    /// Convert text between tags. Runs of whitespace are collapsed like in HTML.
+    fn text(&mut self, text: &str, output: &mut Output<'_, 'a>) {
+        if text.trim().is_empty() {
+            return;
+        }
+        let mut collapsed = String::with_capacity(text.len());
+        for (i, word) in text.split(char::is_whitespace).enumerate() {
+            if i > 0 && !collapsed.ends_with(' ') {
+                collapsed.push(' ');
+            }
+            collapsed.push_str(word);
+        }
+        self.start_table(output);
+        output.emit(ParserEvent::Typst(typst::Event::Text(
+            decode_entities(&collapsed).into(),
+        )));
+    }
+
+    fn tag(&mut self, tag: &str, output: &mut Output<'_, 'a>) {
+        let Some(parsed) = HtmlTag::parse(tag) else {
+            return self.text(tag, output);
+        };
+        let HtmlTag {
+            name,
+            closing,
+            attrs,
+        } = parsed;
+        let attr = |name: &str| {
+            attrs
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        };
+        let span = |name: &str| {
+            attr(name)
+                .and_then(|v| v.trim().parse::<u32>().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(1)
+        };
+        match (name.as_str(), closing) {
+            ("table", false) => {
+                self.start_table(output);
+                self.tables.push(HtmlTable {
+                    settings: Some(typst::TableSettings::default()),
+                    ..Default::default()
+                });
+            }
+            ("table", true) => {
+                self.end_row(output);
+                self.start_table(output);
+                if let Some(table) = self.tables.pop() {
+                    output.emit(ParserEvent::Typst(typst::Event::End(typst::Tag::Table(
+                        vec![],
+                        table.started,
+                    ))));
+                }
+            }
+            ("col", false) => {
+                let width = attr("width").or_else(|| {
+                    let style = attr("style")?;
+                    style.split(';').find_map(|decl| {
+                        let (k, v) = decl.split_once(':')?;
+                        (k.trim() == "width").then_some(v)
+                    })
+                });
+                let width = width.map_or("auto".to_string(), track_size);
+                if let Some(settings) = self.tables.last_mut().and_then(|t| t.settings.as_mut()) {
+                    for _ in 0..span("span") {
+                        settings.widths.push(width.clone().into());
+                    }
+                }
+            }
+            ("thead", closing) => {
+                self.end_row(output);
+                if let Some(table) = self.tables.last_mut() {
+                    table.in_head = !closing;
+                }
+            }
+            ("tbody" | "tfoot", _) => {
+                self.end_row(output);
+                if let Some(table) = self.tables.last_mut() {
+                    table.in_head = false;
+                }
+            }
+            ("tr", false) => {
+                self.start_table(output);
+                self.end_row(output);
+                if let Some(table) = self.tables.last_mut() {
+                    table.row = Some(None);
+                }
+            }
+            ("tr", true) => self.end_row(output),
+            (cell @ ("td" | "th"), false) => self.start_cell(
+                cell == "th",
+                typst::TableCellSpan {
+                    colspan: span("colspan"),
+                    rowspan: span("rowspan"),
+                },
+                output,
+            ),
+            ("td" | "th", true) => self.end_cell(output),
+            ("br", _) if self.tables.last().is_some_and(|t| t.cell.is_some()) => {
+                output.emit(ParserEvent::Typst(typst::Event::Linebreak))
+            }
+            ("colgroup", _) => {}
+            _ => {
+                let html = tag.to_string();
+                output.emit(ParserEvent::Markdown(markdown::Event::Html(html.into())));
+            }
+        }
+    }
+}
+
+/// A parsed HTML tag.
+struct HtmlTag {
+    /// The lowercase tag name.
+    name: String,
+    closing: bool,
+    attrs: Vec<(String, String)>,
+}
+
+impl HtmlTag {
+    /// Parse a tag like `<td colspan="2">`. Returns `None` for comments and text that is
+    /// not a tag.
+    fn parse(tag: &str) -> Option<Self> {
+        let inner = tag.strip_prefix('<')?.strip_suffix('>')?;
+        let inner = inner.strip_suffix('/').unwrap_or(inner);
+        let (closing, inner) = match inner.strip_prefix('/') {
+            Some(inner) => (true, inner),
+            None => (false, inner),
+        };
+        let name_len = inner
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(inner.len());
+        if name_len == 0 {
+            return None;
+        }
+        let mut attrs = vec![];
+        let mut rest = inner[name_len..].trim_start();
+        while !rest.is_empty() {
+            let key_len = rest
+                .find(|c: char| c == '=' || c.is_whitespace())
+                .unwrap_or(rest.len());
+            let key = &rest[..key_len];
+            rest = rest[key_len..].trim_start();
+            let value = match rest.strip_prefix('=').map(str::trim_start) {
+                Some(value) => {
+                    let (value, after) = match value.chars().next() {
+                        Some(q @ ('"' | '\'')) => {
+                            let end = value[1..].find(q).map_or(value.len(), |i| i + 1);
+                            (&value[1..end], value.get(end + 1..).unwrap_or(""))
+                        }
+                        _ => value.split_at(value.find(char::is_whitespace).unwrap_or(value.len())),
+                    };
+                    rest = after.trim_start();
+                    decode_entities(value)
+                }
+                None => String::new(),
+            };
+            if !key.is_empty() {
+                attrs.push((key.to_string(), value));
+            } else {
+                // Skip what cannot be parsed, like a stray `=`.
+                rest = rest.get(1..).unwrap_or("").trim_start();
+            }
+        }
+        Some(HtmlTag {
+            name: inner[..name_len].to_ascii_lowercase(),
+            closing,
+            attrs,
+        })
+    }
+}
+
+/// Find `needle`, which must be lowercase ASCII, in `s` ignoring case.
+fn find_ignore_case(s: &str, needle: &str) -> Option<usize> {
+    s.as_bytes()
+        .windows(needle.len())
+        .position(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Decode the HTML character references in `s`.
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                num => {
+                    let code = match num.strip_prefix('#') {
+                        Some(hex) if hex.starts_with(['x', 'X']) => {
+                            u32::from_str_radix(&hex[1..], 16).ok()
+                        }
+                        Some(dec) => dec.parse().ok(),
+                        None => None,
+                    };
+                    char::from_u32(code?)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Convert an HTML column width like `30%`, `120px`, or `2*` to a Typst track size.
+fn track_size(width: &str) -> String {
+    let width = width.trim();
+    match width.strip_suffix('*') {
+        Some(n) => {
+            let n = n.trim().parse::<f64>().ok().filter(|n| *n >= 0.0);
+            format!("{}fr", n.unwrap_or(1.0))
+        }
+        None => length(width).unwrap_or_else(|| "auto".to_string()),
+    }
+}
+
+/// Convert an HTML or CSS length like `50%`, `300px`, or `300` to a Typst length.
+/// Returns `None` for lengths Typst cannot express.
+fn length(length: &str) -> Option<String> {
+    let length = length.trim();
+    let number = |s: &str| s.trim().parse::<f64>().ok().filter(|n| *n >= 0.0);
+    if let Some(n) = length.strip_suffix('%').and_then(number) {
+        return Some(format!("{n}%"));
+    }
+    for unit in ["pt", "mm", "cm", "in", "em"] {
+        if let Some(n) = length.strip_suffix(unit).and_then(number) {
+            return Some(format!("{n}{unit}"));
+        }
+    }
+    // A CSS pixel is 0.75pt.
+    number(length.strip_suffix("px").unwrap_or(length)).map(|n| format!("{}pt", n * 0.75))
+}
+
+impl<'a> Converter<'a> for ConvertHtmlTables<'a> {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Markdown(markdown::Event::Html(html))
+                if !self.tables.is_empty() || find_ignore_case(&html, "<table").is_some() =>
+            {
+                self.html(&html, output)
+            }
+            ParserEvent::Markdown(markdown::Event::Html(html))
+                if self.markdown_cells > 0
+                    && HtmlTag::parse(html.trim()).is_some_and(|t| t.name == "br") =>
+            {
+                output.emit(ParserEvent::Typst(typst::Event::Linebreak))
+            }
+            event => {
+                match &event {
+                    ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableCell)) => {
+                        self.markdown_cells += 1
+                    }
+                    ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableCell)) => {
+                        self.markdown_cells = self.markdown_cells.saturating_sub(1)
+                    }
+                    _ => {}
+                }
+                // Markdown content in a table starts it.
+                self.start_table(output);
+                output.emit(event);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::markdown::CowStr;
     use crate::markdown::{MarkdownIter, Parser};
+    use crate::IteratorExt;
     use similar_asserts::assert_eq;
     use std::num::NonZeroU8;
 
@@ -514,7 +1511,7 @@ mod tests {
 
 ## This is **rad**!
 ";
-            let i = ConvertHeadings::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHeadings::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -562,7 +1559,7 @@ mod tests {
             let md = "\
 Cool [beans](https://example.com)
 ";
-            let i = ConvertLinks::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertLinks::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -588,7 +1585,7 @@ Cool [beans](https://example.com)
             let md = "\
 Cool <https://example.com>
 ";
-            let i = ConvertLinks::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertLinks::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -614,7 +1611,7 @@ Cool <https://example.com>
             let md = "\
 Who are <you@example.com>
 ";
-            let i = ConvertLinks::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertLinks::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -634,6 +1631,63 @@ Who are <you@example.com>
                 ]
             );
         }
+
+        #[test]
+        fn references() {
+            let md = "\
+[Full][ex], [ex][], and [ex].
+
+[ex]: https://example.com
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertLinks::new());
+            let link = TypstTag::Link(
+                typst::LinkType::Content,
+                CowStr::Borrowed("https://example.com"),
+            );
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Typst(TypstEvent::Start(link.clone())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Full"))),
+                    Typst(TypstEvent::End(link.clone())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(", "))),
+                    Typst(TypstEvent::Start(link.clone())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("ex"))),
+                    Typst(TypstEvent::End(link.clone())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(", and "))),
+                    Typst(TypstEvent::Start(link.clone())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("ex"))),
+                    Typst(TypstEvent::End(link)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("."))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                ]
+            );
+        }
+
+        #[test]
+        fn convert_unknown_link() {
+            let md = "See [it][missing].";
+            let mut callback = markdown::unknown_reference;
+            let parser = Parser::new_with_broken_link_callback(
+                md,
+                markdown::Options::empty(),
+                Some(&mut callback),
+            );
+            let i = MarkdownIter(parser).convert_with(ConvertLinks::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("See "))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("it"))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("."))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                ]
+            );
+        }
     }
 
     /// Markdown docs:
@@ -648,7 +1702,7 @@ Who are <you@example.com>
 
 I **love** cake!
 ";
-            let i = ConvertStrong::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertStrong::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -686,7 +1740,7 @@ I **love** cake!
 
 I *love* cake!
 ";
-            let i = ConvertEmphasis::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertEmphasis::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -722,7 +1776,7 @@ I *love* cake!
             let md = "\
 foo `bar` baz
 ";
-            let i = ConvertCode::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertCode::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -744,7 +1798,7 @@ whatever
     code 1
     code 2
 ";
-            let i = ConvertCode::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertCode::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -754,96 +1808,485 @@ whatever
                     Markdown(MdEvent::End(MdTag::Paragraph)),
                     Typst(TypstEvent::Start(TypstTag::CodeBlock(
                         None,
+                        vec![],
                         typst::CodeBlockDisplay::Block
                     ))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("code 1\n"))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("code 2\n"))),
                     Typst(TypstEvent::End(TypstTag::CodeBlock(
                         None,
+                        vec![],
+                        typst::CodeBlockDisplay::Block
+                    ))),
+                ]
+            );
+        }
+
+        #[test]
+        fn block() {
+            let md = "\
+```
+blah
+```
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertCode::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::CodeBlock(
+                        None,
+                        vec![],
+                        typst::CodeBlockDisplay::Block
+                    ))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("blah\n"))),
+                    Typst(TypstEvent::End(TypstTag::CodeBlock(
+                        None,
+                        vec![],
+                        typst::CodeBlockDisplay::Block
+                    ))),
+                ]
+            );
+        }
+
+        #[test]
+        fn block_with_fence() {
+            let md = "\
+```foo
+blah
+```
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertCode::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::CodeBlock(
+                        Some(CowStr::Borrowed("foo")),
+                        vec![],
                         typst::CodeBlockDisplay::Block
                     ))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("blah\n"))),
+                    Typst(TypstEvent::End(TypstTag::CodeBlock(
+                        Some(CowStr::Borrowed("foo")),
+                        vec![],
+                        typst::CodeBlockDisplay::Block
+                    ))),
+                ]
+            );
+        }
+
+        #[test]
+        fn block_with_attributes() {
+            let md = "\
+```rust,ignore should_panic
+blah
+```
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertCode::new());
+            let tag = TypstTag::CodeBlock(
+                Some(CowStr::Borrowed("rust")),
+                vec![CowStr::Borrowed("ignore"), CowStr::Borrowed("should_panic")],
+                typst::CodeBlockDisplay::Block,
+            );
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(tag.clone())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("blah\n"))),
+                    Typst(TypstEvent::End(tag)),
+                ]
+            );
+        }
+    }
+
+    mod hidden_lines {
+        use super::*;
+
+        fn rust(text: &str) -> Vec<ParserEvent<'_>> {
+            let tag = MdTag::CodeBlock(markdown::CodeBlockKind::Fenced(CowStr::Borrowed("rust")));
+            vec![
+                Markdown(MdEvent::Start(tag.clone())),
+                Markdown(MdEvent::Text(text.to_string().into())),
+                Markdown(MdEvent::End(tag)),
+            ]
+        }
+
+        #[test]
+        fn hidden() {
+            let md = "\
+```rust
+# fn main() {
+#[derive(Debug)]
+struct A;
+    #   let x = 1;
+## not hidden
+#
+# }
+```
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHiddenLines::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                rust("#[derive(Debug)]\nstruct A;\n# not hidden\n")
+            );
+        }
+
+        #[test]
+        fn with_attributes() {
+            let md = "\
+```rust,editable
+# use std::io;
+fn main() {}
+```
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHiddenLines::new());
+            let tag = MdTag::CodeBlock(markdown::CodeBlockKind::Fenced(CowStr::Borrowed(
+                "rust,editable",
+            )));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(tag.clone())),
+                    Markdown(MdEvent::Text("fn main() {}\n".to_string().into())),
+                    Markdown(MdEvent::End(tag)),
+                ]
+            );
+        }
+
+        #[test]
+        fn other_languages() {
+            let md = "\
+```python
+# A comment.
+```
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHiddenLines::new());
+            let tag = MdTag::CodeBlock(markdown::CodeBlockKind::Fenced(CowStr::Borrowed("python")));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(tag.clone())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("# A comment.\n"))),
+                    Markdown(MdEvent::End(tag)),
+                ]
+            );
+        }
+
+        #[test]
+        fn all_hidden() {
+            let md = "\
+```rust
+# fn main() {}
+```
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHiddenLines::new());
+            let mut expected = rust("");
+            expected.remove(1);
+
+            self::assert_eq!(i.collect::<Vec<super::ParserEvent>>(), expected);
+        }
+    }
+
+    /// Markdown docs:
+    /// * https://spec.commonmark.org/0.30/#text Typst docs:
+    /// * https://typst.app/docs/reference/text/
+    mod text {
+        use super::*;
+        #[test]
+        fn convert_text() {
+            let md = "\
+foo
+
+bar
+
+baz
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertText::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("foo"))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("bar"))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("baz"))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    /// mdBook docs:
+    /// * https://rust-lang.github.io/mdBook/format/mathjax.html
+    ///
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/math/
+    mod math {
+        use super::*;
+        use typst::MathDisplay;
+
+        #[test]
+        fn dollars() {
+            let md = "\
+Euler: $e^{i\\pi} + 1 = 0$ costs $5 and $10.
+
+$$
+\\sum_{i=1}^n i
+$$
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertMath::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Euler: "))),
+                    Typst(TypstEvent::Math(
+                        "e^(i pi) + 1 = 0".into(),
+                        MathDisplay::Inline
+                    )),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(" costs $5 and $10."))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Typst(TypstEvent::Math("sum_(i=1)^n i".into(), MathDisplay::Block)),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                ]
+            );
+        }
+
+        #[test]
+        fn mathjax() {
+            let md = "\
+\\\\[ \\frac{a}{b} \\\\] and \\\\( \\alpha_1 \\\\)
+
+```
+$x$
+```
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertMath::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Typst(TypstEvent::Math("frac(a, b)".into(), MathDisplay::Block)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(" and "))),
+                    Typst(TypstEvent::Math("alpha_1".into(), MathDisplay::Inline)),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::CodeBlock(
+                        markdown::CodeBlockKind::Fenced(CowStr::Borrowed(""))
+                    ))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("$x$\n"))),
+                    Markdown(MdEvent::End(MdTag::CodeBlock(
+                        markdown::CodeBlockKind::Fenced(CowStr::Borrowed(""))
+                    ))),
+                ]
+            );
+        }
+
+        #[test]
+        fn no_math() {
+            let md = "a *b*\nc";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertMath::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                MarkdownIter(Parser::new(md)).collect::<Vec<super::ParserEvent>>()
+            );
+        }
+    }
+
+    /// Markdown docs:
+    /// * https://github.github.com/gfm/#strikethrough-extension-
+    ///
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/text/strike/
+    mod strikethrough {
+        use super::*;
+        #[test]
+        fn convert_strikethrough() {
+            let md = "I ~~hate~~ love cake!";
+            let i = MarkdownIter(Parser::new_ext(md, markdown::Options::ENABLE_STRIKETHROUGH))
+                .convert_with(ConvertStrikethrough::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("I "))),
+                    Typst(TypstEvent::Start(TypstTag::Strikethrough)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("hate"))),
+                    Typst(TypstEvent::End(TypstTag::Strikethrough)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(" love cake!"))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    /// Markdown docs:
+    /// * https://github.github.com/gfm/#task-list-items-extension-
+    mod task_lists {
+        use super::*;
+        #[test]
+        fn convert_task_lists() {
+            let md = "\
+- [x] Bake
+- [ ] Eat
+";
+            let i = MarkdownIter(Parser::new_ext(md, markdown::Options::ENABLE_TASKLISTS))
+                .convert_with(ConvertTaskLists::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::List(None))),
+                    Markdown(MdEvent::Start(MdTag::Item)),
+                    Typst(TypstEvent::TaskListMarker(true)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Bake"))),
+                    Markdown(MdEvent::End(MdTag::Item)),
+                    Markdown(MdEvent::Start(MdTag::Item)),
+                    Typst(TypstEvent::TaskListMarker(false)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Eat"))),
+                    Markdown(MdEvent::End(MdTag::Item)),
+                    Markdown(MdEvent::End(MdTag::List(None))),
+                ]
+            );
+        }
+    }
+
+    /// Markdown docs:
+    /// * https://github.github.com/gfm/#footnotes-extension-
+    ///
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/footnote/
+    mod footnotes {
+        use super::*;
+
+        fn parse(md: &str) -> Vec<super::ParserEvent<'_>> {
+            MarkdownIter(markdown::FootnotesFirst::new(
+                md,
+                markdown::Options::ENABLE_FOOTNOTES,
+            ))
+            .convert_with(ConvertFootnotes::new())
+            .collect()
+        }
+
+        #[test]
+        fn definition_after_reference() {
+            let md = "\
+Claim[^note] and more.
+
+[^note]: A *source*.
+";
+            self::assert_eq!(
+                parse(md),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Claim"))),
+                    Typst(TypstEvent::Start(TypstTag::Footnote)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("A "))),
+                    Markdown(MdEvent::Start(MdTag::Emphasis)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("source"))),
+                    Markdown(MdEvent::End(MdTag::Emphasis)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("."))),
+                    Typst(TypstEvent::End(TypstTag::Footnote)),
+                    Typst(TypstEvent::Label("footnote-1".to_string().into())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(" and more."))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                ]
+            );
+        }
+
+        #[test]
+        fn definition_before_reference() {
+            let md = "\
+[^1]: First.
+
+[^2]: Unused.
+
+Text[^1].
+";
+            self::assert_eq!(
+                parse(md),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Text"))),
+                    Typst(TypstEvent::Start(TypstTag::Footnote)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("First."))),
+                    Typst(TypstEvent::End(TypstTag::Footnote)),
+                    Typst(TypstEvent::Label("footnote-1".to_string().into())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("."))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
                 ]
             );
         }
 
         #[test]
-        fn block() {
+        fn repeated_reference() {
             let md = "\
-```
-blah
-```
-";
-            let i = ConvertCode::new(MarkdownIter(Parser::new(&md)));
+A[^n] and B[^n].
 
+[^n]: Shared.
+";
             self::assert_eq!(
-                i.collect::<Vec<super::ParserEvent>>(),
+                parse(md),
                 vec![
-                    Typst(TypstEvent::Start(TypstTag::CodeBlock(
-                        None,
-                        typst::CodeBlockDisplay::Block
-                    ))),
-                    Markdown(MdEvent::Text(CowStr::Borrowed("blah\n"))),
-                    Typst(TypstEvent::End(TypstTag::CodeBlock(
-                        None,
-                        typst::CodeBlockDisplay::Block
-                    ))),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("A"))),
+                    Typst(TypstEvent::Start(TypstTag::Footnote)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Shared."))),
+                    Typst(TypstEvent::End(TypstTag::Footnote)),
+                    Typst(TypstEvent::Label("footnote-1".to_string().into())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(" and B"))),
+                    Typst(TypstEvent::FootnoteReference(
+                        "footnote-1".to_string().into()
+                    )),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("."))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
                 ]
             );
         }
 
         #[test]
-        fn block_with_fence() {
+        fn definition_after_reference_without_first_pass() {
             let md = "\
-```foo
-blah
-```
+Claim[^note].
+
+[^note]: Source.
 ";
-            let i = ConvertCode::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new_ext(md, markdown::Options::ENABLE_FOOTNOTES))
+                .convert_with(ConvertFootnotes::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
                 vec![
-                    Typst(TypstEvent::Start(TypstTag::CodeBlock(
-                        Some(CowStr::Borrowed("foo")),
-                        typst::CodeBlockDisplay::Block
-                    ))),
-                    Markdown(MdEvent::Text(CowStr::Borrowed("blah\n"))),
-                    Typst(TypstEvent::End(TypstTag::CodeBlock(
-                        Some(CowStr::Borrowed("foo")),
-                        typst::CodeBlockDisplay::Block
-                    ))),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Claim"))),
+                    Markdown(MdEvent::Text(CowStr::Boxed("[^note]".into()))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("."))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
                 ]
             );
         }
-    }
 
-    /// Markdown docs:
-    /// * https://spec.commonmark.org/0.30/#text Typst docs:
-    /// * https://typst.app/docs/reference/text/
-    mod text {
-        use super::*;
         #[test]
-        fn convert_text() {
-            let md = "\
-foo
-
-bar
-
-baz
-";
-            let i = ConvertText::new(MarkdownIter(Parser::new(&md)));
-
+        fn missing_definition() {
             self::assert_eq!(
-                i.collect::<Vec<super::ParserEvent>>(),
+                parse("Text[^missing]"),
                 vec![
                     Markdown(MdEvent::Start(MdTag::Paragraph)),
-                    Typst(TypstEvent::Text(CowStr::Borrowed("foo"))),
-                    Markdown(MdEvent::End(MdTag::Paragraph)),
-                    Markdown(MdEvent::Start(MdTag::Paragraph)),
-                    Typst(TypstEvent::Text(CowStr::Borrowed("bar"))),
-                    Markdown(MdEvent::End(MdTag::Paragraph)),
-                    Markdown(MdEvent::Start(MdTag::Paragraph)),
-                    Typst(TypstEvent::Text(CowStr::Borrowed("baz"))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Text"))),
+                    Markdown(MdEvent::Text(CowStr::Boxed("[^missing]".into()))),
                     Markdown(MdEvent::End(MdTag::Paragraph)),
                 ]
             );
@@ -853,6 +2296,7 @@ baz
     /// Markdown docs:
     /// * https://spec.commonmark.org/0.31.2/#hard-line-breaks
     /// * https://spec.commonmark.org/0.31.2/#soft-line-breaks
+    ///
     /// Typst docs:
     /// * https://typst.app/docs/reference/text/
     mod breaks {
@@ -865,7 +2309,7 @@ baz
 foo
 bar
 ";
-            let i = ConvertSoftBreaks::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertSoftBreaks::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -886,7 +2330,7 @@ bar
 foo  
 bar
 ";
-            let i = ConvertHardBreaks::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHardBreaks::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -915,7 +2359,7 @@ bar
 
 baz
 ";
-            let i = ConvertParagraphs::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertParagraphs::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -948,7 +2392,7 @@ baz
 * are
 * cool
 ";
-            let i = ConvertLists::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertLists::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -977,7 +2421,7 @@ baz
 1. cats are _too_
 2. birds are ok
 ";
-            let i = ConvertLists::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertLists::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -1005,7 +2449,7 @@ baz
 6. foo
 1. bar
 ";
-            let i = ConvertLists::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertLists::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -1030,7 +2474,7 @@ baz
 * multiple
   lines
 ";
-            let i = ConvertLists::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertLists::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -1056,7 +2500,7 @@ baz
         fn backslashes_in_backticks() {
             let md = r###"before `\` after"###;
 
-            let i = ConvertText::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertText::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -1075,7 +2519,7 @@ baz
         fn simple_blockquote() {
             let md = "> test";
 
-            let i = ConvertBlockQuotes::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertBlockQuotes::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -1102,7 +2546,7 @@ baz
         fn complex_blockquote() {
             let md = "> one\n> two\n> three";
 
-            let i = ConvertBlockQuotes::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertBlockQuotes::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -1132,6 +2576,25 @@ baz
     mod images {
         use super::*;
 
+        fn figure<'a>(path: &'a str, alt: Option<&'a str>, label: Option<&'a str>) -> TypstTag<'a> {
+            let image = typst::Image {
+                path: path.to_string().into(),
+                width: None,
+                height: None,
+                alt: alt.map(|a| a.to_string().into()),
+            };
+            TypstTag::Figure(image, label.map(|l| l.to_string().into()))
+        }
+
+        fn image<'a>(path: &'a str, alt: Option<&'a str>) -> typst::Image<'a> {
+            typst::Image {
+                path: path.to_string().into(),
+                width: None,
+                height: None,
+                alt: alt.map(|a| a.to_string().into()),
+            }
+        }
+
         #[test]
         fn convert_image() {
             let md = "\
@@ -1139,7 +2602,7 @@ baz
 
 ![整体交互流程图](./images/infeed/image2.png)
 ";
-            let i = ConvertImages::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertImages::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
@@ -1147,7 +2610,17 @@ baz
                     Markdown(MdEvent::Start(MdTag::Paragraph)),
                     Markdown(MdEvent::Text(CowStr::Borrowed("整体交互流程图"))),
                     Markdown(MdEvent::End(MdTag::Paragraph)),
-                    Typst(TypstEvent::FunctionCall(None, "image".into(), vec!["\"images/infeed/image2.png\"".into()])),
+                    Typst(TypstEvent::Start(figure(
+                        "images/infeed/image2.png",
+                        None,
+                        None
+                    ))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("整体交互流程图"))),
+                    Typst(TypstEvent::End(figure(
+                        "images/infeed/image2.png",
+                        None,
+                        None
+                    ))),
                 ]
             );
         }
@@ -1155,60 +2628,149 @@ baz
         #[test]
         fn convert_image_without_prefix() {
             let md = "![alt text](images/test.png)";
-            let i = ConvertImages::new(MarkdownIter(Parser::new(&md)));
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertImages::new());
 
             let events: Vec<_> = i.collect();
-            // Find the FunctionCall event
-            let image_call = events.iter().find(|e| {
-                matches!(e, Typst(TypstEvent::FunctionCall(_, f, _)) if f.as_ref() == "image")
-            });
-            assert!(image_call.is_some(), "Should find image function call");
-            if let Some(Typst(TypstEvent::FunctionCall(_, _, args))) = image_call {
-                assert_eq!(args[0].as_ref(), "\"images/test.png\"");
-            }
+            self::assert_eq!(
+                events[0],
+                Typst(TypstEvent::Start(figure("images/test.png", None, None)))
+            );
         }
 
         #[test]
-        fn convert_image_skips_alt_text() {
-            let md = "![This is alt text](image.png)";
-            let i = ConvertImages::new(MarkdownIter(Parser::new(&md)));
+        fn title_caption() {
+            let md = "![A *cat*](cat.png \"My cat\")";
+            let i = MarkdownIter(Parser::new(md))
+                .convert_with(ConvertEmphasis::new())
+                .convert_with(ConvertImages::new());
 
-            let events: Vec<_> = i.collect();
-            // Alt text should not appear in the output
-            let has_alt_text = events.iter().any(|e| {
-                matches!(e, Markdown(MdEvent::Text(t)) if t.as_ref() == "This is alt text")
-            });
-            assert!(!has_alt_text, "Alt text should be skipped");
-            
-            // But image call should be present
-            let image_call = events.iter().find(|e| {
-                matches!(e, Typst(TypstEvent::FunctionCall(_, f, _)) if f.as_ref() == "image")
-            });
-            assert!(image_call.is_some(), "Should find image function call");
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(figure("cat.png", Some("A cat"), None))),
+                    Typst(TypstEvent::Text("My cat".to_string().into())),
+                    Typst(TypstEvent::End(figure("cat.png", Some("A cat"), None))),
+                ]
+            );
         }
 
         #[test]
-        fn convert_image_in_paragraph_closes_paragraph() {
-            // Test that when an image is inside a paragraph, the paragraph is closed before the image
-            let md = "Some text ![alt text](image.png) more text";
-            let i = ConvertImages::new(ConvertParagraphs::new(MarkdownIter(Parser::new(&md))));
+        fn caption_policies() {
+            let md = "![Alt](cat.png \"Title\")";
+            let captions = |policy| {
+                MarkdownIter(Parser::new(md))
+                    .convert_with(ConvertImages::with_captions(policy))
+                    .collect::<Vec<super::ParserEvent>>()
+            };
 
-            let events: Vec<_> = i.collect();
-            // The paragraph should be closed before the image
-            let mut found_paragraph_end_before_image = false;
-            let mut found_image = false;
-            for event in &events {
-                if matches!(event, Typst(TypstEvent::FunctionCall(_, f, _)) if f.as_ref() == "image") {
-                    found_image = true;
-                    break;
-                }
-                if matches!(event, Typst(TypstEvent::End(TypstTag::Paragraph))) {
-                    found_paragraph_end_before_image = true;
-                }
+            self::assert_eq!(
+                captions(ImageCaptions::Title)[1],
+                Typst(TypstEvent::Text("Title".to_string().into()))
+            );
+            self::assert_eq!(
+                captions(ImageCaptions::Alt),
+                vec![
+                    Typst(TypstEvent::Start(figure("cat.png", None, None))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Alt"))),
+                    Typst(TypstEvent::End(figure("cat.png", None, None))),
+                ]
+            );
+            self::assert_eq!(
+                captions(ImageCaptions::None),
+                vec![
+                    Typst(TypstEvent::Start(figure("cat.png", Some("Alt"), None))),
+                    Typst(TypstEvent::End(figure("cat.png", Some("Alt"), None))),
+                ]
+            );
+        }
+
+        #[test]
+        fn attributes() {
+            let md = "![Cat](cat.png){#fig-cat width=50% height=\"120px\" .wide}\n\n![Dog](dog.png){not attributes}";
+            let i = MarkdownIter(Parser::new(md))
+                .convert_with(ConvertParagraphs::new())
+                .convert_with(ConvertImages::new());
+
+            let mut cat = figure("cat.png", None, Some("fig-cat"));
+            if let TypstTag::Figure(image, _) = &mut cat {
+                image.width = Some("50%".to_string().into());
+                image.height = Some("90pt".to_string().into());
+            }
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(cat.clone())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Cat"))),
+                    Typst(TypstEvent::End(cat)),
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Image(image("dog.png", Some("Dog")))),
+                    Markdown(MdEvent::Text("{not attributes}".to_string().into())),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+
+        #[test]
+        fn html_image() {
+            let md = "<img src=\"cat.png\" width=\"300\" alt=\"A cat\" id=\"cat\">\n";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertImages::new());
+
+            let mut cat = figure("cat.png", None, Some("cat"));
+            if let TypstTag::Figure(image, _) = &mut cat {
+                image.width = Some("225pt".to_string().into());
             }
-            assert!(found_image, "Should find image function call");
-            // When image is in paragraph, paragraph should be closed before image
-            // Note: This test may need adjustment based on actual markdown parsing behavior
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(cat.clone())),
+                    Typst(TypstEvent::Text("A cat".to_string().into())),
+                    Typst(TypstEvent::End(cat)),
+                ]
+            );
+        }
+
+        #[test]
+        fn inline_images() {
+            let md = "![](a.png) inline ![b](b.png){#b width=1em} image";
+            let i = MarkdownIter(Parser::new(md))
+                .convert_with(ConvertParagraphs::new())
+                .convert_with(ConvertImages::new());
+
+            let mut b = image("b.png", Some("b"));
+            b.width = Some("1em".to_string().into());
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Image(image("a.png", None))),
+                    Markdown(MdEvent::Text(" inline ".to_string().into())),
+                    Typst(TypstEvent::Image(b)),
+                    Typst(TypstEvent::Label("b".to_string().into())),
+                    Markdown(MdEvent::Text(" image".to_string().into())),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+
+        #[test]
+        fn inline_html_image() {
+            let md = "A <img src=\"a.png\" alt=\"a\"> b\n\n<img src=\"b.png\">\n";
+            let i = MarkdownIter(Parser::new(md))
+                .convert_with(ConvertParagraphs::new())
+                .convert_with(ConvertImages::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("A "))),
+                    Typst(TypstEvent::Image(image("a.png", Some("a")))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(" b"))),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Start(figure("b.png", None, None))),
+                    Typst(TypstEvent::End(figure("b.png", None, None))),
+                ]
+            );
         }
     }
 
@@ -1222,38 +2784,58 @@ baz
 |---------|---------|
 | Cell1   | Cell2   |
 ";
-            let i = ConvertTables::new(MarkdownIter(Parser::new_ext(
-                &md,
-                pulldown_cmark::Options::ENABLE_TABLES,
-            )));
+            let i = MarkdownIter(Parser::new_ext(md, pulldown_cmark::Options::ENABLE_TABLES))
+                .convert_with(ConvertTables::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
                 vec![
-                    Typst(TypstEvent::Start(TypstTag::Table(vec![
-                        typst::TableCellAlignment::None,
-                        typst::TableCellAlignment::None,
-                    ]))),
+                    Typst(TypstEvent::Start(TypstTag::Table(
+                        vec![
+                            typst::TableCellAlignment::None,
+                            typst::TableCellAlignment::None
+                        ],
+                        typst::TableSettings::default(),
+                    ))),
                     Typst(TypstEvent::Start(TypstTag::TableHead)),
-                    Typst(TypstEvent::Start(TypstTag::TableCell)),
+                    Typst(TypstEvent::Start(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("Header1"))),
-                    Typst(TypstEvent::End(TypstTag::TableCell)),
-                    Typst(TypstEvent::Start(TypstTag::TableCell)),
+                    Typst(TypstEvent::End(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
+                    Typst(TypstEvent::Start(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("Header2"))),
-                    Typst(TypstEvent::End(TypstTag::TableCell)),
+                    Typst(TypstEvent::End(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Typst(TypstEvent::End(TypstTag::TableHead)),
                     Typst(TypstEvent::Start(TypstTag::TableRow)),
-                    Typst(TypstEvent::Start(TypstTag::TableCell)),
+                    Typst(TypstEvent::Start(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("Cell1"))),
-                    Typst(TypstEvent::End(TypstTag::TableCell)),
-                    Typst(TypstEvent::Start(TypstTag::TableCell)),
+                    Typst(TypstEvent::End(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
+                    Typst(TypstEvent::Start(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("Cell2"))),
-                    Typst(TypstEvent::End(TypstTag::TableCell)),
+                    Typst(TypstEvent::End(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Typst(TypstEvent::End(TypstTag::TableRow)),
-                    Typst(TypstEvent::End(TypstTag::Table(vec![
-                        typst::TableCellAlignment::None,
-                        typst::TableCellAlignment::None,
-                    ]))),
+                    Typst(TypstEvent::End(TypstTag::Table(
+                        vec![
+                            typst::TableCellAlignment::None,
+                            typst::TableCellAlignment::None
+                        ],
+                        typst::TableSettings::default(),
+                    ))),
                 ]
             );
         }
@@ -1265,40 +2847,319 @@ baz
 |:--------|:-------:|
 | Cell1   | Cell2   |
 ";
-            let i = ConvertTables::new(MarkdownIter(Parser::new_ext(
-                &md,
-                pulldown_cmark::Options::ENABLE_TABLES,
-            )));
+            let i = MarkdownIter(Parser::new_ext(md, pulldown_cmark::Options::ENABLE_TABLES))
+                .convert_with(ConvertTables::new());
 
             self::assert_eq!(
                 i.collect::<Vec<super::ParserEvent>>(),
                 vec![
-                    Typst(TypstEvent::Start(TypstTag::Table(vec![
-                        typst::TableCellAlignment::Left,
-                        typst::TableCellAlignment::Center,
-                    ]))),
+                    Typst(TypstEvent::Start(TypstTag::Table(
+                        vec![
+                            typst::TableCellAlignment::Left,
+                            typst::TableCellAlignment::Center
+                        ],
+                        typst::TableSettings::default(),
+                    ))),
                     Typst(TypstEvent::Start(TypstTag::TableHead)),
-                    Typst(TypstEvent::Start(TypstTag::TableCell)),
+                    Typst(TypstEvent::Start(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("Header1"))),
-                    Typst(TypstEvent::End(TypstTag::TableCell)),
-                    Typst(TypstEvent::Start(TypstTag::TableCell)),
+                    Typst(TypstEvent::End(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
+                    Typst(TypstEvent::Start(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("Header2"))),
-                    Typst(TypstEvent::End(TypstTag::TableCell)),
+                    Typst(TypstEvent::End(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Typst(TypstEvent::End(TypstTag::TableHead)),
                     Typst(TypstEvent::Start(TypstTag::TableRow)),
-                    Typst(TypstEvent::Start(TypstTag::TableCell)),
+                    Typst(TypstEvent::Start(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("Cell1"))),
-                    Typst(TypstEvent::End(TypstTag::TableCell)),
-                    Typst(TypstEvent::Start(TypstTag::TableCell)),
+                    Typst(TypstEvent::End(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
+                    Typst(TypstEvent::Start(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
                     Markdown(MdEvent::Text(CowStr::Borrowed("Cell2"))),
-                    Typst(TypstEvent::End(TypstTag::TableCell)),
+                    Typst(TypstEvent::End(TypstTag::TableCell(
+                        typst::TableCellSpan::default()
+                    ))),
+                    Typst(TypstEvent::End(TypstTag::TableRow)),
+                    Typst(TypstEvent::End(TypstTag::Table(
+                        vec![
+                            typst::TableCellAlignment::Left,
+                            typst::TableCellAlignment::Center
+                        ],
+                        typst::TableSettings::default(),
+                    ))),
+                ]
+            );
+        }
+    }
+
+    mod html_tables {
+        use super::*;
+
+        fn table(settings: typst::TableSettings<'_>) -> TypstTag<'_> {
+            TypstTag::Table(vec![], settings)
+        }
+
+        fn cell<'a>(colspan: u32, rowspan: u32) -> TypstTag<'a> {
+            TypstTag::TableCell(typst::TableCellSpan { colspan, rowspan })
+        }
+
+        #[test]
+        fn spans_and_header() {
+            let md = "\
+<table>
+<tr><th colspan=\"2\">H</th></tr>
+<tr><td rowspan=2>a &amp; b<br>c</td><td>d</td></tr>
+</table>
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHtmlTables::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(table(Default::default()))),
+                    Typst(TypstEvent::Start(TypstTag::TableHead)),
+                    Typst(TypstEvent::Start(cell(2, 1))),
+                    Typst(TypstEvent::Text("H".to_string().into())),
+                    Typst(TypstEvent::End(cell(2, 1))),
+                    Typst(TypstEvent::End(TypstTag::TableHead)),
+                    Typst(TypstEvent::Start(TypstTag::TableRow)),
+                    Typst(TypstEvent::Start(cell(1, 2))),
+                    Typst(TypstEvent::Text("a & b".to_string().into())),
+                    Typst(TypstEvent::Linebreak),
+                    Typst(TypstEvent::Text("c".to_string().into())),
+                    Typst(TypstEvent::End(cell(1, 2))),
+                    Typst(TypstEvent::Start(cell(1, 1))),
+                    Typst(TypstEvent::Text("d".to_string().into())),
+                    Typst(TypstEvent::End(cell(1, 1))),
+                    Typst(TypstEvent::End(TypstTag::TableRow)),
+                    Typst(TypstEvent::End(table(Default::default()))),
+                ]
+            );
+        }
+
+        #[test]
+        fn block_content() {
+            let md = "\
+<table>
+<thead><tr><td>H</td></tr></thead>
+<tr><td>
+
+- item
+
+</td></tr>
+</table>
+";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHtmlTables::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(table(Default::default()))),
+                    Typst(TypstEvent::Start(TypstTag::TableHead)),
+                    Typst(TypstEvent::Start(cell(1, 1))),
+                    Typst(TypstEvent::Text("H".to_string().into())),
+                    Typst(TypstEvent::End(cell(1, 1))),
+                    Typst(TypstEvent::End(TypstTag::TableHead)),
+                    Typst(TypstEvent::Start(TypstTag::TableRow)),
+                    Typst(TypstEvent::Start(cell(1, 1))),
+                    Markdown(MdEvent::Start(MdTag::List(None))),
+                    Markdown(MdEvent::Start(MdTag::Item)),
+                    Markdown(MdEvent::Text("item".into())),
+                    Markdown(MdEvent::End(MdTag::Item)),
+                    Markdown(MdEvent::End(MdTag::List(None))),
+                    Typst(TypstEvent::End(cell(1, 1))),
+                    Typst(TypstEvent::End(TypstTag::TableRow)),
+                    Typst(TypstEvent::End(table(Default::default()))),
+                ]
+            );
+        }
+
+        #[test]
+        fn column_widths() {
+            let md = "<table><col width=\"30%\"><col span=\"2\" style=\"width: 100px\">\
+                      <tr><td>a</td></tr></table>";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHtmlTables::new());
+            let settings = typst::TableSettings {
+                widths: vec!["30%".into(), "75pt".into(), "75pt".into()],
+                ..Default::default()
+            };
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(table(settings.clone()))),
+                    Typst(TypstEvent::Start(TypstTag::TableRow)),
+                    Typst(TypstEvent::Start(cell(1, 1))),
+                    Typst(TypstEvent::Text("a".to_string().into())),
+                    Typst(TypstEvent::End(cell(1, 1))),
                     Typst(TypstEvent::End(TypstTag::TableRow)),
-                    Typst(TypstEvent::End(TypstTag::Table(vec![
-                        typst::TableCellAlignment::Left,
-                        typst::TableCellAlignment::Center,
-                    ]))),
+                    Typst(TypstEvent::End(table(settings))),
+                ]
+            );
+        }
+
+        #[test]
+        fn markdown_table_line_breaks() {
+            let md = "| a |\n|---|\n| b<br/>c |";
+            let i = MarkdownIter(Parser::new_ext(md, pulldown_cmark::Options::ENABLE_TABLES))
+                .convert_with(ConvertHtmlTables::new())
+                .skip(7);
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::TableCell)),
+                    Markdown(MdEvent::Text("b".into())),
+                    Typst(TypstEvent::Linebreak),
+                    Markdown(MdEvent::Text("c".into())),
+                    Markdown(MdEvent::End(MdTag::TableCell)),
+                    Markdown(MdEvent::End(MdTag::TableRow)),
+                    Markdown(MdEvent::End(MdTag::Table(vec![markdown::Alignment::None]))),
+                ]
+            );
+        }
+
+        #[test]
+        fn other_html() {
+            let md = "<div>x</div>\n\n<p>a <table></table></p>";
+            let i = MarkdownIter(Parser::new(md)).convert_with(ConvertHtmlTables::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Html("<div>x</div>\n".into())),
+                    Markdown(MdEvent::Html("<p>a ".into())),
+                    Typst(TypstEvent::Start(table(Default::default()))),
+                    Typst(TypstEvent::End(table(Default::default()))),
+                    Markdown(MdEvent::Html("</p>".into())),
                 ]
             );
         }
     }
+
+    #[cfg(feature = "builder")]
+    mod conversion {
+        use super::*;
+
+        #[test]
+        fn all() {
+            let md = "# Hi\n\nSome **bold** text.";
+            let i = Conversion::builder().events(Parser::new(md)).build();
+            let h1 = TypstTag::Heading(
+                NonZeroU8::new(1).unwrap(),
+                typst::TableOfContents::Include,
+                typst::Bookmarks::Include,
+            );
+
+            self::assert_eq!(
+                i.collect::<Vec<typst::Event>>(),
+                vec![
+                    TypstEvent::Start(h1.clone()),
+                    TypstEvent::Text("Hi".into()),
+                    TypstEvent::End(h1),
+                    TypstEvent::Start(TypstTag::Paragraph),
+                    TypstEvent::Text("Some ".into()),
+                    TypstEvent::Start(TypstTag::Strong),
+                    TypstEvent::Text("bold".into()),
+                    TypstEvent::End(TypstTag::Strong),
+                    TypstEvent::Text(" text.".into()),
+                    TypstEvent::End(TypstTag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn disabled() {
+            let md = "Some **bold** text.";
+            let i = Conversion::builder()
+                .events(Parser::new(md))
+                .strong(false)
+                .build();
+
+            self::assert_eq!(
+                i.collect::<Vec<typst::Event>>(),
+                vec![
+                    TypstEvent::Start(TypstTag::Paragraph),
+                    TypstEvent::Text("Some ".into()),
+                    TypstEvent::Text("bold".into()),
+                    TypstEvent::Text(" text.".into()),
+                    TypstEvent::End(TypstTag::Paragraph),
+                ]
+            );
+        }
+
+        #[test]
+        fn hidden_lines() {
+            let md = "```rust\n# fn main() {}\nlet x = 1;\n```";
+            let text = |events: &mut dyn Iterator<Item = typst::Event>| {
+                events
+                    .filter_map(|e| match e {
+                        TypstEvent::Text(t) => Some(t.into_string()),
+                        _ => None,
+                    })
+                    .collect::<String>()
+            };
+
+            // Plain Markdown keeps hidden lines by default.
+            let mut i = Conversion::builder().events(Parser::new(md)).build();
+            self::assert_eq!(text(&mut i), "# fn main() {}\nlet x = 1;\n");
+            let mut i = Conversion::builder()
+                .events(Parser::new(md))
+                .hidden_lines(true)
+                .build();
+            self::assert_eq!(text(&mut i), "let x = 1;\n");
+        }
+
+        #[test]
+        fn unknown_references() {
+            let md = "See [missing][].";
+            // The Typst text and errors of a conversion with `policy`.
+            let convert = |policy| {
+                let mut callback = markdown::unknown_reference;
+                let parser = Parser::new_with_broken_link_callback(
+                    md,
+                    markdown::Options::empty(),
+                    Some(&mut callback),
+                );
+                let errors = crate::convert::Errors::new();
+                let events = markdown::UnknownReferences::new(
+                    md,
+                    parser.into_offset_iter(),
+                    policy,
+                    errors.clone(),
+                );
+                let i = Conversion::builder().events(events).build();
+                let (mut text, mut offsets) = (String::new(), vec![]);
+                for result in errors.check(i) {
+                    match result {
+                        Ok(TypstEvent::Text(t)) => text.push_str(&t),
+                        Ok(_) => {}
+                        Err(e) => offsets.push(e.offset()),
+                    }
+                }
+                (text, offsets)
+            };
+
+            self::assert_eq!(
+                convert(markdown::UnknownLinks::Marker),
+                ("See [missing][].".to_string(), vec![])
+            );
+            self::assert_eq!(
+                convert(markdown::UnknownLinks::Error),
+                ("See missing.".to_string(), vec![Some(4)])
+            );
+        }
+    }
 }
@@ -0,0 +1,137 @@
+//! Builder to customize Markdown to Typst conversion.
+
+use core::marker::PhantomData;
+
+use crate::markdown::to::typst::*;
+use crate::markdown::MarkdownIter;
+use crate::typst::TypstFilter;
+use crate::IteratorExt;
+
+#[derive(typed_builder::TypedBuilder)]
+#[builder(build_method(vis="", name=__build))]
+#[builder(field_defaults(default = true))]
+/// Converts Markdown to Typst.
+///
+/// Using the builder one can choose which conversions to apply. By default, all
+/// conversions are enabled except stripping hidden lines from Rust code blocks, which
+/// is mdBook behavior. Events of disabled conversions are left out, so the
+/// result only contains Typst events and can be passed to
+/// [`TypstMarkup`](crate::typst::to::markup::TypstMarkup) directly.
+///
+/// For more control over conversion, use the converters in [the parent
+/// module](crate::markdown::to::typst) directly.
+pub struct Conversion<'a, T> {
+    #[builder(!default)]
+    events: T,
+    footnotes: bool,
+    headings: bool,
+    paragraphs: bool,
+    math: bool,
+    soft_breaks: bool,
+    hard_breaks: bool,
+    /// Whether to strip hidden lines from Rust code blocks like mdBook does. Off by
+    /// default, as plain Markdown has no hidden lines.
+    #[builder(default = false)]
+    hidden_lines: bool,
+    text: bool,
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    task_lists: bool,
+    blockquotes: bool,
+    lists: bool,
+    code: bool,
+    links: bool,
+    images: bool,
+    /// What to use as the caption of image figures.
+    #[builder(default)]
+    image_captions: ImageCaptions,
+    tables: bool,
+    #[builder(default)]
+    _p: PhantomData<&'a ()>,
+}
+
+#[allow(dead_code, non_camel_case_types, missing_docs)]
+impl<
+        'a,
+        T,
+        __footnotes: ::typed_builder::Optional<bool>,
+        __headings: ::typed_builder::Optional<bool>,
+        __paragraphs: ::typed_builder::Optional<bool>,
+        __math: ::typed_builder::Optional<bool>,
+        __soft_breaks: ::typed_builder::Optional<bool>,
+        __hard_breaks: ::typed_builder::Optional<bool>,
+        __hidden_lines: ::typed_builder::Optional<bool>,
+        __text: ::typed_builder::Optional<bool>,
+        __strong: ::typed_builder::Optional<bool>,
+        __emphasis: ::typed_builder::Optional<bool>,
+        __strikethrough: ::typed_builder::Optional<bool>,
+        __task_lists: ::typed_builder::Optional<bool>,
+        __blockquotes: ::typed_builder::Optional<bool>,
+        __lists: ::typed_builder::Optional<bool>,
+        __code: ::typed_builder::Optional<bool>,
+        __links: ::typed_builder::Optional<bool>,
+        __images: ::typed_builder::Optional<bool>,
+        __image_captions: ::typed_builder::Optional<ImageCaptions>,
+        __tables: ::typed_builder::Optional<bool>,
+        ___p: ::typed_builder::Optional<PhantomData<&'a ()>>,
+    >
+    ConversionBuilder<
+        'a,
+        T,
+        (
+            (T,),
+            __footnotes,
+            __headings,
+            __paragraphs,
+            __math,
+            __soft_breaks,
+            __hard_breaks,
+            __hidden_lines,
+            __text,
+            __strong,
+            __emphasis,
+            __strikethrough,
+            __task_lists,
+            __blockquotes,
+            __lists,
+            __code,
+            __links,
+            __images,
+            __image_captions,
+            __tables,
+            ___p,
+        ),
+    >
+where
+    T: Iterator<Item = crate::markdown::Event<'a>> + 'a,
+{
+    pub fn build(self) -> impl Iterator<Item = crate::typst::Event<'a>> {
+        let this = self.__build();
+        let events = MarkdownIter(this.events)
+            .convert_with(this.footnotes.then(ConvertFootnotes::new))
+            .convert_with(this.headings.then(ConvertHeadings::new))
+            .convert_with(this.paragraphs.then(ConvertParagraphs::new))
+            .convert_with(this.math.then(ConvertMath::new))
+            .convert_with(this.soft_breaks.then(ConvertSoftBreaks::new))
+            .convert_with(this.hard_breaks.then(ConvertHardBreaks::new))
+            .convert_with(this.hidden_lines.then(ConvertHiddenLines::new))
+            .convert_with(this.text.then(ConvertText::new))
+            .convert_with(this.strong.then(ConvertStrong::new))
+            .convert_with(this.emphasis.then(ConvertEmphasis::new))
+            .convert_with(this.strikethrough.then(ConvertStrikethrough::new))
+            .convert_with(this.task_lists.then(ConvertTaskLists::new))
+            .convert_with(this.blockquotes.then(ConvertBlockQuotes::new))
+            .convert_with(this.lists.then(ConvertLists::new))
+            .convert_with(this.code.then(ConvertCode::new))
+            .convert_with(this.links.then(ConvertLinks::new))
+            .convert_with(
+                this.images
+                    .then(|| ConvertImages::with_captions(this.image_captions)),
+            )
+            .convert_with(this.tables.then(ConvertHtmlTables::new))
+            .convert_with(this.tables.then(ConvertTables::new));
+
+        TypstFilter(events)
+    }
+}
@@ -0,0 +1,686 @@
+//! Translate LaTeX math, as written for MathJax and KaTeX, to Typst math.
+//!
+//! Only the commonly used subset of LaTeX is understood: fractions, roots, sub- and
+//! superscripts, Greek letters, big operators, relations, arrows, fonts, accents,
+//! `\left`/`\right` delimiters, matrices, `cases`, and alignment environments.
+//! Unknown commands are kept as quoted text so the resulting Typst still compiles.
+//!
+//! See <https://typst.app/docs/reference/math/>.
+
+/// Translate a LaTeX equation, without its delimiters, to Typst math.
+pub fn latex_to_typst(latex: &str) -> String {
+    let mut translator = Translator { src: latex, pos: 0 };
+    let mut out = Out::default();
+    translator.sequence(&mut out, Context::Top);
+    out.s.trim().to_string()
+}
+
+/// Where a sequence of atoms is being translated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context {
+    Top,
+    /// Inside `{...}`, ends at the closing brace.
+    Group,
+    /// Inside `\begin{...}`, ends at `\end{...}` and is split by `&` and `\\`.
+    Environment,
+}
+
+/// Why a sequence of atoms ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stop {
+    End,
+    Brace,
+    Cell,
+    Row,
+    Environment,
+}
+
+/// Typst math output that keeps adjacent identifiers apart.
+///
+/// LaTeX treats `ab` as two variables while Typst reads it as one identifier, so a
+/// space is inserted between alphanumeric pieces.
+#[derive(Debug, Default)]
+struct Out {
+    s: String,
+    /// Separate the next alphanumeric piece even if both sides are digits, e.g. after
+    /// the `1` of `x^12`.
+    separate: bool,
+}
+
+impl Out {
+    fn push(&mut self, s: &str) {
+        let Some(first) = s.chars().next() else {
+            return;
+        };
+        if let Some(last) = self.s.chars().next_back() {
+            let digits = last.is_ascii_digit() && first.is_ascii_digit();
+            if last.is_alphanumeric() && first.is_alphanumeric() && (self.separate || !digits) {
+                self.s.push(' ');
+            }
+        }
+        self.s.push_str(s);
+        self.separate = false;
+    }
+
+    fn space(&mut self) {
+        if !self.s.is_empty() && !self.s.ends_with(' ') {
+            self.s.push(' ');
+        }
+    }
+}
+
+struct Translator<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Translator<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn sequence(&mut self, out: &mut Out, context: Context) -> Stop {
+        loop {
+            let Some(c) = self.peek() else {
+                return Stop::End;
+            };
+            match c {
+                '}' if context == Context::Group => {
+                    self.bump();
+                    return Stop::Brace;
+                }
+                '}' => {
+                    self.bump();
+                    out.push("\\}");
+                }
+                '&' if context == Context::Environment => {
+                    self.bump();
+                    return Stop::Cell;
+                }
+                '\\' if context == Context::Environment && self.rest().starts_with("\\\\") => {
+                    self.pos += 2;
+                    return Stop::Row;
+                }
+                '\\' if context == Context::Environment && self.rest().starts_with("\\end") => {
+                    self.pos += "\\end".len();
+                    let _ = self.raw_argument();
+                    return Stop::Environment;
+                }
+                _ => self.atom(out),
+            }
+        }
+    }
+
+    /// Translate a single character, group, or command with its arguments.
+    fn atom(&mut self, out: &mut Out) {
+        let Some(c) = self.bump() else {
+            return;
+        };
+        match c {
+            // Groups are invisible in LaTeX.
+            '{' => {
+                self.sequence(out, Context::Group);
+            }
+            '^' | '_' => {
+                let arg = self.argument();
+                out.s.push(c);
+                out.s.push_str(&wrap(arg));
+                out.separate = true;
+            }
+            '\\' => self.command(out),
+            // A comment.
+            '%' => {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.bump();
+                }
+            }
+            '~' => out.space(),
+            c if c.is_whitespace() => out.space(),
+            // `/` makes a fraction in Typst.
+            '/' => out.push("\\/"),
+            '#' | '"' | '$' | '@' => out.push(&format!("\\{c}")),
+            // Keep `.` from being read as field access.
+            '.' if out.s.ends_with(char::is_alphabetic) => out.push(" ."),
+            c => out.push(c.encode_utf8(&mut [0; 4])),
+        }
+    }
+
+    /// Translate the argument of a command or script.
+    fn argument(&mut self) -> String {
+        self.skip_whitespace();
+        let mut out = Out::default();
+        match self.peek() {
+            Some('{') => {
+                self.bump();
+                self.sequence(&mut out, Context::Group);
+            }
+            Some('}') | None => {}
+            Some(_) => self.atom(&mut out),
+        }
+        out.s.trim().to_string()
+    }
+
+    /// Read an argument without translating it.
+    fn raw_argument(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let rest = self.rest();
+        if !rest.starts_with('{') {
+            let len = rest.chars().next().map_or(0, char::len_utf8);
+            self.pos += len;
+            return &rest[..len];
+        }
+        let mut depth = 0;
+        for (i, c) in rest.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += i + 1;
+                        return &rest[1..i];
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.pos += rest.len();
+        &rest[1..]
+    }
+
+    /// Read an optional `[...]` argument without translating it.
+    fn optional_argument(&mut self) -> Option<&'a str> {
+        self.skip_whitespace();
+        let rest = self.rest().strip_prefix('[')?;
+        let end = rest.find(']')?;
+        self.pos += end + 2;
+        Some(&rest[..end])
+    }
+
+    fn command(&mut self, out: &mut Out) {
+        let rest = self.rest();
+        let len = match rest.find(|c: char| !c.is_ascii_alphabetic()) {
+            Some(0) => rest.chars().next().map_or(0, char::len_utf8),
+            Some(len) => len,
+            None => rest.len(),
+        };
+        let name = &rest[..len];
+        self.pos += len;
+
+        match name {
+            "" => {}
+            "\\" => out.s.push_str(" \\ "),
+            "{" | "}" | "&" | "_" | "#" | "$" => out.push(&format!("\\{name}")),
+            "%" => out.push("%"),
+            "|" => out.push("bar.double"),
+            "," => out.push("thin"),
+            ":" | ">" => out.push("med"),
+            ";" => out.push("thick"),
+            " " => out.push("space"),
+            "!" | "limits" | "nolimits" | "displaystyle" | "textstyle" | "scriptstyle"
+            | "nonumber" | "notag" => {}
+            // Typst scales delimiters automatically.
+            "left" | "right" | "middle" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr"
+            | "Bigl" | "Bigr" | "biggl" | "biggr" | "Biggl" | "Biggr" => {
+                self.skip_whitespace();
+                if self.peek() == Some('.') {
+                    self.bump();
+                }
+            }
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let num = self.argument();
+                let den = self.argument();
+                out.push(&format!("frac({num}, {den})"));
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let n = self.argument();
+                let k = self.argument();
+                out.push(&format!("binom({n}, {k})"));
+            }
+            "sqrt" => match self.optional_argument() {
+                Some(index) => {
+                    let index = latex_to_typst(index);
+                    let radicand = self.argument();
+                    out.push(&format!("root({index}, {radicand})"));
+                }
+                None => {
+                    let radicand = self.argument();
+                    out.push(&format!("sqrt({radicand})"));
+                }
+            },
+            "text" | "textrm" | "textnormal" | "textup" | "mbox" | "hbox" => {
+                let text = self.raw_argument();
+                out.push(&quote(text));
+            }
+            "textbf" => {
+                let text = self.raw_argument();
+                out.push(&format!("bold({})", quote(text)));
+            }
+            "textit" | "emph" => {
+                let text = self.raw_argument();
+                out.push(&format!("italic({})", quote(text)));
+            }
+            "operatorname" => {
+                let limits = self.peek() == Some('*');
+                if limits {
+                    self.bump();
+                }
+                let name = quote(self.raw_argument());
+                if limits {
+                    out.push(&format!("op({name}, limits: #true)"));
+                } else {
+                    out.push(&format!("op({name})"));
+                }
+            }
+            "pmod" => {
+                let arg = self.argument();
+                out.push(&format!("(mod {arg})"));
+            }
+            "bmod" => out.push("mod"),
+            "begin" => self.environment(out),
+            name => {
+                if let Some(function) = function(name) {
+                    let arg = self.argument();
+                    out.push(&format!("{function}({arg})"));
+                } else if let Some(symbol) = symbol(name) {
+                    out.push(symbol);
+                } else if OPERATORS.contains(&name) {
+                    out.push(name);
+                } else {
+                    out.push(&quote(name));
+                }
+            }
+        }
+    }
+
+    fn environment(&mut self, out: &mut Out) {
+        let name = self.raw_argument();
+        if name == "array" {
+            // Column specification.
+            let _ = self.raw_argument();
+        }
+
+        let mut rows: Vec<Vec<String>> = vec![vec![]];
+        loop {
+            let mut cell = Out::default();
+            let stop = self.sequence(&mut cell, Context::Environment);
+            if let Some(row) = rows.last_mut() {
+                row.push(cell.s.trim().to_string());
+            }
+            match stop {
+                Stop::Cell => {}
+                Stop::Row => rows.push(vec![]),
+                Stop::End | Stop::Brace | Stop::Environment => break,
+            }
+        }
+        // A trailing `\\` does not start a new row.
+        if rows.len() > 1 && rows.last().is_some_and(|row| row.concat().is_empty()) {
+            rows.pop();
+        }
+
+        let delim = match name {
+            "pmatrix" => Some(None),
+            "bmatrix" => Some(Some("\"[\"")),
+            "Bmatrix" => Some(Some("\"{\"")),
+            "vmatrix" => Some(Some("\"|\"")),
+            "Vmatrix" => Some(Some("\"||\"")),
+            "matrix" | "smallmatrix" | "array" => Some(Some("#none")),
+            _ => None,
+        };
+        let markup = if let Some(delim) = delim {
+            let rows = rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|cell| if cell.is_empty() { "\"\"" } else { cell })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            match delim {
+                Some(delim) => format!("mat(delim: {delim}, {rows})"),
+                None => format!("mat({rows})"),
+            }
+        } else {
+            if name == "cases" {
+                let rows = rows.iter().map(|row| row.join(" & "));
+                format!("cases({})", rows.collect::<Vec<_>>().join(", "))
+            } else {
+                let rows = rows.iter().map(|row| row.join(" &").trim().to_string());
+                rows.collect::<Vec<_>>().join(" \\ ")
+            }
+        };
+        out.push(&markup);
+    }
+}
+
+/// Wrap a script or argument in parentheses unless it is a single piece.
+fn wrap(s: String) -> String {
+    let single = s.chars().count() == 1
+        || (!s.is_empty()
+            && !s.starts_with('.')
+            && s.chars().all(|c| c.is_alphanumeric() || c == '.'));
+    if single {
+        s
+    } else {
+        format!("({s})")
+    }
+}
+
+/// Quote text as a Typst string.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Text operators that Typst predefines with the same name as LaTeX.
+pub(crate) const OPERATORS: &[&str] = &[
+    "arccos", "arcsin", "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "deg", "det", "dim",
+    "exp", "gcd", "hom", "inf", "ker", "lg", "lim", "liminf", "limsup", "ln", "log", "max", "min",
+    "Pr", "sec", "sin", "sinh", "sup", "tan", "tanh",
+];
+
+/// Commands taking one argument that map to a Typst function.
+fn function(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "mathbf" | "boldsymbol" | "bm" => "bold",
+        "mathit" => "italic",
+        "mathrm" => "upright",
+        "mathbb" => "bb",
+        "mathcal" => "cal",
+        "mathfrak" => "frak",
+        "mathsf" => "sans",
+        "mathtt" => "mono",
+        "hat" | "widehat" => "hat",
+        "bar" => "macron",
+        "overline" => "overline",
+        "underline" => "underline",
+        "vec" | "overrightarrow" => "arrow",
+        "tilde" | "widetilde" => "tilde",
+        "dot" => "dot",
+        "ddot" => "dot.double",
+        "check" => "caron",
+        "breve" => "breve",
+        "acute" => "acute",
+        "grave" => "grave",
+        "overbrace" => "overbrace",
+        "underbrace" => "underbrace",
+        "cancel" => "cancel",
+        _ => return None,
+    })
+}
+
+/// Commands that map to a Typst symbol.
+fn symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        // Greek letters.
+        "alpha" => "alpha",
+        "beta" => "beta",
+        "gamma" => "gamma",
+        "delta" => "delta",
+        "epsilon" => "epsilon.alt",
+        "varepsilon" => "epsilon",
+        "zeta" => "zeta",
+        "eta" => "eta",
+        "theta" => "theta",
+        "vartheta" => "theta.alt",
+        "iota" => "iota",
+        "kappa" => "kappa",
+        "varkappa" => "kappa.alt",
+        "lambda" => "lambda",
+        "mu" => "mu",
+        "nu" => "nu",
+        "xi" => "xi",
+        "omicron" => "omicron",
+        "pi" => "pi",
+        "varpi" => "pi.alt",
+        "rho" => "rho",
+        "varrho" => "rho.alt",
+        "sigma" => "sigma",
+        "varsigma" => "sigma.alt",
+        "tau" => "tau",
+        "upsilon" => "upsilon",
+        "phi" => "phi.alt",
+        "varphi" => "phi",
+        "chi" => "chi",
+        "psi" => "psi",
+        "omega" => "omega",
+        "Gamma" => "Gamma",
+        "Delta" => "Delta",
+        "Theta" => "Theta",
+        "Lambda" => "Lambda",
+        "Xi" => "Xi",
+        "Pi" => "Pi",
+        "Sigma" => "Sigma",
+        "Upsilon" => "Upsilon",
+        "Phi" => "Phi",
+        "Psi" => "Psi",
+        "Omega" => "Omega",
+        // Big operators.
+        "sum" => "sum",
+        "prod" => "product",
+        "coprod" => "product.co",
+        "int" => "integral",
+        "iint" => "integral.double",
+        "iiint" => "integral.triple",
+        "oint" => "integral.cont",
+        "bigcup" => "union.big",
+        "bigcap" => "sect.big",
+        "bigoplus" => "plus.circle.big",
+        "bigotimes" => "times.circle.big",
+        // Binary operators.
+        "cdot" => "dot.op",
+        "times" => "times",
+        "div" => "div",
+        "pm" => "plus.minus",
+        "mp" => "minus.plus",
+        "ast" => "ast",
+        "star" => "star",
+        "circ" => "compose",
+        "bullet" => "bullet",
+        "oplus" => "plus.circle",
+        "otimes" => "times.circle",
+        "cup" => "union",
+        "cap" => "sect",
+        "setminus" => "without",
+        "wedge" | "land" => "and",
+        "vee" | "lor" => "or",
+        "neg" | "lnot" => "not",
+        // Relations.
+        "leq" | "le" => "<=",
+        "geq" | "ge" => ">=",
+        "neq" | "ne" => "!=",
+        "ll" => "<<",
+        "gg" => ">>",
+        "approx" => "approx",
+        "equiv" => "equiv",
+        "sim" => "tilde.op",
+        "simeq" => "tilde.eq",
+        "cong" => "tilde.equiv",
+        "propto" => "prop",
+        "in" => "in",
+        "notin" => "in.not",
+        "ni" => "in.rev",
+        "subset" => "subset",
+        "subseteq" => "subset.eq",
+        "supset" => "supset",
+        "supseteq" => "supset.eq",
+        "mid" => "divides",
+        "parallel" => "parallel",
+        "perp" => "perp",
+        // Arrows.
+        "to" | "rightarrow" => "->",
+        "leftarrow" | "gets" => "<-",
+        "leftrightarrow" => "<->",
+        "Rightarrow" => "=>",
+        "Leftarrow" => "arrow.l.double",
+        "Leftrightarrow" | "iff" => "<=>",
+        "implies" => "==>",
+        "impliedby" => "<==",
+        "mapsto" => "|->",
+        "uparrow" => "arrow.t",
+        "downarrow" => "arrow.b",
+        "longrightarrow" => "-->",
+        "longleftarrow" => "<--",
+        // Miscellaneous.
+        "infty" => "infinity",
+        "partial" => "diff",
+        "nabla" => "nabla",
+        "forall" => "forall",
+        "exists" => "exists",
+        "nexists" => "exists.not",
+        "emptyset" | "varnothing" => "emptyset",
+        "ldots" | "dots" | "dotsc" | "dotsb" => "dots",
+        "cdots" => "dots.c",
+        "vdots" => "dots.v",
+        "ddots" => "dots.down",
+        "prime" => "prime",
+        "angle" => "angle",
+        "hbar" => "planck.reduce",
+        "ell" => "ell",
+        "Re" => "Re",
+        "Im" => "Im",
+        "aleph" => "aleph",
+        "langle" => "angle.l",
+        "rangle" => "angle.r",
+        "lfloor" => "floor.l",
+        "rfloor" => "floor.r",
+        "lceil" => "ceil.l",
+        "rceil" => "ceil.r",
+        "vert" => "bar.v",
+        "Vert" => "bar.v.double",
+        "lbrace" => "\\{",
+        "rbrace" => "\\}",
+        "quad" => "quad",
+        "qquad" => "wide",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use similar_asserts::assert_eq;
+
+    #[test]
+    fn fractions_and_roots() {
+        self::assert_eq!(latex_to_typst(r"\frac{a+b}{2}"), "frac(a+b, 2)");
+        self::assert_eq!(latex_to_typst(r"\frac12"), "frac(1, 2)");
+        self::assert_eq!(latex_to_typst(r"\sqrt{x^2 + 1}"), "sqrt(x^2 + 1)");
+        self::assert_eq!(latex_to_typst(r"\sqrt[3]{x}"), "root(3, x)");
+        self::assert_eq!(latex_to_typst("a/b"), "a\\/b");
+    }
+
+    #[test]
+    fn scripts() {
+        self::assert_eq!(latex_to_typst("x^2"), "x^2");
+        self::assert_eq!(latex_to_typst("x^{10}"), "x^10");
+        self::assert_eq!(latex_to_typst("x^{-1}"), "x^(-1)");
+        self::assert_eq!(latex_to_typst("a_{i,j}"), "a_(i,j)");
+        self::assert_eq!(latex_to_typst("x^12"), "x^1 2");
+        self::assert_eq!(latex_to_typst(r"e^{i\pi}"), "e^(i pi)");
+        self::assert_eq!(latex_to_typst(r"x^\alpha"), "x^alpha");
+    }
+
+    #[test]
+    fn letters_stay_separate() {
+        self::assert_eq!(latex_to_typst("ab + 2c"), "a b + 2 c");
+        self::assert_eq!(latex_to_typst(r"\alpha\beta"), "alpha beta");
+        self::assert_eq!(
+            latex_to_typst(r"\varepsilon < \epsilon"),
+            "epsilon < epsilon.alt"
+        );
+        self::assert_eq!(latex_to_typst(r"\Omega\phi"), "Omega phi.alt");
+    }
+
+    #[test]
+    fn big_operators() {
+        self::assert_eq!(
+            latex_to_typst(r"\sum_{i=1}^{n} i = \frac{n(n+1)}{2}"),
+            "sum_(i=1)^n i = frac(n(n+1), 2)"
+        );
+        self::assert_eq!(
+            latex_to_typst(r"\int_0^\infty e^{-x^2} \, dx"),
+            "integral_0^infinity e^(-x^2) thin d x"
+        );
+        self::assert_eq!(latex_to_typst(r"\prod_{k} a_k"), "product_k a_k");
+        self::assert_eq!(
+            latex_to_typst(r"\lim_{x \to 0} \sin x"),
+            "lim_(x -> 0) sin x"
+        );
+    }
+
+    #[test]
+    fn relations_and_delimiters() {
+        self::assert_eq!(latex_to_typst(r"a \leq b \neq c"), "a <= b != c");
+        self::assert_eq!(
+            latex_to_typst(r"\left( \frac{1}{2} \right)"),
+            "( frac(1, 2) )"
+        );
+        self::assert_eq!(
+            latex_to_typst(r"\left.\frac{d}{dx}\right|_0"),
+            "frac(d, d x)|_0"
+        );
+        self::assert_eq!(
+            latex_to_typst(r"\{ x \in \mathbb{R} \}"),
+            "\\{ x in bb(R) \\}"
+        );
+    }
+
+    #[test]
+    fn text_and_fonts() {
+        self::assert_eq!(latex_to_typst(r"\text{if } x > 0"), "\"if \" x > 0");
+        self::assert_eq!(
+            latex_to_typst(r"\mathbf{v} \cdot \hat{n}"),
+            "bold(v) dot.op hat(n)"
+        );
+        self::assert_eq!(
+            latex_to_typst(r"\operatorname{argmax}_x"),
+            "op(\"argmax\")_x"
+        );
+        self::assert_eq!(latex_to_typst(r"\unknown x"), "\"unknown\" x");
+    }
+
+    #[test]
+    fn matrices() {
+        self::assert_eq!(
+            latex_to_typst(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            "mat(a, b; c, d)"
+        );
+        self::assert_eq!(
+            latex_to_typst(r"\begin{bmatrix} 1 & 0 \\ 0 & 1 \\ \end{bmatrix}"),
+            "mat(delim: \"[\", 1, 0; 0, 1)"
+        );
+        self::assert_eq!(
+            latex_to_typst(r"\det \begin{vmatrix} a & \\ & d \end{vmatrix}"),
+            "det mat(delim: \"|\", a, \"\"; \"\", d)"
+        );
+    }
+
+    #[test]
+    fn environments() {
+        self::assert_eq!(
+            latex_to_typst(r"|x| = \begin{cases} x & x \geq 0 \\ -x & x < 0 \end{cases}"),
+            "|x| = cases(x & x >= 0, -x & x < 0)"
+        );
+        self::assert_eq!(
+            latex_to_typst(r"\begin{aligned} a &= b \\ &= c \end{aligned}"),
+            "a &= b \\ &= c"
+        );
+    }
+}
@@ -1,14 +1,16 @@
 //! Support for [mdBook](https://github.com/rust-lang/mdBook).
 
-use crate::ParserEvent;
-pub use pulldown_mdbook::{ChapterSource, ChapterStatus, ContentType, Event, Parser, Tag};
+use crate::{Error, ParserEvent};
+pub use pulldown_mdbook::{
+    links, ChapterNumber, ChapterSource, ChapterStatus, ContentType, Event, Parser, Tag,
+};
 
 pub mod to;
 
 /// Assert that an iterator only contains mdBook events. Panics if another type of event
 /// is encountered.
 ///
-/// For a non-panic version, see [`MdbookFilter`].
+/// For non-panic versions, see [`TryAssertMdbook`] and [`MdbookFilter`].
 pub struct AssertMdbook<T>(pub T);
 impl<'a, T> Iterator for AssertMdbook<T>
 where
@@ -28,6 +30,50 @@ where
     }
 }
 
+/// Check that an iterator only contains mdBook events. Yields an [`Error`] for each
+/// event of another type instead of panicking.
+///
+/// For a panicking version, see [`AssertMdbook`].
+pub struct TryAssertMdbook<T> {
+    iter: T,
+    position: usize,
+}
+
+impl<T> TryAssertMdbook<T> {
+    pub fn new(iter: T) -> Self {
+        Self { iter, position: 0 }
+    }
+}
+
+impl<'a, T> Iterator for TryAssertMdbook<T>
+where
+    T: Iterator<Item = ParserEvent<'a>>,
+{
+    type Item = Result<self::Event<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.iter.next()?;
+        self.position += 1;
+        match event {
+            ParserEvent::Mdbook(x) => Some(Ok(x)),
+            #[cfg(feature = "markdown")]
+            ParserEvent::Markdown(x) => Some(Err(Error::UnexpectedEvent {
+                position: self.position - 1,
+                expected: "mdbook",
+                found: "markdown",
+                event: format!("{x:?}"),
+            })),
+            #[cfg(feature = "typst")]
+            ParserEvent::Typst(x) => Some(Err(Error::UnexpectedEvent {
+                position: self.position - 1,
+                expected: "mdbook",
+                found: "typst",
+                event: format!("{x:?}"),
+            })),
+        }
+    }
+}
+
 /// An iterator that only contains mdBook events. Other types of events will be filtered
 /// out.
 ///
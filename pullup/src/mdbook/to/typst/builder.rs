@@ -2,10 +2,11 @@
 
 use core::marker::PhantomData;
 
+use crate::convert::{self, Input, Output};
 use crate::markdown::to::typst::*;
 use crate::mdbook::to::typst::*;
 use crate::mdbook::MdbookIter;
-use crate::ParserEvent;
+use crate::{IteratorExt, ParserEvent};
 
 #[derive(typed_builder::TypedBuilder)]
 #[builder(build_method(vis="", name=__build))]
@@ -22,48 +23,87 @@ use crate::ParserEvent;
 pub struct Conversion<'a, T> {
     #[builder(!default)]
     events: T,
+    title_page: bool,
+    outline: bool,
     title: bool,
     authors: bool,
+    /// Page and text setup. `None` leaves Typst's defaults in place.
+    #[builder(default = Some(Preamble::default()))]
+    preamble: Option<Preamble>,
+    labels: bool,
+    /// How to render part titles. `None` leaves them out.
+    #[builder(default = Some(PartStyle::default()))]
+    parts: Option<PartStyle>,
+    /// How to render separators. `None` leaves them out.
+    #[builder(default = Some(SeparatorStyle::default()))]
+    separators: Option<SeparatorStyle>,
     chapters: bool,
+    /// How to number chapter headings. `None` leaves them unnumbered.
+    #[builder(default = Some(ChapterNumbering::default()))]
+    chapter_numbering: Option<ChapterNumbering>,
+    /// How to render draft chapters.
+    #[builder(default)]
+    drafts: DraftPolicy,
     content: bool,
+    footnotes: bool,
     headings: bool,
     paragraphs: bool,
+    math: bool,
     soft_breaks: bool,
     hard_breaks: bool,
+    hidden_lines: bool,
     text: bool,
     strong: bool,
     emphasis: bool,
+    strikethrough: bool,
+    task_lists: bool,
     blockquotes: bool,
     lists: bool,
     code: bool,
     links: bool,
     images: bool,
+    /// What to use as the caption of image figures.
+    #[builder(default)]
+    image_captions: ImageCaptions,
     tables: bool,
     #[builder(default)]
     _p: PhantomData<&'a ()>,
 }
 
 #[allow(dead_code, non_camel_case_types, missing_docs)]
-#[automatically_derived]
 impl<
         'a,
         T,
+        __title_page: ::typed_builder::Optional<bool>,
+        __outline: ::typed_builder::Optional<bool>,
         __title: ::typed_builder::Optional<bool>,
         __authors: ::typed_builder::Optional<bool>,
+        __preamble: ::typed_builder::Optional<Option<Preamble>>,
+        __labels: ::typed_builder::Optional<bool>,
+        __parts: ::typed_builder::Optional<Option<PartStyle>>,
+        __separators: ::typed_builder::Optional<Option<SeparatorStyle>>,
         __chapters: ::typed_builder::Optional<bool>,
+        __chapter_numbering: ::typed_builder::Optional<Option<ChapterNumbering>>,
+        __drafts: ::typed_builder::Optional<DraftPolicy>,
         __content: ::typed_builder::Optional<bool>,
+        __footnotes: ::typed_builder::Optional<bool>,
         __headings: ::typed_builder::Optional<bool>,
         __paragraphs: ::typed_builder::Optional<bool>,
+        __math: ::typed_builder::Optional<bool>,
         __soft_breaks: ::typed_builder::Optional<bool>,
         __hard_breaks: ::typed_builder::Optional<bool>,
+        __hidden_lines: ::typed_builder::Optional<bool>,
         __text: ::typed_builder::Optional<bool>,
         __strong: ::typed_builder::Optional<bool>,
         __emphasis: ::typed_builder::Optional<bool>,
+        __strikethrough: ::typed_builder::Optional<bool>,
+        __task_lists: ::typed_builder::Optional<bool>,
         __blockquotes: ::typed_builder::Optional<bool>,
         __lists: ::typed_builder::Optional<bool>,
         __code: ::typed_builder::Optional<bool>,
         __links: ::typed_builder::Optional<bool>,
         __images: ::typed_builder::Optional<bool>,
+        __image_captions: ::typed_builder::Optional<ImageCaptions>,
         __tables: ::typed_builder::Optional<bool>,
         ___p: ::typed_builder::Optional<PhantomData<&'a ()>>,
     >
@@ -72,22 +112,36 @@ impl<
         T,
         (
             (T,),
+            __title_page,
+            __outline,
             __title,
             __authors,
+            __preamble,
+            __labels,
+            __parts,
+            __separators,
             __chapters,
+            __chapter_numbering,
+            __drafts,
             __content,
+            __footnotes,
             __headings,
             __paragraphs,
+            __math,
             __soft_breaks,
             __hard_breaks,
+            __hidden_lines,
             __text,
             __strong,
             __emphasis,
+            __strikethrough,
+            __task_lists,
             __blockquotes,
             __lists,
             __code,
             __links,
             __images,
+            __image_captions,
             __tables,
             ___p,
         ),
@@ -97,65 +151,54 @@ where
 {
     pub fn build(self) -> impl Iterator<Item = ParserEvent<'a>> {
         let this = self.__build();
-        let mut events: Box<dyn Iterator<Item = ParserEvent<'_>>> =
-            Box::new(MdbookIter(this.events));
-        if this.title {
-            events = Box::new(ConvertTitle::new(events));
-        }
-        if this.authors {
-            events = Box::new(ConvertAuthors::new(events));
-        }
-        if this.chapters {
-            events = Box::new(ConvertChapter::new(events));
-        }
-        if this.content {
-            events = Box::new(events.map(|e| match e {
-                ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(m)) => {
-                    ParserEvent::Markdown(m)
-                }
-                x => x,
-            }));
-            if this.headings {
-                events = Box::new(ConvertHeadings::new(events));
-            }
-            if this.paragraphs {
-                events = Box::new(ConvertParagraphs::new(events));
-            }
-            if this.soft_breaks {
-                events = Box::new(ConvertSoftBreaks::new(events));
-            }
-            if this.hard_breaks {
-                events = Box::new(ConvertHardBreaks::new(events));
-            }
-            if this.text {
-                events = Box::new(ConvertText::new(events));
-            }
-            if this.strong {
-                events = Box::new(ConvertStrong::new(events));
-            }
-            if this.emphasis {
-                events = Box::new(ConvertEmphasis::new(events));
-            }
-            if this.blockquotes {
-                events = Box::new(ConvertBlockQuotes::new(events));
-            }
-            if this.lists {
-                events = Box::new(ConvertLists::new(events));
-            }
-            if this.code {
-                events = Box::new(ConvertCode::new(events));
-            }
-            if this.links {
-                events = Box::new(ConvertLinks::new(events));
-            }
-            if this.images {
-                events = Box::new(ConvertImages::new(events));
-            }
-            if this.tables {
-                events = Box::new(ConvertTables::new(events));
-            }
-        }
-
-        events
+        // Markdown conversions only apply to chapter content.
+        let content = |enabled: bool| this.content && enabled;
+        MdbookIter(this.events)
+            .convert_with(this.outline.then(ConvertOutline::new))
+            .convert_with(this.title_page.then(ConvertTitlePage::new))
+            .convert_with(this.title.then(ConvertTitle::new))
+            .convert_with(this.authors.then(ConvertAuthors::new))
+            .convert_with(this.preamble.map(ConvertPreamble::new))
+            .convert_with(
+                this.labels
+                    .then(|| ConvertLabels::with_chapter_labels(this.chapters)),
+            )
+            .convert_with(this.parts.map(ConvertParts::new))
+            .convert_with(this.separators.map(ConvertSeparators::new))
+            .convert_with(
+                this.chapters
+                    .then(|| ConvertChapter::new(this.chapter_numbering, this.drafts)),
+            )
+            .convert_with(this.content.then(|| {
+                convert::from_fn(|e, _: &mut Input, output: &mut Output| {
+                    output.emit(match e {
+                        ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(m)) => {
+                            ParserEvent::Markdown(m)
+                        }
+                        x => x,
+                    })
+                })
+            }))
+            .convert_with(content(this.footnotes).then(ConvertFootnotes::new))
+            .convert_with(content(this.headings).then(ConvertHeadings::new))
+            .convert_with(content(this.paragraphs).then(ConvertParagraphs::new))
+            .convert_with(content(this.math).then(ConvertMath::new))
+            .convert_with(content(this.soft_breaks).then(ConvertSoftBreaks::new))
+            .convert_with(content(this.hard_breaks).then(ConvertHardBreaks::new))
+            .convert_with(content(this.hidden_lines).then(ConvertHiddenLines::new))
+            .convert_with(content(this.text).then(ConvertText::new))
+            .convert_with(content(this.strong).then(ConvertStrong::new))
+            .convert_with(content(this.emphasis).then(ConvertEmphasis::new))
+            .convert_with(content(this.strikethrough).then(ConvertStrikethrough::new))
+            .convert_with(content(this.task_lists).then(ConvertTaskLists::new))
+            .convert_with(content(this.blockquotes).then(ConvertBlockQuotes::new))
+            .convert_with(content(this.lists).then(ConvertLists::new))
+            .convert_with(content(this.code).then(ConvertCode::new))
+            .convert_with(content(this.links).then(ConvertLinks::new))
+            .convert_with(
+                content(this.images).then(|| ConvertImages::with_captions(this.image_captions)),
+            )
+            .convert_with(content(this.tables).then(ConvertHtmlTables::new))
+            .convert_with(content(this.tables).then(ConvertTables::new))
     }
 }
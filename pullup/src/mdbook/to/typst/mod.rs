@@ -1,5 +1,6 @@
 //! Convert mdBook to Typst.
 
+use crate::convert::{Converter, Input, Output};
 use crate::converter;
 use crate::markdown;
 use crate::markdown::CowStr;
@@ -9,7 +10,8 @@ use crate::ParserEvent;
 
 use core::cmp::min;
 use core::num::NonZeroU8;
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Component, Path, PathBuf};
 
 #[cfg(feature = "builder")]
 mod builder;
@@ -18,42 +20,32 @@ mod builder;
 pub use builder::Conversion;
 
 /// Convert mdBook authors to Typst authors.
-#[derive(Debug)]
-pub struct ConvertAuthors<'a, T> {
+#[derive(Debug, Default)]
+pub struct ConvertAuthors<'a> {
     authors: Vec<CowStr<'a>>,
-    iter: T,
 }
 
-impl<'a, T> ConvertAuthors<'a, T>
-where
-    T: Iterator<Item = ParserEvent<'a>>,
-{
-    #[allow(dead_code)]
-    fn new(iter: T) -> Self {
-        Self {
-            authors: vec![],
-            iter,
-        }
+impl<'a> ConvertAuthors<'a> {
+    pub fn new() -> Self {
+        Self::default()
     }
 }
 
-impl<'a, T> Iterator for ConvertAuthors<'a, T>
-where
-    T: Iterator<Item = ParserEvent<'a>>,
-{
-    type Item = ParserEvent<'a>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            Some(ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::AuthorList))) => {
+impl<'a> Converter<'a> for ConvertAuthors<'a> {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::AuthorList)) => {
                 self.authors = vec![];
-                self.next()
             }
-            Some(ParserEvent::Mdbook(mdbook::Event::Author(a))) => {
+            ParserEvent::Mdbook(mdbook::Event::Author(a)) => {
                 self.authors.push(a);
-                self.next()
             }
-            Some(ParserEvent::Mdbook(mdbook::Event::End(mdbook::Tag::AuthorList))) => {
+            ParserEvent::Mdbook(mdbook::Event::End(mdbook::Tag::AuthorList)) => {
                 if !self.authors.is_empty() {
                     let markup_array = format!(
                         "({})",
@@ -64,14 +56,13 @@ where
                             .collect::<Vec<_>>()
                             .join(",")
                     );
-                    return Some(ParserEvent::Typst(typst::Event::DocumentSet(
+                    output.emit(ParserEvent::Typst(typst::Event::DocumentSet(
                         "author".into(),
                         markup_array.into(),
                     )));
                 }
-                self.next()
             }
-            x => x,
+            x => output.emit(x),
         }
     }
 }
@@ -80,196 +71,1616 @@ where
 converter!(
     /// Convert mdBook title to Typst set document title event.
     ConvertTitle,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Mdbook(mdbook::Event::Title(title))) => {
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Mdbook(mdbook::Event::Title(title)) => {
                 Some(ParserEvent::Typst(typst::Event::DocumentSet(
                     "title".into(),
                     format!("\"{}\"", title.as_ref()).into()),
                 ))
             },
-            x => x,
+            x => Some(x),
     }
 });
 
+/// Page and text setup for the generated document. Unset fields use Typst's defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preamble {
+    /// The paper size, like `a4` or `us-letter`.
+    pub paper: Option<String>,
+    /// The page margins, as a Typst length or dictionary like `(x: 2cm, y: 3cm)`.
+    pub margin: Option<String>,
+    /// The numbering pattern of page numbers, like `1`.
+    pub page_numbering: Option<String>,
+    /// The font family.
+    pub font: Option<String>,
+    /// The font size, as a Typst length.
+    pub font_size: Option<String>,
+    /// The language of the text. Defaults to the language of the book.
+    pub lang: Option<String>,
+    /// Whether to justify paragraphs.
+    pub justify: Option<bool>,
+    /// The numbering pattern of headings, like `1.1`.
+    pub heading_numbering: Option<String>,
+}
+
+impl Default for Preamble {
+    /// A4 pages with page numbers and justified paragraphs.
+    fn default() -> Self {
+        Self {
+            paper: Some("a4".to_string()),
+            margin: Some("2.5cm".to_string()),
+            page_numbering: Some("1".to_string()),
+            font: None,
+            font_size: None,
+            lang: None,
+            justify: Some(true),
+            heading_numbering: None,
+        }
+    }
+}
+
+/// Convert the book language and a [`Preamble`] to Typst set rules at the end of the
+/// book configuration, before any content.
 #[derive(Debug)]
-pub struct ConvertChapter<'a, T> {
-    chapters: VecDeque<()>,
-    buf: VecDeque<ParserEvent<'a>>,
-    iter: T,
+pub struct ConvertPreamble<'a> {
+    preamble: Preamble,
+    lang: Option<CowStr<'a>>,
 }
 
-impl<'a, T> ConvertChapter<'a, T>
-where
-    T: Iterator<Item = ParserEvent<'a>>,
-{
-    #[allow(dead_code)]
-    fn new(iter: T) -> Self {
+impl<'a> ConvertPreamble<'a> {
+    pub fn new(preamble: Preamble) -> Self {
         Self {
-            chapters: VecDeque::new(),
-            buf: VecDeque::new(),
-            iter,
+            preamble,
+            lang: None,
+        }
+    }
+
+    fn set_rules(&mut self, output: &mut Output<'_, 'a>) {
+        let p = &self.preamble;
+        let page = typst::PageSettings {
+            paper: p.paper.clone().map(Into::into),
+            margin: p.margin.clone().map(Into::into),
+            numbering: p.page_numbering.clone().map(Into::into),
+            ..Default::default()
+        };
+        let text = typst::TextSettings {
+            font: p.font.clone().map(Into::into),
+            size: p.font_size.clone().map(Into::into),
+            lang: p.lang.clone().map(Into::into).or_else(|| self.lang.take()),
+        };
+        let par = typst::ParSettings {
+            justify: p.justify,
+            ..Default::default()
+        };
+        let heading = typst::HeadingSettings {
+            numbering: p.heading_numbering.clone().map(Into::into),
+        };
+        if page != Default::default() {
+            output.emit(ParserEvent::Typst(typst::Event::SetPage(page)));
+        }
+        if text != Default::default() {
+            output.emit(ParserEvent::Typst(typst::Event::SetText(text)));
+        }
+        if par != Default::default() {
+            output.emit(ParserEvent::Typst(typst::Event::SetPar(par)));
+        }
+        if heading != Default::default() {
+            output.emit(ParserEvent::Typst(typst::Event::SetHeading(heading)));
+        }
+    }
+}
+
+impl<'a> Converter<'a> for ConvertPreamble<'a> {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Mdbook(mdbook::Event::Language(lang)) => {
+                self.lang = Some(lang);
+            }
+            e @ ParserEvent::Mdbook(mdbook::Event::End(mdbook::Tag::BookConfiguration)) => {
+                self.set_rules(output);
+                output.emit(e);
+            }
+            x => output.emit(x),
+        }
+    }
+}
+
+/// Add a title page with the book title, description, and authors at the start of the
+/// book content.
+///
+/// The title is a heading left out of the outline and PDF bookmarks. No title page is
+/// added if the book has no title. The configuration events are passed through, so
+/// this needs to run before [`ConvertTitle`] and [`ConvertAuthors`].
+#[derive(Debug, Default)]
+pub struct ConvertTitlePage<'a> {
+    title: Option<CowStr<'a>>,
+    description: Option<CowStr<'a>>,
+    authors: Vec<CowStr<'a>>,
+}
+
+impl<'a> ConvertTitlePage<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn title_page(&mut self, output: &mut Output<'_, 'a>) {
+        let Some(title) = self.title.take() else {
+            return;
+        };
+        let align = typst::Tag::Align("center + horizon".into());
+        let heading = typst::Tag::Heading(
+            NonZeroU8::MIN,
+            typst::TableOfContents::Exclude,
+            typst::Bookmarks::Exclude,
+        );
+        output.extend(
+            [
+                typst::Event::Start(align.clone()),
+                typst::Event::Start(heading.clone()),
+                typst::Event::Text(title),
+                typst::Event::End(heading),
+            ]
+            .map(ParserEvent::Typst),
+        );
+        if let Some(description) = self.description.take() {
+            output.extend(
+                [
+                    typst::Event::Start(typst::Tag::Paragraph),
+                    typst::Event::Text(description),
+                    typst::Event::End(typst::Tag::Paragraph),
+                ]
+                .map(ParserEvent::Typst),
+            );
         }
+        if !self.authors.is_empty() {
+            let authors = self
+                .authors
+                .drain(..)
+                .map(|a| a.into_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            output.extend(
+                [
+                    typst::Event::Start(typst::Tag::Paragraph),
+                    typst::Event::Start(typst::Tag::Emphasis),
+                    typst::Event::Text(authors.into()),
+                    typst::Event::End(typst::Tag::Emphasis),
+                    typst::Event::End(typst::Tag::Paragraph),
+                ]
+                .map(ParserEvent::Typst),
+            );
+        }
+        output.extend([typst::Event::End(align), typst::Event::PageBreak].map(ParserEvent::Typst));
+    }
+}
+
+impl<'a> Converter<'a> for ConvertTitlePage<'a> {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Mdbook(mdbook::Event::Title(ref title)) => {
+                self.title = Some(title.clone());
+                output.emit(event)
+            }
+            ParserEvent::Mdbook(mdbook::Event::Description(ref description)) => {
+                self.description = Some(description.clone());
+                output.emit(event)
+            }
+            ParserEvent::Mdbook(mdbook::Event::Author(ref author)) => {
+                self.authors.push(author.clone());
+                output.emit(event)
+            }
+            e @ ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::BookContent)) => {
+                output.emit(e);
+                self.title_page(output);
+            }
+            x => output.emit(x),
+        }
+    }
+}
+
+/// Add an outline of the book, i.e. a table of contents, on its own page at the start
+/// of the book content. Headings marked with
+/// [`TableOfContents::Exclude`](typst::TableOfContents::Exclude) are left out.
+///
+/// To put the outline after the title page, this needs to run before
+/// [`ConvertTitlePage`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConvertOutline;
+
+impl ConvertOutline {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<'a> Converter<'a> for ConvertOutline {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            e @ ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::BookContent)) => {
+                output.emit(e);
+                output.emit(ParserEvent::Typst(typst::Event::Outline(None, None)));
+                output.emit(ParserEvent::Typst(typst::Event::PageBreak));
+            }
+            x => output.emit(x),
+        }
+    }
+}
+
+/// How to render the titles of book parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PartStyle {
+    /// A page of its own with the title in the middle.
+    #[default]
+    Page,
+    /// A heading before the part's first chapter.
+    Heading,
+}
+
+/// Convert the titles of mdBook parts to level 1 headings, the same level as top-level
+/// chapters, so the outline lists them like mdBook's sidebar does. Parts without a
+/// title are left out.
+#[derive(Debug)]
+pub struct ConvertParts {
+    style: PartStyle,
+}
+
+impl ConvertParts {
+    pub fn new(style: PartStyle) -> Self {
+        Self { style }
+    }
+}
+
+impl<'a> Converter<'a> for ConvertParts {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::Part(Some(title), _))) => {
+                let heading = typst::Tag::Heading(
+                    NonZeroU8::MIN,
+                    typst::TableOfContents::Include,
+                    typst::Bookmarks::Include,
+                );
+                let title = [
+                    typst::Event::Start(heading.clone()),
+                    typst::Event::Text(title),
+                    typst::Event::End(heading),
+                ];
+                // Start parts on a new page.
+                output.emit(ParserEvent::Typst(typst::Event::FunctionCall(
+                    None,
+                    "pagebreak".into(),
+                    vec!["weak: true".into()],
+                )));
+                match self.style {
+                    PartStyle::Page => {
+                        let align = typst::Tag::Align("center + horizon".into());
+                        output.emit(ParserEvent::Typst(typst::Event::Start(align.clone())));
+                        output.extend(title.map(ParserEvent::Typst));
+                        output.emit(ParserEvent::Typst(typst::Event::End(align)));
+                        output.emit(ParserEvent::Typst(typst::Event::PageBreak));
+                    }
+                    PartStyle::Heading => output.extend(title.map(ParserEvent::Typst)),
+                }
+            }
+            ParserEvent::Mdbook(
+                mdbook::Event::Start(mdbook::Tag::Part(..))
+                | mdbook::Event::End(mdbook::Tag::Part(..)),
+            ) => {}
+            x => output.emit(x),
+        }
+    }
+}
+
+/// How to render separators between chapters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeparatorStyle {
+    /// A horizontal line across the page.
+    #[default]
+    Line,
+    /// Vertical space.
+    Space,
+}
+
+/// Convert mdBook separators to a line or vertical space.
+#[derive(Debug)]
+pub struct ConvertSeparators {
+    style: SeparatorStyle,
+}
+
+impl ConvertSeparators {
+    pub fn new(style: SeparatorStyle) -> Self {
+        Self { style }
+    }
+}
+
+impl<'a> Converter<'a> for ConvertSeparators {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        output.emit(match event {
+            ParserEvent::Mdbook(mdbook::Event::Separator) => ParserEvent::Typst(match self.style {
+                SeparatorStyle::Line => {
+                    typst::Event::Line(None, None, Some("100%".into()), None, None)
+                }
+                SeparatorStyle::Space => {
+                    typst::Event::FunctionCall(None, "v".into(), vec!["2em".into()])
+                }
+            }),
+            x => x,
+        })
     }
 }
 
-impl<'a, T> Iterator for ConvertChapter<'a, T>
-where
-    T: Iterator<Item = ParserEvent<'a>>,
-{
-    type Item = ParserEvent<'a>;
+/// How to number the headings of numbered chapters.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ChapterNumbering {
+    /// Put the section number before the chapter name, like `1.2. Setup`, the same way
+    /// mdBook's HTML renderer does.
+    #[default]
+    Text,
+    /// Let Typst number the headings of numbered chapters with a numbering pattern like
+    /// `1.1.`. Headings within those chapters are numbered as well.
+    Typst(String),
+}
+
+/// How to render draft chapters, which have a title but no content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DraftPolicy {
+    /// Leave draft chapters out. Chapters nested in them move up a level.
+    Skip,
+    /// Render the chapter heading followed by a marker, like `Setup (draft)`.
+    Title(String),
+    /// List the chapter in the outline but do not show it in the document.
+    Outline,
+}
+
+impl Default for DraftPolicy {
+    /// Mark draft chapter headings with `(draft)`.
+    fn default() -> Self {
+        Self::Title("(draft)".to_string())
+    }
+}
+
+/// Convert mdBook chapters to headings, with the chapter contents one level deeper.
+/// Headings are numbered according to a [`ChapterNumbering`], though prefix and suffix
+/// chapters are never numbered, and draft chapters are handled according to a
+/// [`DraftPolicy`].
+#[derive(Debug)]
+pub struct ConvertChapter {
+    chapters: VecDeque<()>,
+    numbering: Option<ChapterNumbering>,
+    drafts: DraftPolicy,
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // If we have a previously buffered event, return it.
-        if let Some(buffered) = self.buf.pop_front() {
-            #[cfg(feature = "tracing")]
-            tracing::trace!("returning buffered: {:?}", buffered);
+impl ConvertChapter {
+    pub fn new(numbering: Option<ChapterNumbering>, drafts: DraftPolicy) -> Self {
+        Self {
+            chapters: VecDeque::new(),
+            numbering,
+            drafts,
+        }
+    }
 
-            return Some(buffered);
+    /// The pattern Typst numbers a top-level chapter with, if it does.
+    fn typst_numbering(&self, number: &Option<mdbook::ChapterNumber>) -> Option<String> {
+        match (&self.numbering, number) {
+            (Some(ChapterNumbering::Typst(pattern)), Some(mdbook::ChapterNumber::Numbered(_)))
+                if self.chapters.is_empty() =>
+            {
+                Some(pattern.clone())
+            }
+            _ => None,
         }
-        // Otherwise pull from the inner iterator.
-        match self.iter.next() {
+    }
+}
+
+impl<'a> Converter<'a> for ConvertChapter {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
             // Start of chapter.
-            Some(ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::Chapter(
-                _,
+            ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::Chapter(
+                status,
                 name,
-                _,
-                _,
-            )))) => {
+                source,
+                number,
+            ))) => {
                 #[cfg(feature = "tracing")]
                 tracing::trace!("chapter start: {}", name);
 
+                let draft = status == mdbook::ChapterStatus::Draft;
+                if draft && self.drafts == DraftPolicy::Skip {
+                    return;
+                }
+
+                // Typst numbers the headings of top-level numbered chapters.
+                if let Some(pattern) = self.typst_numbering(&number) {
+                    output.emit(ParserEvent::Typst(typst::Event::SetHeading(
+                        typst::HeadingSettings {
+                            numbering: Some(pattern.into()),
+                        },
+                    )));
+                }
+
                 // Get how many chapters deep we are.
                 let depth = self.chapters.len();
 
-                // Create a Typst heading start event for the chapter.
+                // Create a Typst heading for the chapter.
                 let tag = typst::Tag::Heading(
                     NonZeroU8::new((1 + depth).try_into().expect("nonzero")).expect("nonzero"),
                     typst::TableOfContents::Include,
                     typst::Bookmarks::Include,
                 );
 
-                let start_event = ParserEvent::Typst(typst::Event::Start(tag.clone()));
-                let end_event = ParserEvent::Typst(typst::Event::End(tag));
+                // Hide drafts that are only listed in the outline.
+                let hide = draft && self.drafts == DraftPolicy::Outline;
+                if hide {
+                    output.emit(ParserEvent::Typst(typst::Event::Start(typst::Tag::Hide)));
+                }
+
+                // Emit the heading with the chapter name text and label.
+                let name = match (&self.numbering, number) {
+                    (
+                        Some(ChapterNumbering::Text),
+                        Some(n @ mdbook::ChapterNumber::Numbered(_)),
+                    ) => format!("{n} {name}").into(),
+                    _ => name,
+                };
+                let name = match self.drafts {
+                    DraftPolicy::Title(ref marker) if draft => format!("{name} {marker}").into(),
+                    _ => name,
+                };
+                output.emit(ParserEvent::Typst(typst::Event::Start(tag.clone())));
+                output.emit(ParserEvent::Typst(typst::Event::Text(name)));
+                if let Some(mdbook::ChapterSource::Path(path)) = source {
+                    output.emit(ParserEvent::Typst(typst::Event::Label(
+                        chapter_label(&path).into(),
+                    )));
+                }
+                output.emit(ParserEvent::Typst(typst::Event::End(tag)));
 
-                // Queue up the chapter name text event and heading end event.
-                self.buf
-                    .push_back(ParserEvent::Typst(typst::Event::Text(name.clone())));
-                self.buf.push_back(end_event);
+                if hide {
+                    output.emit(ParserEvent::Typst(typst::Event::End(typst::Tag::Hide)));
+                }
 
                 // Record that we are one chapter deeper.
                 self.chapters.push_back(());
-
-                #[cfg(feature = "tracing")]
-                tracing::trace!("returning: {:?}", start_event);
-
-                // Return the heading start event.
-                Some(start_event)
             }
             // End of a chapter.
-            Some(ParserEvent::Mdbook(mdbook::Event::End(mdbook::Tag::Chapter(_, _name, _, _)))) => {
+            ParserEvent::Mdbook(mdbook::Event::End(mdbook::Tag::Chapter(
+                status,
+                _name,
+                _,
+                number,
+            ))) => {
                 #[cfg(feature = "tracing")]
                 tracing::trace!("chapter end: {}", _name);
 
+                let draft = status == mdbook::ChapterStatus::Draft;
+                if draft && self.drafts == DraftPolicy::Skip {
+                    return;
+                }
+
                 // Record that we are one chapter shallower.
                 let _ = self.chapters.pop_front();
-                // Chapters are converted to page break.
-                Some(ParserEvent::Typst(typst::Event::FunctionCall(
-                    None,
-                    "pagebreak".into(),
-                    vec!["weak: true".into()],
-                )))
+                // Chapters are converted to page break. Hidden drafts take no page of
+                // their own.
+                if !(draft && self.drafts == DraftPolicy::Outline) {
+                    output.emit(ParserEvent::Typst(typst::Event::FunctionCall(
+                        None,
+                        "pagebreak".into(),
+                        vec!["weak: true".into()],
+                    )));
+                }
+                // Stop numbering after a numbered chapter, so parts and unnumbered
+                // chapters are not numbered.
+                if self.typst_numbering(&number).is_some() {
+                    output.emit(ParserEvent::Typst(typst::Event::Set(
+                        "heading".into(),
+                        "numbering".into(),
+                        "none".into(),
+                    )));
+                }
             }
             // Heading start in a chapter.
-            Some(ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(
-                markdown::Event::Start(markdown::Tag::Heading(level, x, y)),
+            ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(markdown::Event::Start(
+                markdown::Tag::Heading(level, x, y),
             ))) => {
                 #[cfg(feature = "tracing")]
                 tracing::trace!("heading start: {}", level);
 
-                use markdown::HeadingLevel;
-                // Get how many chapters deep we are.
-                let depth = self.chapters.len();
-                let new_level = match level {
-                    HeadingLevel::H1 => {
-                        HeadingLevel::try_from(min(1 + depth, 6)).expect("valid heading level")
-                    }
-                    HeadingLevel::H2 => {
-                        HeadingLevel::try_from(min(2 + depth, 6)).expect("valid heading level")
-                    }
-                    HeadingLevel::H3 => {
-                        HeadingLevel::try_from(min(3 + depth, 6)).expect("valid heading level")
-                    }
-                    HeadingLevel::H4 => {
-                        HeadingLevel::try_from(min(4 + depth, 6)).expect("valid heading level")
-                    }
-                    HeadingLevel::H5 => {
-                        HeadingLevel::try_from(min(5 + depth, 6)).expect("valid heading level")
-                    }
-                    HeadingLevel::H6 => HeadingLevel::H6,
-                };
-                self.buf
-                    .push_back(ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(
-                        markdown::Event::Start(markdown::Tag::Heading(new_level, x, y)),
-                    )));
-                self.next()
+                let new_level = shift_level(level, self.chapters.len());
+                output.emit(ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(
+                    markdown::Event::Start(markdown::Tag::Heading(new_level, x, y)),
+                )));
             }
             // Heading end in a chapter.
-            Some(ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(
-                markdown::Event::End(markdown::Tag::Heading(level, x, y)),
+            ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(markdown::Event::End(
+                markdown::Tag::Heading(level, x, y),
             ))) => {
                 #[cfg(feature = "tracing")]
                 tracing::trace!("heading end: {}", level);
-                use markdown::HeadingLevel;
-                // Get how many chapters deep we are.
-                let depth = self.chapters.len();
-                let new_level = match level {
-                    HeadingLevel::H1 => {
-                        HeadingLevel::try_from(min(1 + depth, 6)).expect("valid heading level")
-                    }
-                    HeadingLevel::H2 => {
-                        HeadingLevel::try_from(min(2 + depth, 6)).expect("valid heading level")
-                    }
-                    HeadingLevel::H3 => {
-                        HeadingLevel::try_from(min(3 + depth, 6)).expect("valid heading level")
-                    }
-                    HeadingLevel::H4 => {
-                        HeadingLevel::try_from(min(4 + depth, 6)).expect("valid heading level")
-                    }
-                    HeadingLevel::H5 => {
-                        HeadingLevel::try_from(min(5 + depth, 6)).expect("valid heading level")
+                let new_level = shift_level(level, self.chapters.len());
+                output.emit(ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(
+                    markdown::Event::End(markdown::Tag::Heading(new_level, x, y)),
+                )));
+            }
+
+            x => output.emit(x),
+        }
+    }
+}
+
+/// Shift the level of a heading in a chapter `depth` chapters deep, so it comes below
+/// the chapter heading. Levels past 6 stay at 6.
+fn shift_level(level: markdown::HeadingLevel, depth: usize) -> markdown::HeadingLevel {
+    markdown::HeadingLevel::try_from(min(level as usize + depth, 6)).expect("valid heading level")
+}
+
+/// Labels for the headings of a chapter.
+#[derive(Debug)]
+struct ChapterLabels {
+    /// The source path of the chapter, relative to the book source directory.
+    path: PathBuf,
+    /// The label of the chapter heading.
+    label: String,
+    /// How many times each heading ID has been used.
+    ids: HashMap<String, usize>,
+}
+
+/// Label headings and convert links within the book to Typst label references.
+///
+/// Headings are labeled with the chapter label and the heading ID separated by a `:`,
+/// which chapter labels never contain, so `guide/setup.md#install` becomes
+/// `guide-setup:install`. The heading ID is either set explicitly with `{#id}` or
+/// derived from the heading text the same way mdBook does. Explicit IDs are reduced to
+/// the characters Typst labels allow, and IDs used more than once in a chapter get a
+/// numeric suffix. Relative links to `.md` files and `#anchor` links become
+/// `#link(<label>)[...]` as they are seen. Typst fails on links to missing labels, so
+/// labels that are linked to but never defined, like a mistyped anchor or a chapter
+/// that is not in the book, are defined as empty metadata at the end of the book. Other
+/// links are left as they are.
+///
+/// Chapter labels are added by [`ConvertChapter`], so this needs to run before it.
+#[derive(Debug)]
+pub struct ConvertLabels {
+    chapters: Vec<Option<ChapterLabels>>,
+    /// Whether chapter headings are labeled.
+    chapter_labels: bool,
+    /// The labels of the chapters and headings seen so far.
+    labels: HashSet<String>,
+    /// Labels linked to before they were seen, in the order they were linked to.
+    pending: Vec<String>,
+}
+
+impl ConvertLabels {
+    pub fn new() -> Self {
+        Self::with_chapter_labels(true)
+    }
+
+    /// Convert labels, where `chapter_labels` tells if chapter headings are labeled,
+    /// so links to whole chapters can be converted.
+    pub fn with_chapter_labels(chapter_labels: bool) -> Self {
+        Self {
+            chapters: vec![],
+            chapter_labels,
+            labels: HashSet::new(),
+            pending: vec![],
+        }
+    }
+
+    /// The label a link destination refers to, if it points into the book.
+    fn link_label(&self, dest: &str) -> Option<String> {
+        let chapter = self.chapters.last()?.as_ref()?;
+        if dest.contains("://") || dest.starts_with('/') || dest.starts_with("mailto:") {
+            return None;
+        }
+        let (path, anchor) = match dest.split_once('#') {
+            Some((path, anchor)) => (path, anchor),
+            None => (dest, ""),
+        };
+        let label = match path {
+            "" if anchor.is_empty() => return None,
+            "" => chapter.label.clone(),
+            path if path.ends_with(".md") => {
+                let dir = chapter.path.parent().unwrap_or(Path::new(""));
+                chapter_label(&normalize_path(&dir.join(path))?)
+            }
+            _ => return None,
+        };
+        match anchor {
+            "" if self.chapter_labels => Some(label),
+            "" => None,
+            anchor => Some(heading_label(&label, &normalize_id(anchor))),
+        }
+    }
+
+    /// The label of a Markdown link into the book that `event` starts or ends.
+    fn target(&self, event: &ParserEvent<'_>) -> Option<String> {
+        use markdown::LinkType::*;
+        match event {
+            ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(
+                markdown::Event::Start(markdown::Tag::Link(
+                    Inline | Reference | Collapsed | Shortcut,
+                    dest,
+                    _,
+                ))
+                | markdown::Event::End(markdown::Tag::Link(
+                    Inline | Reference | Collapsed | Shortcut,
+                    dest,
+                    _,
+                )),
+            )) => self.link_label(dest),
+            _ => None,
+        }
+    }
+
+    /// Emit an event, converting it if it starts or ends a link into the book.
+    fn emit<'a>(&mut self, event: ParserEvent<'a>, output: &mut Output<'_, 'a>) {
+        let Some(label) = self.target(&event) else {
+            return output.emit(event);
+        };
+        if !self.labels.contains(&label) && !self.pending.contains(&label) {
+            self.pending.push(label.clone());
+        }
+        output.emit(label_link(event, label));
+    }
+}
+
+impl Default for ConvertLabels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Convert the start or end of a Markdown link to a Typst label link.
+fn label_link(event: ParserEvent<'_>, label: String) -> ParserEvent<'_> {
+    let tag = typst::Tag::Link(typst::LinkType::Label, label.into());
+    match event {
+        ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(markdown::Event::Start(_))) => {
+            ParserEvent::Typst(typst::Event::Start(tag))
+        }
+        _ => ParserEvent::Typst(typst::Event::End(tag)),
+    }
+}
+
+impl<'a> Converter<'a> for ConvertLabels {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::Chapter(
+                status,
+                name,
+                source,
+                number,
+            ))) => {
+                let labels = match source {
+                    Some(mdbook::ChapterSource::Path(ref path)) => Some(ChapterLabels {
+                        path: path.clone(),
+                        label: chapter_label(path),
+                        ids: HashMap::new(),
+                    }),
+                    _ => None,
+                };
+                if let Some(labels) = labels.as_ref().filter(|_| self.chapter_labels) {
+                    self.labels.insert(labels.label.clone());
+                }
+                self.chapters.push(labels);
+                output.emit(ParserEvent::Mdbook(mdbook::Event::Start(
+                    mdbook::Tag::Chapter(status, name, source, number),
+                )));
+            }
+            ParserEvent::Mdbook(mdbook::Event::End(mdbook::Tag::Chapter(
+                status,
+                name,
+                source,
+                number,
+            ))) => {
+                let _ = self.chapters.pop();
+                output.emit(ParserEvent::Mdbook(mdbook::Event::End(
+                    mdbook::Tag::Chapter(status, name, source, number),
+                )));
+            }
+            // Hold back the heading to get its text, then label it before its end.
+            ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(markdown::Event::Start(
+                markdown::Tag::Heading(level, id, classes),
+            ))) if matches!(self.chapters.last(), Some(Some(_))) => {
+                let mut text = HeadingText::default();
+                let mut events = vec![];
+                let end = loop {
+                    match input.next() {
+                        Some(
+                            e @ ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(
+                                markdown::Event::End(markdown::Tag::Heading(..)),
+                            )),
+                        ) => break Some(e),
+                        Some(e) => {
+                            if let ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(ref e)) =
+                                e
+                            {
+                                text.push(e);
+                            }
+                            events.push(e);
+                        }
+                        None => break None,
                     }
-                    HeadingLevel::H6 => HeadingLevel::H6,
                 };
-                self.buf
-                    .push_back(ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(
-                        markdown::Event::End(markdown::Tag::Heading(new_level, x, y)),
-                    )));
-                self.next()
+                let chapter = self
+                    .chapters
+                    .last_mut()
+                    .and_then(Option::as_mut)
+                    .expect("chapter labels");
+                // Explicit IDs can contain characters Typst labels cannot.
+                let anchor = id
+                    .map(normalize_id)
+                    .filter(|id| !id.is_empty())
+                    .unwrap_or_else(|| text.id());
+                let count = chapter.ids.entry(anchor.clone()).or_insert(0);
+                let anchor = match *count {
+                    0 => anchor,
+                    n => format!("{anchor}-{n}"),
+                };
+                *count += 1;
+                let label = heading_label(&chapter.label, &anchor);
+                self.labels.insert(label.clone());
+                output.emit(ParserEvent::Mdbook(mdbook::Event::MarkdownContentEvent(
+                    markdown::Event::Start(markdown::Tag::Heading(level, id, classes)),
+                )));
+                for event in events {
+                    self.emit(event, output);
+                }
+                output.emit(ParserEvent::Typst(typst::Event::Label(label.into())));
+                if let Some(end) = end {
+                    output.emit(end);
+                }
             }
+            e => self.emit(e, output),
+        }
+    }
 
-            x => x,
+    fn finish(&mut self, output: &mut Output<'_, 'a>) {
+        for label in self.pending.drain(..) {
+            if !self.labels.contains(&label) {
+                output.emit(ParserEvent::Typst(typst::Event::Raw(
+                    format!("#metadata(none) <{label}>\n").into(),
+                )));
+            }
         }
     }
 }
 
+/// The text of a heading as mdBook's HTML renderer shows it, which mdBook creates
+/// heading IDs from: the text of links, emphasis, and code, and inline HTML without its
+/// tags. Images show no text. mdBook shows footnote references as their number, which
+/// is left out as it depends on the order of the footnotes in the rendered chapter.
+#[derive(Debug, Default)]
+struct HeadingText {
+    text: String,
+    /// How many images the current event is in.
+    images: usize,
+}
+
+impl HeadingText {
+    fn push(&mut self, event: &markdown::Event<'_>) {
+        match event {
+            markdown::Event::Start(markdown::Tag::Image(..)) => self.images += 1,
+            markdown::Event::End(markdown::Tag::Image(..)) => self.images -= 1,
+            _ if self.images > 0 => {}
+            markdown::Event::Text(t) | markdown::Event::Code(t) => self.text.push_str(t),
+            markdown::Event::SoftBreak | markdown::Event::HardBreak => self.text.push('\n'),
+            markdown::Event::Html(html) => {
+                let mut rest: &str = html;
+                while let Some((before, tag)) = rest.split_once('<') {
+                    self.text.push_str(before);
+                    // An unclosed `<` is text.
+                    let Some((_, after)) = tag.split_once('>') else {
+                        self.text.push('<');
+                        rest = tag;
+                        break;
+                    };
+                    rest = after;
+                }
+                self.text.push_str(rest);
+            }
+            _ => {}
+        }
+    }
+
+    /// The heading ID, created the same way mdBook does.
+    fn id(&self) -> String {
+        normalize_id(self.text.trim().trim_start_matches('#').trim())
+    }
+}
+
+/// Create a heading ID from its text the same way mdBook does.
+fn normalize_id(content: &str) -> String {
+    content
+        .chars()
+        .filter_map(|ch| {
+            if ch.is_alphanumeric() || ch == '_' || ch == '-' {
+                Some(ch.to_ascii_lowercase())
+            } else if ch.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The label of a chapter, created from its source path: `guide/setup.md` becomes
+/// `guide-setup`.
+fn chapter_label(path: &Path) -> String {
+    path.with_extension("")
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(x) => Some(normalize_id(&x.to_string_lossy())),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The label of a heading in a chapter. Chapter labels cannot contain `:`, so heading
+/// labels never collide with the labels of other chapters.
+fn heading_label(chapter: &str, anchor: &str) -> String {
+    format!("{chapter}:{anchor}")
+}
+
+/// Resolve `.` and `..` in a relative path. Returns `None` if the path leaves the book.
+fn normalize_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::Normal(x) => normalized.push(x),
+            _ => return None,
+        }
+    }
+    Some(normalized)
+}
+
 // TODO: tests
 converter!(
     /// Convert mdBook chapters to Typst pagebreaks. This does not affect any content in
     /// the chapter.
     ConvertChapterToPagebreak,
-    ParserEvent<'a> => ParserEvent<'a>,
-    |this: &mut Self| {
-        match this.iter.next() {
-            Some(ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::Chapter(_, _, _, _)))) => this.next(),
-            Some(ParserEvent::Mdbook(mdbook::Event::End(mdbook::Tag::Chapter(_, _, _, _)))) => {
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Mdbook(mdbook::Event::Start(mdbook::Tag::Chapter(_, _, _, _))) => None,
+            ParserEvent::Mdbook(mdbook::Event::End(mdbook::Tag::Chapter(_, _, _, _))) => {
                 Some(ParserEvent::Typst(typst::Event::FunctionCall(
                     None,
                     "pagebreak".into(),
                     vec!["weak: true".into()],
                 )))
             },
-            x => x,
+            x => Some(x),
     }
 });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mdbook::MdbookIter;
+    use crate::IteratorExt;
+    use similar_asserts::assert_eq;
+
+    // Set up type names so they are clearer and more succint.
+    use markdown::Event as MdEvent;
+    use markdown::Tag as MdTag;
+    use mdbook::Event as MdbookEvent;
+    use mdbook::Tag as MdbookTag;
+    use typst::Event as TypstEvent;
+    use typst::Tag as TypstTag;
+    use ParserEvent::*;
+
+    fn chapter(path: &str) -> MdbookTag<'_> {
+        MdbookTag::Chapter(
+            mdbook::ChapterStatus::Active,
+            "Chapter".into(),
+            Some(mdbook::ChapterSource::Path(PathBuf::from(path))),
+            None,
+        )
+    }
+
+    /// A chapter at `path` containing the Markdown `md`.
+    fn book<'a>(path: &'a str, md: &'a str) -> Vec<MdbookEvent<'a>> {
+        let parser = markdown::Parser::new_ext(md, markdown::Options::ENABLE_HEADING_ATTRIBUTES);
+        let mut events = vec![MdbookEvent::Start(chapter(path))];
+        events.extend(parser.map(MdbookEvent::MarkdownContentEvent));
+        events.push(MdbookEvent::End(chapter(path)));
+        events
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/layout/page/
+    /// * https://typst.app/docs/reference/text/text/
+    mod preamble {
+        use super::*;
+
+        fn config() -> Vec<MdbookEvent<'static>> {
+            vec![
+                MdbookEvent::Start(MdbookTag::BookConfiguration),
+                MdbookEvent::Language("de".into()),
+                MdbookEvent::End(MdbookTag::BookConfiguration),
+            ]
+        }
+
+        #[test]
+        fn default() {
+            let i = MdbookIter(config().into_iter())
+                .convert_with(ConvertPreamble::new(Preamble::default()));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Mdbook(MdbookEvent::Start(MdbookTag::BookConfiguration)),
+                    Typst(TypstEvent::SetPage(typst::PageSettings {
+                        paper: Some("a4".into()),
+                        margin: Some("2.5cm".into()),
+                        numbering: Some("1".into()),
+                        ..Default::default()
+                    })),
+                    Typst(TypstEvent::SetText(typst::TextSettings {
+                        lang: Some("de".into()),
+                        ..Default::default()
+                    })),
+                    Typst(TypstEvent::SetPar(typst::ParSettings {
+                        justify: Some(true),
+                        ..Default::default()
+                    })),
+                    Mdbook(MdbookEvent::End(MdbookTag::BookConfiguration)),
+                ]
+            );
+        }
+
+        #[test]
+        fn custom() {
+            let preamble = Preamble {
+                paper: None,
+                margin: None,
+                page_numbering: None,
+                font: Some("Inter".to_string()),
+                font_size: None,
+                lang: Some("en".to_string()),
+                justify: None,
+                heading_numbering: Some("1.1".to_string()),
+            };
+            let i = MdbookIter(config().into_iter()).convert_with(ConvertPreamble::new(preamble));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Mdbook(MdbookEvent::Start(MdbookTag::BookConfiguration)),
+                    Typst(TypstEvent::SetText(typst::TextSettings {
+                        font: Some("Inter".into()),
+                        lang: Some("en".into()),
+                        ..Default::default()
+                    })),
+                    Typst(TypstEvent::SetHeading(typst::HeadingSettings {
+                        numbering: Some("1.1".into()),
+                    })),
+                    Mdbook(MdbookEvent::End(MdbookTag::BookConfiguration)),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/outline/
+    /// * https://typst.app/docs/reference/layout/align/
+    mod title_page {
+        use super::*;
+
+        fn config(title: Option<&str>) -> Vec<MdbookEvent<'_>> {
+            let mut events = vec![MdbookEvent::Start(MdbookTag::BookConfiguration)];
+            events.extend(title.map(|t| MdbookEvent::Title(t.into())));
+            events.extend([
+                MdbookEvent::Description("About things".into()),
+                MdbookEvent::Start(MdbookTag::AuthorList),
+                MdbookEvent::Author("Ann".into()),
+                MdbookEvent::Author("Bob".into()),
+                MdbookEvent::End(MdbookTag::AuthorList),
+                MdbookEvent::End(MdbookTag::BookConfiguration),
+                MdbookEvent::Start(MdbookTag::BookContent),
+                MdbookEvent::End(MdbookTag::BookContent),
+            ]);
+            events
+        }
+
+        #[test]
+        fn title_page_and_outline() {
+            let events = config(Some("Book"));
+            let i = MdbookIter(events.into_iter())
+                .convert_with(ConvertOutline::new())
+                .convert_with(ConvertTitlePage::new());
+            let align = TypstTag::Align("center + horizon".into());
+            let heading = TypstTag::Heading(
+                NonZeroU8::MIN,
+                typst::TableOfContents::Exclude,
+                typst::Bookmarks::Exclude,
+            );
+
+            self::assert_eq!(
+                i.skip_while(|e| *e != Mdbook(MdbookEvent::Start(MdbookTag::BookContent)))
+                    .collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Mdbook(MdbookEvent::Start(MdbookTag::BookContent)),
+                    Typst(TypstEvent::Start(align.clone())),
+                    Typst(TypstEvent::Start(heading.clone())),
+                    Typst(TypstEvent::Text("Book".into())),
+                    Typst(TypstEvent::End(heading)),
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Text("About things".into())),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Start(TypstTag::Emphasis)),
+                    Typst(TypstEvent::Text("Ann, Bob".into())),
+                    Typst(TypstEvent::End(TypstTag::Emphasis)),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                    Typst(TypstEvent::End(align)),
+                    Typst(TypstEvent::PageBreak),
+                    Typst(TypstEvent::Outline(None, None)),
+                    Typst(TypstEvent::PageBreak),
+                    Mdbook(MdbookEvent::End(MdbookTag::BookContent)),
+                ]
+            );
+        }
+
+        #[test]
+        fn configuration_is_passed_through() {
+            let events = config(Some("Book"));
+            let i = MdbookIter(events.clone().into_iter()).convert_with(ConvertTitlePage::new());
+
+            self::assert_eq!(
+                i.take(events.len() - 2)
+                    .collect::<Vec<super::ParserEvent>>(),
+                events[..events.len() - 2]
+                    .iter()
+                    .cloned()
+                    .map(Mdbook)
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn no_title() {
+            let i = MdbookIter(config(None).into_iter()).convert_with(ConvertTitlePage::new());
+
+            self::assert_eq!(i.filter(|e| matches!(e, Typst(_))).count(), 0);
+        }
+    }
+
+    /// mdBook docs:
+    /// * https://rust-lang.github.io/mdBook/format/summary.html
+    ///
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/heading/#parameters-numbering
+    mod numbering {
+        use super::*;
+
+        fn numbered(name: &str, number: mdbook::ChapterNumber) -> MdbookTag<'_> {
+            MdbookTag::Chapter(
+                mdbook::ChapterStatus::Active,
+                name.into(),
+                None,
+                Some(number),
+            )
+        }
+
+        fn events() -> Vec<MdbookEvent<'static>> {
+            let intro = numbered("Intro", mdbook::ChapterNumber::Prefix);
+            let one = numbered("One", mdbook::ChapterNumber::Numbered(vec![1]));
+            let two = numbered("Two", mdbook::ChapterNumber::Numbered(vec![1, 2]));
+            vec![
+                MdbookEvent::Start(intro.clone()),
+                MdbookEvent::End(intro),
+                MdbookEvent::Start(one.clone()),
+                MdbookEvent::Start(two.clone()),
+                MdbookEvent::End(two),
+                MdbookEvent::End(one),
+            ]
+        }
+
+        fn text(i: impl Iterator<Item = super::ParserEvent<'static>>) -> Vec<String> {
+            i.filter_map(|e| match e {
+                Typst(TypstEvent::Text(t)) => Some(t.into_string()),
+                _ => None,
+            })
+            .collect()
+        }
+
+        #[test]
+        fn text_numbers() {
+            let i = MdbookIter(events().into_iter()).convert_with(ConvertChapter::new(
+                Some(ChapterNumbering::Text),
+                DraftPolicy::default(),
+            ));
+            self::assert_eq!(text(i), vec!["Intro", "1. One", "1.2. Two"]);
+        }
+
+        #[test]
+        fn unnumbered() {
+            let i = MdbookIter(events().into_iter())
+                .convert_with(ConvertChapter::new(None, DraftPolicy::default()));
+            self::assert_eq!(text(i), vec!["Intro", "One", "Two"]);
+        }
+
+        #[test]
+        fn typst_numbers() {
+            let i = MdbookIter(events().into_iter()).convert_with(ConvertChapter::new(
+                Some(ChapterNumbering::Typst("1.1.".to_string())),
+                DraftPolicy::default(),
+            ));
+            let events = i.collect::<Vec<super::ParserEvent>>();
+            let rules = events
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| {
+                    matches!(e, Typst(TypstEvent::SetHeading(_) | TypstEvent::Set(..)))
+                })
+                .collect::<Vec<_>>();
+
+            self::assert_eq!(
+                rules,
+                vec![
+                    (
+                        4,
+                        &Typst(TypstEvent::SetHeading(typst::HeadingSettings {
+                            numbering: Some("1.1.".into()),
+                        }))
+                    ),
+                    (
+                        13,
+                        &Typst(TypstEvent::Set(
+                            "heading".into(),
+                            "numbering".into(),
+                            "none".into()
+                        ))
+                    ),
+                ]
+            );
+            self::assert_eq!(text(events.into_iter()), vec!["Intro", "One", "Two"]);
+        }
+    }
+
+    /// mdBook docs:
+    /// * https://rust-lang.github.io/mdBook/format/summary.html#draft-chapters
+    mod drafts {
+        use super::*;
+
+        fn draft(name: &str) -> MdbookTag<'_> {
+            MdbookTag::Chapter(mdbook::ChapterStatus::Draft, name.into(), None, None)
+        }
+
+        fn events() -> Vec<MdbookEvent<'static>> {
+            vec![
+                MdbookEvent::Start(draft("Later")),
+                MdbookEvent::Start(chapter("now.md")),
+                MdbookEvent::End(chapter("now.md")),
+                MdbookEvent::End(draft("Later")),
+            ]
+        }
+
+        fn h(level: u8) -> TypstTag<'static> {
+            TypstTag::Heading(
+                NonZeroU8::new(level).unwrap(),
+                typst::TableOfContents::Include,
+                typst::Bookmarks::Include,
+            )
+        }
+
+        fn pagebreak() -> super::ParserEvent<'static> {
+            Typst(TypstEvent::FunctionCall(
+                None,
+                "pagebreak".into(),
+                vec!["weak: true".into()],
+            ))
+        }
+
+        fn convert(policy: DraftPolicy) -> Vec<super::ParserEvent<'static>> {
+            MdbookIter(events().into_iter())
+                .convert_with(ConvertChapter::new(None, policy))
+                .collect()
+        }
+
+        #[test]
+        fn skip() {
+            self::assert_eq!(
+                convert(DraftPolicy::Skip),
+                vec![
+                    Typst(TypstEvent::Start(h(1))),
+                    Typst(TypstEvent::Text("Chapter".into())),
+                    Typst(TypstEvent::Label("now".into())),
+                    Typst(TypstEvent::End(h(1))),
+                    pagebreak(),
+                ]
+            );
+        }
+
+        #[test]
+        fn title() {
+            self::assert_eq!(
+                convert(DraftPolicy::default())[..3],
+                [
+                    Typst(TypstEvent::Start(h(1))),
+                    Typst(TypstEvent::Text("Later (draft)".into())),
+                    Typst(TypstEvent::End(h(1))),
+                ]
+            );
+        }
+
+        #[test]
+        fn outline() {
+            self::assert_eq!(
+                convert(DraftPolicy::Outline),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Hide)),
+                    Typst(TypstEvent::Start(h(1))),
+                    Typst(TypstEvent::Text("Later".into())),
+                    Typst(TypstEvent::End(h(1))),
+                    Typst(TypstEvent::End(TypstTag::Hide)),
+                    Typst(TypstEvent::Start(h(2))),
+                    Typst(TypstEvent::Text("Chapter".into())),
+                    Typst(TypstEvent::Label("now".into())),
+                    Typst(TypstEvent::End(h(2))),
+                    pagebreak(),
+                ]
+            );
+        }
+    }
+
+    /// mdBook docs:
+    /// * https://rust-lang.github.io/mdBook/format/summary.html
+    mod parts {
+        use super::*;
+
+        fn heading() -> TypstTag<'static> {
+            TypstTag::Heading(
+                NonZeroU8::MIN,
+                typst::TableOfContents::Include,
+                typst::Bookmarks::Include,
+            )
+        }
+
+        fn pagebreak() -> super::ParserEvent<'static> {
+            Typst(TypstEvent::FunctionCall(
+                None,
+                "pagebreak".into(),
+                vec!["weak: true".into()],
+            ))
+        }
+
+        fn events() -> Vec<MdbookEvent<'static>> {
+            vec![
+                MdbookEvent::Start(MdbookTag::Part(None, None)),
+                MdbookEvent::Separator,
+                MdbookEvent::End(MdbookTag::Part(None, None)),
+                MdbookEvent::Start(MdbookTag::Part(Some("Guide".into()), None)),
+                MdbookEvent::End(MdbookTag::Part(Some("Guide".into()), None)),
+            ]
+        }
+
+        #[test]
+        fn page() {
+            let i =
+                MdbookIter(events().into_iter()).convert_with(ConvertParts::new(PartStyle::Page));
+            let align = TypstTag::Align("center + horizon".into());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Mdbook(MdbookEvent::Separator),
+                    pagebreak(),
+                    Typst(TypstEvent::Start(align.clone())),
+                    Typst(TypstEvent::Start(heading())),
+                    Typst(TypstEvent::Text("Guide".into())),
+                    Typst(TypstEvent::End(heading())),
+                    Typst(TypstEvent::End(align)),
+                    Typst(TypstEvent::PageBreak),
+                ]
+            );
+        }
+
+        #[test]
+        fn heading_style() {
+            let i = MdbookIter(events().into_iter())
+                .convert_with(ConvertParts::new(PartStyle::Heading));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Mdbook(MdbookEvent::Separator),
+                    pagebreak(),
+                    Typst(TypstEvent::Start(heading())),
+                    Typst(TypstEvent::Text("Guide".into())),
+                    Typst(TypstEvent::End(heading())),
+                ]
+            );
+        }
+
+        #[test]
+        fn separators() {
+            let line = MdbookIter(events().into_iter())
+                .convert_with(ConvertSeparators::new(SeparatorStyle::Line));
+            self::assert_eq!(
+                line.collect::<Vec<super::ParserEvent>>()[1],
+                Typst(TypstEvent::Line(
+                    None,
+                    None,
+                    Some("100%".into()),
+                    None,
+                    None
+                ))
+            );
+
+            let space = MdbookIter(events().into_iter())
+                .convert_with(ConvertSeparators::new(SeparatorStyle::Space));
+            self::assert_eq!(
+                space.collect::<Vec<super::ParserEvent>>()[1],
+                Typst(TypstEvent::FunctionCall(
+                    None,
+                    "v".into(),
+                    vec!["2em".into()]
+                ))
+            );
+        }
+    }
+
+    /// mdBook docs:
+    /// * https://rust-lang.github.io/mdBook/format/markdown.html#heading-attributes
+    ///
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/foundations/label/
+    mod labels {
+        use super::*;
+
+        #[test]
+        fn headings() {
+            let md = "\
+# Getting Started!
+## Setup
+## Setup
+## Custom {#mine}
+## Mine
+## Odd {#a/b}
+## Other {#setup}
+";
+            let i = MdbookIter(book("guide/intro.md", md).into_iter())
+                .convert_with(ConvertLabels::new());
+            let labels = i
+                .filter_map(|e| match e {
+                    Typst(TypstEvent::Label(x)) => Some(x.into_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            self::assert_eq!(
+                labels,
+                vec![
+                    "guide-intro:getting-started",
+                    "guide-intro:setup",
+                    "guide-intro:setup-1",
+                    "guide-intro:mine",
+                    "guide-intro:mine-1",
+                    "guide-intro:ab",
+                    "guide-intro:setup-2",
+                ]
+            );
+        }
+
+        #[test]
+        fn heading_text() {
+            let md = "\
+# The [`Parser`](parser.md) *type*
+## <span class=\"x\">Inline</span> HTML
+## Logo ![alt text](logo.png) here
+";
+            let i = MdbookIter(book("intro.md", md).into_iter()).convert_with(ConvertLabels::new());
+            let labels = i
+                .filter_map(|e| match e {
+                    Typst(TypstEvent::Label(x)) => Some(x.into_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            self::assert_eq!(
+                labels,
+                vec![
+                    "intro:the-parser-type",
+                    "intro:inline-html",
+                    "intro:logo--here"
+                ]
+            );
+        }
+
+        #[test]
+        fn label_comes_before_heading_end() {
+            let i =
+                MdbookIter(book("intro.md", "# Hi").into_iter()).convert_with(ConvertLabels::new());
+            let heading = MdTag::Heading(markdown::HeadingLevel::H1, None, vec![]);
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Mdbook(MdbookEvent::Start(chapter("intro.md"))),
+                    Mdbook(MdbookEvent::MarkdownContentEvent(MdEvent::Start(
+                        heading.clone()
+                    ))),
+                    Mdbook(MdbookEvent::MarkdownContentEvent(MdEvent::Text(
+                        "Hi".into()
+                    ))),
+                    Typst(TypstEvent::Label("intro:hi".into())),
+                    Mdbook(MdbookEvent::MarkdownContentEvent(MdEvent::End(heading))),
+                    Mdbook(MdbookEvent::End(chapter("intro.md"))),
+                ]
+            );
+        }
+
+        #[test]
+        fn links() {
+            let md = "\
+[a](#setup) [b](other.md) [c](../top.md#x) [d](https://example.com/a.md) [e](image.png) \
+[f](#a/b) [g](missing.md) [h](#nope) [i](../top.md#nope) [j](../top.md#nope)
+
+## Setup
+## Odd {#a/b}
+";
+            let mut events = book("guide/intro.md", md);
+            events.extend(book("guide/other.md", ""));
+            events.extend(book("top.md", "# X"));
+            let i = MdbookIter(events.into_iter()).convert_with(ConvertLabels::new());
+            let links = i
+                .filter_map(|e| match e {
+                    Typst(TypstEvent::Start(TypstTag::Link(typst::LinkType::Label, x))) => {
+                        Some(x.into_string())
+                    }
+                    Mdbook(MdbookEvent::MarkdownContentEvent(MdEvent::Start(MdTag::Link(
+                        _,
+                        x,
+                        _,
+                    )))) => Some(format!("unchanged: {x}")),
+                    Typst(TypstEvent::Raw(x)) => Some(format!("defined: {x}")),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            self::assert_eq!(
+                links,
+                vec![
+                    "guide-intro:setup",
+                    "guide-other",
+                    "top:x",
+                    "unchanged: https://example.com/a.md",
+                    "unchanged: image.png",
+                    "guide-intro:ab",
+                    "guide-missing",
+                    "guide-intro:nope",
+                    "top:nope",
+                    "top:nope",
+                    "defined: #metadata(none) <guide-missing>\n",
+                    "defined: #metadata(none) <guide-intro:nope>\n",
+                    "defined: #metadata(none) <top:nope>\n",
+                ]
+            );
+        }
+
+        #[test]
+        fn links_are_not_held_back() {
+            let mut events = book("intro.md", "[a](later.md#end)");
+            events.extend(book("later.md", "# End"));
+            let mut i = MdbookIter(events.into_iter()).convert_with(ConvertLabels::new());
+
+            self::assert_eq!(
+                i.by_ref().take(5).collect::<Vec<super::ParserEvent>>()[2..],
+                [
+                    Typst(TypstEvent::Start(TypstTag::Link(
+                        typst::LinkType::Label,
+                        "later:end".into()
+                    ))),
+                    Mdbook(MdbookEvent::MarkdownContentEvent(MdEvent::Text("a".into()))),
+                    Typst(TypstEvent::End(TypstTag::Link(
+                        typst::LinkType::Label,
+                        "later:end".into()
+                    ))),
+                ]
+            );
+            assert!(!i.any(|e| matches!(e, Typst(TypstEvent::Raw(_)))));
+        }
+
+        #[test]
+        fn chapter_and_heading_labels_do_not_collide() {
+            let mut events = book("guide/setup.md", "## Install");
+            events.extend(book("guide/setup-install.md", ""));
+            let i = MdbookIter(events.into_iter()).convert_with(ConvertLabels::new());
+            let labels = i
+                .filter_map(|e| match e {
+                    Typst(TypstEvent::Label(x)) => Some(x.into_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            self::assert_eq!(labels, vec!["guide-setup:install"]);
+            assert_ne!(
+                labels[0],
+                chapter_label(Path::new("guide/setup-install.md"))
+            );
+        }
+
+        #[test]
+        fn links_without_chapter_labels() {
+            let mut events = book(
+                "intro.md",
+                "[a](other.md) [b](#hi)
+# Hi",
+            );
+            events.extend(book("other.md", ""));
+            let i = MdbookIter(events.into_iter())
+                .convert_with(ConvertLabels::with_chapter_labels(false));
+            let links = i
+                .filter_map(|e| match e {
+                    Typst(TypstEvent::Start(TypstTag::Link(_, x))) => Some(x.into_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            self::assert_eq!(links, vec!["intro:hi"]);
+        }
+
+        #[test]
+        fn chapter_heading() {
+            let i = MdbookIter(book("guide/intro.md", "").into_iter()).convert_with(
+                ConvertChapter::new(Some(ChapterNumbering::Text), DraftPolicy::default()),
+            );
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>()[..3],
+                [
+                    Typst(TypstEvent::Start(TypstTag::Heading(
+                        NonZeroU8::new(1).unwrap(),
+                        typst::TableOfContents::Include,
+                        typst::Bookmarks::Include,
+                    ))),
+                    Typst(TypstEvent::Text("Chapter".into())),
+                    Typst(TypstEvent::Label("guide-intro".into())),
+                ]
+            );
+        }
+    }
+}
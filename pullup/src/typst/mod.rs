@@ -1,18 +1,19 @@
 //! Support for [Typist](https://typst.app/docs).
 
 pub use pulldown_typst::{
-    Bookmarks, CodeBlockDisplay, Event, LinkType, NumberingPattern, QuoteQuotes, QuoteType,
-    ShowType, TableCellAlignment, TableOfContents, Tag,
+    Bookmarks, CodeBlockDisplay, Event, HeadingSettings, Image, LinkType, MathDisplay,
+    NumberingPattern, PageSettings, ParSettings, Parser, QuoteQuotes, QuoteType, Raw, ShowType,
+    TableCellAlignment, TableCellSpan, TableOfContents, TableSettings, Tag, TextSettings,
 };
 
-use crate::ParserEvent;
+use crate::{Error, ParserEvent};
 
 pub mod to;
 
 /// Assert that an iterator only contains Typst events. Panics if another type of event
 /// is encountered.
 ///
-/// For a non-panic version, see [`TypstFilter`].
+/// For non-panic versions, see [`TryAssertTypst`] and [`TypstFilter`].
 pub struct AssertTypst<T>(pub T);
 impl<'a, T> Iterator for AssertTypst<T>
 where
@@ -32,6 +33,50 @@ where
     }
 }
 
+/// Check that an iterator only contains Typst events. Yields an [`Error`] for each
+/// event of another type instead of panicking.
+///
+/// For a panicking version, see [`AssertTypst`].
+pub struct TryAssertTypst<T> {
+    iter: T,
+    position: usize,
+}
+
+impl<T> TryAssertTypst<T> {
+    pub fn new(iter: T) -> Self {
+        Self { iter, position: 0 }
+    }
+}
+
+impl<'a, T> Iterator for TryAssertTypst<T>
+where
+    T: Iterator<Item = ParserEvent<'a>>,
+{
+    type Item = Result<self::Event<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.iter.next()?;
+        self.position += 1;
+        match event {
+            ParserEvent::Typst(x) => Some(Ok(x)),
+            #[cfg(feature = "markdown")]
+            ParserEvent::Markdown(x) => Some(Err(Error::UnexpectedEvent {
+                position: self.position - 1,
+                expected: "typst",
+                found: "markdown",
+                event: format!("{x:?}"),
+            })),
+            #[cfg(feature = "mdbook")]
+            ParserEvent::Mdbook(x) => Some(Err(Error::UnexpectedEvent {
+                position: self.position - 1,
+                expected: "typst",
+                found: "mdbook",
+                event: format!("{x:?}"),
+            })),
+        }
+    }
+}
+
 /// An iterator that only contains Typst events. Other types of events will be filtered
 /// out.
 ///
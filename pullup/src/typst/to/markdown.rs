@@ -0,0 +1,1601 @@
+//! Convert Typst to Markdown.
+//!
+//! Markdown can only express a subset of Typst. Content with a Markdown equivalent is
+//! converted by the converters in this module. Everything else is lossy:
+//!
+//! * Styling and scripting ([`Set`](typst::Event::Set),
+//!   [`DocumentSet`](typst::Event::DocumentSet), [`Show`](typst::Tag::Show),
+//!   [`Let`](typst::Event::Let)), function calls, page breaks, labels, and raw Typst
+//!   markup have no meaning in Markdown. [`ConvertTypstOnly`] drops them or keeps them
+//!   as HTML comments, depending on the [`TypstOnly`] policy.
+//! * Inline quotes become text wrapped in quotation marks and block quote
+//!   attributions become a trailing paragraph.
+//! * Inline raw blocks become inline code and their language is dropped.
+//! * Lines become thematic breaks regardless of their geometry.
+//! * Heading levels deeper than six are clamped to six.
+//! * Table cell spans, column widths, strokes, and fills are dropped.
+//! * Footnotes become definitions after the block they appear in.
+//! * Figures become images in their own paragraph, with the caption as the title and
+//!   only its text kept.
+//! * Image widths and heights and figure labels are written as `{#label width=50%}`
+//!   attributes after the image, which the Markdown to Typst image converter reads
+//!   back.
+//! * Equations are translated to LaTeX between dollar signs, see [`math`].
+use crate::convert::{Converter, Input, Output};
+use crate::converter;
+use crate::markdown;
+use crate::typst;
+use crate::ParserEvent;
+
+pub mod math;
+
+converter!(
+    /// Convert Typst paragraphs to Markdown paragraphs.
+    ConvertParagraphs,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Paragraph)) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Paragraph)))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Paragraph)) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Paragraph)))
+            },
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Typst text to Markdown text.
+    ConvertText,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Typst(typst::Event::Text(t)) => {
+                Some(ParserEvent::Markdown(markdown::Event::Text(t)))
+            },
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Typst line breaks to Markdown hard breaks. Paragraph breaks are dropped
+    /// as paragraphs are already delimited by their tags.
+    ConvertBreaks,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Typst(typst::Event::Linebreak) => {
+                Some(ParserEvent::Markdown(markdown::Event::HardBreak))
+            },
+            ParserEvent::Typst(typst::Event::Parbreak) => None,
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Typst lines to Markdown thematic breaks.
+    ConvertLines,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Typst(typst::Event::Line(..)) => {
+                Some(ParserEvent::Markdown(markdown::Event::Rule))
+            },
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Typst strong tags to Markdown **strong** tags.
+    ConvertStrong,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Strong)) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Strong)))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Strong)) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Strong)))
+            },
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Typst emphasis tags to Markdown _emphasis_ tags.
+    ConvertEmphasis,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Emphasis)) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Emphasis)))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Emphasis)) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Emphasis)))
+            },
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Typst strikethrough tags to Markdown ~~strikethrough~~ tags.
+    ConvertStrikethrough,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Strikethrough)) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Strikethrough)))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Strikethrough)) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Strikethrough)))
+            },
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Typst headings to Markdown headings.
+    ConvertHeadings,
+    |event: ParserEvent<'a>| {
+        fn level(level: core::num::NonZeroU8) -> markdown::HeadingLevel {
+            markdown::HeadingLevel::try_from(usize::from(level.get()).min(6))
+                .expect("valid heading level")
+        }
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Heading(n, _, _))) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Heading(level(n), None, vec![]))))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Heading(n, _, _))) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Heading(level(n), None, vec![]))))
+            },
+            x => Some(x),
+        }
+   }
+);
+
+converter!(
+    /// Convert Typst lists to Markdown lists.
+    ConvertLists,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::BulletList(_, _))) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::List(None))))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::BulletList(_, _))) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::List(None))))
+            },
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::NumberedList(start, _, _))) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::List(Some(start)))))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::NumberedList(start, _, _))) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::List(Some(start)))))
+            },
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Item)) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Item)))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Item)) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Item)))
+            },
+            x => Some(x),
+        }
+   }
+);
+
+/// Convert Typst task list markers to Markdown task list markers.
+///
+/// The Typst writer writes task list markers as ballot boxes, so an item starting with
+/// `☐` or `☒` gets a marker too.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConvertTaskLists;
+
+impl ConvertTaskLists {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<'a> Converter<'a> for ConvertTaskLists {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        fn ballot_box(text: &str) -> Option<(bool, &str)> {
+            text.strip_prefix("\u{2610} ")
+                .map(|rest| (false, rest))
+                .or_else(|| text.strip_prefix("\u{2612} ").map(|rest| (true, rest)))
+        }
+        match event {
+            ParserEvent::Typst(typst::Event::TaskListMarker(checked)) => output.emit(
+                ParserEvent::Markdown(markdown::Event::TaskListMarker(checked)),
+            ),
+            item @ (ParserEvent::Typst(typst::Event::Start(typst::Tag::Item))
+            | ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Item))) => {
+                output.emit(item);
+                let text = input.next_if(|e| {
+                    matches!(e, ParserEvent::Typst(typst::Event::Text(t)) if ballot_box(t).is_some())
+                });
+                if let Some(ParserEvent::Typst(typst::Event::Text(text))) = text {
+                    let (checked, rest) = ballot_box(&text).expect("starts with a ballot box");
+                    output.emit(ParserEvent::Markdown(markdown::Event::TaskListMarker(
+                        checked,
+                    )));
+                    if !rest.is_empty() {
+                        output.emit(ParserEvent::Typst(typst::Event::Text(
+                            rest.to_string().into(),
+                        )));
+                    }
+                }
+            }
+            x => output.emit(x),
+        }
+    }
+}
+
+/// Convert Typst raw text to Markdown code.
+///
+/// Block raw text becomes a fenced code block. Inline raw text with a language becomes
+/// inline code, dropping the language.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertCode {
+    inline: Option<String>,
+}
+
+impl ConvertCode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<'a> Converter<'a> for ConvertCode {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        let fenced = |lang: Option<markdown::CowStr<'a>>, attributes: Vec<markdown::CowStr<'a>>| {
+            let info = match (lang, attributes.is_empty()) {
+                (Some(lang), true) => lang,
+                (lang, _) => lang
+                    .into_iter()
+                    .chain(attributes)
+                    .collect::<Vec<_>>()
+                    .join(",")
+                    .into(),
+            };
+            markdown::Tag::CodeBlock(markdown::CodeBlockKind::Fenced(info))
+        };
+        match event {
+            ParserEvent::Typst(typst::Event::RawCode(typst::Raw {
+                block: false, text, ..
+            })) => output.emit(ParserEvent::Markdown(markdown::Event::Code(text))),
+            ParserEvent::Typst(typst::Event::RawCode(typst::Raw {
+                lang,
+                block: true,
+                text,
+            })) => {
+                let tag = fenced(lang, vec![]);
+                output.extend([
+                    ParserEvent::Markdown(markdown::Event::Start(tag.clone())),
+                    ParserEvent::Markdown(markdown::Event::Text(text)),
+                    ParserEvent::Markdown(markdown::Event::End(tag)),
+                ]);
+            }
+            ParserEvent::Typst(typst::Event::Code(x)) => {
+                output.emit(ParserEvent::Markdown(markdown::Event::Code(x)))
+            }
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::CodeBlock(
+                _,
+                _,
+                typst::CodeBlockDisplay::Inline,
+            ))) => {
+                self.inline = Some(String::new());
+            }
+            ParserEvent::Typst(typst::Event::End(typst::Tag::CodeBlock(
+                _,
+                _,
+                typst::CodeBlockDisplay::Inline,
+            ))) => {
+                let code = self.inline.take().unwrap_or_default();
+                output.emit(ParserEvent::Markdown(markdown::Event::Code(code.into())))
+            }
+            ParserEvent::Typst(typst::Event::Text(t)) if self.inline.is_some() => {
+                self.inline.as_mut().expect("in inline code").push_str(&t);
+            }
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::CodeBlock(
+                lang,
+                attributes,
+                typst::CodeBlockDisplay::Block,
+            ))) => output.emit(ParserEvent::Markdown(markdown::Event::Start(fenced(
+                lang, attributes,
+            )))),
+            ParserEvent::Typst(typst::Event::End(typst::Tag::CodeBlock(
+                lang,
+                attributes,
+                typst::CodeBlockDisplay::Block,
+            ))) => output.emit(ParserEvent::Markdown(markdown::Event::End(fenced(
+                lang, attributes,
+            )))),
+            x => output.emit(x),
+        }
+    }
+}
+
+/// Convert Typst quotes to Markdown blockquotes.
+///
+/// Inline quotes have no Markdown equivalent and are wrapped in quotation marks
+/// instead. The attribution of a block quote is added as a final paragraph.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConvertQuotes;
+
+impl ConvertQuotes {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<'a> Converter<'a> for ConvertQuotes {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Quote(
+                typst::QuoteType::Block,
+                _,
+                _,
+            ))) => output.emit(ParserEvent::Markdown(markdown::Event::Start(
+                markdown::Tag::BlockQuote,
+            ))),
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Quote(
+                typst::QuoteType::Block,
+                _,
+                attribution,
+            ))) => {
+                if let Some(attribution) = attribution {
+                    output.extend([
+                        ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Paragraph)),
+                        ParserEvent::Markdown(markdown::Event::Text(
+                            format!("— {attribution}").into(),
+                        )),
+                        ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Paragraph)),
+                    ]);
+                }
+                output.emit(ParserEvent::Markdown(markdown::Event::End(
+                    markdown::Tag::BlockQuote,
+                )))
+            }
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Quote(
+                typst::QuoteType::Inline,
+                _,
+                _,
+            ))) => output.emit(ParserEvent::Markdown(markdown::Event::Text("“".into()))),
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Quote(
+                typst::QuoteType::Inline,
+                _,
+                _,
+            ))) => output.emit(ParserEvent::Markdown(markdown::Event::Text("”".into()))),
+            x => output.emit(x),
+        }
+    }
+}
+
+converter!(
+    /// Convert Typst links to Markdown links.
+    ConvertLinks,
+    |event: ParserEvent<'a>| {
+        fn link(ty: typst::LinkType, url: markdown::CowStr<'_>) -> markdown::Tag<'_> {
+            match ty {
+                typst::LinkType::Autolink => markdown::Tag::Link(markdown::LinkType::Autolink, url, "".into()),
+                typst::LinkType::Label => markdown::Tag::Link(markdown::LinkType::Inline, format!("#{url}").into(), "".into()),
+                _ => match url.strip_prefix("mailto:") {
+                    Some(email) => markdown::Tag::Link(markdown::LinkType::Email, email.to_string().into(), "".into()),
+                    None => markdown::Tag::Link(markdown::LinkType::Inline, url, "".into()),
+                },
+            }
+        }
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Link(ty, url))) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(link(ty, url))))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Link(ty, url))) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(link(ty, url))))
+            },
+            x => Some(x),
+    }
+});
+
+/// Convert Typst images to Markdown images, with the image's `alt` as the alt text.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConvertImages;
+
+impl ConvertImages {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<'a> Converter<'a> for ConvertImages {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        _: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Typst(typst::Event::Image(image)) => {
+                let alt = image.alt.clone();
+                image_events(image, alt, "".into(), None, output)
+            }
+            x => output.emit(x),
+        }
+    }
+}
+
+/// Emit a Markdown image, followed by its `{#label width=50% height=3cm}` attributes if
+/// it has any.
+fn image_events<'a>(
+    image: typst::Image<'a>,
+    alt: Option<markdown::CowStr<'a>>,
+    title: markdown::CowStr<'a>,
+    label: Option<markdown::CowStr<'a>>,
+    output: &mut Output<'_, 'a>,
+) {
+    let tag = markdown::Tag::Image(markdown::LinkType::Inline, image.path, title);
+    output.emit(ParserEvent::Markdown(markdown::Event::Start(tag.clone())));
+    output.extend(alt.map(|alt| ParserEvent::Markdown(markdown::Event::Text(alt))));
+    output.emit(ParserEvent::Markdown(markdown::Event::End(tag)));
+
+    let attributes = label
+        .map(|label| format!("#{label}"))
+        .into_iter()
+        .chain(image.width.map(|width| format!("width={width}")))
+        .chain(image.height.map(|height| format!("height={height}")))
+        .collect::<Vec<_>>();
+    if !attributes.is_empty() {
+        output.emit(ParserEvent::Markdown(markdown::Event::Text(
+            format!("{{{}}}", attributes.join(" ")).into(),
+        )));
+    }
+}
+
+/// The text of `events`, without any markup.
+fn plain_text(events: &[ParserEvent<'_>]) -> String {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            ParserEvent::Typst(typst::Event::Text(t) | typst::Event::Code(t))
+            | ParserEvent::Markdown(markdown::Event::Text(t) | markdown::Event::Code(t)) => {
+                Some(t.as_ref())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Collect the events up to the end of the `tag` that has just started, including
+/// nested tags of the same kind.
+fn content<'a>(
+    tag: impl Fn(&typst::Tag<'_>) -> bool,
+    input: &mut Input<'_, 'a>,
+) -> Vec<ParserEvent<'a>> {
+    let mut events = vec![];
+    let mut depth = 0;
+    for event in input {
+        match &event {
+            ParserEvent::Typst(typst::Event::Start(t)) if tag(t) => depth += 1,
+            ParserEvent::Typst(typst::Event::End(t)) if tag(t) => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        events.push(event);
+    }
+    events
+}
+
+/// Convert Typst figures to Markdown images in their own paragraph.
+///
+/// The text of the caption becomes the image title. The image's `alt` is the alt text,
+/// or the caption if there is none, in which case the title is left out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConvertFigures;
+
+impl ConvertFigures {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<'a> Converter<'a> for ConvertFigures {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Figure(image, label))) => {
+                let caption = content(|t| matches!(t, typst::Tag::Figure(..)), input);
+                let caption = plain_text(&caption);
+                let (alt, title) = match image.alt.clone() {
+                    Some(alt) if *alt != *caption => (Some(alt), caption.into()),
+                    Some(alt) => (Some(alt), "".into()),
+                    None if caption.is_empty() => (None, "".into()),
+                    None => (Some(caption.into()), "".into()),
+                };
+                output.emit(ParserEvent::Markdown(markdown::Event::Start(
+                    markdown::Tag::Paragraph,
+                )));
+                image_events(image, alt, title, label, output);
+                output.emit(ParserEvent::Markdown(markdown::Event::End(
+                    markdown::Tag::Paragraph,
+                )));
+            }
+            x => output.emit(x),
+        }
+    }
+}
+
+/// Convert Typst footnotes to Markdown footnotes.
+///
+/// A footnote becomes a reference to a footnote definition with its content, which is
+/// placed after the block containing the footnote. The definition is named after the
+/// footnote's label, so references to the label keep working, or numbered if the
+/// footnote has no label.
+///
+/// Must run before the Typst events inside footnotes are converted.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertFootnotes<'a> {
+    /// Definitions waiting for the end of the current block.
+    definitions: Vec<ParserEvent<'a>>,
+    /// How many blocks and spans the current event is in.
+    depth: usize,
+    /// The number of footnotes without a label.
+    count: usize,
+}
+
+impl<'a> ConvertFootnotes<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the definition of a footnote that has just started and return its name.
+    fn footnote(&mut self, input: &mut Input<'_, 'a>) -> markdown::CowStr<'a> {
+        let mut body = vec![];
+        while let Some(event) = input.next() {
+            match event {
+                ParserEvent::Typst(typst::Event::End(typst::Tag::Footnote)) => break,
+                // Footnotes can contain other footnotes.
+                ParserEvent::Typst(typst::Event::Start(typst::Tag::Footnote)) => {
+                    let name = self.footnote(input);
+                    body.push(ParserEvent::Markdown(markdown::Event::FootnoteReference(
+                        name,
+                    )));
+                }
+                event => body.push(event),
+            }
+        }
+        let name = match input.next_if(|e| matches!(e, ParserEvent::Typst(typst::Event::Label(_))))
+        {
+            Some(ParserEvent::Typst(typst::Event::Label(label))) => label,
+            _ => {
+                self.count += 1;
+                self.count.to_string().into()
+            }
+        };
+
+        let tag = markdown::Tag::FootnoteDefinition(name.clone());
+        let inline = !matches!(
+            body.first(),
+            Some(ParserEvent::Typst(typst::Event::Start(
+                typst::Tag::Paragraph
+            )))
+        );
+        self.definitions
+            .push(ParserEvent::Markdown(markdown::Event::Start(tag.clone())));
+        if inline {
+            self.definitions
+                .push(ParserEvent::Markdown(markdown::Event::Start(
+                    markdown::Tag::Paragraph,
+                )));
+        }
+        self.definitions.extend(body);
+        if inline {
+            self.definitions
+                .push(ParserEvent::Markdown(markdown::Event::End(
+                    markdown::Tag::Paragraph,
+                )));
+        }
+        self.definitions
+            .push(ParserEvent::Markdown(markdown::Event::End(tag)));
+        name
+    }
+}
+
+impl<'a> Converter<'a> for ConvertFootnotes<'a> {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Footnote)) => {
+                let name = self.footnote(input);
+                output.emit(ParserEvent::Markdown(markdown::Event::FootnoteReference(
+                    name,
+                )));
+                if self.depth == 0 {
+                    output.extend(self.definitions.drain(..));
+                }
+            }
+            ParserEvent::Typst(typst::Event::FootnoteReference(label)) => output.emit(
+                ParserEvent::Markdown(markdown::Event::FootnoteReference(label)),
+            ),
+            event => {
+                match event {
+                    ParserEvent::Typst(typst::Event::Start(_))
+                    | ParserEvent::Markdown(markdown::Event::Start(_)) => self.depth += 1,
+                    ParserEvent::Typst(typst::Event::End(_))
+                    | ParserEvent::Markdown(markdown::Event::End(_)) => {
+                        self.depth = self.depth.saturating_sub(1)
+                    }
+                    _ => {}
+                }
+                output.emit(event);
+                if self.depth == 0 {
+                    output.extend(self.definitions.drain(..));
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, output: &mut Output<'_, 'a>) {
+        output.extend(self.definitions.drain(..));
+    }
+}
+
+converter!(
+    /// Convert Typst equations to LaTeX math between dollar signs, as used by MathJax
+    /// and KaTeX. Block equations use `$$`.
+    ConvertMath,
+    |event: ParserEvent<'a>| {
+        match event {
+            ParserEvent::Typst(typst::Event::Math(equation, display)) => {
+                let delimiter = match display {
+                    typst::MathDisplay::Inline => "$",
+                    typst::MathDisplay::Block => "$$",
+                };
+                let latex = math::typst_to_latex(&equation);
+                Some(ParserEvent::Markdown(markdown::Event::Text(format!("{delimiter}{latex}{delimiter}").into())))
+            },
+            x => Some(x),
+    }
+});
+
+converter!(
+    /// Convert Typst tables to Markdown tables.
+    ConvertTables,
+    |event: ParserEvent<'a>| {
+        fn alignment(alignment: Vec<typst::TableCellAlignment>) -> Vec<markdown::Alignment> {
+            alignment.into_iter().map(|a| match a {
+                typst::TableCellAlignment::Left => markdown::Alignment::Left,
+                typst::TableCellAlignment::Center => markdown::Alignment::Center,
+                typst::TableCellAlignment::Right => markdown::Alignment::Right,
+                typst::TableCellAlignment::None => markdown::Alignment::None,
+            }).collect()
+        }
+        match event {
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::Table(a, _))) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::Table(alignment(a)))))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::Table(a, _))) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::Table(alignment(a)))))
+            },
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::TableHead)) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableHead)))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::TableHead)) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableHead)))
+            },
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::TableRow)) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableRow)))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::TableRow)) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableRow)))
+            },
+            ParserEvent::Typst(typst::Event::Start(typst::Tag::TableCell(_))) => {
+                Some(ParserEvent::Markdown(markdown::Event::Start(markdown::Tag::TableCell)))
+            },
+            ParserEvent::Typst(typst::Event::End(typst::Tag::TableCell(_))) => {
+                Some(ParserEvent::Markdown(markdown::Event::End(markdown::Tag::TableCell)))
+            },
+            x => Some(x),
+        }
+    }
+);
+
+/// What to do with Typst constructs that have no Markdown equivalent.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TypstOnly {
+    /// Drop them.
+    #[default]
+    Drop,
+    /// Keep their Typst markup in an HTML comment so it survives a round trip through
+    /// Markdown tooling.
+    Comment,
+}
+
+/// Handle Typst-only constructs according to a [`TypstOnly`] policy.
+///
+/// Run it after the other converters so it only sees the Typst events they left
+/// behind. Set, show, and let rules, function calls, page breaks, labels, raw Typst
+/// markup, and content whose converter did not run, like text, equations, images, or
+/// footnotes, follow the policy. Other tags are dropped and their content is kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConvertTypstOnly {
+    policy: TypstOnly,
+    /// How many blocks and spans the current event is in. Comments outside of them are
+    /// written as their own block.
+    depth: usize,
+}
+
+impl ConvertTypstOnly {
+    pub fn new(policy: TypstOnly) -> Self {
+        Self { policy, depth: 0 }
+    }
+
+    fn typst_only<'a>(&self, events: Vec<typst::Event<'a>>, output: &mut Output<'_, 'a>) {
+        match self.policy {
+            TypstOnly::Drop => {}
+            TypstOnly::Comment => {
+                let markup: String =
+                    typst::to::markup::TypstMarkup::new(events.into_iter()).collect();
+                let markup = markup.trim();
+                if markup.is_empty() {
+                    return;
+                }
+                let newline = if self.depth == 0 { "\n" } else { "" };
+                output.emit(ParserEvent::Markdown(markdown::Event::Html(
+                    format!("<!-- {} -->{newline}", comment_escape(markup)).into(),
+                )))
+            }
+        }
+    }
+}
+
+impl<'a> Converter<'a> for ConvertTypstOnly {
+    fn convert(
+        &mut self,
+        event: ParserEvent<'a>,
+        input: &mut Input<'_, 'a>,
+        output: &mut Output<'_, 'a>,
+    ) {
+        let event = match event {
+            ParserEvent::Typst(event) => event,
+            event => {
+                match event {
+                    ParserEvent::Markdown(markdown::Event::Start(_)) => self.depth += 1,
+                    ParserEvent::Markdown(markdown::Event::End(_)) => {
+                        self.depth = self.depth.saturating_sub(1)
+                    }
+                    _ => {}
+                }
+                return output.emit(event);
+            }
+        };
+        match event {
+            e @ (typst::Event::Set(..)
+            | typst::Event::DocumentSet(..)
+            | typst::Event::SetPage(_)
+            | typst::Event::SetText(_)
+            | typst::Event::SetPar(_)
+            | typst::Event::SetHeading(_)
+            | typst::Event::Let(..)
+            | typst::Event::FunctionCall(..)
+            | typst::Event::DocumentFunctionCall(..)
+            | typst::Event::PageBreak
+            | typst::Event::Outline(..)
+            | typst::Event::Label(_)
+            | typst::Event::Raw(_)
+            | typst::Event::Text(_)
+            | typst::Event::Code(_)
+            | typst::Event::RawCode(_)
+            | typst::Event::Math(..)
+            | typst::Event::FootnoteReference(_)
+            | typst::Event::Image(_)
+            | typst::Event::TaskListMarker(_)
+            | typst::Event::Line(..)
+            | typst::Event::Start(typst::Tag::Show(..))) => self.typst_only(vec![e], output),
+            // Footnotes and figures are kept or dropped as a whole, with the text of their
+            // content.
+            typst::Event::Start(tag @ (typst::Tag::Footnote | typst::Tag::Figure(..))) => {
+                let footnote = tag == typst::Tag::Footnote;
+                let body = content(
+                    |t| match t {
+                        typst::Tag::Footnote => footnote,
+                        typst::Tag::Figure(..) => !footnote,
+                        _ => false,
+                    },
+                    input,
+                );
+                let mut events = vec![
+                    typst::Event::Start(tag.clone()),
+                    typst::Event::Text(plain_text(&body).into()),
+                    typst::Event::End(tag),
+                ];
+                if let Some(ParserEvent::Typst(label)) =
+                    input.next_if(|e| matches!(e, ParserEvent::Typst(typst::Event::Label(_))))
+                {
+                    events.push(label);
+                }
+                self.typst_only(events, output)
+            }
+            // Breaks are implied by the surrounding Markdown blocks.
+            typst::Event::Linebreak | typst::Event::Parbreak => {}
+            // Show rules do not contain anything, the start carries the rule.
+            typst::Event::End(typst::Tag::Show(..)) => {}
+            // Markdown has no equivalent, or its converter did not run. Keep the content.
+            typst::Event::Start(
+                typst::Tag::Paragraph
+                | typst::Tag::Heading(..)
+                | typst::Tag::CodeBlock(..)
+                | typst::Tag::BulletList(..)
+                | typst::Tag::NumberedList(..)
+                | typst::Tag::Item
+                | typst::Tag::Quote(..)
+                | typst::Tag::Emphasis
+                | typst::Tag::Strong
+                | typst::Tag::Strikethrough
+                | typst::Tag::Link(..)
+                | typst::Tag::Align(_)
+                | typst::Tag::Hide
+                | typst::Tag::Table(..)
+                | typst::Tag::TableHead
+                | typst::Tag::TableRow
+                | typst::Tag::TableCell(_),
+            ) => self.depth += 1,
+            typst::Event::End(
+                typst::Tag::Paragraph
+                | typst::Tag::Heading(..)
+                | typst::Tag::CodeBlock(..)
+                | typst::Tag::BulletList(..)
+                | typst::Tag::NumberedList(..)
+                | typst::Tag::Item
+                | typst::Tag::Quote(..)
+                | typst::Tag::Emphasis
+                | typst::Tag::Strong
+                | typst::Tag::Strikethrough
+                | typst::Tag::Link(..)
+                | typst::Tag::Align(_)
+                | typst::Tag::Hide
+                | typst::Tag::Table(..)
+                | typst::Tag::TableHead
+                | typst::Tag::TableRow
+                | typst::Tag::TableCell(_),
+            ) => self.depth = self.depth.saturating_sub(1),
+            // Their start reads up to and including the end.
+            typst::Event::End(typst::Tag::Footnote | typst::Tag::Figure(..)) => {}
+        }
+    }
+}
+
+/// Escape `s` for an HTML comment, which cannot contain `--`, by putting a backslash
+/// between dashes.
+fn comment_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '-' && out.ends_with('-') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::CowStr;
+    use crate::typst::{Parser, TypstIter};
+    use crate::IteratorExt;
+    use similar_asserts::assert_eq;
+
+    // Set up type names so they are clearer and more succint.
+    use markdown::Event as MdEvent;
+    use markdown::HeadingLevel;
+    use markdown::Tag as MdTag;
+    use typst::Event as TypstEvent;
+    use typst::Tag as TypstTag;
+    use ParserEvent::*;
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/heading/
+    mod headings {
+        use super::*;
+
+        #[test]
+        fn convert_headings() {
+            let typ = "\
+= Greetings
+
+======= Deep
+";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertHeadings::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Heading(
+                        HeadingLevel::H1,
+                        None,
+                        vec![]
+                    ))),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("Greetings"))),
+                    Markdown(MdEvent::End(MdTag::Heading(HeadingLevel::H1, None, vec![]))),
+                    Markdown(MdEvent::Start(MdTag::Heading(
+                        HeadingLevel::H6,
+                        None,
+                        vec![]
+                    ))),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("Deep"))),
+                    Markdown(MdEvent::End(MdTag::Heading(HeadingLevel::H6, None, vec![]))),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/par/
+    mod paragraphs {
+        use super::*;
+
+        #[test]
+        fn convert_paragraphs_and_text() {
+            let typ = "\
+foo
+
+#par()[bar]
+";
+            let i = TypstIter(Parser::new(typ))
+                .convert_with(ConvertParagraphs::new())
+                .convert_with(ConvertText::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("foo"))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("bar"))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                ]
+            );
+        }
+
+        #[test]
+        fn linebreak() {
+            let i = TypstIter(Parser::new("a \\\nb")).convert_with(ConvertBreaks::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("a "))),
+                    Markdown(MdEvent::HardBreak),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("\nb"))),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/strong/
+    /// * https://typst.app/docs/reference/model/emph/
+    mod emphasis {
+        use super::*;
+
+        #[test]
+        fn strong_and_emphasis() {
+            let i = TypstIter(Parser::new("*a* _b_"))
+                .convert_with(ConvertStrong::new())
+                .convert_with(ConvertEmphasis::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::Strong)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("a"))),
+                    Markdown(MdEvent::End(MdTag::Strong)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed(" "))),
+                    Markdown(MdEvent::Start(MdTag::Emphasis)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("b"))),
+                    Markdown(MdEvent::End(MdTag::Emphasis)),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/list/
+    /// * https://typst.app/docs/reference/model/enum/
+    mod lists {
+        use super::*;
+
+        #[test]
+        fn bullet_and_numbered() {
+            let typ = "\
+- a
+
+3. b
+";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertLists::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::List(None))),
+                    Markdown(MdEvent::Start(MdTag::Item)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("a"))),
+                    Markdown(MdEvent::End(MdTag::Item)),
+                    Markdown(MdEvent::End(MdTag::List(None))),
+                    Markdown(MdEvent::Start(MdTag::List(Some(3)))),
+                    Markdown(MdEvent::Start(MdTag::Item)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("b"))),
+                    Markdown(MdEvent::End(MdTag::Item)),
+                    Markdown(MdEvent::End(MdTag::List(Some(3)))),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/text/raw/
+    mod code {
+        use super::*;
+
+        #[test]
+        fn block() {
+            let typ = "\
+```rust
+fn main() {}
+```
+";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertCode::new());
+            let fenced = MdTag::CodeBlock(markdown::CodeBlockKind::Fenced("rust".into()));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(fenced.clone())),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("fn main() {}\n"))),
+                    Markdown(MdEvent::End(fenced)),
+                ]
+            );
+        }
+
+        #[test]
+        fn block_with_attributes() {
+            let tag = TypstTag::CodeBlock(
+                Some("rust".into()),
+                vec!["ignore".into()],
+                typst::CodeBlockDisplay::Block,
+            );
+            let i = vec![
+                Typst(TypstEvent::Start(tag.clone())),
+                Typst(TypstEvent::End(tag)),
+            ]
+            .into_iter()
+            .convert_with(ConvertCode::new());
+            let fenced = MdTag::CodeBlock(markdown::CodeBlockKind::Fenced("rust,ignore".into()));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(fenced.clone())),
+                    Markdown(MdEvent::End(fenced)),
+                ]
+            );
+        }
+
+        #[test]
+        fn typed() {
+            let raw = |block| {
+                Typst(TypstEvent::RawCode(typst::Raw {
+                    lang: Some("rust".into()),
+                    block,
+                    text: "x".into(),
+                }))
+            };
+            let i = vec![raw(false), raw(true)]
+                .into_iter()
+                .convert_with(ConvertCode::new());
+            let fenced = MdTag::CodeBlock(markdown::CodeBlockKind::Fenced("rust".into()));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Code("x".into())),
+                    Markdown(MdEvent::Start(fenced.clone())),
+                    Markdown(MdEvent::Text("x".into())),
+                    Markdown(MdEvent::End(fenced)),
+                ]
+            );
+        }
+
+        #[test]
+        fn inline() {
+            let i = TypstIter(Parser::new("`a` and ```rust b```")).convert_with(ConvertCode::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Markdown(MdEvent::Code(CowStr::Borrowed("a"))),
+                    Typst(TypstEvent::Text(CowStr::Borrowed(" and "))),
+                    Markdown(MdEvent::Code(CowStr::Borrowed(" b"))),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/quote/
+    mod quotes {
+        use super::*;
+
+        #[test]
+        fn block_with_attribution() {
+            let typ = "#quote(block: true, attribution: [someone])[wise words]";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertQuotes::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::BlockQuote)),
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("wise words"))),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text("— someone".into())),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::End(MdTag::BlockQuote)),
+                ]
+            );
+        }
+
+        #[test]
+        fn inline() {
+            let i = TypstIter(Parser::new("says #quote[hi]")).convert_with(ConvertQuotes::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("says "))),
+                    Markdown(MdEvent::Text("“".into())),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("hi"))),
+                    Markdown(MdEvent::Text("”".into())),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/link/
+    mod links {
+        use super::*;
+
+        #[test]
+        fn content_and_email() {
+            let typ = "#link(\"https://example.com\")[beans] #link(\"mailto:a@b.c\")[mail]";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertLinks::new());
+            let inline = MdTag::Link(
+                markdown::LinkType::Inline,
+                "https://example.com".into(),
+                "".into(),
+            );
+            let email = MdTag::Link(markdown::LinkType::Email, "a@b.c".into(), "".into());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Markdown(MdEvent::Start(inline.clone())),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("beans"))),
+                    Markdown(MdEvent::End(inline)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed(" "))),
+                    Markdown(MdEvent::Start(email.clone())),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("mail"))),
+                    Markdown(MdEvent::End(email)),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+
+        #[test]
+        fn label() {
+            let typ = "#link(<intro>)[Intro]";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertLinks::new());
+            let inline = MdTag::Link(markdown::LinkType::Inline, "#intro".into(), "".into());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Markdown(MdEvent::Start(inline.clone())),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("Intro"))),
+                    Markdown(MdEvent::End(inline)),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    mod images {
+        use super::*;
+
+        #[test]
+        fn image() {
+            let typ = "and #image(\"a.png\", width: 50%) #box(image(\"b.png\", alt: \"B\"))";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertImages::new());
+            let image = |path| MdTag::Image(markdown::LinkType::Inline, path, "".into());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("and "))),
+                    Markdown(MdEvent::Start(image("a.png".into()))),
+                    Markdown(MdEvent::End(image("a.png".into()))),
+                    Markdown(MdEvent::Text("{width=50%}".into())),
+                    Typst(TypstEvent::Text(CowStr::Borrowed(" "))),
+                    Markdown(MdEvent::Start(image("b.png".into()))),
+                    Markdown(MdEvent::Text("B".into())),
+                    Markdown(MdEvent::End(image("b.png".into()))),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/text/strike/
+    mod strikethrough {
+        use super::*;
+
+        #[test]
+        fn strike() {
+            let i = TypstIter(Parser::new("#strike[old] new"))
+                .convert_with(ConvertStrikethrough::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::Strikethrough)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("old"))),
+                    Markdown(MdEvent::End(MdTag::Strikethrough)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed(" new"))),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    mod task_lists {
+        use super::*;
+
+        #[test]
+        fn ballot_boxes() {
+            let typ = "- \u{2612} done\n- \u{2610} todo\n- plain\n";
+            let i = TypstIter(Parser::new(typ))
+                .convert_with(ConvertLists::new())
+                .convert_with(ConvertTaskLists::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::List(None))),
+                    Markdown(MdEvent::Start(MdTag::Item)),
+                    Markdown(MdEvent::TaskListMarker(true)),
+                    Typst(TypstEvent::Text("done".into())),
+                    Markdown(MdEvent::End(MdTag::Item)),
+                    Markdown(MdEvent::Start(MdTag::Item)),
+                    Markdown(MdEvent::TaskListMarker(false)),
+                    Typst(TypstEvent::Text("todo".into())),
+                    Markdown(MdEvent::End(MdTag::Item)),
+                    Markdown(MdEvent::Start(MdTag::Item)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("plain"))),
+                    Markdown(MdEvent::End(MdTag::Item)),
+                    Markdown(MdEvent::End(MdTag::List(None))),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/footnote/
+    mod footnotes {
+        use super::*;
+
+        #[test]
+        fn definition_after_block() {
+            let typ = "#par()[Text#footnote[The note.] <footnote-1> and#footnote(<footnote-1>)]\n\nMore#footnote[Other.]";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertFootnotes::new());
+            let definition = |name: &'static str, text: &'static str| {
+                vec![
+                    Markdown(MdEvent::Start(MdTag::FootnoteDefinition(name.into()))),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed(text))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::End(MdTag::FootnoteDefinition(name.into()))),
+                ]
+            };
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                [
+                    vec![
+                        Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                        Typst(TypstEvent::Text(CowStr::Borrowed("Text"))),
+                        Markdown(MdEvent::FootnoteReference("footnote-1".into())),
+                        Typst(TypstEvent::Text(CowStr::Borrowed(" and"))),
+                        Markdown(MdEvent::FootnoteReference("footnote-1".into())),
+                        Typst(TypstEvent::End(TypstTag::Paragraph)),
+                    ],
+                    definition("footnote-1", "The note."),
+                    vec![
+                        Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                        Typst(TypstEvent::Text(CowStr::Borrowed("More"))),
+                        Markdown(MdEvent::FootnoteReference("1".into())),
+                        Typst(TypstEvent::End(TypstTag::Paragraph)),
+                    ],
+                    definition("1", "Other."),
+                ]
+                .concat()
+            );
+        }
+
+        #[test]
+        fn nested() {
+            let typ = "A#footnote[B#footnote[C]]";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertFootnotes::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("A"))),
+                    Markdown(MdEvent::FootnoteReference("2".into())),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::FootnoteDefinition("1".into()))),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("C"))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::End(MdTag::FootnoteDefinition("1".into()))),
+                    Markdown(MdEvent::Start(MdTag::FootnoteDefinition("2".into()))),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("B"))),
+                    Markdown(MdEvent::FootnoteReference("1".into())),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::End(MdTag::FootnoteDefinition("2".into()))),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/model/figure/
+    mod figures {
+        use super::*;
+
+        #[test]
+        fn caption_and_label() {
+            let typ = "#figure(image(\"cat.png\", width: 50%, alt: \"A cat\"), caption: [My _cat_]) <fig-cat>\n\n#figure(image(\"dog.png\"), caption: [Dog])\n";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertFigures::new());
+            let image = |path, title| MdTag::Image(markdown::LinkType::Inline, path, title);
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Start(image("cat.png".into(), "My cat".into()))),
+                    Markdown(MdEvent::Text("A cat".into())),
+                    Markdown(MdEvent::End(image("cat.png".into(), "My cat".into()))),
+                    Markdown(MdEvent::Text("{#fig-cat width=50%}".into())),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Start(image("dog.png".into(), "".into()))),
+                    Markdown(MdEvent::Text("Dog".into())),
+                    Markdown(MdEvent::End(image("dog.png".into(), "".into()))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    /// Typst docs:
+    /// * https://typst.app/docs/reference/math/
+    mod math {
+        use super::*;
+
+        #[test]
+        fn inline_and_block() {
+            let typ = "Area $pi r^2$ and\n\n$ sum_(i=1)^n i $\n";
+            let i = TypstIter(Parser::new(typ)).convert_with(ConvertMath::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Text(CowStr::Borrowed("Area "))),
+                    Markdown(MdEvent::Text("$\\pi r^2$".into())),
+                    Typst(TypstEvent::Text(CowStr::Borrowed(" and"))),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                    Typst(TypstEvent::Start(TypstTag::Paragraph)),
+                    Markdown(MdEvent::Text("$$\\sum_{i=1}^n i$$".into())),
+                    Typst(TypstEvent::End(TypstTag::Paragraph)),
+                ]
+            );
+        }
+    }
+
+    mod tables {
+        use super::*;
+
+        #[test]
+        fn simple_table() {
+            let alignment = vec![typst::TableCellAlignment::Left];
+            let input = vec![
+                TypstEvent::Start(TypstTag::Table(
+                    alignment.clone(),
+                    typst::TableSettings::default(),
+                )),
+                TypstEvent::Start(TypstTag::TableHead),
+                TypstEvent::Start(TypstTag::TableCell(typst::TableCellSpan::default())),
+                TypstEvent::Text("H".into()),
+                TypstEvent::End(TypstTag::TableCell(typst::TableCellSpan::default())),
+                TypstEvent::End(TypstTag::TableHead),
+                TypstEvent::Start(TypstTag::TableRow),
+                TypstEvent::Start(TypstTag::TableCell(typst::TableCellSpan::default())),
+                TypstEvent::Text("C".into()),
+                TypstEvent::End(TypstTag::TableCell(typst::TableCellSpan::default())),
+                TypstEvent::End(TypstTag::TableRow),
+                TypstEvent::End(TypstTag::Table(alignment, typst::TableSettings::default())),
+            ];
+            let i = TypstIter(input.into_iter()).convert_with(ConvertTables::new());
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Table(vec![
+                        markdown::Alignment::Left
+                    ]))),
+                    Markdown(MdEvent::Start(MdTag::TableHead)),
+                    Markdown(MdEvent::Start(MdTag::TableCell)),
+                    Typst(TypstEvent::Text("H".into())),
+                    Markdown(MdEvent::End(MdTag::TableCell)),
+                    Markdown(MdEvent::End(MdTag::TableHead)),
+                    Markdown(MdEvent::Start(MdTag::TableRow)),
+                    Markdown(MdEvent::Start(MdTag::TableCell)),
+                    Typst(TypstEvent::Text("C".into())),
+                    Markdown(MdEvent::End(MdTag::TableCell)),
+                    Markdown(MdEvent::End(MdTag::TableRow)),
+                    Markdown(MdEvent::End(MdTag::Table(vec![markdown::Alignment::Left]))),
+                ]
+            );
+        }
+    }
+
+    mod typst_only {
+        use super::*;
+
+        const TYP: &str = "\
+#set text(size: 11pt)
+#show link: underline
+#let x = 1
+= Title
+";
+
+        #[test]
+        fn drop() {
+            let i = TypstIter(Parser::new(TYP))
+                .convert_with(ConvertHeadings::new())
+                .convert_with(ConvertText::new())
+                .convert_with(ConvertTypstOnly::new(TypstOnly::Drop));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Heading(
+                        HeadingLevel::H1,
+                        None,
+                        vec![]
+                    ))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Title"))),
+                    Markdown(MdEvent::End(MdTag::Heading(HeadingLevel::H1, None, vec![]))),
+                ]
+            );
+        }
+
+        #[test]
+        fn comment() {
+            let i =
+                TypstIter(Parser::new(TYP)).convert_with(ConvertTypstOnly::new(TypstOnly::Comment));
+
+            self::assert_eq!(
+                i.take(3).collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Html("<!-- #set text(size: 11pt) -->\n".into())),
+                    Markdown(MdEvent::Html("<!-- #show link:underline -->\n".into())),
+                    Markdown(MdEvent::Html("<!-- #let x = 1 -->\n".into())),
+                ]
+            );
+        }
+
+        #[test]
+        fn comment_escapes_dashes() {
+            let typ = "#let x = \"a---b-->\"\n";
+            let i =
+                TypstIter(Parser::new(typ)).convert_with(ConvertTypstOnly::new(TypstOnly::Comment));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![Markdown(MdEvent::Html(
+                    "<!-- #let x = \"a-\\-\\-b-\\->\" -->\n".into()
+                ))]
+            );
+        }
+
+        #[test]
+        fn unconverted_content() {
+            let typ = "A#footnote[B] <note> #strike[C] $x$\n";
+            let i = TypstIter(Parser::new(typ))
+                .convert_with(ConvertParagraphs::new())
+                .convert_with(ConvertText::new())
+                .convert_with(ConvertTypstOnly::new(TypstOnly::Comment));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("A"))),
+                    Markdown(MdEvent::Html("<!-- #footnote[B] <note> -->".into())),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(" "))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("C"))),
+                    Markdown(MdEvent::Text(CowStr::Borrowed(" "))),
+                    Markdown(MdEvent::Html("<!-- $x$ -->".into())),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                ]
+            );
+        }
+
+        #[test]
+        fn title_page() {
+            let typ = "#align(center)[#par()[Book]\n]\n#outline()\n";
+            let i = TypstIter(Parser::new(typ))
+                .convert_with(ConvertParagraphs::new())
+                .convert_with(ConvertText::new())
+                .convert_with(ConvertTypstOnly::new(TypstOnly::Comment));
+
+            self::assert_eq!(
+                i.collect::<Vec<super::ParserEvent>>(),
+                vec![
+                    Markdown(MdEvent::Start(MdTag::Paragraph)),
+                    Markdown(MdEvent::Text(CowStr::Borrowed("Book"))),
+                    Markdown(MdEvent::End(MdTag::Paragraph)),
+                    Markdown(MdEvent::Html("<!-- #outline() -->\n".into())),
+                ]
+            );
+        }
+    }
+}
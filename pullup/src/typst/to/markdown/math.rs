@@ -0,0 +1,749 @@
+//! Translate Typst math to LaTeX math, as understood by MathJax and KaTeX.
+//!
+//! This is the reverse of
+//! [`latex_to_typst`](crate::markdown::to::typst::math::latex_to_typst) and understands
+//! the same subset: fractions, roots, sub- and superscripts, Greek letters, big
+//! operators, relations, arrows, fonts, accents, matrices, `cases`, and alignment.
+//! Unknown identifiers are kept as upright text.
+//!
+//! See <https://typst.app/docs/reference/math/>.
+
+use crate::markdown::to::typst::math::OPERATORS;
+
+/// Translate a Typst equation, without its delimiters, to LaTeX math.
+pub fn typst_to_latex(typst: &str) -> String {
+    let mut translator = Translator {
+        src: typst,
+        pos: 0,
+        aligned: false,
+    };
+    let latex = translator.sequence(&[]);
+    let latex = latex.trim();
+    if translator.aligned {
+        format!("\\begin{{aligned}} {latex} \\end{{aligned}}")
+    } else {
+        latex.to_string()
+    }
+}
+
+/// A translated piece of math.
+#[derive(Debug, Default)]
+struct Atom {
+    latex: String,
+    /// The content of a parenthesized group. Typst drops the parentheses when the group
+    /// is a script, an argument, or part of a fraction.
+    group: Option<String>,
+}
+
+impl Atom {
+    fn new(latex: impl Into<String>) -> Self {
+        Self {
+            latex: latex.into(),
+            group: None,
+        }
+    }
+
+    fn is_space(&self) -> bool {
+        self.latex == " "
+    }
+
+    /// The atom as an argument, without the parentheses of a group.
+    fn argument(&self) -> &str {
+        self.group.as_deref().unwrap_or(&self.latex)
+    }
+}
+
+/// The arguments of a function call.
+#[derive(Debug, Default)]
+struct Arguments<'a> {
+    /// Positional arguments, in rows separated by `;`.
+    rows: Vec<Vec<String>>,
+    named: Vec<(&'a str, &'a str)>,
+}
+
+impl Arguments<'_> {
+    fn positional(&self, i: usize) -> &str {
+        self.rows.iter().flatten().nth(i).map_or("", String::as_str)
+    }
+
+    fn named(&self, name: &str) -> Option<&str> {
+        self.named.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)
+    }
+}
+
+struct Translator<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Whether the equation uses `&` or `\` at the top level and needs an `aligned`
+    /// environment in LaTeX.
+    aligned: bool,
+}
+
+impl<'a> Translator<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    /// Translate atoms up to the end or one of the `stop` characters, which is not
+    /// consumed.
+    fn sequence(&mut self, stop: &[char]) -> String {
+        let mut atoms: Vec<Atom> = vec![];
+        while let Some(c) = self.peek().filter(|c| !stop.contains(c)) {
+            match c {
+                c if c.is_whitespace() => {
+                    self.skip_whitespace();
+                    if !atoms.last().is_some_and(Atom::is_space) {
+                        atoms.push(Atom::new(" "));
+                    }
+                }
+                '/' => {
+                    self.bump();
+                    while atoms.last().is_some_and(Atom::is_space) {
+                        atoms.pop();
+                    }
+                    let num = atoms.pop().unwrap_or_default();
+                    self.skip_whitespace();
+                    let den = self.operand(stop);
+                    atoms.push(Atom::new(format!(
+                        "\\frac{{{}}}{{{}}}",
+                        num.argument(),
+                        den.argument()
+                    )));
+                }
+                _ => {
+                    let atom = self.operand(stop);
+                    atoms.push(atom);
+                }
+            }
+        }
+        join(&atoms)
+    }
+
+    /// Translate an atom with its scripts and primes.
+    fn operand(&mut self, stop: &[char]) -> Atom {
+        let mut atom = self.atom(stop);
+        loop {
+            match self.peek() {
+                Some(c @ ('_' | '^')) => {
+                    self.bump();
+                    let script = self.atom(stop);
+                    atom.latex.push(c);
+                    atom.latex.push_str(&braced(script.argument()));
+                }
+                Some('\'') => {
+                    self.bump();
+                    atom.latex.push('\'');
+                }
+                _ => break,
+            }
+            atom.group = None;
+        }
+        atom
+    }
+
+    /// Translate a single symbol, group, string, or function call.
+    fn atom(&mut self, stop: &[char]) -> Atom {
+        let Some(c) = self.peek() else {
+            return Atom::default();
+        };
+        // Scripts without a base are attached by the caller.
+        if stop.contains(&c) || c == '_' || c == '^' {
+            return Atom::default();
+        }
+        if let Some((typst, latex)) = SHORTHANDS.iter().find(|(s, _)| self.rest().starts_with(s)) {
+            self.pos += typst.len();
+            return Atom::new(*latex);
+        }
+        self.bump();
+        match c {
+            '(' => {
+                let inner = self.sequence(&[')']);
+                self.bump();
+                let inner = inner.trim().to_string();
+                Atom {
+                    latex: format!("({inner})"),
+                    group: Some(inner),
+                }
+            }
+            '"' => {
+                let mut text = String::new();
+                while let Some(c) = self.bump() {
+                    match c {
+                        '"' => break,
+                        '\\' => text.extend(self.bump()),
+                        c => text.push(c),
+                    }
+                }
+                Atom::new(format!("\\text{{{text}}}"))
+            }
+            '\\' => match self.peek() {
+                // A line break.
+                None => Atom::default(),
+                Some(c) if c.is_whitespace() => {
+                    self.aligned |= stop.is_empty();
+                    Atom::new("\\\\")
+                }
+                Some(c) => {
+                    self.bump();
+                    match c {
+                        '{' | '}' | '&' | '_' | '#' | '$' | '%' => Atom::new(format!("\\{c}")),
+                        c => Atom::new(c),
+                    }
+                }
+            },
+            '&' => {
+                self.aligned |= stop.is_empty();
+                Atom::new("&")
+            }
+            '{' | '}' | '#' | '%' => Atom::new(format!("\\{c}")),
+            c if c.is_ascii_digit() => {
+                let start = self.pos - 1;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.bump();
+                }
+                let rest = self.rest();
+                if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                    self.bump();
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        self.bump();
+                    }
+                }
+                Atom::new(&self.src[start..self.pos])
+            }
+            c if c.is_alphabetic() => self.identifier(c.len_utf8()),
+            c => Atom::new(c),
+        }
+    }
+
+    /// Translate an identifier whose first `len` bytes have been read.
+    fn identifier(&mut self, len: usize) -> Atom {
+        let start = self.pos - len;
+        loop {
+            while self.peek().is_some_and(char::is_alphabetic) {
+                self.bump();
+            }
+            // Modifiers, like `arrow.r`.
+            let rest = self.rest();
+            if rest.starts_with('.') && rest[1..].starts_with(char::is_alphabetic) {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        let name = &self.src[start..self.pos];
+
+        if self.peek() == Some('(') {
+            if let Some(atom) = self.call(name) {
+                return atom;
+            }
+        }
+        if name.chars().count() == 1 {
+            Atom::new(name)
+        } else if let Some(symbol) = symbol(name) {
+            Atom::new(symbol)
+        } else if OPERATORS.contains(&name) {
+            Atom::new(format!("\\{name}"))
+        } else {
+            Atom::new(format!("\\text{{{name}}}"))
+        }
+    }
+
+    /// Translate a call to a known function. Returns `None`, reading nothing, for other
+    /// identifiers.
+    fn call(&mut self, name: &str) -> Option<Atom> {
+        let known = matches!(
+            name,
+            "frac"
+                | "binom"
+                | "sqrt"
+                | "root"
+                | "op"
+                | "abs"
+                | "norm"
+                | "floor"
+                | "ceil"
+                | "mat"
+                | "vec"
+                | "cases"
+                | "lr"
+                | "display"
+                | "inline"
+        ) || function(name).is_some();
+        if !known {
+            return None;
+        }
+        self.bump();
+        let args = self.arguments();
+        let arg = |i| args.positional(i);
+
+        let latex = match name {
+            "frac" => format!("\\frac{{{}}}{{{}}}", arg(0), arg(1)),
+            "binom" => format!("\\binom{{{}}}{{{}}}", arg(0), arg(1)),
+            "sqrt" => format!("\\sqrt{{{}}}", arg(0)),
+            "root" => format!("\\sqrt[{}]{{{}}}", arg(0), arg(1)),
+            "op" => {
+                let text = arg(0);
+                let text = text
+                    .strip_prefix("\\text{")
+                    .and_then(|t| t.strip_suffix('}'))
+                    .unwrap_or(text);
+                let star = if args.named("limits") == Some("#true") {
+                    "*"
+                } else {
+                    ""
+                };
+                format!("\\operatorname{star}{{{text}}}")
+            }
+            "abs" => format!("\\left| {} \\right|", arg(0)),
+            "norm" => format!("\\left\\| {} \\right\\|", arg(0)),
+            "floor" => format!("\\left\\lfloor {} \\right\\rfloor", arg(0)),
+            "ceil" => format!("\\left\\lceil {} \\right\\rceil", arg(0)),
+            "lr" | "display" | "inline" => arg(0).to_string(),
+            "mat" => {
+                let env = match args.named("delim") {
+                    Some("\"[\"") => "bmatrix",
+                    Some("\"{\"") => "Bmatrix",
+                    Some("\"|\"") => "vmatrix",
+                    Some("\"||\"") => "Vmatrix",
+                    Some("#none") => "matrix",
+                    _ => "pmatrix",
+                };
+                let rows = args.rows.iter().map(|row| row.join(" & "));
+                environment(env, rows)
+            }
+            "vec" => environment("pmatrix", args.rows.iter().flatten().cloned()),
+            "cases" => environment("cases", args.rows.iter().flatten().cloned()),
+            name => {
+                let function = function(name).expect("known function");
+                format!("{function}{{{}}}", arg(0))
+            }
+        };
+        Some(Atom::new(latex))
+    }
+
+    /// Read the arguments of a function call after its opening parenthesis.
+    fn arguments(&mut self) -> Arguments<'a> {
+        let mut args = Arguments {
+            rows: vec![vec![]],
+            named: vec![],
+        };
+        loop {
+            self.skip_whitespace();
+            if let Some(named) = self.named_argument() {
+                args.named.push(named);
+            } else {
+                let arg = self.sequence(&[',', ';', ')']);
+                if let Some(row) = args.rows.last_mut() {
+                    row.push(arg.trim().to_string());
+                }
+            }
+            match self.bump() {
+                Some(',') => {}
+                Some(';') => args.rows.push(vec![]),
+                _ => break,
+            }
+        }
+        // A trailing separator does not start another argument.
+        if let Some(row) = args.rows.last_mut() {
+            if row.last().is_some_and(String::is_empty) {
+                row.pop();
+            }
+        }
+        if args.rows.len() > 1 && args.rows.last().is_some_and(Vec::is_empty) {
+            args.rows.pop();
+        }
+        args
+    }
+
+    /// Read a `name: value` argument, keeping the value as Typst code.
+    fn named_argument(&mut self) -> Option<(&'a str, &'a str)> {
+        let rest = self.rest();
+        let len = rest.find(|c: char| !(c.is_ascii_alphabetic() || c == '-'))?;
+        let value = rest[len..]
+            .strip_prefix(':')
+            .filter(|v| !v.starts_with('='))?;
+        if len == 0 {
+            return None;
+        }
+        let mut string = false;
+        let mut escaped = false;
+        let mut end = value.len();
+        for (i, c) in value.char_indices() {
+            match c {
+                '"' if !escaped => string = !string,
+                ',' | ';' | ')' if !string => {
+                    end = i;
+                    break;
+                }
+                _ => {}
+            }
+            escaped = c == '\\' && !escaped;
+        }
+        self.pos += len + 1 + end;
+        Some((&rest[..len], value[..end].trim()))
+    }
+}
+
+/// Join atoms, keeping a command like `\alpha` apart from a following letter.
+fn join(atoms: &[Atom]) -> String {
+    let mut s = String::new();
+    for atom in atoms {
+        if ends_with_command(&s) && atom.latex.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            s.push(' ');
+        }
+        s.push_str(&atom.latex);
+    }
+    s
+}
+
+fn ends_with_command(s: &str) -> bool {
+    let rest = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    rest.len() < s.len() && rest.ends_with('\\') && !rest.ends_with("\\\\")
+}
+
+/// Wrap a script in braces unless it is a single character.
+fn braced(s: &str) -> String {
+    if s.chars().count() == 1 {
+        s.to_string()
+    } else {
+        format!("{{{s}}}")
+    }
+}
+
+/// A LaTeX environment with one row per line.
+fn environment(name: &str, rows: impl Iterator<Item = String>) -> String {
+    let rows = rows.collect::<Vec<_>>().join(" \\\\ ");
+    format!("\\begin{{{name}}} {rows} \\end{{{name}}}")
+}
+
+/// Typst shorthands, longest first.
+const SHORTHANDS: &[(&str, &str)] = &[
+    ("<==>", "\\Longleftrightarrow"),
+    ("==>", "\\implies"),
+    ("<==", "\\impliedby"),
+    ("-->", "\\longrightarrow"),
+    ("<--", "\\longleftarrow"),
+    ("|->", "\\mapsto"),
+    ("<=>", "\\Leftrightarrow"),
+    ("<->", "\\leftrightarrow"),
+    ("...", "\\ldots"),
+    ("->", "\\to"),
+    ("<-", "\\leftarrow"),
+    ("=>", "\\Rightarrow"),
+    ("<=", "\\leq"),
+    (">=", "\\geq"),
+    ("!=", "\\neq"),
+    ("<<", "\\ll"),
+    (">>", "\\gg"),
+    ("||", "\\|"),
+];
+
+/// Typst functions taking one argument that map to a LaTeX command.
+fn function(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "bold" => "\\mathbf",
+        "italic" => "\\mathit",
+        "upright" => "\\mathrm",
+        "bb" => "\\mathbb",
+        "cal" => "\\mathcal",
+        "frak" => "\\mathfrak",
+        "sans" => "\\mathsf",
+        "mono" => "\\mathtt",
+        "hat" => "\\hat",
+        "macron" => "\\bar",
+        "overline" => "\\overline",
+        "underline" => "\\underline",
+        "arrow" => "\\vec",
+        "tilde" => "\\tilde",
+        "dot" => "\\dot",
+        "dot.double" => "\\ddot",
+        "caron" => "\\check",
+        "breve" => "\\breve",
+        "acute" => "\\acute",
+        "grave" => "\\grave",
+        "overbrace" => "\\overbrace",
+        "underbrace" => "\\underbrace",
+        "cancel" => "\\cancel",
+        _ => return None,
+    })
+}
+
+/// Typst symbols that map to a LaTeX command.
+fn symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        // Greek letters.
+        "alpha" => "\\alpha",
+        "beta" => "\\beta",
+        "gamma" => "\\gamma",
+        "delta" => "\\delta",
+        "epsilon" => "\\varepsilon",
+        "epsilon.alt" => "\\epsilon",
+        "zeta" => "\\zeta",
+        "eta" => "\\eta",
+        "theta" => "\\theta",
+        "theta.alt" => "\\vartheta",
+        "iota" => "\\iota",
+        "kappa" => "\\kappa",
+        "kappa.alt" => "\\varkappa",
+        "lambda" => "\\lambda",
+        "mu" => "\\mu",
+        "nu" => "\\nu",
+        "xi" => "\\xi",
+        "omicron" => "o",
+        "pi" => "\\pi",
+        "pi.alt" => "\\varpi",
+        "rho" => "\\rho",
+        "rho.alt" => "\\varrho",
+        "sigma" => "\\sigma",
+        "sigma.alt" => "\\varsigma",
+        "tau" => "\\tau",
+        "upsilon" => "\\upsilon",
+        "phi" => "\\varphi",
+        "phi.alt" => "\\phi",
+        "chi" => "\\chi",
+        "psi" => "\\psi",
+        "omega" => "\\omega",
+        "Gamma" => "\\Gamma",
+        "Delta" => "\\Delta",
+        "Theta" => "\\Theta",
+        "Lambda" => "\\Lambda",
+        "Xi" => "\\Xi",
+        "Pi" => "\\Pi",
+        "Sigma" => "\\Sigma",
+        "Upsilon" => "\\Upsilon",
+        "Phi" => "\\Phi",
+        "Psi" => "\\Psi",
+        "Omega" => "\\Omega",
+        // Big operators.
+        "sum" => "\\sum",
+        "product" => "\\prod",
+        "product.co" => "\\coprod",
+        "integral" => "\\int",
+        "integral.double" => "\\iint",
+        "integral.triple" => "\\iiint",
+        "integral.cont" => "\\oint",
+        "union.big" => "\\bigcup",
+        "sect.big" => "\\bigcap",
+        "plus.circle.big" => "\\bigoplus",
+        "times.circle.big" => "\\bigotimes",
+        // Binary operators.
+        "dot" | "dot.op" => "\\cdot",
+        "times" => "\\times",
+        "div" => "\\div",
+        "plus.minus" => "\\pm",
+        "minus.plus" => "\\mp",
+        "ast" => "\\ast",
+        "star" => "\\star",
+        "compose" => "\\circ",
+        "bullet" => "\\bullet",
+        "plus.circle" => "\\oplus",
+        "times.circle" => "\\otimes",
+        "union" => "\\cup",
+        "sect" => "\\cap",
+        "without" => "\\setminus",
+        "and" => "\\wedge",
+        "or" => "\\vee",
+        "not" => "\\neg",
+        "mod" => "\\bmod",
+        // Relations.
+        "eq" => "=",
+        "eq.not" => "\\neq",
+        "lt.eq" => "\\leq",
+        "gt.eq" => "\\geq",
+        "approx" => "\\approx",
+        "equiv" => "\\equiv",
+        "tilde.op" => "\\sim",
+        "tilde.eq" => "\\simeq",
+        "tilde.equiv" => "\\cong",
+        "prop" => "\\propto",
+        "in" => "\\in",
+        "in.not" => "\\notin",
+        "in.rev" => "\\ni",
+        "subset" => "\\subset",
+        "subset.eq" => "\\subseteq",
+        "supset" => "\\supset",
+        "supset.eq" => "\\supseteq",
+        "divides" => "\\mid",
+        "parallel" => "\\parallel",
+        "perp" => "\\perp",
+        // Arrows.
+        "arrow.r" => "\\rightarrow",
+        "arrow.l" => "\\leftarrow",
+        "arrow.l.r" => "\\leftrightarrow",
+        "arrow.r.double" => "\\Rightarrow",
+        "arrow.l.double" => "\\Leftarrow",
+        "arrow.l.r.double" => "\\Leftrightarrow",
+        "arrow.r.bar" => "\\mapsto",
+        "arrow.t" => "\\uparrow",
+        "arrow.b" => "\\downarrow",
+        "arrow.r.long" => "\\longrightarrow",
+        "arrow.l.long" => "\\longleftarrow",
+        // Miscellaneous.
+        "infinity" | "oo" => "\\infty",
+        "diff" => "\\partial",
+        "nabla" => "\\nabla",
+        "forall" => "\\forall",
+        "exists" => "\\exists",
+        "exists.not" => "\\nexists",
+        "emptyset" => "\\emptyset",
+        "dots" | "dots.h" => "\\ldots",
+        "dots.c" => "\\cdots",
+        "dots.v" => "\\vdots",
+        "dots.down" => "\\ddots",
+        "prime" => "\\prime",
+        "angle" => "\\angle",
+        "planck.reduce" => "\\hbar",
+        "ell" => "\\ell",
+        "Re" => "\\Re",
+        "Im" => "\\Im",
+        "aleph" => "\\aleph",
+        "angle.l" => "\\langle",
+        "angle.r" => "\\rangle",
+        "floor.l" => "\\lfloor",
+        "floor.r" => "\\rfloor",
+        "ceil.l" => "\\lceil",
+        "ceil.r" => "\\rceil",
+        "bar.v" => "|",
+        "bar.v.double" | "bar.double" => "\\|",
+        "thin" => "\\,",
+        "med" => "\\:",
+        "thick" => "\\;",
+        "space" => "\\ ",
+        "quad" => "\\quad",
+        "wide" => "\\qquad",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use similar_asserts::assert_eq;
+
+    #[test]
+    fn fractions_and_roots() {
+        self::assert_eq!(typst_to_latex("frac(a+b, 2)"), r"\frac{a+b}{2}");
+        self::assert_eq!(typst_to_latex("(a+b)/2"), r"\frac{a+b}{2}");
+        self::assert_eq!(typst_to_latex("x^2 / y"), r"\frac{x^2}{y}");
+        self::assert_eq!(typst_to_latex("sqrt(x^2 + 1)"), r"\sqrt{x^2 + 1}");
+        self::assert_eq!(typst_to_latex("root(3, x)"), r"\sqrt[3]{x}");
+        self::assert_eq!(typst_to_latex("a\\/b"), "a/b");
+    }
+
+    #[test]
+    fn scripts() {
+        self::assert_eq!(typst_to_latex("x^2"), "x^2");
+        self::assert_eq!(typst_to_latex("x^10"), "x^{10}");
+        self::assert_eq!(typst_to_latex("x^(-1)"), "x^{-1}");
+        self::assert_eq!(typst_to_latex("a_(i,j)"), "a_{i,j}");
+        self::assert_eq!(typst_to_latex("e^(i pi)"), r"e^{i \pi}");
+        self::assert_eq!(typst_to_latex("f'(x)"), "f'(x)");
+    }
+
+    #[test]
+    fn symbols() {
+        self::assert_eq!(typst_to_latex("alpha beta"), r"\alpha \beta");
+        self::assert_eq!(
+            typst_to_latex("epsilon < epsilon.alt"),
+            r"\varepsilon < \epsilon"
+        );
+        self::assert_eq!(typst_to_latex("a <= b != c"), r"a \leq b \neq c");
+        self::assert_eq!(
+            typst_to_latex("A arrow.r.double B -> C"),
+            r"A \Rightarrow B \to C"
+        );
+        self::assert_eq!(typst_to_latex("x in bb(R)"), r"x \in \mathbb{R}");
+    }
+
+    #[test]
+    fn big_operators() {
+        self::assert_eq!(
+            typst_to_latex("sum_(i=1)^n i = frac(n(n+1), 2)"),
+            r"\sum_{i=1}^n i = \frac{n(n+1)}{2}"
+        );
+        self::assert_eq!(
+            typst_to_latex("integral_0^infinity e^(-x^2) thin d x"),
+            r"\int_0^{\infty} e^{-x^2} \, d x"
+        );
+        self::assert_eq!(
+            typst_to_latex("lim_(x -> 0) sin x"),
+            r"\lim_{x \to 0} \sin x"
+        );
+    }
+
+    #[test]
+    fn text_and_fonts() {
+        self::assert_eq!(typst_to_latex("\"if \" x > 0"), r"\text{if } x > 0");
+        self::assert_eq!(
+            typst_to_latex("bold(v) dot.op hat(n)"),
+            r"\mathbf{v} \cdot \hat{n}"
+        );
+        self::assert_eq!(
+            typst_to_latex("op(\"argmax\")_x"),
+            r"\operatorname{argmax}_x"
+        );
+        self::assert_eq!(typst_to_latex("abs(x)"), r"\left| x \right|");
+        self::assert_eq!(typst_to_latex("unknown x"), r"\text{unknown} x");
+    }
+
+    #[test]
+    fn matrices() {
+        self::assert_eq!(
+            typst_to_latex("mat(a, b; c, d)"),
+            r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"
+        );
+        self::assert_eq!(
+            typst_to_latex("mat(delim: \"[\", 1, 0; 0, 1)"),
+            r"\begin{bmatrix} 1 & 0 \\ 0 & 1 \end{bmatrix}"
+        );
+        self::assert_eq!(
+            typst_to_latex("vec(1, 2)"),
+            r"\begin{pmatrix} 1 \\ 2 \end{pmatrix}"
+        );
+    }
+
+    #[test]
+    fn alignment() {
+        self::assert_eq!(
+            typst_to_latex("|x| = cases(x & x >= 0, -x & x < 0)"),
+            r"|x| = \begin{cases} x & x \geq 0 \\ -x & x < 0 \end{cases}"
+        );
+        self::assert_eq!(
+            typst_to_latex("a &= b \\ &= c"),
+            r"\begin{aligned} a &= b \\ &= c \end{aligned}"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        use crate::markdown::to::typst::math::latex_to_typst;
+
+        for latex in [
+            r"\frac{a+b}{2}",
+            r"\sqrt[3]{x}",
+            r"x^{-1}",
+            r"\sum_{i=1}^n i",
+            r"\alpha \leq \beta",
+            r"\mathbf{v} \cdot \hat{n}",
+            r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}",
+        ] {
+            self::assert_eq!(typst_to_latex(&latex_to_typst(latex)), latex);
+        }
+    }
+}
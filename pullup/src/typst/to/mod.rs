@@ -1,3 +1,5 @@
 //! Convert Typst _to_ other formats.
 
+#[cfg(feature = "markdown")]
+pub mod markdown;
 pub mod markup;